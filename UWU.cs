@@ -0,0 +1,29 @@
+// Game side API of uwu. Unlike UWUClient.cs this must NOT go in an Editor folder, so that game
+// scripts can call it. Outside of the editor it does nothing, or what Unity does
+public static class UWU {
+    // Stops play mode, and makes `uwu play --wait` exit with exitCode after printing result.
+    // The keys must match PlayModeResult in UWUClient.cs
+    public static void ExitPlayMode(int exitCode = 0, string result = null) {
+#if UNITY_EDITOR
+        UnityEditor.SessionState.SetInt("UWU.ExitCode", exitCode);
+        UnityEditor.SessionState.SetString("UWU.Result", result ?? "");
+        UnityEditor.EditorApplication.ExitPlaymode();
+#endif
+    }
+
+    // Input.GetAxis, unless `uwu input axis` set the axis for this Play mode. The keys must
+    // match SimulatedInput in UWUClient.cs
+    public static float GetAxis(string axisName) {
+#if UNITY_EDITOR
+        float simulated = UnityEditor.SessionState.GetFloat("UWU.Axis." + axisName, float.NaN);
+        if (!float.IsNaN(simulated)) {
+            return simulated;
+        }
+#endif
+#if ENABLE_LEGACY_INPUT_MANAGER
+        return UnityEngine.Input.GetAxis(axisName);
+#else
+        return 0;
+#endif
+    }
+}
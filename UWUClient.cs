@@ -4,11 +4,20 @@ using UnityEditor;
 using System.Net.Sockets;
 using System.Net;
 using System;
+using System.Diagnostics;
+using System.IO;
+using System.Runtime.InteropServices;
+using System.Threading;
+using Debug = UnityEngine.Debug;
 
 public enum ExecResult {
     Success,
     Error,
     Wait,
+    // sent any number of times after Wait, before the final Success or Error
+    Progress,
+    // a console message for `uwu logs`
+    Log,
 }
 
 static class Util {
@@ -21,172 +30,3434 @@ static class Util {
         return EditorApplication.isCompiling || EditorApplication.isUpdating;
     }
 
-    public static string ResultToJSON(ExecResult result) {
+    // the names that people actually type, the rest are parsed as BuildTarget names
+    private static Dictionary<string, BuildTarget> buildTargetAliases = new Dictionary<string, BuildTarget> {
+        { "win", BuildTarget.StandaloneWindows },
+        { "win64", BuildTarget.StandaloneWindows64 },
+        { "mac", BuildTarget.StandaloneOSX },
+        { "osx", BuildTarget.StandaloneOSX },
+        { "linux", BuildTarget.StandaloneLinux64 },
+        { "linux64", BuildTarget.StandaloneLinux64 },
+    };
+
+    public static bool TryParseBuildTarget(string name, out BuildTarget target) {
+        if (buildTargetAliases.TryGetValue(name.ToLowerInvariant(), out target)) {
+            return true;
+        }
+        try {
+            target = (BuildTarget)Enum.Parse(typeof(BuildTarget), name, true);
+            return true;
+        } catch (ArgumentException) {
+            target = BuildTarget.NoTarget;
+            return false;
+        }
+    }
+
+    // The Console window has no public API, LogEntries is what its Clear button calls
+    public static Reply ClearConsole() {
+        var logEntries = FindType("UnityEditor.LogEntries") ?? FindType("UnityEditorInternal.LogEntries");
+        var clear = logEntries != null
+            ? logEntries.GetMethod("Clear", System.Reflection.BindingFlags.Static | System.Reflection.BindingFlags.Public | System.Reflection.BindingFlags.NonPublic)
+            : null;
+        if (clear == null) {
+            return Reply.Error("This version of Unity has no LogEntries.Clear");
+        }
+
+        clear.Invoke(null, null);
+        return Reply.Success();
+    }
+
+    // Looks in every loaded assembly, including the ones of packages that the plugin can't reference
+    public static Type FindType(string fullName) {
+        foreach (var assembly in AppDomain.CurrentDomain.GetAssemblies()) {
+            var type = assembly.GetType(fullName);
+            if (type != null) {
+                return type;
+            }
+        }
+        return null;
+    }
+
+    // Calls Namespace.Class.Method like -executeMethod does, converting the arguments from
+    // strings to whatever the method takes. The result is returned as {"result": ...}
+    public static Reply InvokeStaticMethod(string fullName, string[] args) {
+        int dot = fullName.LastIndexOf('.');
+        if (dot <= 0) {
+            return Reply.Error("Expected Namespace.Class.Method, got '" + fullName + "'");
+        }
+        string typeName = fullName.Substring(0, dot);
+        string methodName = fullName.Substring(dot + 1);
+
+        Type type = FindType(typeName);
+        if (type == null) {
+            return Reply.Error("Type '" + typeName + "' not found");
+        }
+
+        const System.Reflection.BindingFlags flags = System.Reflection.BindingFlags.Static
+            | System.Reflection.BindingFlags.Public | System.Reflection.BindingFlags.NonPublic;
+        System.Reflection.MethodInfo method = null;
+        foreach (var candidate in type.GetMethods(flags)) {
+            if (candidate.Name == methodName && candidate.GetParameters().Length == args.Length) {
+                method = candidate;
+                break;
+            }
+        }
+        if (method == null) {
+            return Reply.Error("No static method " + fullName + " taking " + args.Length + " arguments");
+        }
+
+        var parameters = method.GetParameters();
+        var values = new object[args.Length];
+        for (int i = 0; i < args.Length; ++i) {
+            var parameterType = parameters[i].ParameterType;
+            try {
+                values[i] = parameterType.IsEnum
+                    ? Enum.Parse(parameterType, args[i], true)
+                    : Convert.ChangeType(args[i], parameterType, System.Globalization.CultureInfo.InvariantCulture);
+            } catch (Exception) {
+                return Reply.Error("Can't convert '" + args[i] + "' to " + parameterType.Name + " for parameter " + parameters[i].Name);
+            }
+        }
+
+        object result;
+        try {
+            result = method.Invoke(null, values);
+        } catch (System.Reflection.TargetInvocationException e) {
+            var inner = e.InnerException;
+            return Reply.Error(inner.GetType().Name + ": " + inner.Message, inner.StackTrace);
+        }
+
+        return ResultReply(method.ReturnType == typeof(void) ? null : result);
+    }
+
+    // The payload of Exec and Eval
+    public static Reply ResultReply(object result) {
+        if (result == null) {
+            return Reply.Success("{\"result\":null}");
+        }
+        if (result is bool) {
+            return Reply.Success("{\"result\":" + ((bool)result ? "true" : "false") + "}");
+        }
+        if (result is int || result is long || result is float || result is double) {
+            return Reply.Success("{\"result\":" + Convert.ToString(result, System.Globalization.CultureInfo.InvariantCulture) + "}");
+        }
+        return Reply.Success("{\"result\":" + JsonString(result.ToString()) + "}");
+    }
+
+    // Every menu item that ExecuteMenuItem accepts: the built-in menus and the [MenuItem]s of the project
+    public static List<string> MenuItems() {
+        var items = new SortedSet<string>();
+
+        foreach (var menu in new[] { "File", "Edit", "Assets", "GameObject", "Component", "Window", "Help" }) {
+            foreach (var item in Unsupported.GetSubmenus(menu)) {
+                items.Add(item);
+            }
+        }
+
+        foreach (var method in TypeCache.GetMethodsWithAttribute<MenuItem>()) {
+            foreach (MenuItem attribute in method.GetCustomAttributes(typeof(MenuItem), false)) {
+                // validation functions and context menus can't be executed
+                if (!attribute.validate && !attribute.menuItem.StartsWith("CONTEXT/")) {
+                    items.Add(attribute.menuItem);
+                }
+            }
+        }
+
+        return new List<string>(items);
+    }
+
+    // A scene asset path, from either a path or just the name of the scene
+    public static string FindScene(string scene, out string error) {
+        error = null;
+        if (scene.EndsWith(".unity")) {
+            if (File.Exists(scene)) {
+                return scene;
+            }
+            error = "No scene at " + scene;
+            return null;
+        }
+
+        var matches = new List<string>();
+        foreach (var guid in AssetDatabase.FindAssets("t:Scene " + scene)) {
+            var path = AssetDatabase.GUIDToAssetPath(guid);
+            if (Path.GetFileNameWithoutExtension(path) == scene) {
+                matches.Add(path);
+            }
+        }
+
+        if (matches.Count == 1) {
+            return matches[0];
+        }
+        error = matches.Count == 0
+            ? "No scene named '" + scene + "'"
+            : "Several scenes are named '" + scene + "', pass the path of one: " + string.Join(", ", matches.ToArray());
+        return null;
+    }
+
+    // A GameObject of the open scenes from its hierarchy path, like Level/Enemies/Boss.
+    // Unlike GameObject.Find, inactive objects are found too
+    public static GameObject FindGameObject(string path) {
+        var names = path.Trim('/').Split(new[] { '/' }, 2);
+        for (int i = 0; i < UnityEngine.SceneManagement.SceneManager.sceneCount; ++i) {
+            var scene = UnityEngine.SceneManagement.SceneManager.GetSceneAt(i);
+            if (!scene.isLoaded) {
+                continue;
+            }
+            foreach (var root in scene.GetRootGameObjects()) {
+                if (root.name != names[0]) {
+                    continue;
+                }
+                var found = names.Length == 1 ? root.transform : root.transform.Find(names[1]);
+                if (found != null) {
+                    return found.gameObject;
+                }
+            }
+        }
+        return null;
+    }
+
+    public static bool HasUnsavedScenes() {
+        for (int i = 0; i < UnityEngine.SceneManagement.SceneManager.sceneCount; ++i) {
+            if (UnityEngine.SceneManagement.SceneManager.GetSceneAt(i).isDirty) {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    // a JPEG of this quality, or a PNG if 0
+    public static byte[] RenderCamera(Camera camera, int width, int height, int quality) {
+        var target = RenderTexture.GetTemporary(width, height, 24);
+        var previousTarget = camera.targetTexture;
+        var previousActive = RenderTexture.active;
+        try {
+            camera.targetTexture = target;
+            camera.Render();
+
+            RenderTexture.active = target;
+            var texture = new Texture2D(width, height, TextureFormat.RGB24, false);
+            texture.ReadPixels(new Rect(0, 0, width, height), 0, 0);
+            texture.Apply();
+
+            byte[] image = quality > 0 ? texture.EncodeToJPG(quality) : texture.EncodeToPNG();
+            UnityEngine.Object.DestroyImmediate(texture);
+            return image;
+        } finally {
+            camera.targetTexture = previousTarget;
+            RenderTexture.active = previousActive;
+            RenderTexture.ReleaseTemporary(target);
+        }
+    }
+
+    // base64 for the CLI, which decodes the url-safe alphabet without padding
+    public static string Base64Url(byte[] bytes, int offset, int length) {
+        return Convert.ToBase64String(bytes, offset, length).TrimEnd('=').Replace('+', '-').Replace('/', '_');
+    }
+
+    // Package Manager requests complete in the background, check on them every editor update
+    public static void WhenDone(UnityEditor.PackageManager.Requests.Request request, Action done) {
+        EditorApplication.CallbackFunction poll = null;
+        poll = () => {
+            if (request.IsCompleted) {
+                EditorApplication.update -= poll;
+                done();
+            }
+        };
+        EditorApplication.update += poll;
+    }
+
+    public static string PackageJson(UnityEditor.PackageManager.PackageInfo package) {
+        return "{\"name\":" + JsonString(package.name)
+            + ",\"version\":" + JsonString(package.version)
+            + ",\"source\":" + JsonString(package.source.ToString()) + "}";
+    }
+
+    // What the editor is doing, as reported to `uwu instances`
+    public static string EditorState() {
+        if (EditorApplication.isCompiling) {
+            return "compiling";
+        }
+        if (EditorApplication.isUpdating) {
+            return "importing";
+        }
+        if (EditorApplication.isPlaying) {
+            return EditorApplication.isPaused ? "paused" : "playing";
+        }
+        return "idle";
+    }
+
+#if UNITY_EDITOR_WIN
+    [DllImport("user32.dll")]
+    private static extern bool SetForegroundWindow(IntPtr hWnd);
+
+    [DllImport("user32.dll")]
+    private static extern bool ShowWindow(IntPtr hWnd, int nCmdShow);
+
+    [DllImport("user32.dll")]
+    private static extern bool IsIconic(IntPtr hWnd);
+
+    private const int SW_RESTORE = 9;
+#endif
+
+    // Raises the main editor window above every other application window.
+    // Returns why it failed, or null on success
+    public static string FocusEditorWindow() {
+        try {
+#if UNITY_EDITOR_WIN
+            IntPtr handle = Process.GetCurrentProcess().MainWindowHandle;
+            if (handle == IntPtr.Zero) {
+                return "Could not find the editor main window";
+            }
+
+            // only restore minimized windows, restoring a maximized one would shrink it
+            if (IsIconic(handle)) {
+                ShowWindow(handle, SW_RESTORE);
+            }
+            if (!SetForegroundWindow(handle)) {
+                return "Windows refused to bring the editor to the foreground";
+            }
+            return null;
+#elif UNITY_EDITOR_OSX
+            int pid = Process.GetCurrentProcess().Id;
+            var info = new ProcessStartInfo("osascript",
+                "-e \"tell application \\\"System Events\\\" to set frontmost of (first process whose unix id is " + pid + ") to true\"");
+            info.UseShellExecute = false;
+            info.CreateNoWindow = true;
+
+            using (var process = Process.Start(info)) {
+                process.WaitForExit();
+                if (process.ExitCode != 0) {
+                    return "osascript failed to bring the editor to the foreground";
+                }
+            }
+            return null;
+#else
+            return "Focusing the editor window is not supported on this platform";
+#endif
+        } catch (Exception e) {
+            return "Failed to focus the editor window: " + e.Message;
+        }
+    }
+
+    public static string JsonString(string value) {
+        var builder = new System.Text.StringBuilder("\"");
+        foreach (char c in value) {
+            switch (c) {
+                case '"': builder.Append("\\\""); break;
+                case '\\': builder.Append("\\\\"); break;
+                case '\n': builder.Append("\\n"); break;
+                case '\r': builder.Append("\\r"); break;
+                case '\t': builder.Append("\\t"); break;
+                default:
+                    if (c < ' ') {
+                        builder.Append("\\u" + ((int)c).ToString("x4"));
+                    } else {
+                        builder.Append(c);
+                    }
+                    break;
+            }
+        }
+        return builder.Append('"').ToString();
+    }
+}
+
+// What a command sends back, matching Response on the CLI side
+class Reply {
+    public ExecResult result;
+    // raw JSON, only for Success
+    public string payload;
+    // only for Error, Progress and Log
+    public string message;
+    public string stack;
+    // only for Log
+    public string kind;
+    // raw JSON, only for the Progress of Test
+    public string test;
+    // from 0 to 100 for Progress that knows how far along it is, negative otherwise
+    public float percent = -1;
+
+    public static Reply Success(string payload = null) {
+        var reply = new Reply();
+        reply.result = ExecResult.Success;
+        reply.payload = payload;
+        return reply;
+    }
+
+    public static Reply Error(string message, string stack = null) {
+        var reply = new Reply();
+        reply.result = ExecResult.Error;
+        reply.message = message;
+        reply.stack = stack;
+        return reply;
+    }
+
+    public static Reply Wait() {
+        var reply = new Reply();
+        reply.result = ExecResult.Wait;
+        return reply;
+    }
+
+    public static Reply Log(string kind, string message, string stack) {
+        var reply = new Reply();
+        reply.result = ExecResult.Log;
+        reply.kind = kind;
+        reply.message = message;
+        reply.stack = stack;
+        return reply;
+    }
+
+    public static Reply Progress(string message, string test = null) {
+        var reply = new Reply();
+        reply.result = ExecResult.Progress;
+        reply.message = message;
+        reply.test = test;
+        return reply;
+    }
+
+    public static Reply Progress(string message, float percent) {
+        var reply = Progress(message);
+        reply.percent = percent;
+        return reply;
+    }
+
+    // id is the id of the request being answered, so that the CLI can drop stale replies
+    public string ToJSON(string id) {
+        string json = "{\"id\":" + Util.JsonString(id);
         switch (result) {
             case ExecResult.Success:
-                return "\"Success\"";
+                json += ",\"status\":\"Success\"";
+                if (payload != null) {
+                    json += ",\"payload\":" + payload;
+                }
+                return json + "}";
             case ExecResult.Error:
-                return "\"Error\"";
+                json += ",\"status\":\"Error\",\"message\":" + Util.JsonString(message);
+                if (stack != null) {
+                    json += ",\"stack\":" + Util.JsonString(stack);
+                }
+                return json + "}";
             case ExecResult.Wait:
-                return "\"Wait\"";
+                return json + ",\"status\":\"Wait\"}";
+            case ExecResult.Log:
+                json += ",\"status\":\"Log\",\"kind\":" + Util.JsonString(kind) + ",\"message\":" + Util.JsonString(message);
+                if (!string.IsNullOrEmpty(stack)) {
+                    json += ",\"stack\":" + Util.JsonString(stack);
+                }
+                return json + "}";
+            case ExecResult.Progress:
+                json += ",\"status\":\"Progress\",\"message\":" + Util.JsonString(message);
+                if (test != null) {
+                    json += ",\"test\":" + test;
+                }
+                if (percent >= 0) {
+                    json += ",\"percent\":" + percent.ToString(System.Globalization.CultureInfo.InvariantCulture);
+                }
+                return json + "}";
             default:
                 throw new Exception("Unknown result type");
         }
     }
 }
 
-class Command {
-    public delegate void MessageSender(ExecResult mode);
+// Where the replies to a request go, depending on the transport it came from
+interface IReplyChannel {
+    // id is the request that bytes answers
+    void Send(string id, byte[] bytes);
+}
+
+class UdpReplyChannel : IReplyChannel {
+    UdpClient client;
+    IPEndPoint requester;
+
+    public UdpReplyChannel(UdpClient client, IPEndPoint requester) {
+        this.client = client;
+        this.requester = requester;
+    }
+
+    public IPEndPoint GetRequester() {
+        return requester;
+    }
+
+    public void Send(string id, byte[] bytes) {
+        Fragments.Send(client, requester, id, bytes);
+    }
+}
+
+// Messages that don't fit in a datagram go in fragments, that the CLI puts back together.
+// Each starts with "UWUf", the id of the request as 8 bytes, its index, the number of
+// fragments and the size of the whole message, little endian. Must match fragment.rs
+static class Fragments {
+    const int MaxDatagramSize = 65507;
+    const int FragmentSize = 60 * 1024;
+    const int HeaderSize = 20;
+    static readonly byte[] Magic = { (byte)'U', (byte)'W', (byte)'U', (byte)'f' };
+
+    // the ids are the 8 bytes of an Id64 in base64url
+    static byte[] IdBytes(string id) {
+        try {
+            string base64 = id.Replace('-', '+').Replace('_', '/');
+            base64 = base64.PadRight(base64.Length + (4 - base64.Length % 4) % 4, '=');
+            byte[] bytes = Convert.FromBase64String(base64);
+            if (bytes.Length == 8) {
+                return bytes;
+            }
+        } catch (FormatException) {
+        }
+        // still reassembled, as long as no other big message is on its way
+        return new byte[8];
+    }
+
+    static void WriteLittleEndian(byte[] buffer, int offset, int value, int size) {
+        for (int i = 0; i < size; ++i) {
+            buffer[offset + i] = (byte)(value >> (8 * i));
+        }
+    }
+
+    public static void Send(UdpClient client, IPEndPoint requester, string id, byte[] bytes) {
+        if (bytes.Length <= MaxDatagramSize) {
+            client.Send(bytes, bytes.Length, requester);
+            return;
+        }
+
+        byte[] idBytes = IdBytes(id);
+        int count = (bytes.Length + FragmentSize - 1) / FragmentSize;
+        for (int index = 0; index < count; ++index) {
+            int offset = index * FragmentSize;
+            int size = Math.Min(FragmentSize, bytes.Length - offset);
+
+            var datagram = new byte[HeaderSize + size];
+            Array.Copy(Magic, 0, datagram, 0, 4);
+            Array.Copy(idBytes, 0, datagram, 4, 8);
+            WriteLittleEndian(datagram, 12, index, 2);
+            WriteLittleEndian(datagram, 14, count, 2);
+            WriteLittleEndian(datagram, 16, bytes.Length, 4);
+            Array.Copy(bytes, offset, datagram, HeaderSize, size);
+
+            client.Send(datagram, datagram.Length, requester);
+        }
+    }
+}
+
+// Stream transports prefix every message with its length as a little endian 32 bit int
+static class Framing {
+    public static byte[] Frame(byte[] bytes) {
+        var frame = new byte[4 + bytes.Length];
+        frame[0] = (byte)bytes.Length;
+        frame[1] = (byte)(bytes.Length >> 8);
+        frame[2] = (byte)(bytes.Length >> 16);
+        frame[3] = (byte)(bytes.Length >> 24);
+        Array.Copy(bytes, 0, frame, 4, bytes.Length);
+        return frame;
+    }
+
+    // Blocks until a whole frame is read. Returns null once the stream is closed
+    public static byte[] ReadFrame(Stream stream) {
+        var header = ReadExactly(stream, 4);
+        if (header == null) {
+            return null;
+        }
+        int length = header[0] | (header[1] << 8) | (header[2] << 16) | (header[3] << 24);
+        return ReadExactly(stream, length);
+    }
+
+    private static byte[] ReadExactly(Stream stream, int count) {
+        var buffer = new byte[count];
+        int offset = 0;
+        while (offset < count) {
+            int read = stream.Read(buffer, offset, count - offset);
+            if (read == 0) {
+                return null;
+            }
+            offset += read;
+        }
+        return buffer;
+    }
+}
+
+// A TCP client
+class TcpConnection : IReplyChannel {
+    TcpClient client;
+    // bytes received so far that don't form a whole frame yet
+    List<byte> pending = new List<byte>();
+
+    public TcpConnection(TcpClient client) {
+        this.client = client;
+    }
+
+    public bool IsClosed() {
+        if (!client.Connected) {
+            return true;
+        }
+        // a socket that is readable with nothing to read has been closed by the CLI
+        try {
+            return client.Client.Poll(0, SelectMode.SelectRead) && client.Available == 0;
+        } catch (Exception) {
+            return true;
+        }
+    }
+
+    public void Close() {
+        client.Close();
+    }
+
+    // Returns every complete frame received since the last call, without blocking
+    public List<byte[]> ReadFrames() {
+        var frames = new List<byte[]>();
+
+        try {
+            var stream = client.GetStream();
+            while (stream.DataAvailable) {
+                var chunk = new byte[client.Available];
+                int read = stream.Read(chunk, 0, chunk.Length);
+                if (read == 0) {
+                    break;
+                }
+                for (int i = 0; i < read; ++i) {
+                    pending.Add(chunk[i]);
+                }
+            }
+        } catch (Exception e) {
+            Debug.LogWarning("UWU: TCP client dropped: " + e.Message);
+            client.Close();
+            return frames;
+        }
+
+        while (pending.Count >= 4) {
+            int length = pending[0] | (pending[1] << 8) | (pending[2] << 16) | (pending[3] << 24);
+            if (pending.Count < 4 + length) {
+                break;
+            }
+            frames.Add(pending.GetRange(4, length).ToArray());
+            pending.RemoveRange(0, 4 + length);
+        }
+
+        return frames;
+    }
+
+    public void Send(string id, byte[] bytes) {
+        var frame = Framing.Frame(bytes);
+
+        try {
+            client.GetStream().Write(frame, 0, frame.Length);
+        } catch (Exception e) {
+            // the CLI gave up on this connection and will retry on a new one
+            Debug.LogWarning("UWU: Could not reply over TCP: " + e.Message);
+        }
+    }
+}
+
+// A client of the IPC transport, read on its own thread
+class IpcConnection : IReplyChannel {
+    Stream stream;
+
+    public IpcConnection(Stream stream) {
+        this.stream = stream;
+    }
+
+    public void Close() {
+        stream.Close();
+    }
+
+    public void Send(string id, byte[] bytes) {
+        var frame = Framing.Frame(bytes);
+
+        try {
+            stream.Write(frame, 0, frame.Length);
+            stream.Flush();
+        } catch (Exception e) {
+            Debug.LogWarning("UWU: Could not reply over IPC: " + e.Message);
+        }
+    }
+}
+
+// Local IPC transport, a named pipe on Windows and a Unix domain socket elsewhere.
+// Blocking accepts and reads happen on background threads, and the frames are handed over
+// to the main thread because the Unity API can only be used from there
+class IpcServer {
+    // what the CLI needs to connect, written to the port file
+    public string address;
+
+    volatile bool running = true;
+    List<IpcConnection> connections = new List<IpcConnection>();
+    Queue<KeyValuePair<byte[], IpcConnection>> received = new Queue<KeyValuePair<byte[], IpcConnection>>();
+
+#if UNITY_EDITOR_WIN
+    string pipeName;
+
+    public IpcServer() {
+        pipeName = "uwu-" + Process.GetCurrentProcess().Id;
+        address = @"\\.\pipe\" + pipeName;
+
+        new Thread(() => {
+            while (running) {
+                var pipe = new System.IO.Pipes.NamedPipeServerStream(pipeName,
+                    System.IO.Pipes.PipeDirection.InOut,
+                    System.IO.Pipes.NamedPipeServerStream.MaxAllowedServerInstances);
+                try {
+                    pipe.WaitForConnection();
+                } catch (Exception) {
+                    pipe.Close();
+                    return;
+                }
+                Accept(new IpcConnection(pipe), pipe);
+            }
+        }).Start();
+    }
+
+    public void Stop() {
+        running = false;
+
+        // unblock WaitForConnection with a connection of our own
+        try {
+            using (var client = new System.IO.Pipes.NamedPipeClientStream(".", pipeName)) {
+                client.Connect(100);
+            }
+        } catch (Exception) {
+            // the thread is gone already
+        }
+        CloseConnections();
+    }
+#elif UNITY_2021_2_OR_NEWER
+    Socket listener;
+
+    public IpcServer() {
+        // socket paths have a short length limit, so they can't live in the project folder
+        address = Path.Combine(Path.GetTempPath(), "uwu-" + Process.GetCurrentProcess().Id + ".sock");
+        File.Delete(address);
+
+        listener = new Socket(AddressFamily.Unix, SocketType.Stream, ProtocolType.Unspecified);
+        listener.Bind(new UnixDomainSocketEndPoint(address));
+        listener.Listen(4);
+
+        new Thread(() => {
+            while (running) {
+                Socket client;
+                try {
+                    client = listener.Accept();
+                } catch (Exception) {
+                    return;
+                }
+                var stream = new NetworkStream(client, true);
+                Accept(new IpcConnection(stream), stream);
+            }
+        }).Start();
+    }
+
+    public void Stop() {
+        running = false;
+        listener.Close();
+        File.Delete(address);
+        CloseConnections();
+    }
+#else
+    public IpcServer() {
+        // Unix domain sockets need .NET Standard 2.1
+        address = null;
+    }
+
+    public void Stop() {
+    }
+#endif
+
+    private void Accept(IpcConnection connection, Stream stream) {
+        lock (connections) {
+            connections.Add(connection);
+        }
+
+        new Thread(() => {
+            try {
+                byte[] frame;
+                while (running && (frame = Framing.ReadFrame(stream)) != null) {
+                    lock (received) {
+                        received.Enqueue(new KeyValuePair<byte[], IpcConnection>(frame, connection));
+                    }
+                }
+            } catch (Exception) {
+                // the CLI went away
+            }
+            lock (connections) {
+                connections.Remove(connection);
+            }
+            connection.Close();
+        }).Start();
+    }
+
+    private void CloseConnections() {
+        lock (connections) {
+            foreach (var connection in connections) {
+                connection.Close();
+            }
+            connections.Clear();
+        }
+    }
+
+    // Called from the main thread
+    public List<KeyValuePair<byte[], IpcConnection>> Drain() {
+        lock (received) {
+            var frames = new List<KeyValuePair<byte[], IpcConnection>>(received);
+            received.Clear();
+            return frames;
+        }
+    }
+}
+
+// Answers the requests that came in MessagePack in MessagePack too, like the mock server does
+class MsgPackChannel : IReplyChannel {
+    IReplyChannel inner;
+
+    public MsgPackChannel(IReplyChannel inner) {
+        this.inner = inner;
+    }
+
+    public IReplyChannel GetInner() {
+        return inner;
+    }
+
+    public void Send(string id, byte[] bytes) {
+        inner.Send(id, MsgPack.FromJson(bytes));
+    }
+}
+
+// For the CLIs that pick msgpack in their Hello. JsonUtility only reads JSON, so requests are
+// turned into JSON when they arrive and replies into MessagePack when they leave.
+// Must match what rmp_serde writes for encoding.rs: structs are maps with their field names
+static class MsgPack {
+    // JSON always starts with the '{' of an object, MessagePack maps never do
+    public static bool Is(byte[] bytes) {
+        return bytes.Length > 0 && bytes[0] != (byte)'{';
+    }
+
+    public static string ToJson(byte[] bytes) {
+        var json = new System.Text.StringBuilder();
+        int pos = 0;
+        ReadValue(bytes, ref pos, json);
+        return json.ToString();
+    }
+
+    public static byte[] FromJson(byte[] json) {
+        string text = System.Text.Encoding.UTF8.GetString(json, 0, json.Length);
+        var output = new MemoryStream();
+        int pos = 0;
+        WriteValue(ParseValue(text, ref pos), output);
+        return output.ToArray();
+    }
+
+    private static ulong ReadBigEndian(byte[] bytes, ref int pos, int size) {
+        ulong value = 0;
+        for (int i = 0; i < size; i++) {
+            value = (value << 8) | bytes[pos++];
+        }
+        return value;
+    }
+
+    private static void ReadValue(byte[] bytes, ref int pos, System.Text.StringBuilder json) {
+        byte marker = bytes[pos++];
+        if (marker <= 0x7f) {
+            json.Append(marker);
+        } else if (marker <= 0x8f) {
+            ReadMap(bytes, ref pos, marker & 0x0f, json);
+        } else if (marker <= 0x9f) {
+            ReadArray(bytes, ref pos, marker & 0x0f, json);
+        } else if (marker <= 0xbf) {
+            ReadString(bytes, ref pos, marker & 0x1f, json);
+        } else if (marker >= 0xe0) {
+            json.Append((sbyte)marker);
+        } else {
+            var culture = System.Globalization.CultureInfo.InvariantCulture;
+            switch (marker) {
+                case 0xc0: json.Append("null"); break;
+                case 0xc2: json.Append("false"); break;
+                case 0xc3: json.Append("true"); break;
+                // serde only writes bin for byte buffers, which are arrays of numbers in JSON
+                case 0xc4: ReadBinary(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 1), json); break;
+                case 0xc5: ReadBinary(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 2), json); break;
+                case 0xc6: ReadBinary(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 4), json); break;
+                case 0xca:
+                    json.Append(BitConverter.ToSingle(BitConverter.GetBytes((int)ReadBigEndian(bytes, ref pos, 4)), 0).ToString("R", culture));
+                    break;
+                case 0xcb:
+                    json.Append(BitConverter.Int64BitsToDouble((long)ReadBigEndian(bytes, ref pos, 8)).ToString("R", culture));
+                    break;
+                case 0xcc: json.Append(ReadBigEndian(bytes, ref pos, 1)); break;
+                case 0xcd: json.Append(ReadBigEndian(bytes, ref pos, 2)); break;
+                case 0xce: json.Append(ReadBigEndian(bytes, ref pos, 4)); break;
+                case 0xcf: json.Append(ReadBigEndian(bytes, ref pos, 8)); break;
+                case 0xd0: json.Append((sbyte)ReadBigEndian(bytes, ref pos, 1)); break;
+                case 0xd1: json.Append((short)ReadBigEndian(bytes, ref pos, 2)); break;
+                case 0xd2: json.Append((int)ReadBigEndian(bytes, ref pos, 4)); break;
+                case 0xd3: json.Append((long)ReadBigEndian(bytes, ref pos, 8)); break;
+                case 0xd9: ReadString(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 1), json); break;
+                case 0xda: ReadString(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 2), json); break;
+                case 0xdb: ReadString(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 4), json); break;
+                case 0xdc: ReadArray(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 2), json); break;
+                case 0xdd: ReadArray(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 4), json); break;
+                case 0xde: ReadMap(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 2), json); break;
+                case 0xdf: ReadMap(bytes, ref pos, (int)ReadBigEndian(bytes, ref pos, 4), json); break;
+                default: throw new FormatException("Unsupported MessagePack marker 0x" + marker.ToString("x2"));
+            }
+        }
+    }
+
+    private static void ReadString(byte[] bytes, ref int pos, int length, System.Text.StringBuilder json) {
+        json.Append(Util.JsonString(System.Text.Encoding.UTF8.GetString(bytes, pos, length)));
+        pos += length;
+    }
+
+    private static void ReadBinary(byte[] bytes, ref int pos, int length, System.Text.StringBuilder json) {
+        json.Append('[');
+        for (int i = 0; i < length; i++) {
+            json.Append(i > 0 ? "," : "").Append(bytes[pos++]);
+        }
+        json.Append(']');
+    }
+
+    private static void ReadArray(byte[] bytes, ref int pos, int count, System.Text.StringBuilder json) {
+        json.Append('[');
+        for (int i = 0; i < count; i++) {
+            if (i > 0) {
+                json.Append(',');
+            }
+            ReadValue(bytes, ref pos, json);
+        }
+        json.Append(']');
+    }
+
+    private static void ReadMap(byte[] bytes, ref int pos, int count, System.Text.StringBuilder json) {
+        json.Append('{');
+        for (int i = 0; i < count; i++) {
+            if (i > 0) {
+                json.Append(',');
+            }
+            // JSON keys are strings, whatever the key was
+            var key = new System.Text.StringBuilder();
+            ReadValue(bytes, ref pos, key);
+            string text = key.ToString();
+            json.Append(text.StartsWith("\"") ? text : Util.JsonString(text)).Append(':');
+            ReadValue(bytes, ref pos, json);
+        }
+        json.Append('}');
+    }
+
+    // The replies are JSON written by hand, parsed into lists, maps and numbers to be written again
+    private static object ParseValue(string text, ref int pos) {
+        SkipWhitespace(text, ref pos);
+        char c = text[pos];
+        if (c == '{') {
+            var map = new List<KeyValuePair<string, object>>();
+            pos++;
+            SkipWhitespace(text, ref pos);
+            if (text[pos] == '}') {
+                pos++;
+                return map;
+            }
+            while (true) {
+                SkipWhitespace(text, ref pos);
+                string key = ParseString(text, ref pos);
+                SkipWhitespace(text, ref pos);
+                Expect(text, ref pos, ':');
+                map.Add(new KeyValuePair<string, object>(key, ParseValue(text, ref pos)));
+                SkipWhitespace(text, ref pos);
+                if (text[pos] == '}') {
+                    pos++;
+                    return map;
+                }
+                Expect(text, ref pos, ',');
+            }
+        } else if (c == '[') {
+            var list = new List<object>();
+            pos++;
+            SkipWhitespace(text, ref pos);
+            if (text[pos] == ']') {
+                pos++;
+                return list;
+            }
+            while (true) {
+                list.Add(ParseValue(text, ref pos));
+                SkipWhitespace(text, ref pos);
+                if (text[pos] == ']') {
+                    pos++;
+                    return list;
+                }
+                Expect(text, ref pos, ',');
+            }
+        } else if (c == '"') {
+            return ParseString(text, ref pos);
+        } else if (string.CompareOrdinal(text, pos, "true", 0, 4) == 0) {
+            pos += 4;
+            return true;
+        } else if (string.CompareOrdinal(text, pos, "false", 0, 5) == 0) {
+            pos += 5;
+            return false;
+        } else if (string.CompareOrdinal(text, pos, "null", 0, 4) == 0) {
+            pos += 4;
+            return null;
+        }
+
+        int start = pos;
+        while (pos < text.Length && "+-0123456789.eE".IndexOf(text[pos]) >= 0) {
+            pos++;
+        }
+        string number = text.Substring(start, pos - start);
+        var culture = System.Globalization.CultureInfo.InvariantCulture;
+        long integer;
+        ulong unsigned;
+        if (long.TryParse(number, System.Globalization.NumberStyles.AllowLeadingSign, culture, out integer)) {
+            return integer;
+        }
+        if (ulong.TryParse(number, System.Globalization.NumberStyles.None, culture, out unsigned)) {
+            return unsigned;
+        }
+        return double.Parse(number, System.Globalization.NumberStyles.Float, culture);
+    }
+
+    private static string ParseString(string text, ref int pos) {
+        Expect(text, ref pos, '"');
+        var builder = new System.Text.StringBuilder();
+        while (text[pos] != '"') {
+            char c = text[pos++];
+            if (c != '\\') {
+                builder.Append(c);
+                continue;
+            }
+            char escaped = text[pos++];
+            switch (escaped) {
+                case 'b': builder.Append('\b'); break;
+                case 'f': builder.Append('\f'); break;
+                case 'n': builder.Append('\n'); break;
+                case 'r': builder.Append('\r'); break;
+                case 't': builder.Append('\t'); break;
+                // surrogate pairs come as two escapes, which make up the pair again
+                case 'u':
+                    builder.Append((char)Convert.ToInt32(text.Substring(pos, 4), 16));
+                    pos += 4;
+                    break;
+                default: builder.Append(escaped); break;
+            }
+        }
+        pos++;
+        return builder.ToString();
+    }
+
+    private static void SkipWhitespace(string text, ref int pos) {
+        while (pos < text.Length && char.IsWhiteSpace(text[pos])) {
+            pos++;
+        }
+    }
+
+    private static void Expect(string text, ref int pos, char expected) {
+        if (text[pos] != expected) {
+            throw new FormatException("Expected '" + expected + "' at " + pos + " of a reply");
+        }
+        pos++;
+    }
+
+    private static void WriteBigEndian(Stream output, byte marker, ulong value, int size) {
+        output.WriteByte(marker);
+        for (int i = size - 1; i >= 0; i--) {
+            output.WriteByte((byte)(value >> (8 * i)));
+        }
+    }
+
+    private static void WriteLength(Stream output, int length, byte fix, int fixLimit, byte marker16, byte marker32) {
+        if (length < fixLimit) {
+            output.WriteByte((byte)(fix | length));
+        } else if (length <= 0xffff) {
+            WriteBigEndian(output, marker16, (ulong)length, 2);
+        } else {
+            WriteBigEndian(output, marker32, (ulong)length, 4);
+        }
+    }
+
+    private static void WriteString(string value, Stream output) {
+        byte[] utf8 = System.Text.Encoding.UTF8.GetBytes(value);
+        if (utf8.Length < 32) {
+            output.WriteByte((byte)(0xa0 | utf8.Length));
+        } else if (utf8.Length <= 0xff) {
+            WriteBigEndian(output, 0xd9, (ulong)utf8.Length, 1);
+        } else if (utf8.Length <= 0xffff) {
+            WriteBigEndian(output, 0xda, (ulong)utf8.Length, 2);
+        } else {
+            WriteBigEndian(output, 0xdb, (ulong)utf8.Length, 4);
+        }
+        output.Write(utf8, 0, utf8.Length);
+    }
+
+    private static void WriteValue(object value, Stream output) {
+        if (value == null) {
+            output.WriteByte(0xc0);
+        } else if (value is bool) {
+            output.WriteByte((bool)value ? (byte)0xc3 : (byte)0xc2);
+        } else if (value is string) {
+            WriteString((string)value, output);
+        } else if (value is ulong) {
+            WriteBigEndian(output, 0xcf, (ulong)value, 8);
+        } else if (value is long) {
+            long integer = (long)value;
+            if (integer >= 0 && integer <= 0x7f || integer < 0 && integer >= -32) {
+                output.WriteByte((byte)integer);
+            } else if (integer >= 0) {
+                WriteBigEndian(output, 0xcf, (ulong)integer, 8);
+            } else {
+                WriteBigEndian(output, 0xd3, (ulong)integer, 8);
+            }
+        } else if (value is double) {
+            WriteBigEndian(output, 0xcb, (ulong)BitConverter.DoubleToInt64Bits((double)value), 8);
+        } else if (value is List<object>) {
+            var list = (List<object>)value;
+            WriteLength(output, list.Count, 0x90, 16, 0xdc, 0xdd);
+            foreach (var item in list) {
+                WriteValue(item, output);
+            }
+        } else {
+            var map = (List<KeyValuePair<string, object>>)value;
+            WriteLength(output, map.Count, 0x80, 16, 0xde, 0xdf);
+            foreach (var pair in map) {
+                WriteString(pair.Key, output);
+                WriteValue(pair.Value, output);
+            }
+        }
+    }
+}
+
+class Command {
+    public delegate void MessageSender(Reply reply);
+
+
+    Request request;
+    IReplyChannel channel;
+    MessageSender onDone;
+
+    public Command(Request request, IReplyChannel channel) {
+        this.request = request;
+        this.channel = channel;
+    }
+
+    public string GetCmd() {
+        return request.cmd;
+    }
+
+    public string GetId() {
+        return request.id;
+    }
+
+    public IReplyChannel GetChannel() {
+        return channel;
+    }
+
+    private void WaitEditorNotBusy() {
+        if (!Util.IsUnityEditorBusy()) {
+            Debug.Log("UWU: Editor is not busy anymore");
+
+            EditorApplication.update -= WaitEditorNotBusy;
+            onDone(Reply.Success());
+
+            onDone = null;
+        }
+    }
+
+    private void OkAfterEditorNotBusy(MessageSender onDone) {
+        Debug.Log("UWU: Waiting for editor to finish compiling");
+
+        this.onDone = onDone;
+        EditorApplication.update += WaitEditorNotBusy;
+    }
+
+    public void Execute(MessageSender sender) {
+        if (request.cmd == "Hello") {
+            // The CLI checks the protocol version before sending anything else.
+            // Always reply with ours, the CLI knows which side needs to be updated.
+            // Older CLIs offer no encodings and only speak JSON
+            bool msgpack = request.encodings != null && Array.IndexOf(request.encodings, "msgpack") >= 0;
+            sender(Reply.Success("{\"version\":" + UWUClient.ProtocolVersion
+                + ",\"encoding\":" + (msgpack ? "\"msgpack\"" : "\"json\"")
+                + ",\"unity_version\":" + Util.JsonString(Application.unityVersion)
+                + ",\"plugin_version\":" + Util.JsonString(UWUClient.PluginVersion) + "}"));
+        } else if (request.cmd == "Status") {
+            sender(Reply.Success("{\"compiling\":" + (EditorApplication.isCompiling ? "true" : "false")
+                + ",\"importing\":" + (EditorApplication.isUpdating ? "true" : "false")
+                + ",\"playing\":" + (EditorApplication.isPlaying ? "true" : "false")
+                + ",\"paused\":" + (EditorApplication.isPaused ? "true" : "false") + "}"));
+        } else if (request.cmd == "Play") {
+            Debug.Log("UWU: Received Play command, entering play mode");
+
+            if (!string.IsNullOrEmpty(request.scene)) {
+                if (EditorApplication.isPlaying) {
+                    sender(Reply.Error("Already in play mode, stop it first to play another scene"));
+                    return;
+                }
+
+                string error;
+                string path = Util.FindScene(request.scene, out error);
+                if (path == null) {
+                    sender(Reply.Error(error));
+                    return;
+                }
+
+                // the current scenes come back on stop, so they must be saved to survive the switch
+                for (int i = 0; i < UnityEngine.SceneManagement.SceneManager.sceneCount; ++i) {
+                    var scene = UnityEngine.SceneManagement.SceneManager.GetSceneAt(i);
+                    if (scene.isDirty && string.IsNullOrEmpty(scene.path)) {
+                        sender(Reply.Error("An untitled scene has unsaved changes, save it from the editor once"));
+                        return;
+                    }
+                }
+                if (!UnityEditor.SceneManagement.EditorSceneManager.SaveOpenScenes()) {
+                    sender(Reply.Error("Could not save the open scenes"));
+                    return;
+                }
+
+                SceneStash.Stash();
+                UnityEditor.SceneManagement.EditorSceneManager.OpenScene(path, UnityEditor.SceneManagement.OpenSceneMode.Single);
+            }
+
+            if (EditorApplication.isPlaying) {
+                Debug.Log("UWU: Already in play mode");
+            } else {
+                if (request.fast) {
+                    PlayModeOptions.PlayFast();
+                }
+                PlayModeResult.Clear();
+                EditorApplication.EnterPlaymode();
+            }
+
+            // Play is a bit of a mess. The Editor will kill UWUClient when it enters play mode
+            // So we need to return success and then rely on the server to send a *different*
+            // command to block until play mode has started
+            sender(Reply.Success());
+
+        } else if (request.cmd == "WaitPlayMode") {
+            // play mode may even be over already, if the game called UWU.ExitPlayMode quickly
+            if (!EditorApplication.isPlaying) {
+                sender(Reply.Success(PlayModeResult.ToJson()));
+                return;
+            }
+
+            // the game may have failed before the CLI got to ask
+            if (request.stop_on_error && PlayModeResult.HasError()) {
+                sender(Reply.Success(PlayModeResult.ToJson()));
+                return;
+            }
+
+            sender(Reply.Wait());
+            Action<PlayModeStateChange> onStop = null;
+            Action onError = null;
+            Action done = () => {
+                EditorApplication.playModeStateChanged -= onStop;
+                PlayModeResult.error -= onError;
+                sender(Reply.Success(PlayModeResult.ToJson()));
+            };
+            onStop = (PlayModeStateChange state) => {
+                if (state == PlayModeStateChange.EnteredEditMode) {
+                    done();
+                }
+            };
+            onError = () => {
+                if (request.stop_on_error) {
+                    done();
+                }
+            };
+            EditorApplication.playModeStateChanged += onStop;
+            PlayModeResult.error += onError;
+        } else if (request.cmd == "CheckAlive") {
+            // This is a special command that is used to check if the client has (re)booted
+            // for example, when the CLI has requested play mode.
+            // It relies on the CLI blocking and retrying until this returns success.
+            // After a refresh or a build, it's also the end of the cycle that CycleTimings measured
+            sender(Reply.Success(CycleTimings.End()));
+        } else if (request.cmd == "Stop") {
+            Debug.Log("UWU: Received Stop command, stopping play mode");
+
+            if (EditorApplication.isPlaying) {
+                sender(Reply.Wait());
+
+                EditorApplication.ExitPlaymode();
+                EditorApplication.playModeStateChanged += (PlayModeStateChange state) => {
+                    if (state == PlayModeStateChange.EnteredEditMode) {
+                        sender(Reply.Success());
+                    }
+                };
+            } else {
+                sender(Reply.Success());
+            }
+        } else if (request.cmd == "PlayModeOptions") {
+            Debug.Log("UWU: Received PlayModeOptions command");
+
+            PlayModeOptions.Set(PlayModeOptions.ParseToggle(request.domain_reload), PlayModeOptions.ParseToggle(request.scene_reload));
+            sender(Reply.Success(PlayModeOptions.ToJson()));
+        } else if (request.cmd == "Pause") {
+            Debug.Log("UWU: Received Pause command");
+
+            // toggles, like the pause button of the toolbar
+            if (EditorApplication.isPlaying) {
+                EditorApplication.isPaused = !EditorApplication.isPaused;
+                sender(Reply.Success("{\"paused\":" + (EditorApplication.isPaused ? "true" : "false") + "}"));
+            } else {
+                sender(Reply.Error("Not in play mode"));
+            }
+        } else if (request.cmd == "Step") {
+            Debug.Log("UWU: Received Step command, advancing one frame");
+
+            // stepping pauses first if needed, same as the toolbar
+            if (EditorApplication.isPlaying) {
+                EditorApplication.Step();
+                sender(Reply.Success());
+            } else {
+                sender(Reply.Error("Not in play mode"));
+            }
+        } else if (request.cmd == "TimeScale") {
+            Debug.Log("UWU: Received TimeScale command");
+
+            if (EditorApplication.isPlaying) {
+                TimeScale.Set(request.scale);
+                sender(Reply.Success("{\"scale\":" + Time.timeScale.ToString(System.Globalization.CultureInfo.InvariantCulture) + "}"));
+            } else {
+                sender(Reply.Error("Not in play mode"));
+            }
+        } else if (request.cmd == "PressKey" || request.cmd == "Click") {
+            Debug.Log("UWU: Received " + request.cmd + " command");
+
+            if (!EditorApplication.isPlaying) {
+                sender(Reply.Error("Not in play mode"));
+                return;
+            }
+
+            // answers once the key or the button is up again, so that scripts can chain them
+            Action released = () => sender(Reply.Success());
+            string error = request.cmd == "PressKey"
+                ? SimulatedInput.PressKey(request.key, request.hold, released)
+                : SimulatedInput.Click(new Vector2(request.x, request.y), request.hold, released);
+            sender(error == null ? Reply.Wait() : Reply.Error(error));
+        } else if (request.cmd == "SetAxis") {
+            Debug.Log("UWU: Received SetAxis command");
+
+            if (EditorApplication.isPlaying) {
+                SimulatedInput.SetAxis(request.axis, request.value);
+                sender(Reply.Success());
+            } else {
+                sender(Reply.Error("Not in play mode"));
+            }
+        } else if (request.cmd == "Refresh") {
+            Debug.Log("UWU: Received asset refresh command");
+
+            // Refresh also sends success immediately and relies on the CLI to block. See Play
+            sender(Reply.Success());
+
+            CycleTimings.Begin();
+            var import = Stopwatch.StartNew();
+            AssetDatabase.Refresh();
+            CycleTimings.Imported(import.Elapsed.TotalSeconds);
+
+        } else if (request.cmd == "BackgroundRefresh") {
+            Debug.Log("UWU: Received a background refresh command");
+
+            // Refresh also sends success immediately and relies on the CLI to block. See Play
+            sender(Reply.Success());
+            if (!Util.IsUnityEditorFocused()) {
+
+                AssetDatabase.Refresh();
+            }
+        } else if (request.cmd == "Build") {
+            Debug.Log("UWU: Received Script build command");
+
+            sender(Reply.Wait());
+
+            CycleTimings.Begin();
+            UnityEditor.Compilation.CompilationPipeline.RequestScriptCompilation();
+            UnityEditor.Compilation.CompilationPipeline.compilationFinished += (object o) => {
+                sender(Reply.Success());
+            };
+        } else if (request.cmd == "Test") {
+            Debug.Log("UWU: Received Test command, running " + request.mode + " tests");
+
+            sender(Reply.Wait());
+
+            TestRun.Start(request, channel, sender);
+        } else if (request.cmd == "BuildPlayer") {
+            Debug.Log("UWU: Received BuildPlayer command for " + request.target);
+
+            BuildTarget target;
+            if (!Util.TryParseBuildTarget(request.target, out target)) {
+                sender(Reply.Error("Unknown build target '" + request.target + "'"));
+                return;
+            }
+
+            sender(Reply.Wait());
+
+            var scenes = new List<string>();
+            foreach (var scene in EditorBuildSettings.scenes) {
+                if (scene.enabled) {
+                    scenes.Add(scene.path);
+                }
+            }
+
+            var options = new BuildPlayerOptions {
+                scenes = scenes.ToArray(),
+                locationPathName = request.output,
+                target = target,
+                targetGroup = BuildPipeline.GetBuildTargetGroup(target),
+                options = request.development ? BuildOptions.Development : BuildOptions.None,
+            };
+
+            // BuildPlayer blocks the main thread, the build callbacks report how far it got
+            PlayerBuildProgress.sender = sender;
+            PlayerBuildProgress.sceneCount = options.scenes.Length;
+            UnityEditor.Build.Reporting.BuildReport report;
+            try {
+                report = BuildPipeline.BuildPlayer(options);
+            } finally {
+                PlayerBuildProgress.sender = null;
+            }
+
+            var summary = report.summary;
+            if (summary.result == UnityEditor.Build.Reporting.BuildResult.Succeeded) {
+                string payload = "{\"output_path\":" + Util.JsonString(summary.outputPath)
+                    + ",\"total_size\":" + summary.totalSize
+                    + ",\"duration_secs\":" + summary.totalTime.TotalSeconds.ToString(System.Globalization.CultureInfo.InvariantCulture);
+                // too big for a message with all the assets, the CLI fetches it
+                if (request.report) {
+                    byte[] json = System.Text.Encoding.UTF8.GetBytes(PlayerBuildProgress.ToJson(report, scenes));
+                    payload += ",\"report\":" + Transfers.Start(request.id, json);
+                }
+                sender(Reply.Success(payload + "}"));
+            } else {
+                sender(Reply.Error(PlayerBuildProgress.Summarize(report)));
+            }
+        } else if (request.cmd == "AddDefine" || request.cmd == "RemoveDefine" || request.cmd == "ListDefines") {
+            BuildTargetGroup group;
+            if (!Defines.TryParseGroup(request.group, out group)) {
+                sender(Reply.Error("Unknown build target group '" + request.group + "'"));
+                return;
+            }
+
+            var symbols = Defines.Get(group);
+            bool changed = false;
+            if (request.cmd == "AddDefine" && !symbols.Contains(request.symbol)) {
+                symbols.Add(request.symbol);
+                changed = true;
+            } else if (request.cmd == "RemoveDefine") {
+                changed = symbols.Remove(request.symbol);
+            }
+
+            string payload = Defines.ToJson(symbols);
+            if (!changed) {
+                sender(Reply.Success(payload));
+                return;
+            }
+
+            Debug.Log("UWU: Setting the scripting define symbols of " + group + " to " + string.Join(";", symbols.ToArray()));
+
+            // the scripts recompile with the new symbols, answer once they did, before the domain reload
+            sender(Reply.Wait());
+            UnityEditor.Compilation.CompilationPipeline.compilationFinished += (object o) => {
+                sender(Reply.Success(payload));
+            };
+            PlayerSettings.SetScriptingDefineSymbolsForGroup(group, string.Join(";", symbols.ToArray()));
+        } else if (request.cmd == "GetPref" || request.cmd == "SetPref") {
+            Debug.Log("UWU: Received " + request.cmd + " command for " + request.key);
+
+            string error = null;
+            string payload = null;
+            if (request.cmd == "SetPref") {
+                error = request.player
+                    ? Prefs.SetPlayer(request.key, request.new_value)
+                    : Prefs.SetEditor(request.key, request.new_value, request.kind);
+            }
+            if (error == null) {
+                error = request.player ? Prefs.PlayerJson(request.key, out payload) : Prefs.EditorJson(request.key, out payload);
+            }
+            sender(error == null ? Reply.Success(payload) : Reply.Error(error));
+        } else if (request.cmd == "ListBuildScenes" || request.cmd == "EnableBuildScene" || request.cmd == "DisableBuildScene") {
+            var scenes = new List<EditorBuildSettingsScene>(EditorBuildSettings.scenes);
+
+            if (request.cmd != "ListBuildScenes") {
+                string error;
+                string path = BuildScenes.Find(scenes, request.scene, out error);
+                if (path == null) {
+                    sender(Reply.Error(error));
+                    return;
+                }
+
+                bool enabled = request.cmd == "EnableBuildScene";
+                var index = scenes.FindIndex(scene => scene.path == path);
+                if (index >= 0) {
+                    scenes[index].enabled = enabled;
+                } else if (enabled) {
+                    scenes.Add(new EditorBuildSettingsScene(path, true));
+                } else {
+                    sender(Reply.Error(path + " is not in the Build Settings"));
+                    return;
+                }
+
+                Debug.Log("UWU: " + (enabled ? "Enabling " : "Disabling ") + path + " in the Build Settings");
+                EditorBuildSettings.scenes = scenes.ToArray();
+            }
+
+            sender(Reply.Success(BuildScenes.ToJson(scenes)));
+        } else if (request.cmd == "TargetInfo") {
+            sender(Reply.Success(TargetInfo.ToJson()));
+        } else if (request.cmd == "SwitchTarget") {
+            Debug.Log("UWU: Received SwitchTarget command for " + request.target);
+
+            BuildTarget target;
+            if (!Util.TryParseBuildTarget(request.target, out target)) {
+                sender(Reply.Error("Unknown build target '" + request.target + "'"));
+                return;
+            }
+            if (target == EditorUserBuildSettings.activeBuildTarget) {
+                sender(Reply.Success());
+                return;
+            }
+            var group = BuildPipeline.GetBuildTargetGroup(target);
+            if (!BuildPipeline.IsBuildTargetSupported(group, target)) {
+                sender(Reply.Error("The " + target + " module is not installed, add it from the Unity Hub"));
+                return;
+            }
+
+            // reimports every asset for the new platform before returning. The scripts are
+            // recompiled afterwards, uwu build waits for that
+            sender(Reply.Wait());
+            if (EditorUserBuildSettings.SwitchActiveBuildTarget(group, target)) {
+                sender(Reply.Success());
+            } else {
+                sender(Reply.Error("Could not switch to " + target + ", see the editor log"));
+            }
+        } else if (request.cmd == "BuildBundles") {
+            Debug.Log("UWU: Received BuildBundles command");
+
+            BuildTarget target = EditorUserBuildSettings.activeBuildTarget;
+            if (!string.IsNullOrEmpty(request.target) && !Util.TryParseBuildTarget(request.target, out target)) {
+                sender(Reply.Error("Unknown build target '" + request.target + "'"));
+                return;
+            }
+
+            sender(Reply.Wait());
+
+            Directory.CreateDirectory(request.output);
+            var manifest = BuildPipeline.BuildAssetBundles(request.output, BuildAssetBundleOptions.None, target);
+            if (manifest == null) {
+                sender(Reply.Error("The build failed, or no asset is assigned to a bundle. See the editor log"));
+                return;
+            }
+
+            var bundles = new List<string>();
+            foreach (var name in manifest.GetAllAssetBundles()) {
+                long size = new FileInfo(Path.Combine(request.output, name)).Length;
+                bundles.Add("{\"name\":" + Util.JsonString(name)
+                    + ",\"size\":" + size
+                    + ",\"hash\":" + Util.JsonString(manifest.GetAssetBundleHash(name).ToString()) + "}");
+            }
+            sender(Reply.Success("{\"bundles\":[" + string.Join(",", bundles.ToArray()) + "]}"));
+        } else if (request.cmd == "Exec") {
+            Debug.Log("UWU: Received Exec command for " + request.method);
+
+            sender(Util.InvokeStaticMethod(request.method, request.args ?? new string[0]));
+        } else if (request.cmd == "Eval") {
+            Debug.Log("UWU: Received Eval command");
+
+            sender(Eval.Run(request.code));
+        } else if (request.cmd == "Menu") {
+            Debug.Log("UWU: Received Menu command for '" + request.path + "'");
+
+            if (EditorApplication.ExecuteMenuItem(request.path)) {
+                sender(Reply.Success());
+            } else {
+                sender(Reply.Error("No menu item '" + request.path + "', see uwu menu --list"));
+            }
+        } else if (request.cmd == "Select") {
+            Debug.Log("UWU: Received Select command for '" + request.path + "'");
+
+            UnityEngine.Object selected = request.path.StartsWith("Assets/") || request.path.StartsWith("Packages/")
+                ? AssetDatabase.LoadMainAssetAtPath(request.path)
+                : Util.FindGameObject(request.path);
+            if (selected == null) {
+                sender(Reply.Error("Nothing at '" + request.path + "', in the project or the open scenes"));
+                return;
+            }
+
+            Selection.activeObject = selected;
+            // highlights it in the Hierarchy or the Project window
+            EditorGUIUtility.PingObject(selected);
+            sender(Reply.Success());
+        } else if (request.cmd == "FindAssets") {
+            foreach (var folder in request.folders) {
+                if (!AssetDatabase.IsValidFolder(folder)) {
+                    sender(Reply.Error("No folder at " + folder));
+                    return;
+                }
+            }
+
+            var guids = request.folders.Length > 0
+                ? AssetDatabase.FindAssets(request.filter, request.folders)
+                : AssetDatabase.FindAssets(request.filter);
+            var assets = new List<string>();
+            foreach (var guid in guids) {
+                assets.Add("{\"path\":" + Util.JsonString(AssetDatabase.GUIDToAssetPath(guid))
+                    + ",\"guid\":" + Util.JsonString(guid) + "}");
+            }
+            sender(Reply.Success("{\"assets\":[" + string.Join(",", assets.ToArray()) + "]}"));
+        } else if (request.cmd == "Hierarchy") {
+            var scenes = new List<string>();
+            for (int i = 0; i < UnityEngine.SceneManagement.SceneManager.sceneCount; ++i) {
+                var scene = UnityEngine.SceneManagement.SceneManager.GetSceneAt(i);
+                if (!scene.isLoaded) {
+                    continue;
+                }
+                if (!string.IsNullOrEmpty(request.scene) && scene.name != request.scene && scene.path != request.scene) {
+                    continue;
+                }
+                scenes.Add(Hierarchy.SceneToJson(scene, request.depth));
+            }
+
+            if (scenes.Count == 0 && !string.IsNullOrEmpty(request.scene)) {
+                sender(Reply.Error("No loaded scene is named '" + request.scene + "'"));
+                return;
+            }
+            sender(Reply.Success("{\"scenes\":[" + string.Join(",", scenes.ToArray()) + "]}"));
+        } else if (request.cmd == "ListMenus") {
+            var items = new List<string>();
+            foreach (var item in Util.MenuItems()) {
+                items.Add(Util.JsonString(item));
+            }
+            sender(Reply.Success("{\"items\":[" + string.Join(",", items.ToArray()) + "]}"));
+        } else if (request.cmd == "OpenScene") {
+            Debug.Log("UWU: Received OpenScene command for '" + request.scene + "'");
+
+            string error;
+            string path = Util.FindScene(request.scene, out error);
+            if (path == null) {
+                sender(Reply.Error(error));
+            } else if (EditorApplication.isPlaying) {
+                sender(Reply.Error("Can't open scenes in play mode, stop it first"));
+            } else if (!request.additive && Util.HasUnsavedScenes()) {
+                // the save dialog would block the editor until someone clicks it
+                sender(Reply.Error("The open scenes have unsaved changes"));
+            } else {
+                var mode = request.additive
+                    ? UnityEditor.SceneManagement.OpenSceneMode.Additive
+                    : UnityEditor.SceneManagement.OpenSceneMode.Single;
+                UnityEditor.SceneManagement.EditorSceneManager.OpenScene(path, mode);
+                sender(Reply.Success("{\"path\":" + Util.JsonString(path) + "}"));
+            }
+        } else if (request.cmd == "Save") {
+            Debug.Log("UWU: Received Save command");
+
+            var saved = new List<string>();
+            if (request.scenes) {
+                if (EditorApplication.isPlaying) {
+                    sender(Reply.Error("Can't save scenes in play mode, stop it first"));
+                    return;
+                }
+
+                for (int i = 0; i < UnityEngine.SceneManagement.SceneManager.sceneCount; ++i) {
+                    var scene = UnityEngine.SceneManagement.SceneManager.GetSceneAt(i);
+                    if (!scene.isDirty) {
+                        continue;
+                    }
+                    // saving would ask where to, and block the editor until someone answers
+                    if (string.IsNullOrEmpty(scene.path)) {
+                        sender(Reply.Error("An untitled scene has unsaved changes, save it from the editor once"));
+                        return;
+                    }
+                    if (!UnityEditor.SceneManagement.EditorSceneManager.SaveScene(scene)) {
+                        sender(Reply.Error("Could not save " + scene.path));
+                        return;
+                    }
+                    saved.Add(Util.JsonString(scene.path));
+                }
+            }
+            if (request.assets) {
+                AssetDatabase.SaveAssets();
+            }
+
+            sender(Reply.Success("{\"saved_scenes\":[" + string.Join(",", saved.ToArray()) + "]}"));
+        } else if (request.cmd == "Screenshot") {
+            Debug.Log("UWU: Received Screenshot command for the " + request.view + " view");
+
+            Camera camera;
+            if (request.view == "scene") {
+                camera = SceneView.lastActiveSceneView != null ? SceneView.lastActiveSceneView.camera : null;
+            } else {
+                camera = Camera.main;
+            }
+            if (camera == null) {
+                sender(Reply.Error(request.view == "scene" ? "No Scene view is open" : "The scene has no main camera"));
+                return;
+            }
+
+            int width = request.width > 0 ? request.width : (request.view == "scene" ? camera.pixelWidth : 1920);
+            int height = request.height > 0 ? request.height : (request.view == "scene" ? camera.pixelHeight : 1080);
+
+            // the image doesn't fit in a message, the CLI fetches it with FetchChunk
+            byte[] image = Util.RenderCamera(camera, width, height, request.quality);
+            sender(Reply.Success(Transfers.Start(request.id, image)));
+        } else if (request.cmd == "FetchChunk") {
+            sender(Transfers.Fetch(request.transfer, request.index));
+        } else if (request.cmd == "Quit") {
+            Debug.Log("UWU: Received Quit command");
+
+            if (!request.force && Util.HasUnsavedScenes()) {
+                // Quit would show the save dialog, and wait for someone to answer it
+                sender(Reply.Error("The open scenes have unsaved changes, run uwu save first or pass --force"));
+                return;
+            }
+
+            // answer first, there's nobody left to answer afterwards
+            sender(Reply.Success());
+            bool force = request.force;
+            EditorApplication.delayCall += () => {
+                if (force) {
+                    EditorApplication.Exit(0);
+                } else {
+                    EditorApplication.Quit();
+                }
+            };
+        } else if (request.cmd == "AddPackage") {
+            Debug.Log("UWU: Received AddPackage command for " + request.package);
+
+            sender(Reply.Wait());
+
+            var add = UnityEditor.PackageManager.Client.Add(request.package);
+            Util.WhenDone(add, () => {
+                if (add.Status == UnityEditor.PackageManager.StatusCode.Success) {
+                    sender(Reply.Success(Util.PackageJson(add.Result)));
+                } else {
+                    sender(Reply.Error(add.Error.message));
+                }
+            });
+        } else if (request.cmd == "RemovePackage") {
+            Debug.Log("UWU: Received RemovePackage command for " + request.package);
+
+            sender(Reply.Wait());
+
+            var remove = UnityEditor.PackageManager.Client.Remove(request.package);
+            Util.WhenDone(remove, () => {
+                if (remove.Status == UnityEditor.PackageManager.StatusCode.Success) {
+                    sender(Reply.Success());
+                } else {
+                    sender(Reply.Error(remove.Error.message));
+                }
+            });
+        } else if (request.cmd == "ListPackages") {
+            sender(Reply.Wait());
+
+            // offline, the manifest and the cache are enough
+            var list = UnityEditor.PackageManager.Client.List(true);
+            Util.WhenDone(list, () => {
+                if (list.Status == UnityEditor.PackageManager.StatusCode.Success) {
+                    var packages = new List<string>();
+                    foreach (var package in list.Result) {
+                        packages.Add(Util.PackageJson(package));
+                    }
+                    sender(Reply.Success("{\"packages\":[" + string.Join(",", packages.ToArray()) + "]}"));
+                } else {
+                    sender(Reply.Error(list.Error.message));
+                }
+            });
+        } else if (request.cmd == "Import") {
+            Debug.Log("UWU: Received Import command for " + request.paths.Length + " assets");
+
+            foreach (var path in request.paths) {
+                if (!File.Exists(path) && !Directory.Exists(path)) {
+                    sender(Reply.Error("No asset at " + path));
+                    return;
+                }
+            }
+
+            // batch the imports, so that dependencies are only processed once
+            AssetDatabase.StartAssetEditing();
+            try {
+                foreach (var path in request.paths) {
+                    AssetDatabase.ImportAsset(path, ImportAssetOptions.ForceUpdate | ImportAssetOptions.ImportRecursive);
+                }
+            } finally {
+                AssetDatabase.StopAssetEditing();
+            }
+
+            // scripts may recompile afterwards, the CLI waits with CheckAlive like for Refresh
+            sender(Reply.Success());
+        } else if (request.cmd == "ReimportAll") {
+            Debug.Log("UWU: Received ReimportAll command, reimporting the whole project");
+
+            // this blocks the editor for as long as it takes, so the CLI has to wait for the end
+            sender(Reply.Wait());
+
+            AssetDatabase.ImportAsset("Assets",
+                ImportAssetOptions.ForceUpdate | ImportAssetOptions.ImportRecursive | ImportAssetOptions.DontDownloadFromCacheServer);
+
+            sender(Reply.Success());
+        } else if (request.cmd == "SubscribeLogs") {
+            LogStream.Subscribe(request, channel, sender);
+        } else if (request.cmd == "RenewLogs") {
+            if (LogStream.Renew(request.subscription)) {
+                sender(Reply.Success());
+            } else {
+                sender(Reply.Error("Unknown subscription, the editor probably reloaded"));
+            }
+        } else if (request.cmd == "CompilerMessages") {
+            sender(Reply.Success("{\"messages\":[" + string.Join(",", CompilerMessages.All().ToArray()) + "]}"));
+        } else if (request.cmd == "ClearConsole") {
+            sender(Util.ClearConsole());
+        } else if (request.cmd == "ProfileStart") {
+            Debug.Log("UWU: Received ProfileStart command");
+
+            if (!ProfilerCapture.Start(request.id)) {
+                sender(Reply.Error("The profiler is already recording, run uwu profile stop first"));
+                return;
+            }
+            if (request.frames <= 0) {
+                sender(Reply.Success());
+                return;
+            }
+
+            sender(Reply.Wait());
+
+            // counts player loop frames, which only run continuously in play mode
+            int last = Time.frameCount + request.frames;
+            EditorApplication.CallbackFunction poll = null;
+            poll = () => {
+                if (Time.frameCount >= last) {
+                    EditorApplication.update -= poll;
+                    ProfilerCapture.Stop(sender);
+                }
+            };
+            EditorApplication.update += poll;
+        } else if (request.cmd == "ProfileStop") {
+            Debug.Log("UWU: Received ProfileStop command");
+
+            if (!UnityEngine.Profiling.Profiler.enableBinaryLog) {
+                sender(Reply.Error("The profiler is not recording, run uwu profile start first"));
+                return;
+            }
+
+            sender(Reply.Wait());
+            ProfilerCapture.Stop(sender);
+        } else if (request.cmd == "BakeLighting") {
+            Debug.Log("UWU: Received BakeLighting command");
+
+            if (EditorApplication.isPlaying) {
+                sender(Reply.Error("Can't bake in play mode, stop it first"));
+                return;
+            }
+            if (UnityEditor.Lightmapping.isRunning) {
+                sender(Reply.Error("A bake is already running"));
+                return;
+            }
+            if (!string.IsNullOrEmpty(request.scene)) {
+                string error;
+                string path = Util.FindScene(request.scene, out error);
+                if (path == null) {
+                    sender(Reply.Error(error));
+                    return;
+                }
+                if (Util.HasUnsavedScenes()) {
+                    // the save dialog would block the editor until someone clicks it
+                    sender(Reply.Error("The open scenes have unsaved changes"));
+                    return;
+                }
+                UnityEditor.SceneManagement.EditorSceneManager.OpenScene(path, UnityEditor.SceneManagement.OpenSceneMode.Single);
+            }
+
+            if (!UnityEditor.Lightmapping.BakeAsync()) {
+                sender(Reply.Error("The bake could not start"));
+                return;
+            }
+            if (!request.wait) {
+                sender(Reply.Success());
+                return;
+            }
+
+            sender(Reply.Wait());
+
+            // bakes take hours, tell the CLI every percent
+            int reported = 0;
+            EditorApplication.CallbackFunction poll = null;
+            poll = () => {
+                if (UnityEditor.Lightmapping.isRunning) {
+                    int percent = (int)(UnityEditor.Lightmapping.buildProgress * 100);
+                    if (percent > reported) {
+                        reported = percent;
+                        sender(Reply.Progress("Baking lighting", percent));
+                    }
+                    return;
+                }
+
+                EditorApplication.update -= poll;
+                // the scenes now point to the new lighting data, which is lost unless they are saved
+                if (!UnityEditor.SceneManagement.EditorSceneManager.SaveOpenScenes()) {
+                    sender(Reply.Error("The bake finished, but the scenes could not be saved"));
+                    return;
+                }
+                sender(Reply.Success());
+            };
+            EditorApplication.update += poll;
+        } else if (request.cmd == "BuildAddressables") {
+            Debug.Log("UWU: Received BuildAddressables command");
+
+            sender(Reply.Wait());
+            sender(AddressablesBuild.Build(request.profile, request.clean));
+        } else if (request.cmd == "FocusEditor") {
+            Debug.Log("UWU: Received Focus command, bringing the editor to the foreground");
+
+            string error = Util.FocusEditorWindow();
+            if (error == null && request.game) {
+                // GameView is internal, but GetWindow opens or focuses any window type
+                var gameView = typeof(EditorWindow).Assembly.GetType("UnityEditor.GameView");
+                if (gameView == null) {
+                    error = "This version of Unity has no UnityEditor.GameView";
+                } else {
+                    EditorWindow.GetWindow(gameView).Focus();
+                }
+            }
+            sender(error == null ? Reply.Success() : Reply.Error(error));
+        } else {
+            Debug.LogError("Unknown remote command received '" + request.cmd + "'");
+            sender(Reply.Error("Unknown command '" + request.cmd + "'"));
+        }
+    }
+}
+
+
+// Reports the steps of `uwu build-player` while BuildPipeline.BuildPlayer blocks the main thread
+class PlayerBuildProgress : UnityEditor.Build.IPreprocessBuildWithReport, UnityEditor.Build.IProcessSceneWithReport {
+    // only set during a build started by uwu
+    public static Command.MessageSender sender;
+    // the scenes of the build, and how many were processed so far
+    public static int sceneCount;
+    static int scenesDone;
+
+    public int callbackOrder { get { return 0; } }
+
+    public void OnPreprocessBuild(UnityEditor.Build.Reporting.BuildReport report) {
+        if (sender != null) {
+            scenesDone = 0;
+            sender(Reply.Progress("Building " + report.summary.platform + " player"));
+        }
+    }
+
+    public void OnProcessScene(UnityEngine.SceneManagement.Scene scene, UnityEditor.Build.Reporting.BuildReport report) {
+        // report is null when entering play mode, only player builds have one
+        if (sender != null && report != null) {
+            // the scenes are only part of the build, the rest isn't reported
+            if (sceneCount > 0) {
+                sender(Reply.Progress("Processing " + scene.path, 100f * scenesDone / sceneCount));
+            } else {
+                sender(Reply.Progress("Processing " + scene.path));
+            }
+            scenesDone++;
+        }
+    }
+
+    // What `uwu build-player --report` writes: the sizes, the scenes, the steps and how long
+    // they took, and how much each asset weighs in the player, the heaviest first
+    public static string ToJson(UnityEditor.Build.Reporting.BuildReport report, List<string> scenes) {
+        var inv = System.Globalization.CultureInfo.InvariantCulture;
+        var summary = report.summary;
+
+        var stripped = new List<string>();
+        foreach (var scene in EditorBuildSettings.scenes) {
+            if (!scene.enabled) {
+                stripped.Add(Util.JsonString(scene.path));
+            }
+        }
+        var included = new List<string>();
+        foreach (var scene in scenes) {
+            included.Add(Util.JsonString(scene));
+        }
+
+        var steps = new List<string>();
+        foreach (var step in report.steps) {
+            steps.Add("{\"name\":" + Util.JsonString(step.name)
+                + ",\"depth\":" + step.depth
+                + ",\"duration_secs\":" + step.duration.TotalSeconds.ToString(inv) + "}");
+        }
+
+        // an asset can be packed in several files, like the scenes that share it
+        var sizes = new Dictionary<string, ulong>();
+        var types = new Dictionary<string, string>();
+        foreach (var packed in report.packedAssets) {
+            foreach (var content in packed.contents) {
+                string path = string.IsNullOrEmpty(content.sourceAssetPath) ? "(built-in)" : content.sourceAssetPath;
+                ulong size;
+                sizes.TryGetValue(path, out size);
+                sizes[path] = size + content.packedSize;
+                types[path] = content.type != null ? content.type.Name : "";
+            }
+        }
+        var paths = new List<string>(sizes.Keys);
+        paths.Sort((a, b) => sizes[b].CompareTo(sizes[a]));
+        var assets = new List<string>();
+        foreach (var path in paths) {
+            assets.Add("{\"path\":" + Util.JsonString(path)
+                + ",\"type\":" + Util.JsonString(types[path])
+                + ",\"size\":" + sizes[path] + "}");
+        }
+
+        return "{\"platform\":" + Util.JsonString(summary.platform.ToString())
+            + ",\"output_path\":" + Util.JsonString(summary.outputPath)
+            + ",\"total_size\":" + summary.totalSize
+            + ",\"duration_secs\":" + summary.totalTime.TotalSeconds.ToString(inv)
+            + ",\"warnings\":" + summary.totalWarnings
+            + ",\"scenes\":[" + string.Join(",", included.ToArray()) + "]"
+            + ",\"stripped_scenes\":[" + string.Join(",", stripped.ToArray()) + "]"
+            + ",\"steps\":[" + string.Join(",", steps.ToArray()) + "]"
+            + ",\"assets\":[" + string.Join(",", assets.ToArray()) + "]}";
+    }
+
+    // The errors of a failed build, which otherwise are only in the editor log
+    public static string Summarize(UnityEditor.Build.Reporting.BuildReport report) {
+        var summary = "Build " + report.summary.result.ToString().ToLowerInvariant()
+            + " with " + report.summary.totalErrors + " errors";
+        foreach (var step in report.steps) {
+            foreach (var message in step.messages) {
+                if (message.type == LogType.Error || message.type == LogType.Exception) {
+                    summary += "\n" + message.content;
+                }
+            }
+        }
+        return summary;
+    }
+}
+
+// Data too big for a single message, that the CLI fetches one chunk at a time with FetchChunk.
+// Every chunk is a separate request, so UDP retries work for them like for any other command
+static class Transfers {
+    // small enough to fit in a UDP datagram once in base64
+    private const int ChunkSize = 32 * 1024;
+
+    private static Dictionary<string, byte[]> pending = new Dictionary<string, byte[]>();
+
+    // Returns the payload that tells the CLI how to fetch the data
+    public static string Start(string id, byte[] data) {
+        // only the latest transfer of each kind is ever fetched, don't hoard memory
+        if (pending.Count > 8) {
+            pending.Clear();
+        }
+        pending[id] = data;
+
+        int chunks = (data.Length + ChunkSize - 1) / ChunkSize;
+        return "{\"transfer\":" + Util.JsonString(id) + ",\"size\":" + data.Length + ",\"chunk_count\":" + chunks + "}";
+    }
+
+    public static Reply Fetch(string id, int index) {
+        byte[] data;
+        if (!pending.TryGetValue(id, out data)) {
+            return Reply.Error("Unknown transfer " + id + ", did the editor reload?");
+        }
+
+        int offset = index * ChunkSize;
+        if (index < 0 || offset >= data.Length) {
+            return Reply.Error("Chunk " + index + " is out of range");
+        }
+        int length = Math.Min(ChunkSize, data.Length - offset);
+
+        return Reply.Success("{\"data\":\"" + Util.Base64Url(data, offset, length) + "\"}");
+    }
+}
+
+// What the game passed to UWU.ExitPlayMode, see UWU.cs. Kept in SessionState, because the game
+// and the editor don't share assemblies
+// How long the phases of the last refresh or build took: the import, the compilation and
+// the domain reload. Kept in SessionState, because the reload is measured on both sides of it
+static class CycleTimings {
+    private const string StartKey = "UWU.Cycle.Start";
+    private const string ImportKey = "UWU.Cycle.Import";
+    private const string CompileKey = "UWU.Cycle.Compile";
+    private const string CompileStartKey = "UWU.Cycle.CompileStart";
+    private const string ReloadKey = "UWU.Cycle.Reload";
+    private const string ReloadStartKey = "UWU.Cycle.ReloadStart";
+
+    public static void Init() {
+        UnityEditor.Compilation.CompilationPipeline.compilationStarted += (object o) => {
+            if (Active()) {
+                SetTime(CompileStartKey);
+            }
+        };
+        UnityEditor.Compilation.CompilationPipeline.compilationFinished += (object o) => {
+            Add(CompileKey, Since(CompileStartKey));
+        };
+        AssemblyReloadEvents.beforeAssemblyReload += () => {
+            if (Active()) {
+                SetTime(ReloadStartKey);
+            }
+        };
+
+        // this runs again once the domain is reloaded
+        Add(ReloadKey, Since(ReloadStartKey));
+    }
+
+    public static void Begin() {
+        Clear();
+        SetTime(StartKey);
+    }
+
+    public static void Imported(double seconds) {
+        Add(ImportKey, seconds);
+    }
+
+    // the payload of CheckAlive: the timings, if a cycle is over, and nothing otherwise
+    public static string End() {
+        if (!Active()) {
+            return null;
+        }
+
+        var inv = System.Globalization.CultureInfo.InvariantCulture;
+        Func<string, string> secs = (string key) => {
+            float value = SessionState.GetFloat(key, -1);
+            return value < 0 ? "null" : value.ToString(inv);
+        };
+        string json = "{\"timings\":{\"import_secs\":" + secs(ImportKey)
+            + ",\"compile_secs\":" + secs(CompileKey)
+            + ",\"reload_secs\":" + secs(ReloadKey)
+            + ",\"total_secs\":" + Since(StartKey).ToString(inv) + "}}";
+        Clear();
+        return json;
+    }
+
+    private static bool Active() {
+        return SessionState.GetString(StartKey, "") != "";
+    }
+
+    private static void Clear() {
+        foreach (var key in new[] { StartKey, ImportKey, CompileKey, CompileStartKey, ReloadKey, ReloadStartKey }) {
+            SessionState.EraseString(key);
+            SessionState.EraseFloat(key);
+        }
+    }
+
+    private static void SetTime(string key) {
+        SessionState.SetString(key, DateTime.UtcNow.Ticks.ToString());
+    }
+
+    // how long ago the time of key was, negative if it wasn't set. Clears it
+    private static double Since(string key) {
+        string ticks = SessionState.GetString(key, "");
+        if (ticks == "") {
+            return -1;
+        }
+        if (key != StartKey) {
+            SessionState.EraseString(key);
+        }
+        return (DateTime.UtcNow - new DateTime(long.Parse(ticks), DateTimeKind.Utc)).TotalSeconds;
+    }
+
+    // several compilations add up, like those of a refresh that changes scripts twice
+    private static void Add(string key, double seconds) {
+        if (seconds < 0 || !Active()) {
+            return;
+        }
+        float total = Math.Max(SessionState.GetFloat(key, 0), 0);
+        SessionState.SetFloat(key, total + (float)seconds);
+    }
+}
+
+// The first error that the game logs is kept too, for `uwu play --stop-on-error`
+static class PlayModeResult {
+    private const string ExitCodeKey = "UWU.ExitCode";
+    private const string ResultKey = "UWU.Result";
+    private const string ErrorKey = "UWU.PlayError";
+    private const string ErrorStackKey = "UWU.PlayErrorStack";
+
+    // the first error of the game, once it's kept
+    public static event Action error;
+
+    public static void Init() {
+        Application.logMessageReceived += (string message, string stack, LogType type) => {
+            if (!EditorApplication.isPlaying || HasError() || message.StartsWith("UWU:")) {
+                return;
+            }
+            if (type != LogType.Error && type != LogType.Exception && type != LogType.Assert) {
+                return;
+            }
+            SessionState.SetString(ErrorKey, message);
+            SessionState.SetString(ErrorStackKey, stack);
+            if (error != null) {
+                error();
+            }
+        };
+    }
+
+    public static void Clear() {
+        SessionState.EraseInt(ExitCodeKey);
+        SessionState.EraseString(ResultKey);
+        SessionState.EraseString(ErrorKey);
+        SessionState.EraseString(ErrorStackKey);
+    }
+
+    public static bool HasError() {
+        return SessionState.GetString(ErrorKey, "").Length > 0;
+    }
+
+    public static string ToJson() {
+        string result = SessionState.GetString(ResultKey, "");
+        string json = "{\"exit_code\":" + SessionState.GetInt(ExitCodeKey, 0)
+            + ",\"result\":" + (result.Length > 0 ? Util.JsonString(result) : "null");
+        if (HasError()) {
+            string stack = SessionState.GetString(ErrorStackKey, "");
+            json += ",\"error\":{\"message\":" + Util.JsonString(SessionState.GetString(ErrorKey, ""))
+                + ",\"stack\":" + (stack.Length > 0 ? Util.JsonString(stack) : "null") + "}";
+        }
+        return json + "}";
+    }
+}
+
+// The scenes that were open before `uwu play --scene`, restored when play mode stops however
+// it stops. Kept in SessionState, because entering play mode reloads the domain
+static class SceneStash {
+    private const string Key = "UWU.SceneStash";
+
+    public static void Init() {
+        EditorApplication.playModeStateChanged += (PlayModeStateChange state) => {
+            if (state == PlayModeStateChange.EnteredEditMode) {
+                Restore();
+            }
+        };
+    }
+
+    // one line per scene: its path, whether it's loaded and whether it's the active one
+    public static void Stash() {
+        var lines = new List<string>();
+        foreach (var setup in UnityEditor.SceneManagement.EditorSceneManager.GetSceneManagerSetup()) {
+            lines.Add(setup.path + "\t" + setup.isLoaded + "\t" + setup.isActive);
+        }
+        SessionState.SetString(Key, string.Join("\n", lines.ToArray()));
+    }
+
+    private static void Restore() {
+        string stash = SessionState.GetString(Key, "");
+        SessionState.EraseString(Key);
+        if (stash.Length == 0) {
+            return;
+        }
+
+        var setups = new List<UnityEditor.SceneManagement.SceneSetup>();
+        foreach (var line in stash.Split('\n')) {
+            var fields = line.Split('\t');
+            var setup = new UnityEditor.SceneManagement.SceneSetup();
+            setup.path = fields[0];
+            setup.isLoaded = bool.Parse(fields[1]);
+            setup.isActive = bool.Parse(fields[2]);
+            setups.Add(setup);
+        }
+
+        Debug.Log("UWU: Restoring the scenes that were open before uwu play --scene");
+        UnityEditor.SceneManagement.EditorSceneManager.RestoreSceneManagerSetup(setups.ToArray());
+    }
+}
+
+// The Enter Play Mode Settings, for `uwu playmode-options` and `uwu play --fast`
+static class PlayModeOptions {
+    // the settings to put back once the Play mode of --fast stops
+    private const string RestoreKey = "UWU.PlayModeOptions.Restore";
+
+    public static void Init() {
+        EditorApplication.playModeStateChanged += (PlayModeStateChange state) => {
+            if (state == PlayModeStateChange.EnteredEditMode) {
+                Restore();
+            }
+        };
+    }
+
+    // null leaves the setting alone
+    public static void Set(bool? domainReload, bool? sceneReload) {
+        var options = EditorSettings.enterPlayModeOptionsEnabled ? EditorSettings.enterPlayModeOptions : EnterPlayModeOptions.None;
+        if (domainReload.HasValue) {
+            options = domainReload.Value ? options & ~EnterPlayModeOptions.DisableDomainReload : options | EnterPlayModeOptions.DisableDomainReload;
+        }
+        if (sceneReload.HasValue) {
+            options = sceneReload.Value ? options & ~EnterPlayModeOptions.DisableSceneReload : options | EnterPlayModeOptions.DisableSceneReload;
+        }
+
+        // reloading everything is the same as not overriding anything
+        EditorSettings.enterPlayModeOptionsEnabled = options != EnterPlayModeOptions.None;
+        EditorSettings.enterPlayModeOptions = options;
+    }
+
+    public static void PlayFast() {
+        if (!SessionState.GetBool(RestoreKey + ".Set", false)) {
+            SessionState.SetBool(RestoreKey + ".Set", true);
+            SessionState.SetBool(RestoreKey + ".Enabled", EditorSettings.enterPlayModeOptionsEnabled);
+            SessionState.SetInt(RestoreKey, (int)EditorSettings.enterPlayModeOptions);
+        }
+        Set(false, false);
+    }
+
+    private static void Restore() {
+        if (!SessionState.GetBool(RestoreKey + ".Set", false)) {
+            return;
+        }
+        EditorSettings.enterPlayModeOptionsEnabled = SessionState.GetBool(RestoreKey + ".Enabled", false);
+        EditorSettings.enterPlayModeOptions = (EnterPlayModeOptions)SessionState.GetInt(RestoreKey, 0);
+        SessionState.EraseBool(RestoreKey + ".Set");
+    }
+
+    // "on" or "off", like the CLI sends them
+    public static bool? ParseToggle(string toggle) {
+        if (string.IsNullOrEmpty(toggle)) {
+            return null;
+        }
+        return toggle == "on";
+    }
+
+    public static string ToJson() {
+        var options = EditorSettings.enterPlayModeOptionsEnabled ? EditorSettings.enterPlayModeOptions : EnterPlayModeOptions.None;
+        bool domainReload = (options & EnterPlayModeOptions.DisableDomainReload) == 0;
+        bool sceneReload = (options & EnterPlayModeOptions.DisableSceneReload) == 0;
+        return "{\"domain_reload\":" + (domainReload ? "true" : "false")
+            + ",\"scene_reload\":" + (sceneReload ? "true" : "false") + "}";
+    }
+}
+
+// Time.timeScale for `uwu timescale`. Scripts set it at runtime too, so the one to put back
+// is the one from before the first uwu timescale of this Play mode
+static class TimeScale {
+    // kept in the session, the domain may reload in Play mode
+    private const string RestoreKey = "UWU.TimeScale.Restore";
+
+    public static void Init() {
+        EditorApplication.playModeStateChanged += (PlayModeStateChange state) => {
+            if (state == PlayModeStateChange.EnteredEditMode) {
+                Restore();
+            }
+        };
+    }
+
+    public static void Set(float scale) {
+        if (!SessionState.GetBool(RestoreKey + ".Set", false)) {
+            SessionState.SetBool(RestoreKey + ".Set", true);
+            SessionState.SetFloat(RestoreKey, Time.timeScale);
+        }
+        Time.timeScale = scale;
+    }
+
+    private static void Restore() {
+        if (!SessionState.GetBool(RestoreKey + ".Set", false)) {
+            return;
+        }
+        Time.timeScale = SessionState.GetFloat(RestoreKey, 1.0f);
+        SessionState.EraseBool(RestoreKey + ".Set");
+    }
+}
+
+// Synthetic input for `uwu input`. Keys and clicks go through the Input System, so the game
+// reads them like real ones. The Input Manager can't be fed, so the axes that uwu sets are
+// read through UWU.GetAxis, see UWU.cs
+static class SimulatedInput {
+    // the keys must match UWU.GetAxis in UWU.cs
+    private const string AxisKey = "UWU.Axis.";
+    // the axes that are set, one per line, to clear them when Play mode stops
+    private const string AxesKey = "UWU.Axes";
+
+    public static void Init() {
+        EditorApplication.playModeStateChanged += (PlayModeStateChange state) => {
+            if (state == PlayModeStateChange.EnteredEditMode) {
+                ClearAxes();
+            }
+        };
+    }
+
+    public static void SetAxis(string axis, float value) {
+        SessionState.SetFloat(AxisKey + axis, value);
+        var axes = new HashSet<string>(SessionState.GetString(AxesKey, "").Split(new[] { '\n' }, StringSplitOptions.RemoveEmptyEntries));
+        axes.Add(axis);
+        SessionState.SetString(AxesKey, string.Join("\n", axes));
+    }
+
+    private static void ClearAxes() {
+        foreach (var axis in SessionState.GetString(AxesKey, "").Split(new[] { '\n' }, StringSplitOptions.RemoveEmptyEntries)) {
+            SessionState.EraseFloat(AxisKey + axis);
+        }
+        SessionState.EraseString(AxesKey);
+    }
+
+#if ENABLE_INPUT_SYSTEM
+    // Returns why it can't, or null once the key is down. released is called once it's up
+    public static string PressKey(string code, float hold, Action released) {
+        // 1 is Digit1, not the key that happens to be 1 in the enum
+        if (code.Length > 0 && char.IsDigit(code[0])) {
+            code = "Digit" + code;
+        }
+        UnityEngine.InputSystem.Key key;
+        if (!Enum.TryParse(code, true, out key) || key == UnityEngine.InputSystem.Key.None) {
+            return "Unknown key " + code + ", the keys are the names of UnityEngine.InputSystem.Key";
+        }
+
+        var keyboard = UnityEngine.InputSystem.Keyboard.current ?? UnityEngine.InputSystem.InputSystem.AddDevice<UnityEngine.InputSystem.Keyboard>();
+        UnityEngine.InputSystem.InputSystem.QueueStateEvent(keyboard, new UnityEngine.InputSystem.LowLevel.KeyboardState(key));
+        AfterHold(hold, () => {
+            UnityEngine.InputSystem.InputSystem.QueueStateEvent(keyboard, new UnityEngine.InputSystem.LowLevel.KeyboardState());
+            released();
+        });
+        return null;
+    }
+
+    // position is in the pixels of the Game view, from its bottom left corner
+    public static string Click(Vector2 position, float hold, Action released) {
+        var mouse = UnityEngine.InputSystem.Mouse.current ?? UnityEngine.InputSystem.InputSystem.AddDevice<UnityEngine.InputSystem.Mouse>();
+        var up = new UnityEngine.InputSystem.LowLevel.MouseState { position = position };
+        UnityEngine.InputSystem.InputSystem.QueueStateEvent(mouse, up.WithButton(UnityEngine.InputSystem.LowLevel.MouseButton.Left));
+        AfterHold(hold, () => {
+            UnityEngine.InputSystem.InputSystem.QueueStateEvent(mouse, up);
+            released();
+        });
+        return null;
+    }
+
+    // a frame at least, or the game would never see it down
+    private static void AfterHold(float hold, Action release) {
+        int frame = Time.frameCount;
+        double until = EditorApplication.timeSinceStartup + hold;
+        EditorApplication.CallbackFunction check = null;
+        check = () => {
+            bool held = Time.frameCount > frame && EditorApplication.timeSinceStartup >= until;
+            if (held || !EditorApplication.isPlaying) {
+                EditorApplication.update -= check;
+                release();
+            }
+        };
+        EditorApplication.update += check;
+    }
+#else
+    private const string NoInputSystem = "Keys and clicks need the Input System package, enabled in Player Settings > Active Input Handling";
+
+    public static string PressKey(string code, float hold, Action released) {
+        return NoInputSystem;
+    }
+
+    public static string Click(Vector2 position, float hold, Action released) {
+        return NoInputSystem;
+    }
+#endif
+}
+
+// Compiles C# snippets for `uwu eval`. Each snippet is an assembly of its own, which stays
+// loaded until the next domain reload
+static class Eval {
+    private const string Prefix = "using System;\nusing System.Linq;\nusing System.Collections.Generic;\n"
+        + "using UnityEngine;\nusing UnityEditor;\n"
+        + "public static class UWUEval {\npublic static object Run() {\n#line 1\n";
+
+    public static Reply Run(string code) {
+        code = code.Trim();
+
+        // an expression first, the statements of a snippet don't compile as one
+        string error;
+        var assembly = Compile(Prefix + "return (object)(" + code + ");\n}\n}\n", out error);
+        if (assembly == null) {
+            string statements = code.EndsWith(";") || code.EndsWith("}") ? code : code + ";";
+            assembly = Compile(Prefix + statements + "\n#line hidden\nreturn null;\n}\n}\n", out error);
+        }
+        if (assembly == null) {
+            return Reply.Error(error);
+        }
+
+        object result;
+        try {
+            result = assembly.GetType("UWUEval").GetMethod("Run").Invoke(null, null);
+        } catch (System.Reflection.TargetInvocationException e) {
+            var inner = e.InnerException;
+            return Reply.Error(inner.GetType().Name + ": " + inner.Message, inner.StackTrace);
+        }
+        return Util.ResultReply(result);
+    }
+
+    private static System.Reflection.Assembly Compile(string source, out string error) {
+        error = null;
+        var parameters = new System.CodeDom.Compiler.CompilerParameters();
+        parameters.GenerateInMemory = true;
+        parameters.GenerateExecutable = false;
+
+        // everything the editor has loaded, so that the snippet sees the scripts of the project.
+        // The snippets of earlier evals have no location
+        var referenced = new HashSet<string>();
+        foreach (var loaded in AppDomain.CurrentDomain.GetAssemblies()) {
+            if (loaded.IsDynamic || string.IsNullOrEmpty(loaded.Location) || !referenced.Add(loaded.GetName().Name)) {
+                continue;
+            }
+            parameters.ReferencedAssemblies.Add(loaded.Location);
+        }
+
+        System.CodeDom.Compiler.CompilerResults results;
+        using (var provider = new Microsoft.CSharp.CSharpCodeProvider()) {
+            results = provider.CompileAssemblyFromSource(parameters, source);
+        }
+
+        var errors = new List<string>();
+        foreach (System.CodeDom.Compiler.CompilerError compilerError in results.Errors) {
+            if (!compilerError.IsWarning) {
+                errors.Add("(" + compilerError.Line + "," + compilerError.Column + "): error " + compilerError.ErrorNumber + ": " + compilerError.ErrorText);
+            }
+        }
+        if (errors.Count > 0) {
+            error = string.Join("\n", errors.ToArray());
+            return null;
+        }
+        return results.CompiledAssembly;
+    }
+}
+
+// The GameObjects of a scene, for `uwu hierarchy`
+static class Hierarchy {
+    // depth 0 is the whole hierarchy
+    public static string SceneToJson(UnityEngine.SceneManagement.Scene scene, int depth) {
+        var roots = new List<string>();
+        foreach (var root in scene.GetRootGameObjects()) {
+            roots.Add(ToJson(root, depth));
+        }
+        return "{\"name\":" + Util.JsonString(scene.name)
+            + ",\"path\":" + Util.JsonString(scene.path)
+            + ",\"roots\":[" + string.Join(",", roots.ToArray()) + "]}";
+    }
+
+    private static string ToJson(GameObject gameObject, int depth) {
+        var components = new List<string>();
+        foreach (var component in gameObject.GetComponents<Component>()) {
+            // the scripts that are missing, or that don't compile
+            components.Add(Util.JsonString(component == null ? "Missing" : component.GetType().Name));
+        }
+
+        var children = new List<string>();
+        var transform = gameObject.transform;
+        if (depth != 1) {
+            for (int i = 0; i < transform.childCount; ++i) {
+                children.Add(ToJson(transform.GetChild(i).gameObject, depth == 0 ? 0 : depth - 1));
+            }
+        }
+
+        return "{\"name\":" + Util.JsonString(gameObject.name)
+            + ",\"active\":" + (gameObject.activeSelf ? "true" : "false")
+            + ",\"components\":[" + string.Join(",", components.ToArray()) + "]"
+            + ",\"children\":[" + string.Join(",", children.ToArray()) + "]"
+            + ",\"child_count\":" + transform.childCount + "}";
+    }
+}
+
+// The scenes of the Build Settings, for `uwu scenes`
+static class BuildScenes {
+    // the scenes of the list are found by path or name even if they were deleted, the other
+    // ones like uwu play --scene finds them
+    public static string Find(List<EditorBuildSettingsScene> scenes, string scene, out string error) {
+        error = null;
+        var listed = scenes.FindAll(s => s.path == scene || Path.GetFileNameWithoutExtension(s.path) == scene);
+        if (listed.Count == 1) {
+            return listed[0].path;
+        }
+        if (listed.Count > 1) {
+            error = "Several scenes of the Build Settings are named '" + scene + "', pass the path of one";
+            return null;
+        }
+        return Util.FindScene(scene, out error);
+    }
+
+    public static string ToJson(List<EditorBuildSettingsScene> scenes) {
+        var items = new List<string>();
+        int buildIndex = 0;
+        foreach (var scene in scenes) {
+            string index = scene.enabled ? (buildIndex++).ToString() : "null";
+            items.Add("{\"path\":" + Util.JsonString(scene.path)
+                + ",\"enabled\":" + (scene.enabled ? "true" : "false")
+                + ",\"index\":" + index + "}");
+        }
+        return "{\"scenes\":[" + string.Join(",", items.ToArray()) + "]}";
+    }
+}
+
+// EditorPrefs and a few PlayerSettings, for `uwu prefs`
+static class Prefs {
+    // what uwu prefs --player knows, by the names the CLI uses
+    private static readonly string[] PlayerKeys = {
+        "product_name", "company_name", "bundle_version", "application_identifier", "android_version_code", "ios_build_number",
+    };
+
+    // EditorPrefs don't say what type a key has, but a key of another type reads as the
+    // default, so a type that has the key reads the same with two defaults
+    private static string KindOf(string key) {
+        if (!EditorPrefs.HasKey(key)) {
+            return null;
+        }
+        if (EditorPrefs.GetString(key, "a") == EditorPrefs.GetString(key, "b")) {
+            return "string";
+        }
+        if (EditorPrefs.GetInt(key, 0) == EditorPrefs.GetInt(key, 1)) {
+            return "int";
+        }
+        return "float";
+    }
+
+    private static string Json(string key, string value, string kind) {
+        return "{\"key\":" + Util.JsonString(key) + ",\"value\":" + Util.JsonString(value) + ",\"kind\":" + Util.JsonString(kind) + "}";
+    }
+
+    public static string EditorJson(string key, out string json) {
+        json = null;
+        string kind = KindOf(key);
+        if (kind == null) {
+            return "There's no EditorPrefs key " + key;
+        }
+        var culture = System.Globalization.CultureInfo.InvariantCulture;
+        string value = kind == "string" ? EditorPrefs.GetString(key)
+            : kind == "int" ? EditorPrefs.GetInt(key).ToString(culture)
+            : EditorPrefs.GetFloat(key).ToString(culture);
+        json = Json(key, value, kind);
+        return null;
+    }
+
+    public static string SetEditor(string key, string value, string kind) {
+        if (string.IsNullOrEmpty(kind)) {
+            kind = KindOf(key) ?? "string";
+        }
+        var culture = System.Globalization.CultureInfo.InvariantCulture;
+        if (kind == "string") {
+            EditorPrefs.SetString(key, value);
+        } else if (kind == "int") {
+            int number;
+            if (!int.TryParse(value, System.Globalization.NumberStyles.Integer, culture, out number)) {
+                return key + " is an int, and " + value + " isn't";
+            }
+            EditorPrefs.SetInt(key, number);
+        } else if (kind == "float") {
+            float number;
+            if (!float.TryParse(value, System.Globalization.NumberStyles.Float, culture, out number)) {
+                return key + " is a float, and " + value + " isn't";
+            }
+            EditorPrefs.SetFloat(key, number);
+        } else {
+            return "Unknown type " + kind + ", use string, int or float";
+        }
+        return null;
+    }
+
+    private static string UnknownPlayerKey(string key) {
+        return "uwu doesn't know the player setting " + key + ", use " + string.Join(", ", PlayerKeys);
+    }
+
+    public static string PlayerJson(string key, out string json) {
+        json = null;
+        string value;
+        var group = BuildPipeline.GetBuildTargetGroup(EditorUserBuildSettings.activeBuildTarget);
+        switch (key) {
+            case "product_name": value = PlayerSettings.productName; break;
+            case "company_name": value = PlayerSettings.companyName; break;
+            case "bundle_version": value = PlayerSettings.bundleVersion; break;
+            case "application_identifier": value = PlayerSettings.GetApplicationIdentifier(group); break;
+            case "android_version_code": value = PlayerSettings.Android.bundleVersionCode.ToString(); break;
+            case "ios_build_number": value = PlayerSettings.iOS.buildNumber; break;
+            default: return UnknownPlayerKey(key);
+        }
+        json = Json(key, value, "player");
+        return null;
+    }
+
+    // saved right away, so that a build that follows has them
+    public static string SetPlayer(string key, string value) {
+        var group = BuildPipeline.GetBuildTargetGroup(EditorUserBuildSettings.activeBuildTarget);
+        switch (key) {
+            case "product_name": PlayerSettings.productName = value; break;
+            case "company_name": PlayerSettings.companyName = value; break;
+            case "bundle_version": PlayerSettings.bundleVersion = value; break;
+            case "application_identifier": PlayerSettings.SetApplicationIdentifier(group, value); break;
+            case "android_version_code":
+                int code;
+                if (!int.TryParse(value, out code) || code <= 0) {
+                    return "The Android version code must be a positive number";
+                }
+                PlayerSettings.Android.bundleVersionCode = code;
+                break;
+            case "ios_build_number": PlayerSettings.iOS.buildNumber = value; break;
+            default: return UnknownPlayerKey(key);
+        }
+        AssetDatabase.SaveAssets();
+        return null;
+    }
+}
+
+// The active build target and the modules of the editor, for `uwu target info`
+static class TargetInfo {
+    public static string ToJson() {
+        var target = EditorUserBuildSettings.activeBuildTarget;
+        var group = BuildPipeline.GetBuildTargetGroup(target);
+
+        // by name, the obsolete targets share their values with the current ones
+        var installed = new List<string>();
+        foreach (var name in Enum.GetNames(typeof(BuildTarget))) {
+            var field = typeof(BuildTarget).GetField(name);
+            if (field.IsDefined(typeof(ObsoleteAttribute), false)) {
+                continue;
+            }
+            var candidate = (BuildTarget)field.GetValue(null);
+            if (candidate != BuildTarget.NoTarget && BuildPipeline.IsBuildTargetSupported(BuildPipeline.GetBuildTargetGroup(candidate), candidate)) {
+                installed.Add(Util.JsonString(name));
+            }
+        }
+
+        return "{\"target\":" + Util.JsonString(target.ToString())
+            + ",\"group\":" + Util.JsonString(group.ToString())
+            + ",\"installed_targets\":[" + string.Join(",", installed.ToArray()) + "]"
+            + ",\"scripting_backend\":" + Util.JsonString(PlayerSettings.GetScriptingBackend(group).ToString())
+            + ",\"api_compatibility_level\":" + Util.JsonString(PlayerSettings.GetApiCompatibilityLevel(group).ToString()) + "}";
+    }
+}
+
+// Scripting define symbols for `uwu defines`
+static class Defines {
+    // accepts groups like Standalone, and build targets like win64 for their group
+    public static bool TryParseGroup(string name, out BuildTargetGroup group) {
+        if (string.IsNullOrEmpty(name)) {
+            group = BuildPipeline.GetBuildTargetGroup(EditorUserBuildSettings.activeBuildTarget);
+            return true;
+        }
+        try {
+            group = (BuildTargetGroup)Enum.Parse(typeof(BuildTargetGroup), name, true);
+            return true;
+        } catch (ArgumentException) {
+            BuildTarget target;
+            group = Util.TryParseBuildTarget(name, out target) ? BuildPipeline.GetBuildTargetGroup(target) : BuildTargetGroup.Unknown;
+            return group != BuildTargetGroup.Unknown;
+        }
+    }
+
+    public static List<string> Get(BuildTargetGroup group) {
+        var symbols = new List<string>();
+        foreach (var symbol in PlayerSettings.GetScriptingDefineSymbolsForGroup(group).Split(';')) {
+            if (symbol.Trim().Length > 0) {
+                symbols.Add(symbol.Trim());
+            }
+        }
+        return symbols;
+    }
+
+    public static string ToJson(List<string> symbols) {
+        var json = new List<string>();
+        foreach (var symbol in symbols) {
+            json.Add(Util.JsonString(symbol));
+        }
+        return "{\"symbols\":[" + string.Join(",", json.ToArray()) + "]}";
+    }
+}
+
+// Content builds for `uwu addressables build`. Addressables is a package that most projects
+// don't have, so it is only reached through reflection
+static class AddressablesBuild {
+    const System.Reflection.BindingFlags Flags = System.Reflection.BindingFlags.Static
+        | System.Reflection.BindingFlags.Instance | System.Reflection.BindingFlags.Public;
+
+    public static Reply Build(string profile, bool clean) {
+        var defaultObject = Util.FindType("UnityEditor.AddressableAssets.AddressableAssetSettingsDefaultObject");
+        var settingsType = Util.FindType("UnityEditor.AddressableAssets.Settings.AddressableAssetSettings");
+        if (defaultObject == null || settingsType == null) {
+            return Reply.Error("The Addressables package is not installed");
+        }
+
+        object settings = defaultObject.GetProperty("Settings", Flags).GetValue(null, null);
+        if (settings == null) {
+            return Reply.Error("Addressables is not set up in this project, create its settings from Window > Asset Management > Addressables");
+        }
+
+        var activeProfile = settingsType.GetProperty("activeProfileId", Flags);
+        string previousProfile = (string)activeProfile.GetValue(settings, null);
+        if (!string.IsNullOrEmpty(profile)) {
+            object profiles = settingsType.GetProperty("profileSettings", Flags).GetValue(settings, null);
+            string id = (string)profiles.GetType().GetMethod("GetProfileId", Flags).Invoke(profiles, new object[] { profile });
+            if (string.IsNullOrEmpty(id)) {
+                return Reply.Error("No Addressables profile named '" + profile + "'");
+            }
+            activeProfile.SetValue(settings, id, null);
+        }
+
+        try {
+            if (clean) {
+                // null cleans the content of every builder
+                settingsType.GetMethod("CleanPlayerContent", Flags).Invoke(null, new object[] { null });
+            }
+
+            var build = new object[] { null };
+            settingsType.GetMethod("BuildPlayerContent", Flags, null, new[] { Util.FindType("UnityEditor.AddressableAssets.Build.AddressablesPlayerBuildResult").MakeByRefType() }, null)
+                .Invoke(null, build);
+
+            object result = build[0];
+            string error = (string)result.GetType().GetProperty("Error", Flags).GetValue(result, null);
+            if (!string.IsNullOrEmpty(error)) {
+                return Reply.Error(error);
+            }
+
+            string outputPath = (string)result.GetType().GetProperty("OutputPath", Flags).GetValue(result, null);
+            double duration = (double)result.GetType().GetProperty("Duration", Flags).GetValue(result, null);
+            return Reply.Success("{\"output_path\":" + Util.JsonString(outputPath)
+                + ",\"duration_secs\":" + duration.ToString(System.Globalization.CultureInfo.InvariantCulture) + "}");
+        } catch (System.Reflection.TargetInvocationException e) {
+            var inner = e.InnerException;
+            return Reply.Error(inner.GetType().Name + ": " + inner.Message, inner.StackTrace);
+        } finally {
+            // --profile is for this build only
+            activeProfile.SetValue(settings, previousProfile, null);
+        }
+    }
+}
+
+// Binary profiler logs for `uwu profile`, that the CLI fetches as a transfer.
+// The file can be opened in the Profiler window
+static class ProfilerCapture {
+    private static string file;
+
+    public static bool Start(string id) {
+        if (UnityEngine.Profiling.Profiler.enableBinaryLog) {
+            return false;
+        }
+
+        file = Path.Combine(Path.GetFullPath("Temp"), "uwu-profile-" + id);
+        UnityEngine.Profiling.Profiler.logFile = file;
+        UnityEngine.Profiling.Profiler.enableBinaryLog = true;
+        UnityEngine.Profiling.Profiler.enabled = true;
+        return true;
+    }
+
+    public static void Stop(Command.MessageSender sender) {
+        UnityEngine.Profiling.Profiler.enabled = false;
+        UnityEngine.Profiling.Profiler.enableBinaryLog = false;
+        UnityEngine.Profiling.Profiler.logFile = "";
+
+        // the last frames are written out in the background, give them an update to land
+        EditorApplication.delayCall += () => {
+            // Unity adds the extension itself
+            string raw = file + ".raw";
+            if (!File.Exists(raw)) {
+                sender(Reply.Error("The profiler didn't write " + raw + ", were any frames recorded?"));
+                return;
+            }
+
+            byte[] capture = File.ReadAllBytes(raw);
+            File.Delete(raw);
+            sender(Reply.Success(Transfers.Start(Path.GetFileName(file), capture)));
+        };
+    }
+}
+
+// Console output for `uwu logs`. Messages can arrive on any thread, so they are queued and sent
+// from the main thread. Subscriptions expire unless the CLI renews them, because there's no
+// telling when a UDP client went away
+static class LogStream {
+    class Subscriber {
+        public string id;
+        public IReplyChannel channel;
+        public int level;
+        public DateTime renewed;
+    }
 
+    private struct Entry {
+        public string kind;
+        public string message;
+        public string stack;
+        public int level;
+    }
 
-    Request request;
-    IPAddress requester;
-    MessageSender onDone;
+    private const int HistorySize = 200;
+    // keeps every message in a single datagram
+    private const int MaxMessageLength = 16 * 1024;
+    private static readonly TimeSpan Expiry = TimeSpan.FromSeconds(30);
 
-    public Command(Request request, IPAddress requester) {
-        this.request = request;
-        this.requester = requester;
+    private static Queue<Entry> incoming = new Queue<Entry>();
+    private static Queue<Entry> history = new Queue<Entry>();
+    private static List<Subscriber> subscribers = new List<Subscriber>();
+
+    public static void Init() {
+        Application.logMessageReceivedThreaded += OnLog;
+        AssemblyReloadEvents.beforeAssemblyReload += () => {
+            Application.logMessageReceivedThreaded -= OnLog;
+        };
     }
 
-    public string GetCmd() {
-        return request.cmd;
+    private static int Level(string name) {
+        return name == "error" ? 2 : name == "warn" ? 1 : 0;
+    }
+
+    private static void OnLog(string message, string stack, LogType type) {
+        // uwu's own chatter would drown everything else, starting with the renewals
+        if (message.StartsWith("UWU: ")) {
+            return;
+        }
+
+        var entry = new Entry();
+        entry.kind = type == LogType.Log ? "info" : type == LogType.Warning ? "warn" : "error";
+        entry.level = Level(entry.kind);
+        entry.message = message.Length > MaxMessageLength ? message.Substring(0, MaxMessageLength) : message;
+        entry.stack = stack.Length > MaxMessageLength ? stack.Substring(0, MaxMessageLength) : stack;
+
+        lock (incoming) {
+            incoming.Enqueue(entry);
+        }
     }
 
-    public string GetRequester() {
-        return requester.ToString();
+    private static void Send(Entry entry, string id, IReplyChannel channel) {
+        var reply = Reply.Log(entry.kind, entry.message, entry.stack);
+        channel.Send(id, System.Text.Encoding.UTF8.GetBytes(reply.ToJSON(id)));
     }
 
-    private void WaitEditorNotBusy() {
-        if (!Util.IsUnityEditorBusy()) {
-            Debug.Log("UWU: Editor is not busy anymore");
+    public static void Subscribe(Request request, IReplyChannel channel, Command.MessageSender sender) {
+        sender(Reply.Wait());
 
-            EditorApplication.update -= WaitEditorNotBusy;
-            onDone(ExecResult.Success);
+        int level = Level(request.level);
+        if (request.history) {
+            foreach (var entry in history) {
+                if (entry.level >= level) {
+                    Send(entry, request.id, channel);
+                }
+            }
+        }
 
-            onDone = null;
+        if (request.follow) {
+            var subscriber = new Subscriber();
+            subscriber.id = request.id;
+            subscriber.channel = channel;
+            subscriber.level = level;
+            subscriber.renewed = DateTime.UtcNow;
+            subscribers.Add(subscriber);
+        } else {
+            sender(Reply.Success());
         }
     }
 
-    private void OkAfterEditorNotBusy(MessageSender onDone) {
-        Debug.Log("UWU: Waiting for editor to finish compiling");
+    public static bool Renew(string id) {
+        foreach (var subscriber in subscribers) {
+            if (subscriber.id == id) {
+                subscriber.renewed = DateTime.UtcNow;
+                return true;
+            }
+        }
+        return false;
+    }
 
-        this.onDone = onDone;
-        EditorApplication.update += WaitEditorNotBusy;
+    // Called every editor update
+    public static void Flush() {
+        var now = DateTime.UtcNow;
+        subscribers.RemoveAll(subscriber => now - subscriber.renewed > Expiry);
+
+        lock (incoming) {
+            while (incoming.Count > 0) {
+                var entry = incoming.Dequeue();
+
+                history.Enqueue(entry);
+                if (history.Count > HistorySize) {
+                    history.Dequeue();
+                }
+
+                foreach (var subscriber in subscribers) {
+                    if (entry.level >= subscriber.level) {
+                        try {
+                            Send(entry, subscriber.id, subscriber.channel);
+                        } catch (Exception) {
+                            // gone, the subscription expires soon
+                        }
+                    }
+                }
+            }
+        }
     }
+}
 
-    public void Execute(MessageSender sender) {
-        if (request.cmd == "Play") {
-            Debug.Log("UWU: Received Play command, entering play mode");
+// The errors and warnings of the last compilation of every assembly, for `uwu errors`.
+// Kept in SessionState, because a successful compilation reloads the domain right after
+static class CompilerMessages {
+    private const string Key = "UWU.CompilerMessages";
 
+    public static void Init() {
+        UnityEditor.Compilation.CompilationPipeline.assemblyCompilationFinished += OnAssemblyCompiled;
+    }
 
-            if (EditorApplication.isPlaying) {
-                Debug.Log("UWU: Already in play mode");
-            } else {
-                EditorApplication.EnterPlaymode();
+    // one line per message: the assembly, a tab, and the message as JSON
+    private static List<string[]> Load() {
+        var entries = new List<string[]>();
+        foreach (var line in SessionState.GetString(Key, "").Split('\n')) {
+            int tab = line.IndexOf('\t');
+            if (tab > 0) {
+                entries.Add(new[] { line.Substring(0, tab), line.Substring(tab + 1) });
             }
+        }
+        return entries;
+    }
 
-            // Play is a bit of a mess. The Editor will kill UWUClient when it enters play mode
-            // So we need to return success and then rely on the server to send a *different*
-            // command to block until play mode has started
-            sender(ExecResult.Success);
+    private static void OnAssemblyCompiled(string assembly, UnityEditor.Compilation.CompilerMessage[] messages) {
+        // replace whatever the previous compilation of this assembly reported
+        var lines = new List<string>();
+        foreach (var entry in Load()) {
+            if (entry[0] != assembly) {
+                lines.Add(entry[0] + "\t" + entry[1]);
+            }
+        }
 
-        } else if (request.cmd == "CheckAlive") {
-            // This is a special command that is used to check if the client has (re)booted
-            // for example, when the CLI has requested play mode.
-            // It relies on the CLI blocking and retrying until this returns success
+        foreach (var message in messages) {
+            var kind = message.type == UnityEditor.Compilation.CompilerMessageType.Error ? "error" : "warning";
+            lines.Add(assembly + "\t{\"file\":" + Util.JsonString(message.file)
+                + ",\"line\":" + message.line
+                + ",\"column\":" + message.column
+                + ",\"kind\":\"" + kind + "\""
+                + ",\"message\":" + Util.JsonString(message.message) + "}");
+        }
 
-            sender(ExecResult.Success);
-        } else if (request.cmd == "Stop") {
-            Debug.Log("UWU: Received Stop command, stopping play mode");
+        SessionState.SetString(Key, string.Join("\n", lines.ToArray()));
+    }
 
-            if (EditorApplication.isPlaying) {
-                sender(ExecResult.Wait);
+    public static List<string> All() {
+        var messages = new List<string>();
+        foreach (var entry in Load()) {
+            messages.Add(entry[1]);
+        }
+        return messages;
+    }
+}
 
-                EditorApplication.ExitPlaymode();
-                EditorApplication.playModeStateChanged += (PlayModeStateChange state) => {
-                    if (state == PlayModeStateChange.EnteredEditMode) {
-                        sender(ExecResult.Success);
-                    }
-                };
-            } else {
-                sender(ExecResult.Success);
+// Sent instead of a plain Request when the CLI found a token, see Auth
+class SignedRequest {
+    // the request as url-safe base64
+    public string payload;
+    // hex HMAC-SHA256 of the payload bytes
+    public string sig;
+}
+
+// Optional shared secret: with UWU_AUTH=1 the editor only accepts requests signed with the token
+// in Library/uwu-token, so that other programs on the machine can't drive the editor
+static class Auth {
+    public static byte[] key;
+
+    public static void Init() {
+        if (Environment.GetEnvironmentVariable("UWU_AUTH") != "1") {
+            key = null;
+            return;
+        }
+
+        var path = Path.Combine(Directory.GetCurrentDirectory(), "Library", "uwu-token");
+
+        // keep the token across domain reloads and restarts, so a running CLI doesn't lose it
+        if (!File.Exists(path)) {
+            var secret = new byte[32];
+            using (var rng = new System.Security.Cryptography.RNGCryptoServiceProvider()) {
+                rng.GetBytes(secret);
             }
-        } else if (request.cmd == "Refresh") {
-            Debug.Log("UWU: Received asset refresh command");
+            File.WriteAllText(path, BitConverter.ToString(secret).Replace("-", "").ToLowerInvariant());
+        }
 
-            // Refresh also sends success immediately and relies on the CLI to block. See Play
-            sender(ExecResult.Success);
+        key = System.Text.Encoding.UTF8.GetBytes(File.ReadAllText(path).Trim());
+    }
 
-            AssetDatabase.Refresh();
+    public static byte[] DecodePayload(string payload) {
+        var base64 = payload.Replace('-', '+').Replace('_', '/');
+        base64 += new string('=', (4 - base64.Length % 4) % 4);
+        return Convert.FromBase64String(base64);
+    }
 
-        } else if (request.cmd == "BackgroundRefresh") {
-            Debug.Log("UWU: Received a background refresh command");
+    public static bool Verify(byte[] payload, string sig) {
+        byte[] expected;
+        using (var hmac = new System.Security.Cryptography.HMACSHA256(key)) {
+            expected = hmac.ComputeHash(payload);
+        }
+        var expectedSig = BitConverter.ToString(expected).Replace("-", "").ToLowerInvariant();
 
-            // Refresh also sends success immediately and relies on the CLI to block. See Play
-            sender(ExecResult.Success);
-            if (!Util.IsUnityEditorFocused()) {
+        // compare in constant time
+        if (sig.Length != expectedSig.Length) {
+            return false;
+        }
+        int diff = 0;
+        for (int i = 0; i < sig.Length; ++i) {
+            diff |= sig[i] ^ expectedSig[i];
+        }
+        return diff == 0;
+    }
+}
 
-                AssetDatabase.Refresh();
+// Runs the Unity Test Framework for `uwu test`, needs the com.unity.test-framework package.
+// Play mode tests reload the domain, which loses the command and its callbacks, so the
+// request is remembered in SessionState and answered by whichever domain sees the run finish
+class TestRun : UnityEditor.TestTools.TestRunner.Api.ICallbacks {
+    private const string PendingIdKey = "UWU.TestRun.Id";
+    private const string PendingAddressKey = "UWU.TestRun.Address";
+    private const string PendingPortKey = "UWU.TestRun.Port";
+    private const string WantsXmlKey = "UWU.TestRun.Xml";
+    private const string TotalKey = "UWU.TestRun.Total";
+    private const string MsgPackKey = "UWU.TestRun.MsgPack";
+
+    // only set in the domain that started the run
+    private static Command.MessageSender pendingSender;
+
+    public static void Start(Request request, IReplyChannel channel, Command.MessageSender sender) {
+        pendingSender = sender;
+        SessionState.SetString(PendingIdKey, request.id);
+        SessionState.SetBool(WantsXmlKey, request.xml);
+
+        // only UDP can be answered from another domain, stream connections die with the old one
+        var msgpack = channel as MsgPackChannel;
+        SessionState.SetBool(MsgPackKey, msgpack != null);
+        var udp = (msgpack != null ? msgpack.GetInner() : channel) as UdpReplyChannel;
+        SessionState.SetString(PendingAddressKey, udp != null ? udp.GetRequester().Address.ToString() : "");
+        SessionState.SetInt(PendingPortKey, udp != null ? udp.GetRequester().Port : 0);
+
+        var filter = new UnityEditor.TestTools.TestRunner.Api.Filter {
+            testMode = request.mode == "playmode"
+                ? UnityEditor.TestTools.TestRunner.Api.TestMode.PlayMode
+                : UnityEditor.TestTools.TestRunner.Api.TestMode.EditMode,
+        };
+        if (!string.IsNullOrEmpty(request.filter)) {
+            filter.groupNames = new[] { request.filter };
+        }
+
+        var api = ScriptableObject.CreateInstance<UnityEditor.TestTools.TestRunner.Api.TestRunnerApi>();
+        api.Execute(new UnityEditor.TestTools.TestRunner.Api.ExecutionSettings(filter));
+    }
+
+    // called by every domain, so that runs that went through a domain reload still get answered
+    public static void RegisterCallbacks() {
+        var api = ScriptableObject.CreateInstance<UnityEditor.TestTools.TestRunner.Api.TestRunnerApi>();
+        api.RegisterCallbacks(new TestRun());
+    }
+
+    public void RunStarted(UnityEditor.TestTools.TestRunner.Api.ITestAdaptor testsToRun) {
+        SessionState.SetInt(TotalKey, CountLeaves(testsToRun));
+    }
+
+    // each test that starts and finishes is reported as it goes, so that the CLI can show
+    // the failures of long runs before they end
+    public void TestStarted(UnityEditor.TestTools.TestRunner.Api.ITestAdaptor test) {
+        if (test.HasChildren) {
+            return;
+        }
+        string progress = "{\"name\":" + Util.JsonString(test.FullName)
+            + ",\"total\":" + SessionState.GetInt(TotalKey, 0) + "}";
+        Report(Reply.Progress("Running " + test.FullName, progress));
+    }
+
+    public void TestFinished(UnityEditor.TestTools.TestRunner.Api.ITestResultAdaptor result) {
+        if (result.HasChildren) {
+            return;
+        }
+        string status = result.TestStatus.ToString();
+        string message = string.IsNullOrEmpty(result.Message) ? "" : ",\"message\":" + Util.JsonString(result.Message);
+        string progress = "{\"name\":" + Util.JsonString(result.Test.FullName)
+            + ",\"result\":" + Util.JsonString(status) + message
+            + ",\"total\":" + SessionState.GetInt(TotalKey, 0) + "}";
+
+        string text = status + " " + result.Test.FullName;
+        if (status == "Failed" && !string.IsNullOrEmpty(result.Message)) {
+            text += "\n    " + result.Message.Trim().Replace("\n", "\n    ");
+        }
+        Report(Reply.Progress(text, progress));
+    }
+
+    public void RunFinished(UnityEditor.TestTools.TestRunner.Api.ITestResultAdaptor result) {
+        string id = SessionState.GetString(PendingIdKey, "");
+        if (id == "") {
+            // not started by uwu
+            return;
+        }
+        SessionState.EraseString(PendingIdKey);
+
+        var tests = new List<string>();
+        CollectLeaves(result, tests);
+
+        string payload = "{\"passed\":" + result.PassCount
+            + ",\"failed\":" + result.FailCount
+            + ",\"skipped\":" + (result.SkipCount + result.InconclusiveCount)
+            + ",\"tests\":[" + string.Join(",", tests.ToArray()) + "]";
+        if (SessionState.GetBool(WantsXmlKey, false)) {
+            payload += ",\"xml\":" + Util.JsonString(result.ToXml().OuterXml);
+        }
+        payload += "}";
+
+        Report(Reply.Success(payload), id);
+        pendingSender = null;
+    }
+
+    // Answers the request that started the run, from whichever domain this is
+    private static void Report(Reply reply, string id = null) {
+        id = id ?? SessionState.GetString(PendingIdKey, "");
+        if (id == "") {
+            // not started by uwu
+            return;
+        }
+        if (pendingSender != null) {
+            pendingSender(reply);
+            return;
+        }
+
+        string address = SessionState.GetString(PendingAddressKey, "");
+        if (address == "") {
+            if (reply.result == ExecResult.Success) {
+                Debug.LogWarning("UWU: The test run finished after a domain reload, and can't be reported over TCP or IPC");
             }
-        } else if (request.cmd == "Build") {
-            Debug.Log("UWU: Received Script build command");
+            return;
+        }
+        var requester = new IPEndPoint(IPAddress.Parse(address), SessionState.GetInt(PendingPortKey, 0));
+        byte[] bytes = System.Text.Encoding.UTF8.GetBytes(reply.ToJSON(id));
+        UWUClient.SendUdp(id, SessionState.GetBool(MsgPackKey, false) ? MsgPack.FromJson(bytes) : bytes, requester);
+    }
 
-            sender(ExecResult.Wait);
+    private static int CountLeaves(UnityEditor.TestTools.TestRunner.Api.ITestAdaptor test) {
+        if (!test.HasChildren) {
+            return 1;
+        }
+        int count = 0;
+        foreach (var child in test.Children) {
+            count += CountLeaves(child);
+        }
+        return count;
+    }
 
-            UnityEditor.Compilation.CompilationPipeline.RequestScriptCompilation();
-            UnityEditor.Compilation.CompilationPipeline.compilationFinished += (object o) => {
-                sender(ExecResult.Success);
-            };
-        } else {
-            Debug.LogError("Unknown remote command received '" + request.cmd + "'");
-            sender(ExecResult.Error);
+    private static void CollectLeaves(UnityEditor.TestTools.TestRunner.Api.ITestResultAdaptor result, List<string> tests) {
+        if (result.HasChildren) {
+            foreach (var child in result.Children) {
+                CollectLeaves(child, tests);
+            }
+            return;
         }
+
+        string message = string.IsNullOrEmpty(result.Message) ? "" : ",\"message\":" + Util.JsonString(result.Message);
+        tests.Add("{\"name\":" + Util.JsonString(result.Test.FullName)
+            + ",\"result\":" + Util.JsonString(result.TestStatus.ToString()) + message + "}");
     }
 }
 
-
 class Request {
     public string cmd;
     public string id;
+
+    // Hello
+    public int version;
+    public string[] encodings;
+
+    // Menu, Select
+    public string path;
+
+    // Hierarchy, 0 for all of it
+    public int depth;
+
+    // FindAssets
+    public string[] folders;
+
+    // Eval
+    public string code;
+
+    // OpenScene, BakeLighting, Play, EnableBuildScene, DisableBuildScene
+    public string scene;
+    public bool additive;
+
+    // Play
+    public bool fast;
+
+    // TimeScale
+    public float scale;
+
+    // PressKey, Click, SetAxis, GetPref, SetPref
+    public string key;
+    public float x;
+    public float y;
+    public float hold;
+    public string axis;
+    public float value;
+
+    // GetPref, SetPref. kind is null to keep the type of the key
+    public bool player;
+    public string new_value;
+    public string kind;
+
+    // PlayModeOptions, "on", "off" or null
+    public string domain_reload;
+    public string scene_reload;
+
+    // BakeLighting
+    public bool wait;
+
+    // WaitPlayMode
+    public bool stop_on_error;
+
+    // Save
+    public bool scenes;
+    public bool assets;
+
+    // Screenshot
+    public string view;
+    public int width;
+    public int height;
+    public int quality;
+
+    // FetchChunk
+    public string transfer;
+    public int index;
+
+    // Quit
+    public bool force;
+
+    // FocusEditor
+    public bool game;
+
+    // ProfileStart
+    public int frames;
+
+    // AddDefine, RemoveDefine, ListDefines
+    public string symbol;
+    public string group;
+
+    // BuildAddressables
+    public string profile;
+    public bool clean;
+
+    // AddPackage, RemovePackage
+    public string package;
+
+    // Import
+    public string[] paths;
+
+    // SubscribeLogs
+    public string level;
+    public bool follow;
+    public bool history;
+
+    // RenewLogs
+    public string subscription;
+
+    // Exec
+    public string method;
+    public string[] args;
+
+    // BuildPlayer, BuildBundles, SwitchTarget
+    public string target;
+    public string output;
+
+    // BuildPlayer
+    public bool development;
+    public bool report;
+
+    // Test
+    public string mode;
+
+    // Test, FindAssets
+    public string filter;
+    public bool xml;
 }
 
 public static class UWUClient {
 
+    // must match PROTOCOL_VERSION in the CLI
+    public const int ProtocolVersion = 5;
+    // the version of uwu this file comes from, see `uwu version`
+    public const string PluginVersion = "0.1.0";
+
     private static Command currentCmd = null;
 
     // UDP socket
     private static UdpClient udpClient;
     private static IPEndPoint groupEP;
 
+    // TCP listener on the same port, see `uwu --transport tcp`
+    private static TcpListener tcpListener;
+    private static List<TcpConnection> tcpConnections = new List<TcpConnection>();
+
+    // see `uwu --transport ipc`
+    private static IpcServer ipcServer;
+
+    // every editor joins this group to answer `uwu instances`. Must match the CLI
+    private const string DiscoveryGroup = "239.255.38.91";
+    private const int DiscoveryPort = 38909;
+    private static UdpClient discoveryClient;
+
+    private static int port;
+    private static int tcpPort;
+
     // a Hash Set of every ID that has already been seem
     private static HashSet<string> seenIds = new HashSet<string>();
 
     // Fifo queue of Commands to be executed
     private static Queue<Command> commandQueue = new Queue<Command>();
 
+    private static string DecodeMessage(Byte[] bytes) {
+        return MsgPack.Is(bytes) ? MsgPack.ToJson(bytes) : System.Text.Encoding.UTF8.GetString(bytes, 0, bytes.Length);
+    }
+
+    private static void HandleMessage(Byte[] bytes, IReplyChannel channel) {
+        if (MsgPack.Is(bytes)) {
+            channel = new MsgPackChannel(channel);
+        }
+        string cmd;
+        try {
+            cmd = DecodeMessage(bytes);
+        } catch (Exception e) {
+            Debug.LogWarning("UWU: Could not decode a MessagePack request: " + e.Message);
+            return;
+        }
+
+        // unwrap signed requests, even if this editor doesn't check them.
+        // The signed payload is in the same encoding as the envelope
+        bool verified = false;
+        SignedRequest signed = JsonUtility.FromJson<SignedRequest>(cmd);
+        if (!string.IsNullOrEmpty(signed.sig)) {
+            var payload = Auth.DecodePayload(signed.payload);
+            verified = Auth.key != null && Auth.Verify(payload, signed.sig);
+            cmd = DecodeMessage(payload);
+        }
+
+        // deserialize the request from JSON
+        Request request = JsonUtility.FromJson<Request>(cmd);
+
+        Debug.Log("UWU: Received command '" + request.cmd + "'");
+
+        if (Auth.key != null && !verified) {
+            Debug.LogWarning("UWU: Rejected an unsigned or badly signed '" + request.cmd + "' command");
+            var reply = Reply.Error("This editor only accepts signed requests. Run uwu from the project folder or pass --project, so that it finds Library/uwu-token");
+            channel.Send(request.id, System.Text.Encoding.UTF8.GetBytes(reply.ToJSON(request.id)));
+            return;
+        }
+
+        // if the ID has already been seen, ignore it
+        if (seenIds.Contains(request.id)) {
+            Debug.Log("UWU: Already seen ID " + request.id + ", ignoring");
+            return;
+        }
+
+        seenIds.Add(request.id);
+
+        // the queue only runs while the editor isn't busy. These commands don't need to wait,
+        // and status is precisely about whether the editor is busy, so they skip the queue.
+        // WaitPlayMode would hold the queue for the whole play session
+        if (request.cmd == "Hello" || request.cmd == "Status" || request.cmd == "FetchChunk"
+            || request.cmd == "SubscribeLogs" || request.cmd == "RenewLogs" || request.cmd == "CompilerMessages"
+            || request.cmd == "WaitPlayMode") {
+            new Command(request, channel).Execute((Reply reply) => {
+                channel.Send(request.id, System.Text.Encoding.UTF8.GetBytes(reply.ToJSON(request.id)));
+            });
+            return;
+        }
+
+        // create a new command and add it to the queue
+        commandQueue.Enqueue(new Command(request, channel));
+    }
+
     private static void OnUpdate() {
+        LogStream.Flush();
+
         // drain the UDP socket so the buffer doesn't back up
         while (udpClient.Available > 0) {
             Byte[] bytes = udpClient.Receive(ref groupEP);
-            string cmd = System.Text.Encoding.UTF8.GetString(bytes, 0, bytes.Length);
-
-            // deserialize the request from JSON
-            Request request = JsonUtility.FromJson<Request>(cmd);
+            HandleMessage(bytes, new UdpReplyChannel(udpClient, groupEP));
+        }
 
-            Debug.Log("UWU: Received command '" + request.cmd + "'");
+        while (tcpListener.Pending()) {
+            tcpConnections.Add(new TcpConnection(tcpListener.AcceptTcpClient()));
+        }
 
-            // if the ID has already been seen, ignore it
-            if (seenIds.Contains(request.id)) {
-                Debug.Log("UWU: Already seen ID " + request.id + ", ignoring");
-                continue;
+        if (discoveryClient != null) {
+            while (discoveryClient.Available > 0) {
+                var sender = new IPEndPoint(IPAddress.Any, 0);
+                Byte[] bytes = discoveryClient.Receive(ref sender);
+                AnswerDiscovery(bytes, sender);
             }
+        }
 
-            seenIds.Add(request.id);
-
-            // create a new command and add it to the queue
-            Command command = new Command(request, groupEP.Address);
+        foreach (var connection in tcpConnections) {
+            foreach (var frame in connection.ReadFrames()) {
+                HandleMessage(frame, connection);
+            }
+        }
+        tcpConnections.RemoveAll(connection => connection.IsClosed());
 
-            commandQueue.Enqueue(command);
+        if (ipcServer != null) {
+            foreach (var message in ipcServer.Drain()) {
+                HandleMessage(message.Key, message.Value);
+            }
         }
 
         if (currentCmd != null || Util.IsUnityEditorBusy()) {
@@ -197,18 +3468,18 @@ public static class UWUClient {
         if (commandQueue.Count > 0) {
             currentCmd = commandQueue.Dequeue();
 
-            currentCmd.Execute((ExecResult mode) => {
-                // Convert the mode to JSON
-                string json = Util.ResultToJSON(mode);
+            currentCmd.Execute((Reply reply) => {
+                // Convert the reply to JSON
+                string json = reply.ToJSON(currentCmd.GetId());
 
                 Debug.Log("UWU: Sending result '" + json + "'");
 
                 // send the result back to the requester
                 Byte[] bytes = System.Text.Encoding.UTF8.GetBytes(json);
-                udpClient.Send(bytes, bytes.Length, currentCmd.GetRequester().ToString(), groupEP.Port);
+                currentCmd.GetChannel().Send(currentCmd.GetId(), bytes);
 
                 // if mode is not wait, then we are done
-                if (mode != ExecResult.Wait) {
+                if (reply.result == ExecResult.Success || reply.result == ExecResult.Error) {
                     currentCmd = null;
                 }
             });
@@ -218,13 +3489,146 @@ public static class UWUClient {
 
     [InitializeOnLoadMethod]
     private static void Init() {
+        Auth.Init();
+
         // set up the socket and start listening
-        var port = 38910;
-        udpClient = new UdpClient(port);
+        // UWU_PORT allows running several editors side by side, see `uwu --port`
+        port = 38910;
+        var portOverride = Environment.GetEnvironmentVariable("UWU_PORT");
+        if (!string.IsNullOrEmpty(portOverride)) {
+            port = int.Parse(portOverride);
+        }
+        try {
+            udpClient = new UdpClient(port);
+        } catch (SocketException) {
+            // another editor owns the port, pick any free one. The CLI finds it in the port file
+            Debug.Log("UWU: Port " + port + " is taken, picking a free one");
+            udpClient = new UdpClient(0);
+            port = ((IPEndPoint)udpClient.Client.LocalEndPoint).Port;
+        }
         groupEP = new IPEndPoint(IPAddress.Any, port);
 
+        // TCP and UDP ports are separate, so the same number is usually free for both
+        tcpPort = port;
+        try {
+            tcpListener = new TcpListener(IPAddress.Any, port);
+            tcpListener.Start();
+        } catch (SocketException) {
+            tcpListener = new TcpListener(IPAddress.Any, 0);
+            tcpListener.Start();
+            tcpPort = ((IPEndPoint)tcpListener.LocalEndpoint).Port;
+        }
+
+        try {
+            ipcServer = new IpcServer();
+        } catch (Exception e) {
+            Debug.LogWarning("UWU: IPC is not available: " + e.Message);
+            ipcServer = null;
+        }
+
+        WritePortFile(port, tcpPort, ipcServer != null ? ipcServer.address : null);
+
+        // several editors share the discovery port, so it must allow reuse
+        try {
+            discoveryClient = new UdpClient();
+            discoveryClient.ExclusiveAddressUse = false;
+            discoveryClient.Client.SetSocketOption(SocketOptionLevel.Socket, SocketOptionName.ReuseAddress, true);
+            discoveryClient.Client.Bind(new IPEndPoint(IPAddress.Any, DiscoveryPort));
+            discoveryClient.JoinMulticastGroup(IPAddress.Parse(DiscoveryGroup));
+        } catch (SocketException e) {
+            Debug.LogWarning("UWU: discovery is not available: " + e.Message);
+            discoveryClient = null;
+        }
+
         EditorApplication.update += OnUpdate;
 
-        Debug.Log("UWU: Listening on port " + port);
+        TestRun.RegisterCallbacks();
+        LogStream.Init();
+        CompilerMessages.Init();
+        SceneStash.Init();
+        PlayModeResult.Init();
+        CycleTimings.Init();
+        PlayModeOptions.Init();
+        TimeScale.Init();
+        SimulatedInput.Init();
+
+        // release the ports before the domain reloads, so that the next Init can bind them again
+        AssemblyReloadEvents.beforeAssemblyReload += () => {
+            udpClient.Close();
+            tcpListener.Stop();
+            foreach (var connection in tcpConnections) {
+                connection.Close();
+            }
+            if (ipcServer != null) {
+                ipcServer.Stop();
+            }
+            if (discoveryClient != null) {
+                discoveryClient.Close();
+            }
+        };
+        EditorApplication.quitting += DeletePortFile;
+
+        Debug.Log("UWU: Listening on port " + port + (tcpPort != port ? " (TCP " + tcpPort + ")" : ""));
+    }
+
+    // Answered right away without going through the command queue, so that busy editors show up too
+    private static void AnswerDiscovery(Byte[] bytes, IPEndPoint sender) {
+        string text = System.Text.Encoding.UTF8.GetString(bytes, 0, bytes.Length);
+        Request request;
+        try {
+            request = JsonUtility.FromJson<Request>(text);
+        } catch (Exception) {
+            return;
+        }
+        if (request == null || request.cmd != "Discover") {
+            return;
+        }
+
+        string ipc = ipcServer != null ? ipcServer.address : null;
+        string payload = "{\"project\":" + Util.JsonString(Directory.GetCurrentDirectory())
+            + ",\"unity_version\":" + Util.JsonString(Application.unityVersion)
+            + ",\"port\":" + port
+            + ",\"tcp_port\":" + tcpPort
+            + (ipc != null ? ",\"ipc\":" + Util.JsonString(ipc) : "")
+            + ",\"state\":" + Util.JsonString(Util.EditorState()) + "}";
+
+        Byte[] reply = System.Text.Encoding.UTF8.GetBytes(Reply.Success(payload).ToJSON(request.id));
+        discoveryClient.Send(reply, reply.Length, sender);
+    }
+
+    public static void SendUdp(string id, Byte[] bytes, IPEndPoint requester) {
+        Fragments.Send(udpClient, requester, id, bytes);
+    }
+
+    private static string PortFilePath() {
+        // the working directory of the editor is the project root
+        return Path.Combine(Directory.GetCurrentDirectory(), "Library", "uwu-port.json");
+    }
+
+    // Lets the CLI find this editor with `uwu --project` when several editors are open
+    private static void WritePortFile(int port, int tcpPort, string ipcAddress) {
+        // so that the CLI can tell a stale port file from an editor that's busy
+        string json = "{\"port\":" + port + ",\"pid\":" + Process.GetCurrentProcess().Id;
+        if (tcpPort != port) {
+            json += ",\"tcp_port\":" + tcpPort;
+        }
+        if (ipcAddress != null) {
+            json += ",\"ipc\":" + Util.JsonString(ipcAddress);
+        }
+        json += "}";
+
+        try {
+            File.WriteAllText(PortFilePath(), json);
+        } catch (Exception e) {
+            Debug.LogWarning("UWU: Could not write the port file: " + e.Message);
+        }
+    }
+
+    private static void DeletePortFile() {
+        try {
+            File.Delete(PortFilePath());
+        } catch (Exception) {
+            // nothing to clean up
+        }
     }
 }
@@ -0,0 +1,5 @@
+fn main() {
+    // the gRPC server is generated from proto/uwu.proto, which needs protoc
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/uwu.proto").expect("Could not compile proto/uwu.proto");
+}
@@ -0,0 +1,106 @@
+use crate::{print_result, single_command, stats};
+use serde::Serialize;
+use std::time::Instant;
+use uwu_core::{
+    protocol::{Alive, Command},
+    transport::Endpoint,
+};
+
+/// How the durations of the iterations spread, in seconds
+#[derive(Debug, Serialize)]
+struct Spread {
+    min: f64,
+    median: f64,
+    p95: f64,
+    max: f64,
+}
+
+impl Spread {
+    fn of(secs: &[f64]) -> Option<Spread> {
+        let sorted = stats::sorted(secs);
+        Some(Spread {
+            min: *sorted.first()?,
+            median: stats::quantile(&sorted, 0.5),
+            p95: stats::quantile(&sorted, 0.95),
+            max: *sorted.last()?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Bench {
+    command: &'static str,
+    iterations: usize,
+    /// From sending the command until uwu has the answer
+    round_trip_secs: Spread,
+    /// What the editor spent on it, only known for refresh
+    #[serde(skip_serializing_if = "Option::is_none")]
+    editor_secs: Option<Spread>,
+}
+
+// The round trip, and what the editor says it took. Refresh is done once the plugin
+// is back, like uwu refresh
+async fn iteration(endpoint: &Endpoint, refresh: bool) -> anyhow::Result<(f64, Option<f64>)> {
+    let started = Instant::now();
+    if refresh {
+        single_command(endpoint, Command::Refresh).await?;
+    }
+    let alive: Alive = serde_json::from_value(single_command(endpoint, Command::CheckAlive).await?)
+        .unwrap_or_default();
+
+    let editor = alive.timings.and_then(|timings| timings.total_secs);
+    Ok((started.elapsed().as_secs_f64(), editor.filter(|_| refresh)))
+}
+
+fn millis(secs: f64) -> String {
+    format!("{:.1}ms", secs * 1000.0)
+}
+
+/// uwu bench: sends the command again and again, and reports how long it took
+pub async fn bench(
+    endpoint: &Endpoint,
+    command: &'static str,
+    iterations: usize,
+) -> anyhow::Result<()> {
+    let refresh = command == "refresh";
+
+    let mut round_trips = vec![];
+    let mut editor = vec![];
+    for index in 0..iterations {
+        let (round_trip, editor_secs) = iteration(endpoint, refresh).await?;
+        log::info!("{}/{}: {}", index + 1, iterations, millis(round_trip));
+        round_trips.push(round_trip);
+        editor.extend(editor_secs);
+    }
+
+    let bench = Bench {
+        command,
+        iterations,
+        round_trip_secs: Spread::of(&round_trips).expect("At least one iteration"),
+        editor_secs: Spread::of(&editor),
+    };
+    print_result(&bench, |bench| {
+        println!("{} x{}", bench.command, bench.iterations);
+        println!(
+            "  {:<10} {:>10} {:>10} {:>10} {:>10}",
+            "", "min", "median", "p95", "max"
+        );
+        let rows = [
+            ("round trip", Some(&bench.round_trip_secs)),
+            ("editor", bench.editor_secs.as_ref()),
+        ];
+        for (name, spread) in rows.iter() {
+            if let Some(spread) = spread {
+                println!(
+                    "  {:<10} {:>10} {:>10} {:>10} {:>10}",
+                    name,
+                    millis(spread.min),
+                    millis(spread.median),
+                    millis(spread.p95),
+                    millis(spread.max)
+                );
+            }
+        }
+    });
+    Ok(())
+}
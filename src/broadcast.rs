@@ -0,0 +1,231 @@
+use crate::{
+    discovery::{self, Instance},
+    format_compiler_message, hub, print_result, progress, retry_policy, JsonError,
+    DISCOVERY_TIMEOUT, ID_SOURCE, STARTED,
+};
+use anyhow::bail;
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::path::PathBuf;
+use uwu_core::{
+    auth,
+    error::UwuError,
+    protocol::{BuildOutput, Command, CompilerMessage, CompilerMessages, Request},
+    send::{self, decode_payload, Settings},
+    transport::{Endpoint, TransportKind},
+};
+
+/// What one editor made of the command
+#[derive(Serialize)]
+pub struct Outcome {
+    pub project: PathBuf,
+    pub endpoint: String,
+    /// Where a build put its output, if anywhere
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonError>,
+    #[serde(skip)]
+    compile_errors: Vec<CompilerMessage>,
+}
+
+fn endpoint_of(instance: &Instance, kind: TransportKind) -> Option<Endpoint> {
+    let addr = instance.addr.expect("Filled in by discover");
+    match kind {
+        TransportKind::Udp => Some(Endpoint::Udp(addr)),
+        TransportKind::Tcp => Some(Endpoint::Tcp(std::net::SocketAddr::new(
+            addr.ip(),
+            instance.tcp_port,
+        ))),
+        TransportKind::Ipc => instance.ipc.clone().map(Endpoint::Ipc),
+    }
+}
+
+// Each editor gets its own settings, the globals of main are about a single one
+struct Editor {
+    endpoint: Endpoint,
+    settings: Settings,
+}
+
+impl Editor {
+    async fn send(&self, command: Command) -> anyhow::Result<serde_json::Value> {
+        let request = Request {
+            id: ID_SOURCE.lock().unwrap().next_id(),
+            cmd: command,
+        };
+        let max_attempts = retry_policy().max_attempts;
+        if let Endpoint::Udp(addr) = &self.endpoint {
+            if let Some(result) =
+                hub::send(*addr, &self.settings, &request, max_attempts, &mut ()).await
+            {
+                return result;
+            }
+        }
+        send::send(
+            &self.endpoint,
+            &self.settings,
+            &request,
+            max_attempts,
+            &mut (),
+        )
+        .await
+    }
+
+    async fn handshake(&mut self) -> anyhow::Result<()> {
+        let max_attempts = retry_policy().max_attempts;
+        if let Endpoint::Udp(addr) = &self.endpoint {
+            if let Some(result) = hub::handshake(*addr, &mut self.settings, max_attempts).await {
+                return result;
+            }
+        }
+        let id = ID_SOURCE.lock().unwrap().next_id();
+        send::handshake(&self.endpoint, &mut self.settings, id, max_attempts).await
+    }
+
+    // like explain_failure, but keeps the errors for the summary
+    async fn compile_errors(&self, error: &anyhow::Error) -> Vec<CompilerMessage> {
+        if !matches!(UwuError::of(error), Some(UwuError::Editor { .. })) {
+            return vec![];
+        }
+
+        match self
+            .send(Command::CompilerMessages)
+            .await
+            .and_then(decode_payload::<CompilerMessages>)
+        {
+            Ok(compiler) => compiler
+                .messages
+                .into_iter()
+                .filter(|m| m.kind == "error")
+                .collect(),
+            Err(e) => {
+                log::debug!("Could not get the compile errors: {:#}", e);
+                vec![]
+            }
+        }
+    }
+
+    // Build answers with its output, refresh and stop with nothing
+    async fn run(&mut self, command: &Command) -> anyhow::Result<Option<String>> {
+        self.handshake().await?;
+
+        match command {
+            Command::Build => {
+                let output: BuildOutput = decode_payload(self.send(Command::Build).await?)?;
+                Ok(output.output_path)
+            }
+            Command::Refresh => {
+                self.send(Command::Refresh).await?;
+                // like refresh of a single editor, until the plugin is back
+                self.send(Command::CheckAlive).await?;
+                Ok(None)
+            }
+            Command::Stop => {
+                self.send(Command::Stop).await?;
+                Ok(None)
+            }
+            command => bail!("--all can't send {:?}", command),
+        }
+    }
+}
+
+async fn run_on(instance: Instance, kind: TransportKind, command: &Command) -> Outcome {
+    let mut outcome = Outcome {
+        endpoint: String::new(),
+        output_path: None,
+        error: None,
+        compile_errors: vec![],
+        project: instance.project.clone(),
+    };
+
+    let endpoint = match endpoint_of(&instance, kind) {
+        Some(endpoint) => endpoint,
+        None => {
+            let error = anyhow::format_err!("The editor doesn't offer IPC");
+            outcome.error = Some(JsonError::new(&error));
+            return outcome;
+        }
+    };
+    outcome.endpoint = endpoint.to_string();
+
+    // remote projects have no token here, and editors that don't need it ignore it
+    let token = auth::read_token(&instance.project).unwrap_or_else(|e| {
+        log::debug!("No token for {}: {:#}", instance.project.display(), e);
+        None
+    });
+    let mut editor = Editor {
+        endpoint,
+        settings: Settings {
+            timeout: retry_policy().timeout,
+            max_wait: retry_policy().max_wait,
+            token,
+            ..Settings::default()
+        },
+    };
+
+    match editor.run(command).await {
+        Ok(output_path) => outcome.output_path = output_path,
+        Err(error) => {
+            outcome.compile_errors = editor.compile_errors(&error).await;
+            let error = match outcome.compile_errors.len() {
+                0 => error,
+                errors => error.context(UwuError::CompileFailed { errors }),
+            };
+            outcome.error = Some(JsonError::new(&error));
+        }
+    }
+    outcome
+}
+
+/// Sends command to every editor that answers discovery at the same time, and prints
+/// how it went for each of them. Fails if it failed for any editor
+pub async fn broadcast(command: Command, kind: TransportKind) -> anyhow::Result<()> {
+    let instances = discovery::discover(DISCOVERY_TIMEOUT).await?;
+    if instances.is_empty() {
+        bail!("No Unity editors found. Is the editor running with the uwu plugin?");
+    }
+    log::debug!("Sending {:?} to {} editors", command, instances.len());
+
+    let outcomes = join_all(
+        instances
+            .into_iter()
+            .map(|instance| run_on(instance, kind, &command)),
+    )
+    .await;
+
+    print_result(&outcomes, |outcomes| {
+        let colors = progress::colors(atty::Stream::Stdout);
+        for outcome in outcomes {
+            let status = match &outcome.error {
+                None => progress::ok_line(STARTED.elapsed()),
+                Some(_) if colors => progress::paint("failed", progress::RED),
+                Some(_) => "failed".to_string(),
+            };
+            println!(
+                "{}\t{}\t{}",
+                outcome.project.display(),
+                outcome.endpoint,
+                status
+            );
+
+            if let Some(output_path) = &outcome.output_path {
+                println!("  Build output: {}", output_path);
+            }
+            if let Some(error) = &outcome.error {
+                println!("  {}", error.message);
+            }
+            for message in &outcome.compile_errors {
+                eprintln!("{}", format_compiler_message(message, atty::Stream::Stderr));
+            }
+        }
+    });
+
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.error.is_some())
+        .count();
+    if failed > 0 {
+        bail!("Failed for {} of {} editors", failed, outcomes.len());
+    }
+    Ok(())
+}
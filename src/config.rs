@@ -0,0 +1,183 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Defaults for the command line, from uwu.toml at the root of the project and from
+/// ~/.config/uwu/config.toml. The project's settings win over the user's, and the command
+/// line wins over both
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Like --addr
+    pub addr: Option<String>,
+    pub port: Option<u16>,
+    pub transport: Option<String>,
+    /// In seconds, like --timeout
+    pub timeout: Option<f64>,
+    pub retries: Option<u32>,
+    /// In seconds, like --max-wait
+    pub max_wait: Option<f64>,
+    /// Like --log-file, relative to the project
+    pub log_file: Option<PathBuf>,
+    /// For build-player and bundles build, when they get no --target
+    pub build_target: Option<String>,
+    /// Like --notify
+    pub notify: Option<String>,
+    /// Like --otel-endpoint
+    pub otel_endpoint: Option<String>,
+    pub watch: WatchConfig,
+    /// The names of uwu instances name, for --instance
+    pub instances: BTreeMap<String, NamedInstance>,
+}
+
+/// An editor that --instance can pick by name. Local editors are known by their project,
+/// since their port changes between sessions, the others by their address
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NamedInstance {
+    #[serde(default)]
+    pub project: Option<PathBuf>,
+    #[serde(default)]
+    pub addr: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Added to the --ignore globs
+    pub ignore: Vec<String>,
+    /// In seconds, like --delay
+    pub delay: Option<u64>,
+    pub exec: Option<String>,
+    pub exec_after: Option<String>,
+}
+
+impl Config {
+    // the settings of self, and those of fallback for what self doesn't set
+    fn or(self, fallback: Config) -> Config {
+        let mut ignore = self.watch.ignore;
+        ignore.extend(fallback.watch.ignore);
+        let mut instances = fallback.instances;
+        instances.extend(self.instances);
+
+        Config {
+            addr: self.addr.or(fallback.addr),
+            port: self.port.or(fallback.port),
+            transport: self.transport.or(fallback.transport),
+            timeout: self.timeout.or(fallback.timeout),
+            retries: self.retries.or(fallback.retries),
+            max_wait: self.max_wait.or(fallback.max_wait),
+            log_file: self.log_file.or(fallback.log_file),
+            build_target: self.build_target.or(fallback.build_target),
+            notify: self.notify.or(fallback.notify),
+            otel_endpoint: self.otel_endpoint.or(fallback.otel_endpoint),
+            watch: WatchConfig {
+                ignore,
+                delay: self.watch.delay.or(fallback.watch.delay),
+                exec: self.watch.exec.or(fallback.watch.exec),
+                exec_after: self.watch.exec_after.or(fallback.watch.exec_after),
+            },
+            instances,
+        }
+    }
+}
+
+pub fn project_config_path(project: &Path) -> PathBuf {
+    project.join("uwu.toml")
+}
+
+/// ~/.config/uwu even on Windows and macOS, so that the same dotfiles work everywhere
+pub fn user_dir() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => {
+            let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+            PathBuf::from(home).join(".config")
+        }
+    };
+    Some(config.join("uwu"))
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    user_dir().map(|dir| dir.join("config.toml"))
+}
+
+fn read(path: &Path) -> anyhow::Result<Config> {
+    if !path.is_file() {
+        return Ok(Config::default());
+    }
+
+    log::debug!("Reading the settings of {}", path.display());
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    toml::from_str(&contents).with_context(|| format!("Invalid {}", path.display()))
+}
+
+/// The settings for a project, empty if there are no config files
+pub fn load(project: &Path) -> anyhow::Result<Config> {
+    let user = match user_config_path() {
+        Some(path) => read(&path)?,
+        None => Config::default(),
+    };
+
+    Ok(read(&project_config_path(project))?.or(user))
+}
+
+/// Remembers the instance under name in ~/.config/uwu/config.toml, keeping the rest of the
+/// file as it is. Returns the path of the file
+pub fn name_instance(name: &str, instance: &NamedInstance) -> anyhow::Result<PathBuf> {
+    let mut entry = toml_edit::InlineTable::new();
+    if let Some(project) = &instance.project {
+        entry.insert("project", project.display().to_string().into());
+    }
+    if let Some(addr) = &instance.addr {
+        entry.insert("addr", addr.as_str().into());
+    }
+
+    edit_user_config(|doc| {
+        let instances = doc["instances"]
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .context("instances must be a table")?;
+        instances.insert(name, toml_edit::value(entry));
+        Ok(())
+    })
+}
+
+/// Forgets the name of uwu instances name. Returns the path of the file
+pub fn forget_instance(name: &str) -> anyhow::Result<PathBuf> {
+    edit_user_config(|doc| {
+        doc.get_mut("instances")
+            .and_then(|instances| instances.as_table_like_mut())
+            .and_then(|instances| instances.remove(name))
+            .with_context(|| format!("No instance is named {}", name))?;
+        Ok(())
+    })
+}
+
+fn edit_user_config(
+    edit: impl FnOnce(&mut toml_edit::Document) -> anyhow::Result<()>,
+) -> anyhow::Result<PathBuf> {
+    let path = user_config_path().context("Could not find the home directory")?;
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Could not read {}", path.display())),
+    };
+    let mut doc: toml_edit::Document = contents
+        .parse()
+        .with_context(|| format!("Invalid {}", path.display()))?;
+
+    edit(&mut doc)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create {}", dir.display()))?;
+    }
+    std::fs::write(&path, doc.to_string())
+        .with_context(|| format!("Could not write {}", path.display()))?;
+    Ok(path)
+}
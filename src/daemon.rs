@@ -0,0 +1,365 @@
+use anyhow::{bail, Context};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+// how long `uwu watch start` waits for the background watcher to come up
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+// a client that doesn't send its line by then doesn't hold up the others
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Written into the Library folder by a background watcher, so that `uwu watch stop` and
+/// `uwu watch status` can find its control socket
+#[derive(Debug, Deserialize, Serialize)]
+struct State {
+    pid: u32,
+    port: u16,
+    /// Sent along every control command, so that other users of the machine can't send any
+    key: String,
+}
+
+fn state_path(project: &Path) -> PathBuf {
+    project.join("Library").join("uwu-watch.json")
+}
+
+pub fn log_path(project: &Path) -> PathBuf {
+    project.join("Library").join("uwu-watch.log")
+}
+
+fn read_state(project: &Path) -> anyhow::Result<Option<State>> {
+    let path = state_path(project);
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read(&path)?;
+    let state = serde_json::from_slice(&contents)
+        .map_err(|e| anyhow::format_err!("Invalid watcher state {}: {}", path.display(), e))?;
+
+    Ok(Some(state))
+}
+
+/// What the watcher did so far, updated by the watcher and reported by `uwu watch status`
+#[derive(Debug)]
+pub struct Stats {
+    pub events: AtomicU64,
+    pub refreshes: AtomicU64,
+    pub online: AtomicBool,
+    started: Instant,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            events: AtomicU64::new(0),
+            refreshes: AtomicU64::new(0),
+            online: AtomicBool::new(false),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Reply to the status control command
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Status {
+    pub pid: u32,
+    pub project: PathBuf,
+    pub online: bool,
+    pub events: u64,
+    pub refreshes: u64,
+    pub uptime_secs: u64,
+}
+
+/// Writes a file that holds a key: only the user can read it, and it never shows up half
+/// written. The file goes next to it first, then takes its place
+pub fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    // the mode only applies to new files, and a crash may have left one behind
+    let _ = std::fs::remove_file(&partial);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options
+        .open(&partial)
+        .and_then(|mut file| file.write_all(contents).and_then(|()| file.sync_all()))
+        .and_then(|()| std::fs::rename(&partial, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    written
+}
+
+// Unity projects ignore Library, but the key must not end up in a commit of one that
+// doesn't: then it goes into the local excludes of the repository, which aren't committed
+fn exclude_from_git(path: &Path) {
+    let dir = path.parent().expect("Files are in a folder");
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .stderr(Stdio::null())
+            .output()
+    };
+
+    let file_name = path
+        .file_name()
+        .expect("Files have a name")
+        .to_string_lossy();
+    // 1 means not ignored, anything else is ignored already, or no repository or no git
+    let file_name: &str = &file_name;
+    match git(&["check-ignore", "-q", file_name]) {
+        Ok(output) if output.status.code() == Some(1) => {}
+        _ => return,
+    }
+    let output = match git(&["rev-parse", "--git-path", "info/exclude", "--show-prefix"]) {
+        Ok(output) if output.status.success() => output,
+        _ => return,
+    };
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    let (exclude, prefix) = match (lines.next(), lines.next()) {
+        (Some(exclude), prefix) => (dir.join(exclude), prefix.unwrap_or_default()),
+        _ => return,
+    };
+
+    let pattern = format!("/{}{}\n", prefix, file_name);
+    let appended = exclude
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&exclude)
+        })
+        .and_then(|mut file| file.write_all(pattern.as_bytes()));
+    match appended {
+        Ok(()) => log::info!("Added {} to {}", pattern.trim(), exclude.display()),
+        Err(e) => log::warn!("{} is not ignored by git: {}", path.display(), e),
+    }
+}
+
+/// Removes a state file when dropped, so that it goes away however its process stops
+pub struct StateGuard(pub PathBuf);
+
+impl Drop for StateGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Serves the control socket of a background watcher. Returns when asked to stop
+pub async fn serve(project: &Path, stats: Arc<Stats>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let state = State {
+        pid: std::process::id(),
+        port: listener.local_addr()?.port(),
+        key: format!("{:032x}", rand::thread_rng().gen::<u128>()),
+    };
+    let path = state_path(project);
+    write_private(&path, &serde_json::to_vec(&state)?)
+        .with_context(|| format!("Could not write {}", path.display()))?;
+    exclude_from_git(&path);
+    let _guard = StateGuard(path);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+
+        let stop =
+            tokio::time::timeout(CONTROL_TIMEOUT, handle(stream, &state, project, &stats)).await;
+        match stop {
+            Ok(Ok(true)) => return Ok(()),
+            Ok(Ok(false)) => {}
+            Ok(Err(e)) => log::debug!("Control connection failed: {}", e),
+            Err(_) => log::debug!("Control connection timed out"),
+        }
+    }
+}
+
+// one line per connection: the key, a space and the command. Returns whether to stop
+async fn handle(
+    stream: TcpStream,
+    state: &State,
+    project: &Path,
+    stats: &Stats,
+) -> anyhow::Result<bool> {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+
+    let (key, command) = line.trim().split_once(' ').unwrap_or(("", ""));
+    if key != state.key {
+        stream.write_all(b"error: wrong key\n").await?;
+        return Ok(false);
+    }
+
+    match command {
+        "status" => {
+            let status = Status {
+                pid: state.pid,
+                project: project.to_path_buf(),
+                online: stats.online.load(Ordering::Relaxed),
+                events: stats.events.load(Ordering::Relaxed),
+                refreshes: stats.refreshes.load(Ordering::Relaxed),
+                uptime_secs: stats.started.elapsed().as_secs(),
+            };
+            let mut reply = serde_json::to_vec(&status)?;
+            reply.push(b'\n');
+            stream.write_all(&reply).await?;
+            Ok(false)
+        }
+        "stop" => {
+            println!("Stopped by uwu watch stop");
+            stream.write_all(b"ok\n").await?;
+            Ok(true)
+        }
+        _ => {
+            stream.write_all(b"error: unknown command\n").await?;
+            Ok(false)
+        }
+    }
+}
+
+/// Sends a control command to the background watcher of the project. Returns None if
+/// there's none
+async fn request(project: &Path, command: &str) -> anyhow::Result<Option<String>> {
+    let state = match read_state(project)? {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+
+    let stream = match TcpStream::connect(("127.0.0.1", state.port)).await {
+        Ok(stream) => stream,
+        // the watcher died without cleaning up
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            let _ = std::fs::remove_file(state_path(project));
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut stream = BufReader::new(stream);
+    stream
+        .write_all(format!("{} {}\n", state.key, command).as_bytes())
+        .await?;
+
+    let mut reply = String::new();
+    stream.read_line(&mut reply).await?;
+
+    match reply.trim().strip_prefix("error: ") {
+        Some(error) => bail!("The watcher refused {}: {}", command, error),
+        None => Ok(Some(reply.trim().to_string())),
+    }
+}
+
+pub async fn status(project: &Path) -> anyhow::Result<Option<Status>> {
+    match request(project, "status").await? {
+        Some(reply) => Ok(Some(
+            serde_json::from_str(&reply).context("Unexpected status from the watcher")?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Returns false if no watcher was running
+pub async fn stop(project: &Path) -> anyhow::Result<bool> {
+    Ok(request(project, "stop").await?.is_some())
+}
+
+#[cfg(unix)]
+fn detach(command: &mut std::process::Command) {
+    use std::os::unix::process::CommandExt;
+
+    // out of the process group of the shell, so that closing the terminal doesn't kill it
+    command.process_group(0);
+}
+
+#[cfg(windows)]
+fn detach(command: &mut std::process::Command) {
+    use std::os::windows::process::CommandExt;
+
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+}
+
+/// Runs `uwu ... watch start ...` again as `uwu ... watch --daemon ...` in the background,
+/// and waits for it to be up
+pub async fn start(project: &Path) -> anyhow::Result<u32> {
+    if status(project).await?.is_some() {
+        bail!(
+            "A watcher is already running for {}, see uwu watch status",
+            project.display()
+        );
+    }
+
+    let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let watch = args
+        .iter()
+        .position(|arg| arg == "watch")
+        .context("Expected a watch subcommand")?;
+    let start = args[watch..]
+        .iter()
+        .position(|arg| arg == "start")
+        .context("Expected watch start")?;
+    args[watch + start] = "--daemon".into();
+
+    let log = std::fs::File::create(log_path(project))
+        .with_context(|| format!("Could not create {}", log_path(project).display()))?;
+
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    detach(&mut command);
+
+    let mut child = command.spawn().context("Could not start the watcher")?;
+
+    let deadline = Instant::now() + START_TIMEOUT;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            bail!(
+                "The watcher exited with {}, see {}",
+                status,
+                log_path(project).display()
+            );
+        }
+        if let Some(state) = read_state(project)? {
+            if state.pid == child.id() {
+                return Ok(state.pid);
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    bail!(
+        "The watcher didn't start in time, see {}",
+        log_path(project).display()
+    )
+}
@@ -0,0 +1,147 @@
+use crate::ID_SOURCE;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::Instant};
+use uwu_core::{
+    protocol::{Command, Request, Response, ResponseMessage},
+    transport::TransportKind,
+};
+
+// every editor with the plugin joins this group, must match the plugin
+const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 38, 91);
+const DISCOVERY_PORT: u16 = 38909;
+
+/// Written by the Unity plugin into the Library folder of its project, so that each
+/// project can be reached even when several editors are open at the same time
+#[derive(Debug, Deserialize)]
+pub struct PortFile {
+    pub port: u16,
+    /// Only set if the TCP listener couldn't get the same port as UDP
+    #[serde(default)]
+    pub tcp_port: Option<u16>,
+    /// The Unix domain socket or named pipe of the editor, if the platform supports it
+    #[serde(default)]
+    pub ipc: Option<PathBuf>,
+    /// The process of the editor, older plugins don't write it
+    #[serde(default)]
+    pub pid: Option<u32>,
+}
+
+impl PortFile {
+    pub fn port_for(&self, kind: TransportKind) -> u16 {
+        match kind {
+            TransportKind::Udp => self.port,
+            TransportKind::Tcp => self.tcp_port.unwrap_or(self.port),
+            TransportKind::Ipc => unreachable!("IPC endpoints don't have a port"),
+        }
+    }
+}
+
+pub fn port_file_path(project: &Path) -> PathBuf {
+    project.join("Library").join("uwu-port.json")
+}
+
+/// The Unity project that the directory is in, found by the Assets and ProjectSettings
+/// folders that every project has
+pub fn find_project(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join("Assets").is_dir() && dir.join("ProjectSettings").is_dir())
+        .map(Path::to_path_buf)
+}
+
+/// Returns None if the project has no port file, which happens when the editor is closed
+/// or runs an older version of the plugin
+pub fn read_port_file(project: &Path) -> anyhow::Result<Option<PortFile>> {
+    let path = port_file_path(project);
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read(&path)?;
+    let port_file = serde_json::from_slice(&contents)
+        .map_err(|e| anyhow::format_err!("Invalid port file {}: {}", path.display(), e))?;
+
+    Ok(Some(port_file))
+}
+
+/// An editor that answered `uwu instances`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Instance {
+    pub project: PathBuf,
+    pub unity_version: String,
+    pub port: u16,
+    pub tcp_port: u16,
+    #[serde(default)]
+    pub ipc: Option<PathBuf>,
+    /// idle, playing, paused, compiling or importing
+    pub state: String,
+    /// Where the answer came from, filled in by discover
+    #[serde(skip_deserializing)]
+    pub addr: Option<SocketAddr>,
+    /// What uwu instances name called it, if anything
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Asks every editor on the local network to introduce itself, and collects the answers
+/// that arrive within the timeout
+pub async fn discover(timeout: Duration) -> anyhow::Result<Vec<Instance>> {
+    let request = Request {
+        id: ID_SOURCE.lock().unwrap().next_id(),
+        cmd: Command::Discover,
+    };
+    let msg = serde_json::to_vec(&request)?;
+
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    // editors on this machine must hear it too
+    socket.set_multicast_loop_v4(true)?;
+    socket
+        .send_to(&msg, (DISCOVERY_GROUP, DISCOVERY_PORT))
+        .await?;
+
+    let deadline = Instant::now() + timeout;
+    let mut instances = vec![];
+    let mut seen = HashSet::new();
+    let mut recv_buf = vec![0; 65507];
+
+    while let Ok(result) = tokio::time::timeout_at(deadline, socket.recv_from(&mut recv_buf)).await
+    {
+        let (size, src) = result?;
+
+        // anything could be listening on the group, ignore what doesn't look like an answer
+        let message: ResponseMessage = match serde_json::from_slice(&recv_buf[..size]) {
+            Ok(message) => message,
+            Err(e) => {
+                log::debug!("Ignoring an invalid discovery answer from {}: {}", src, e);
+                continue;
+            }
+        };
+        if message.id != request.id {
+            continue;
+        }
+
+        if let Response::Success { payload } = message.response {
+            let mut instance: Instance = match serde_json::from_value(payload) {
+                Ok(instance) => instance,
+                Err(e) => {
+                    log::debug!("Ignoring an invalid discovery answer from {}: {}", src, e);
+                    continue;
+                }
+            };
+
+            // editors on several interfaces answer once per interface
+            if seen.insert((src.ip(), instance.port)) {
+                instance.addr = Some(SocketAddr::new(src.ip(), instance.port));
+                instances.push(instance);
+            }
+        }
+    }
+
+    Ok(instances)
+}
@@ -0,0 +1,420 @@
+use crate::{
+    discovery::{self, PortFile},
+    handshake, install, launch, print_result, progress, resolve_endpoint, retry_policy,
+    send_command,
+};
+use anyhow::bail;
+use clap::ArgMatches;
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use std::{
+    io::ErrorKind,
+    net::{TcpListener, UdpSocket},
+    path::Path,
+    time::Instant,
+};
+use uwu_core::{
+    encoding::Encoding,
+    error::UwuError,
+    protocol::{Command, HelloReply, PROTOCOL_VERSION},
+    transport::Endpoint,
+};
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pass,
+    Fail,
+    /// An earlier check failed, or there's no way to tell
+    Skip,
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    /// What to do about a failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fix: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Check {
+        Check {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Check {
+        Check {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn skip(name: &'static str, detail: impl Into<String>) -> Check {
+        Check {
+            name,
+            status: Status::Skip,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+}
+
+const LAUNCH_FIX: &str = "Open the project in Unity, or run `uwu launch`";
+const INSTALL_FIX: &str = "Run `uwu install`";
+
+fn check_project(project: &Path) -> Check {
+    match discovery::find_project(project) {
+        Some(found) if found == project => Check::pass("project", project.display().to_string()),
+        Some(found) => Check::fail(
+            "project",
+            format!(
+                "{} is inside the project {}",
+                project.display(),
+                found.display()
+            ),
+            format!("Run uwu from {} or pass it to --project", found.display()),
+        ),
+        None => Check::fail(
+            "project",
+            format!("{} is not a Unity project", project.display()),
+            "Run uwu from a directory with Assets and ProjectSettings, or pass one to --project",
+        ),
+    }
+}
+
+fn check_port_file(project: &Path) -> (Check, Option<PortFile>) {
+    let path = discovery::port_file_path(project);
+    match discovery::read_port_file(project) {
+        Ok(Some(port_file)) => {
+            let check = Check::pass("port file", format!("port {}", port_file.port));
+            (check, Some(port_file))
+        }
+        Ok(None) => {
+            let check = Check::fail(
+                "port file",
+                format!("No {}", path.display()),
+                format!(
+                    "{}. The plugin writes it once it starts, older plugins don't write it",
+                    LAUNCH_FIX
+                ),
+            );
+            (check, None)
+        }
+        Err(e) => {
+            let check = Check::fail(
+                "port file",
+                format!("Could not read {}: {:#}", path.display(), e),
+                "Delete it, the plugin writes it again when the editor restarts",
+            );
+            (check, None)
+        }
+    }
+}
+
+fn check_process(project: &Path, port_file: Option<&PortFile>) -> Check {
+    let pid = port_file.and_then(|port_file| port_file.pid);
+    match (launch::editor_running(project, port_file), pid) {
+        (Some(true), Some(pid)) => Check::pass("editor", format!("Unity is running, pid {}", pid)),
+        (Some(true), None) => Check::pass("editor", "Unity is running"),
+        (Some(false), _) => Check::fail(
+            "editor",
+            format!("Unity is not running for project {}", project.display()),
+            LAUNCH_FIX,
+        ),
+        (None, _) => Check::skip("editor", "An editor runs, but uwu can't tell which project"),
+    }
+}
+
+// Binding the port ourselves fails if the editor owns it. Only for local editors, and
+// Windows may let us share a port that the editor bound to every address
+fn check_port(endpoint: &Endpoint) -> Check {
+    let addr = match endpoint {
+        Endpoint::Udp(addr) | Endpoint::Tcp(addr) => *addr,
+        Endpoint::Ipc(path) if path.exists() => {
+            return Check::pass("port", format!("{} exists", path.display()))
+        }
+        Endpoint::Ipc(path) => {
+            return Check::fail(
+                "port",
+                format!("{} doesn't exist", path.display()),
+                LAUNCH_FIX,
+            )
+        }
+    };
+    if !addr.ip().is_loopback() {
+        return Check::skip("port", format!("{} is on another machine", addr));
+    }
+
+    let bound = match endpoint {
+        Endpoint::Tcp(_) => TcpListener::bind(addr).map(drop),
+        _ => UdpSocket::bind(addr).map(drop),
+    };
+    match bound {
+        Err(e) if e.kind() == ErrorKind::AddrInUse => Check::pass(
+            "port",
+            format!("{} is taken, by the editor hopefully", endpoint),
+        ),
+        Err(e) => Check::skip("port", format!("Could not check {}: {}", endpoint, e)),
+        Ok(()) => Check::fail(
+            "port",
+            format!("Nothing listens on {}", endpoint),
+            format!(
+                "{}. If it's open, check the console of the editor for errors of the plugin",
+                LAUNCH_FIX
+            ),
+        ),
+    }
+}
+
+fn check_plugin(project: &Path) -> (Check, Option<String>) {
+    let path = match install::find_file(project, "UWUClient.cs") {
+        Some(path) => path,
+        None => {
+            let check = Check::fail("plugin", "UWUClient.cs is not in the project", INSTALL_FIX);
+            return (check, None);
+        }
+    };
+
+    let relative = path.strip_prefix(project).unwrap_or(&path).display();
+    let source = std::fs::read_to_string(&path).unwrap_or_default();
+    match install::plugin_version(&source) {
+        Some(version) => {
+            let check = Check::pass("plugin", format!("{} {}", relative, version));
+            (check, Some(version))
+        }
+        None => {
+            let check = Check::pass("plugin", format!("{}, too old to have a version", relative));
+            (check, None)
+        }
+    }
+}
+
+async fn check_protocol(endpoint: &Endpoint, installed: Option<&str>) -> Check {
+    let hello = Command::Hello {
+        version: PROTOCOL_VERSION,
+        encodings: Encoding::supported(),
+    };
+    let max_attempts = retry_policy().max_attempts.or(Some(1));
+
+    let payload = match send_command(endpoint, hello, max_attempts).await {
+        Ok(payload) => payload,
+        Err(e) if UwuError::is_unreachable(&e) => {
+            return Check::fail(
+                "protocol",
+                format!("Unity is not reachable at {}", endpoint),
+                format!(
+                    "{}. Check --addr, --port and --transport, and that no firewall blocks it",
+                    LAUNCH_FIX
+                ),
+            )
+        }
+        Err(e) => {
+            return Check::fail(
+                "protocol",
+                format!("{:#}", e),
+                "Check the console of the editor",
+            )
+        }
+    };
+
+    let reply: HelloReply = match serde_json::from_value(payload) {
+        Ok(reply) => reply,
+        Err(_) => {
+            return Check::fail(
+                "protocol",
+                "The plugin is too old to say which protocol it speaks",
+                INSTALL_FIX,
+            )
+        }
+    };
+    if reply.version != PROTOCOL_VERSION {
+        return Check::fail(
+            "protocol",
+            format!(
+                "The plugin speaks v{} but uwu needs v{}",
+                reply.version, PROTOCOL_VERSION
+            ),
+            "Run `uwu install`, or `uwu self-update` if the plugin is newer",
+        );
+    }
+
+    let running = reply.plugin_version.as_deref();
+    if let (Some(running), Some(installed)) = (running, installed) {
+        if running != installed {
+            return Check::fail(
+                "protocol",
+                format!(
+                    "The editor runs plugin {}, but UWUClient.cs is {}",
+                    running, installed
+                ),
+                "Focus the editor or run `uwu refresh`, so that it compiles the new plugin",
+            );
+        }
+    }
+    Check::pass(
+        "protocol",
+        format!(
+            "v{}, plugin {}",
+            reply.version,
+            running.unwrap_or("unknown")
+        ),
+    )
+}
+
+async fn check_round_trip(endpoint: &Endpoint) -> Check {
+    let max_attempts = retry_policy().max_attempts.or(Some(1));
+    let started = Instant::now();
+
+    let alive = async {
+        handshake(endpoint, max_attempts).await?;
+        send_command(endpoint, Command::CheckAlive, max_attempts).await
+    };
+    match alive.await {
+        Ok(_) => Check::pass(
+            "round trip",
+            format!(
+                "CheckAlive took {:.0}ms",
+                started.elapsed().as_secs_f64() * 1000.0
+            ),
+        ),
+        Err(e) => Check::fail(
+            "round trip",
+            format!("CheckAlive failed: {:#}", e),
+            "The editor may be busy, try again once it's idle or check its console",
+        ),
+    }
+}
+
+// Like uwu watch, which needs to watch Assets recursively
+fn check_watchable(project: &Path) -> Check {
+    let assets = project.join("Assets");
+    let watched = notify::recommended_watcher(|_: notify::Result<notify::Event>| {})
+        .and_then(|mut watcher| watcher.watch(&assets, RecursiveMode::Recursive));
+
+    match watched {
+        Ok(()) => Check::pass("watch", format!("{} can be watched", assets.display())),
+        Err(e) => {
+            let fix = if cfg!(target_os = "linux") {
+                "Raise fs.inotify.max_user_watches, or use `uwu watch --poll`"
+            } else {
+                "Use `uwu watch --poll`"
+            };
+            Check::fail(
+                "watch",
+                format!("Can't watch {}: {}", assets.display(), e),
+                fix,
+            )
+        }
+    }
+}
+
+async fn run_checks(
+    matches: &ArgMatches<'_>,
+    project: &Path,
+    fallback_project: &Path,
+    config: &crate::config::Config,
+) -> Vec<Check> {
+    let mut checks = vec![check_project(project)];
+    let is_project = checks[0].status == Status::Pass;
+
+    let (check, port_file) = check_port_file(project);
+    checks.push(check);
+    checks.push(check_process(project, port_file.as_ref()));
+
+    let endpoint = match resolve_endpoint(matches, fallback_project, config).await {
+        Ok(endpoint) => Some(endpoint),
+        Err(e) => {
+            checks.push(Check::fail(
+                "port",
+                format!("{:#}", e),
+                "Pass --addr or --port, or open the project in the editor",
+            ));
+            None
+        }
+    };
+    if let Some(endpoint) = &endpoint {
+        checks.push(check_port(endpoint));
+    }
+
+    let installed = if is_project {
+        let (check, installed) = check_plugin(project);
+        checks.push(check);
+        installed
+    } else {
+        checks.push(Check::skip("plugin", "Not a Unity project"));
+        None
+    };
+
+    match &endpoint {
+        Some(endpoint) => {
+            let protocol = check_protocol(endpoint, installed.as_deref()).await;
+            let talks = protocol.status == Status::Pass;
+            checks.push(protocol);
+            checks.push(if talks {
+                check_round_trip(endpoint).await
+            } else {
+                Check::skip("round trip", "The protocol check failed")
+            });
+        }
+        None => {
+            checks.push(Check::skip("protocol", "No address for the editor"));
+            checks.push(Check::skip("round trip", "No address for the editor"));
+        }
+    }
+
+    checks.push(if is_project {
+        check_watchable(project)
+    } else {
+        Check::skip("watch", "Not a Unity project")
+    });
+    checks
+}
+
+/// uwu doctor: checks what it takes to talk to the editor of the project, one step at a
+/// time, and suggests a fix for each step that fails
+pub async fn doctor(
+    matches: &ArgMatches<'_>,
+    project: &Path,
+    fallback_project: &Path,
+    config: &crate::config::Config,
+) -> anyhow::Result<()> {
+    let checks = run_checks(matches, project, fallback_project, config).await;
+
+    print_result(&checks, |checks| {
+        let colors = progress::colors(atty::Stream::Stdout);
+        for check in checks {
+            let (label, color) = match check.status {
+                Status::Pass => ("pass", progress::GREEN),
+                Status::Fail => ("FAIL", progress::RED),
+                Status::Skip => ("skip", progress::DIM),
+            };
+            let label = if colors {
+                progress::paint(label, color)
+            } else {
+                label.to_string()
+            };
+            println!("{}  {:<10}  {}", label, check.name, check.detail);
+            if let Some(fix) = &check.fix {
+                println!("      {:<10}  {}", "", fix);
+            }
+        }
+    });
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == Status::Fail)
+        .count();
+    if failed > 0 {
+        bail!("{} of {} checks failed", failed, checks.len());
+    }
+    Ok(())
+}
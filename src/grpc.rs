@@ -0,0 +1,268 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tonic::{Request, Response, Status};
+use uwu_core::{
+    protocol::{self, Command},
+    send::decode_payload,
+    AsyncClient, UwuError,
+};
+
+mod proto {
+    tonic::include_proto!("uwu");
+}
+
+use proto::{
+    editor_server::{Editor, EditorServer},
+    event, BuildBundlesRequest, BuildPlayerRequest, CompilerMessage, CompilerMessageList, Empty,
+    ExecRequest, LogMessage, LogsRequest, MenuRequest, PlayRequest, TestRequest,
+};
+
+type Events = ReceiverStream<Result<proto::Event, Status>>;
+
+// so that the orchestrator can tell an editor that's down from a command that failed
+fn to_status(e: anyhow::Error) -> Status {
+    let message = format!("{:#}", e);
+    match UwuError::of(&e) {
+        Some(UwuError::Unreachable(_)) => Status::unavailable(message),
+        Some(UwuError::Timeout(_)) => Status::deadline_exceeded(message),
+        Some(UwuError::ProtocolMismatch(_)) | Some(UwuError::CompileFailed { .. }) => {
+            Status::failed_precondition(message)
+        }
+        Some(UwuError::Editor { .. }) => Status::aborted(message),
+        None => Status::internal(message),
+    }
+}
+
+struct Service {
+    client: Arc<AsyncClient>,
+}
+
+impl Service {
+    // Streams what the editor reports, then the result. Commands that reload the scripts
+    // only end once the plugin is back
+    fn run(&self, command: Command, reloads: bool) -> Response<Events> {
+        let (tx, rx) = mpsc::channel(16);
+        let client = self.client.clone();
+
+        tokio::spawn(async move {
+            let mut events = client.send_streaming(command);
+
+            while let Some(reported) = events.next().await {
+                let event = match reported {
+                    uwu_core::Event::Progress(message) => Ok(event::Event::Progress(message)),
+                    uwu_core::Event::Log(message) => Ok(event::Event::Log(message)),
+                    uwu_core::Event::Done(Ok(payload)) => {
+                        let reloaded = if reloads {
+                            client.send(Command::CheckAlive).await.map(|_| ())
+                        } else {
+                            Ok(())
+                        };
+                        reloaded.map(|()| event::Event::Result(payload.to_string()))
+                    }
+                    uwu_core::Event::Done(Err(e)) => Err(e),
+                };
+
+                let event = event
+                    .map(|event| proto::Event { event: Some(event) })
+                    .map_err(to_status);
+                // the caller went away
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Response::new(ReceiverStream::new(rx))
+    }
+}
+
+#[tonic::async_trait]
+impl Editor for Service {
+    type PlayStream = Events;
+    type StopStream = Events;
+    type PauseStream = Events;
+    type StepStream = Events;
+    type RefreshStream = Events;
+    type BuildStream = Events;
+    type TestStream = Events;
+    type BuildPlayerStream = Events;
+    type BuildBundlesStream = Events;
+    type ExecStream = Events;
+    type MenuStream = Events;
+    type LogsStream = ReceiverStream<Result<LogMessage, Status>>;
+
+    async fn play(&self, request: Request<PlayRequest>) -> Result<Response<Events>, Status> {
+        let request = request.into_inner();
+        let play = Command::Play {
+            scene: request.scene,
+            fast: request.fast,
+        };
+        Ok(self.run(play, true))
+    }
+
+    async fn stop(&self, _: Request<Empty>) -> Result<Response<Events>, Status> {
+        Ok(self.run(Command::Stop, false))
+    }
+
+    async fn pause(&self, _: Request<Empty>) -> Result<Response<Events>, Status> {
+        Ok(self.run(Command::Pause, false))
+    }
+
+    async fn step(&self, _: Request<Empty>) -> Result<Response<Events>, Status> {
+        Ok(self.run(Command::Step, false))
+    }
+
+    async fn refresh(&self, _: Request<Empty>) -> Result<Response<Events>, Status> {
+        Ok(self.run(Command::Refresh, true))
+    }
+
+    async fn build(&self, _: Request<Empty>) -> Result<Response<Events>, Status> {
+        Ok(self.run(Command::Build, false))
+    }
+
+    async fn test(&self, request: Request<TestRequest>) -> Result<Response<Events>, Status> {
+        let request = request.into_inner();
+        let mode = match request.mode.as_str() {
+            "" => "editmode".to_string(),
+            "editmode" | "playmode" => request.mode,
+            mode => {
+                return Err(Status::invalid_argument(format!(
+                    "Unknown mode '{}', use editmode or playmode",
+                    mode
+                )))
+            }
+        };
+
+        let test = Command::Test {
+            mode,
+            filter: request.filter,
+            xml: false,
+        };
+        Ok(self.run(test, false))
+    }
+
+    async fn build_player(
+        &self,
+        request: Request<BuildPlayerRequest>,
+    ) -> Result<Response<Events>, Status> {
+        let request = request.into_inner();
+        let build = Command::BuildPlayer {
+            target: request.target,
+            output: PathBuf::from(request.output),
+            development: request.development,
+            report: false,
+        };
+        Ok(self.run(build, false))
+    }
+
+    async fn build_bundles(
+        &self,
+        request: Request<BuildBundlesRequest>,
+    ) -> Result<Response<Events>, Status> {
+        let request = request.into_inner();
+        let build = Command::BuildBundles {
+            output: PathBuf::from(request.output),
+            target: request.target,
+        };
+        Ok(self.run(build, false))
+    }
+
+    async fn exec(&self, request: Request<ExecRequest>) -> Result<Response<Events>, Status> {
+        let request = request.into_inner();
+        let exec = Command::Exec {
+            method: request.method,
+            args: request.args,
+        };
+        Ok(self.run(exec, false))
+    }
+
+    async fn menu(&self, request: Request<MenuRequest>) -> Result<Response<Events>, Status> {
+        let path = request.into_inner().path;
+        Ok(self.run(Command::Menu { path }, false))
+    }
+
+    async fn compiler_messages(
+        &self,
+        _: Request<Empty>,
+    ) -> Result<Response<CompilerMessageList>, Status> {
+        let payload = self
+            .client
+            .send(Command::CompilerMessages)
+            .await
+            .map_err(to_status)?;
+        let compiler: protocol::CompilerMessages = decode_payload(payload).map_err(to_status)?;
+
+        let messages = compiler
+            .messages
+            .into_iter()
+            .map(|message| CompilerMessage {
+                file: message.file,
+                line: message.line,
+                column: message.column,
+                kind: message.kind,
+                message: message.message,
+            })
+            .collect();
+        Ok(Response::new(CompilerMessageList { messages }))
+    }
+
+    async fn status(&self, _: Request<Empty>) -> Result<Response<proto::EditorStatus>, Status> {
+        let status = self.client.status().await.map_err(to_status)?;
+
+        Ok(Response::new(proto::EditorStatus {
+            compiling: status.compiling,
+            importing: status.importing,
+            playing: status.playing,
+            paused: status.paused,
+        }))
+    }
+
+    async fn logs(
+        &self,
+        request: Request<LogsRequest>,
+    ) -> Result<Response<Self::LogsStream>, Status> {
+        let request = request.into_inner();
+        let level = match request.level.as_str() {
+            "" | "info" => "info",
+            "warn" => "warn",
+            "error" => "error",
+            level => {
+                return Err(Status::invalid_argument(format!(
+                    "Unknown level '{}', use info, warn or error",
+                    level
+                )))
+            }
+        };
+
+        let (tx, rx) = mpsc::channel(64);
+        let mut logs = self.client.logs(level, request.follow);
+        tokio::spawn(async move {
+            while let Some(log) = logs.next().await {
+                let log = log
+                    .map(|log| LogMessage {
+                        kind: log.kind,
+                        message: log.message,
+                        stack: log.stack,
+                    })
+                    .map_err(to_status);
+                if tx.send(log).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Serves proto/uwu.proto, for build orchestrators that speak gRPC
+pub async fn serve(addr: SocketAddr, client: Arc<AsyncClient>) -> anyhow::Result<()> {
+    log::info!("Serving {} over gRPC on {}", client.endpoint(), addr);
+
+    tonic::transport::Server::builder()
+        .add_service(EditorServer::new(Service { client }))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! uwu hook install: git hooks that tell the editor about what git changed, so that it
+//! doesn't wait for someone to focus it
+
+use crate::print_result;
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// how uwu recognizes its own hooks, which it can replace
+const MARKER: &str = "# Installed by uwu hook install";
+
+#[derive(Debug, Serialize)]
+struct Hook {
+    name: &'static str,
+    path: PathBuf,
+    command: String,
+}
+
+fn git(project: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(project)
+        .output()
+        .context("Could not run git, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "{} is not in a git repository: {}",
+            project.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Hooks run from the root of the working tree, and they never fail what git does: the
+// editor may well be closed
+fn script(exe: &Path, project: &Path, command: &str) -> String {
+    format!(
+        "#!/bin/sh\n{}\n\"{}\" --project \"{}\" {} || true\n",
+        MARKER,
+        exe.display(),
+        project.display(),
+        command
+    )
+}
+
+/// Writes a hook for each (hook, uwu command) pair. Hooks that uwu didn't write are only
+/// replaced with force
+pub fn install(
+    project: &Path,
+    hooks: &[(&'static str, String)],
+    force: bool,
+) -> anyhow::Result<()> {
+    for (name, command) in hooks {
+        match shell_words::split(command) {
+            Ok(words) if !words.is_empty() => {}
+            _ => bail!("--{} needs an uwu command, like refresh", name),
+        }
+    }
+
+    // core.hooksPath and worktrees move the hooks, git knows where they are
+    let hooks_dir = PathBuf::from(git(project, &["rev-parse", "--git-path", "hooks"])?);
+    let hooks_dir = project.join(hooks_dir);
+    let root = PathBuf::from(git(project, &["rev-parse", "--show-toplevel"])?);
+
+    let project = project
+        .canonicalize()
+        .with_context(|| format!("No project at {}", project.display()))?;
+    let root = root.canonicalize().unwrap_or(root);
+    let relative = match project.strip_prefix(&root) {
+        Ok(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => project.clone(),
+    };
+    let exe = std::env::current_exe().context("Could not find the uwu executable")?;
+
+    let mut installed = vec![];
+    for (name, command) in hooks {
+        let path = hooks_dir.join(name);
+        if let Ok(existing) = fs::read_to_string(&path) {
+            if !existing.contains(MARKER) && !force {
+                bail!(
+                    "{} already exists, and uwu didn't write it. Pass --force to replace it",
+                    path.display()
+                );
+            }
+        }
+
+        fs::create_dir_all(&hooks_dir)
+            .and_then(|()| fs::write(&path, script(&exe, &relative, command)))
+            .with_context(|| format!("Could not write {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        }
+
+        installed.push(Hook {
+            name,
+            path,
+            command: command.clone(),
+        });
+    }
+
+    print_result(&installed, |installed| {
+        for hook in installed {
+            println!("{}: uwu {}", hook.name, hook.command);
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,693 @@
+use crate::{
+    config,
+    daemon::{self, StateGuard},
+    discovery, ID_SOURCE,
+};
+use anyhow::{bail, Context};
+use once_cell::sync::OnceCell;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use uwu_core::{
+    auth,
+    encoding::Encoding,
+    id64::Id64,
+    protocol::{Command, Request},
+    send::{self, Observer, Settings},
+    transport::Endpoint,
+    UwuError,
+};
+
+// how often the daemon looks for editors that started or went away
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(5);
+const DISCOVERY_TIMEOUT: Duration = Duration::from_millis(500);
+// an editor that doesn't answer this many discoveries in a row is offline
+const MAX_MISSED: u32 = 3;
+// a client that doesn't send its call by then doesn't hold a task for nothing
+const CALL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Written into ~/.config/uwu by `uwu daemon`, so that every uwu of the user can find it
+#[derive(Debug, Deserialize, Serialize)]
+struct State {
+    pid: u32,
+    port: u16,
+    /// Sent along every call, so that other users of the machine can't make any
+    key: String,
+}
+
+fn state_path() -> Option<PathBuf> {
+    config::user_dir().map(|dir| dir.join("daemon.json"))
+}
+
+fn read_state() -> Option<State> {
+    let path = state_path()?;
+    let contents = std::fs::read(&path).ok()?;
+
+    match serde_json::from_slice(&contents) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            log::debug!("Invalid daemon state {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// What a client asks, the first and only line of its connection
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Call {
+    // a handshake, unless the daemon already knows the editor
+    Connect {
+        addr: SocketAddr,
+        token: Option<String>,
+        max_attempts: Option<u32>,
+    },
+    // runs once the commands sent to the editor before it are done
+    Send {
+        addr: SocketAddr,
+        token: Option<String>,
+        command: Command,
+        /// The id of the client's request, so that --seed gives the same ids either way
+        #[serde(default)]
+        id: Option<Id64>,
+        #[serde(flatten)]
+        policy: Policy,
+    },
+    Editors,
+    Stop,
+}
+
+// the --timeout, --max-wait and --retries of the client
+#[derive(Debug, Deserialize)]
+struct Policy {
+    timeout: Option<f64>,
+    max_wait: Option<f64>,
+    max_attempts: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    key: String,
+    #[serde(flatten)]
+    call: Call,
+}
+
+// What the daemon answers, one JSON line each. Done, Failed and Editors are the last
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Reply {
+    Waiting,
+    Progress {
+        message: String,
+        #[serde(default)]
+        percent: Option<f32>,
+    },
+    Log {
+        message: String,
+    },
+    Done {
+        result: serde_json::Value,
+    },
+    Failed {
+        /// One of UwuError::kind, so that the client fails the same way
+        kind: Option<String>,
+        message: String,
+        stack: Option<String>,
+    },
+    Editors {
+        editors: Vec<Editor>,
+    },
+}
+
+fn failed(e: &anyhow::Error) -> Reply {
+    let kind = UwuError::of(e).map(|typed| typed.kind().to_string());
+    let (message, stack) = match UwuError::of(e) {
+        Some(UwuError::Timeout(message)) | Some(UwuError::ProtocolMismatch(message)) => {
+            (message.clone(), None)
+        }
+        Some(UwuError::Editor { message, stack }) => (message.clone(), stack.clone()),
+        _ => (format!("{:#}", e), None),
+    };
+    Reply::Failed {
+        kind,
+        message,
+        stack,
+    }
+}
+
+fn error_of(
+    addr: SocketAddr,
+    kind: Option<String>,
+    message: String,
+    stack: Option<String>,
+) -> anyhow::Error {
+    match kind.as_deref() {
+        Some("unreachable") => UwuError::Unreachable(Endpoint::Udp(addr)).into(),
+        Some("timeout") => UwuError::Timeout(message).into(),
+        Some("protocol_mismatch") => UwuError::ProtocolMismatch(message).into(),
+        Some("editor") => send::editor_error(message, stack),
+        _ => anyhow::Error::msg(message),
+    }
+}
+
+/// An editor that the daemon knows about, for `uwu daemon status`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Editor {
+    pub addr: SocketAddr,
+    /// Only known for the editors that answered a discovery
+    pub project: Option<PathBuf>,
+    pub unity_version: Option<String>,
+    /// What the editor was doing when it last answered a discovery
+    pub state: Option<String>,
+    pub online: bool,
+    /// The commands that are running or waiting for this editor
+    pub queued: usize,
+}
+
+// how to talk to an editor, negotiated once by the handshake
+struct Connection {
+    settings: Settings,
+    // commands to the same editor run one after the other, in the order they came
+    queue: tokio::sync::Mutex<()>,
+    queued: AtomicUsize,
+}
+
+#[derive(Default)]
+struct Known {
+    project: Option<PathBuf>,
+    unity_version: Option<String>,
+    state: Option<String>,
+    connection: Option<Arc<Connection>>,
+    missed: u32,
+}
+
+struct Hub {
+    key: String,
+    editors: Mutex<HashMap<SocketAddr, Known>>,
+}
+
+// forwards what the command reports to the client
+struct Forward(mpsc::UnboundedSender<Reply>);
+
+impl Observer for Forward {
+    fn waiting(&mut self, _request: &Request) {
+        let _ = self.0.send(Reply::Waiting);
+    }
+
+    fn progress(&mut self, message: &str) {
+        let _ = self.0.send(Reply::Progress {
+            message: message.to_string(),
+            percent: None,
+        });
+    }
+
+    fn percent(&mut self, message: &str, percent: f32) {
+        let _ = self.0.send(Reply::Progress {
+            message: message.to_string(),
+            percent: Some(percent),
+        });
+    }
+
+    fn log(&mut self, message: &str) {
+        let _ = self.0.send(Reply::Log {
+            message: message.to_string(),
+        });
+    }
+}
+
+fn decode_token(token: Option<String>) -> anyhow::Result<Option<Vec<u8>>> {
+    token
+        .map(|token| base64_url::decode(&token).context("The token is not valid base64"))
+        .transpose()
+}
+
+impl Hub {
+    // The connection to the editor at addr, after a handshake if there's none yet
+    async fn connect(
+        &self,
+        addr: SocketAddr,
+        token: Option<Vec<u8>>,
+        max_attempts: Option<u32>,
+    ) -> anyhow::Result<Arc<Connection>> {
+        let known = self
+            .editors
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .and_then(|known| known.connection.clone());
+        if let Some(connection) = known {
+            return Ok(connection);
+        }
+
+        let mut settings = Settings {
+            token,
+            ..Settings::default()
+        };
+        let id = ID_SOURCE.lock().unwrap().next_id();
+        send::handshake(&Endpoint::Udp(addr), &mut settings, id, max_attempts).await?;
+        log::info!("Connected to {}", addr);
+
+        let connection = Arc::new(Connection {
+            settings,
+            queue: tokio::sync::Mutex::new(()),
+            queued: AtomicUsize::new(0),
+        });
+        self.editors
+            .lock()
+            .unwrap()
+            .entry(addr)
+            .or_default()
+            .connection = Some(connection.clone());
+        Ok(connection)
+    }
+
+    async fn send(
+        &self,
+        addr: SocketAddr,
+        token: Option<Vec<u8>>,
+        command: Command,
+        id: Option<Id64>,
+        policy: Policy,
+        replies: &mpsc::UnboundedSender<Reply>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let max_attempts = policy.max_attempts;
+        let connection = self.connect(addr, token, max_attempts).await?;
+
+        let mut settings = connection.settings.clone();
+        settings.timeout = policy.timeout.map(Duration::from_secs_f64);
+        settings.max_wait = policy.max_wait.map(Duration::from_secs_f64);
+        let request = Request {
+            id: id.unwrap_or_else(|| ID_SOURCE.lock().unwrap().next_id()),
+            cmd: command,
+        };
+
+        connection.queued.fetch_add(1, Ordering::Relaxed);
+        let result = {
+            let _turn = connection.queue.lock().await;
+            log::info!("{:?} to {}", request.cmd, addr);
+            let mut forward = Forward(replies.clone());
+            send::send(
+                &Endpoint::Udp(addr),
+                &settings,
+                &request,
+                max_attempts,
+                &mut forward,
+            )
+            .await
+        };
+        connection.queued.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+
+    fn update(&self, instances: Vec<discovery::Instance>) {
+        let mut editors = self.editors.lock().unwrap();
+
+        // only the discovered editors can go missing, the others are never looked for
+        for known in editors.values_mut().filter(|known| known.project.is_some()) {
+            known.missed += 1;
+        }
+        for instance in instances {
+            let addr = instance.addr.expect("Filled in by discover");
+            let known = editors.entry(addr).or_default();
+            if known.project.is_none() || known.missed >= MAX_MISSED {
+                log::info!("Found {} at {}", instance.project.display(), addr);
+            }
+            known.project = Some(instance.project);
+            known.unity_version = Some(instance.unity_version);
+            known.state = Some(instance.state);
+            known.missed = 0;
+        }
+
+        for (addr, known) in editors.iter_mut() {
+            // it may come back with another plugin, which needs a handshake of its own
+            if known.missed == MAX_MISSED && known.connection.take().is_some() {
+                log::info!("Lost {}", addr);
+            }
+        }
+    }
+
+    // the online editors that aren't connected yet, and the tokens of their projects
+    fn unconnected(&self) -> Vec<(SocketAddr, Option<Vec<u8>>)> {
+        self.editors
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, known)| known.connection.is_none() && known.missed == 0)
+            .filter_map(|(addr, known)| {
+                let project = known.project.as_ref()?;
+                Some((*addr, auth::read_token(project).ok().flatten()))
+            })
+            .collect()
+    }
+
+    fn editors(&self) -> Vec<Editor> {
+        let mut editors: Vec<Editor> = self
+            .editors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(addr, known)| Editor {
+                addr: *addr,
+                project: known.project.clone(),
+                unity_version: known.unity_version.clone(),
+                state: known.state.clone(),
+                online: known.missed < MAX_MISSED,
+                queued: known
+                    .connection
+                    .as_ref()
+                    .map_or(0, |connection| connection.queued.load(Ordering::Relaxed)),
+            })
+            .collect();
+        editors.sort_by_key(|editor| editor.addr);
+        editors
+    }
+}
+
+// Looks for editors for as long as the daemon runs, and connects to the new ones, so
+// that their first command doesn't wait for a handshake
+async fn discover(hub: Arc<Hub>) {
+    loop {
+        match discovery::discover(DISCOVERY_TIMEOUT).await {
+            Ok(instances) => hub.update(instances),
+            Err(e) => log::warn!("Could not look for editors: {:#}", e),
+        }
+
+        for (addr, token) in hub.unconnected() {
+            if let Err(e) = hub.connect(addr, token, Some(1)).await {
+                log::debug!("Could not connect to {}: {:#}", addr, e);
+            }
+        }
+
+        tokio::time::sleep(DISCOVERY_INTERVAL).await;
+    }
+}
+
+// Answers one client, streaming the replies as they come. Returns whether to stop
+async fn handle(hub: Arc<Hub>, stream: TcpStream) -> anyhow::Result<bool> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    tokio::time::timeout(CALL_TIMEOUT, BufReader::new(reader).read_line(&mut line))
+        .await
+        .context("The client didn't send its call in time")??;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let work = async move {
+        let envelope: Envelope = match serde_json::from_str(&line) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                let _ = tx.send(failed(&anyhow::format_err!("Invalid call: {}", e)));
+                return false;
+            }
+        };
+        if envelope.key != hub.key {
+            let _ = tx.send(failed(&anyhow::format_err!("Wrong key")));
+            return false;
+        }
+
+        let mut stop = false;
+        let reply = match envelope.call {
+            Call::Connect {
+                addr,
+                token,
+                max_attempts,
+            } => match decode_token(token) {
+                Ok(token) => hub
+                    .connect(addr, token, max_attempts)
+                    .await
+                    .map(|connection| {
+                        let settings = &connection.settings;
+                        let encoding = match settings.encoding {
+                            Encoding::Json => "json",
+                            Encoding::MessagePack => "msgpack",
+                        };
+                        let rtt = settings.rtt.map(|rtt| rtt.as_secs_f64());
+                        Reply::Done {
+                            result: json!({ "encoding": encoding, "rtt": rtt }),
+                        }
+                    }),
+                Err(e) => Err(e),
+            },
+            Call::Send {
+                addr,
+                token,
+                command,
+                id,
+                policy,
+            } => match decode_token(token) {
+                Ok(token) => hub
+                    .send(addr, token, command, id, policy, &tx)
+                    .await
+                    .map(|result| Reply::Done { result }),
+                Err(e) => Err(e),
+            },
+            Call::Editors => Ok(Reply::Editors {
+                editors: hub.editors(),
+            }),
+            Call::Stop => {
+                stop = true;
+                Ok(Reply::Done {
+                    result: serde_json::Value::Null,
+                })
+            }
+        };
+
+        let _ = tx.send(reply.unwrap_or_else(|e| failed(&e)));
+        stop
+    };
+    // a client that went away doesn't cancel its command, the editor runs it anyway
+    let write = async move {
+        while let Some(reply) = rx.recv().await {
+            let mut line = serde_json::to_vec(&reply)?;
+            line.push(b'\n');
+            writer.write_all(&line).await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let (stop, written) = tokio::join!(work, write);
+    if let Err(e) = written {
+        log::debug!("A client went away: {}", e);
+    }
+    Ok(stop)
+}
+
+/// Keeps in touch with every editor on the network, and sends the commands of the other
+/// uwu processes of this user for them, one at a time for each editor. Returns when
+/// asked to stop
+pub async fn serve() -> anyhow::Result<()> {
+    let path = state_path().context("Could not tell where the home folder is")?;
+    if let Some(state) = read_state() {
+        if call(&state, json!({ "op": "editors" }), |_| {})
+            .await
+            .is_ok()
+        {
+            bail!("A daemon is already running (pid {})", state.pid);
+        }
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let state = State {
+        pid: std::process::id(),
+        port: listener.local_addr()?.port(),
+        key: format!("{:032x}", rand::thread_rng().gen::<u128>()),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create {}", dir.display()))?;
+    }
+    daemon::write_private(&path, &serde_json::to_vec(&state)?)
+        .with_context(|| format!("Could not write {}", path.display()))?;
+    let _guard = StateGuard(path);
+    log::info!("Serving on 127.0.0.1:{}", state.port);
+
+    let hub = Arc::new(Hub {
+        key: state.key,
+        editors: Mutex::new(HashMap::new()),
+    });
+    tokio::spawn(discover(hub.clone()));
+
+    let (stop_tx, mut stop_rx) = mpsc::channel(1);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                let hub = hub.clone();
+                let stop_tx = stop_tx.clone();
+                tokio::spawn(async move {
+                    match handle(hub, stream).await {
+                        Ok(true) => {
+                            let _ = stop_tx.send(()).await;
+                        }
+                        Ok(false) => {}
+                        Err(e) => log::debug!("A call failed: {:#}", e),
+                    }
+                });
+            }
+            _ = stop_rx.recv() => {
+                log::info!("Stopped by uwu daemon stop");
+                return Ok(());
+            }
+        }
+    }
+}
+
+// the daemon that the commands go through, looked up once. None without one, or with
+// --no-daemon
+static DAEMON: OnceCell<Option<State>> = OnceCell::new();
+
+pub fn init(enabled: bool) {
+    let state = if enabled { read_state() } else { None };
+    if let Some(state) = &state {
+        log::debug!(
+            "Sending the commands through the daemon (pid {})",
+            state.pid
+        );
+    }
+    DAEMON
+        .set(state)
+        .expect("The daemon is only looked up once");
+}
+
+// Makes a call, handing every reply to on_reply, and returns the last one
+async fn call(
+    state: &State,
+    mut message: serde_json::Value,
+    mut on_reply: impl FnMut(Reply),
+) -> anyhow::Result<Reply> {
+    message["key"] = json!(state.key);
+    let stream = TcpStream::connect(("127.0.0.1", state.port)).await?;
+    let (reader, mut writer) = stream.into_split();
+    writer
+        .write_all(format!("{}\n", message).as_bytes())
+        .await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        match serde_json::from_str(&line).context("Unexpected reply from the daemon")? {
+            reply @ Reply::Done { .. }
+            | reply @ Reply::Failed { .. }
+            | reply @ Reply::Editors { .. } => return Ok(reply),
+            reply => on_reply(reply),
+        }
+    }
+    bail!("The daemon went away")
+}
+
+// A call for the editor at addr. None if there's no daemon, or it can't be reached, in
+// which case the CLI talks to the editor itself
+async fn call_for(
+    addr: SocketAddr,
+    request: serde_json::Value,
+    on_reply: impl FnMut(Reply),
+) -> Option<anyhow::Result<serde_json::Value>> {
+    let state = DAEMON.get()?.as_ref()?;
+
+    match call(state, request, on_reply).await {
+        Ok(Reply::Done { result }) => Some(Ok(result)),
+        Ok(Reply::Failed {
+            kind,
+            message,
+            stack,
+        }) => Some(Err(error_of(addr, kind, message, stack))),
+        Ok(_) => Some(Err(anyhow::format_err!("Unexpected reply from the daemon"))),
+        // dead without cleaning up, nothing was sent
+        Err(e)
+            if e.downcast_ref::<std::io::Error>().map(|e| e.kind())
+                == Some(std::io::ErrorKind::ConnectionRefused) =>
+        {
+            log::debug!("The daemon is gone, talking to the editor directly");
+            None
+        }
+        Err(e) => Some(Err(e)),
+    }
+}
+
+/// Like send::handshake, through the daemon, which only does it for editors it doesn't
+/// know yet. None if there's no daemon
+pub async fn handshake(
+    addr: SocketAddr,
+    settings: &mut Settings,
+    max_attempts: Option<u32>,
+) -> Option<anyhow::Result<()>> {
+    let connect = json!({
+        "op": "connect",
+        "addr": addr,
+        "token": settings.token.as_ref().map(base64_url::encode),
+        "max_attempts": max_attempts,
+    });
+    let result = match call_for(addr, connect, |_| {}).await? {
+        Ok(result) => result,
+        Err(e) => return Some(Err(e)),
+    };
+
+    if let Some(encoding) = result["encoding"].as_str().and_then(Encoding::from_name) {
+        settings.encoding = encoding;
+    }
+    settings.rtt = result["rtt"].as_f64().map(Duration::from_secs_f64);
+    Some(Ok(()))
+}
+
+/// Like send::send, through the daemon, which queues the request behind the others for
+/// the same editor. None if there's no daemon
+pub async fn send(
+    addr: SocketAddr,
+    settings: &Settings,
+    request: &Request,
+    max_attempts: Option<u32>,
+    observer: &mut dyn Observer,
+) -> Option<anyhow::Result<serde_json::Value>> {
+    let send = json!({
+        "op": "send",
+        "addr": addr,
+        "token": settings.token.as_ref().map(base64_url::encode),
+        "command": request.cmd,
+        "id": request.id,
+        "timeout": settings.timeout.map(|timeout| timeout.as_secs_f64()),
+        "max_wait": settings.max_wait.map(|max_wait| max_wait.as_secs_f64()),
+        "max_attempts": max_attempts,
+    });
+
+    call_for(addr, send, |reply| match reply {
+        Reply::Waiting => observer.waiting(request),
+        Reply::Progress {
+            message,
+            percent: Some(percent),
+        } => observer.percent(&message, percent),
+        Reply::Progress { message, .. } => observer.progress(&message),
+        Reply::Log { message } => observer.log(&message),
+        _ => {}
+    })
+    .await
+}
+
+fn running() -> anyhow::Result<State> {
+    read_state().context("No daemon is running, start one with uwu daemon")
+}
+
+/// What `uwu daemon status` prints
+pub async fn editors() -> anyhow::Result<Vec<Editor>> {
+    match call(&running()?, json!({ "op": "editors" }), |_| {}).await? {
+        Reply::Editors { editors } => Ok(editors),
+        _ => bail!("Unexpected reply from the daemon"),
+    }
+}
+
+pub async fn stop() -> anyhow::Result<()> {
+    call(&running()?, json!({ "op": "stop" }), |_| {}).await?;
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! uwu install: the plugin is built into uwu, so each uwu installs the plugin that speaks
+//! its protocol
+
+use crate::{discovery, print_result};
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const CLIENT: &str = include_str!("../UWUClient.cs");
+const GAME_API: &str = include_str!("../UWU.cs");
+
+struct PluginFile {
+    name: &'static str,
+    contents: &'static str,
+    /// Where it goes if the project doesn't have it yet
+    default_dir: &'static str,
+}
+
+const FILES: &[PluginFile] = &[
+    PluginFile {
+        name: "UWUClient.cs",
+        contents: CLIENT,
+        default_dir: "Assets/Editor/uwu",
+    },
+    // game scripts call it, so it must not be in an Editor folder
+    PluginFile {
+        name: "UWU.cs",
+        contents: GAME_API,
+        default_dir: "Assets/uwu",
+    },
+];
+
+/// A file of the plugin, anywhere in Assets or in an embedded package
+pub fn find_file(project: &Path, name: &str) -> Option<PathBuf> {
+    ["Assets", "Packages"]
+        .iter()
+        .map(|dir| project.join(dir))
+        .filter(|dir| dir.is_dir())
+        .flat_map(|dir| {
+            ignore::WalkBuilder::new(dir)
+                .standard_filters(false)
+                .build()
+        })
+        .filter_map(Result::ok)
+        .map(ignore::DirEntry::into_path)
+        .find(|path| path.file_name().map_or(false, |file| file == name))
+}
+
+/// The version of the plugin in the source of UWUClient.cs, older plugins don't have one
+pub fn plugin_version(source: &str) -> Option<String> {
+    let version = regex::Regex::new(r#"PluginVersion\s*=\s*"([^"]*)""#).expect("Valid regex");
+    Some(version.captures(source)?[1].to_string())
+}
+
+// Versions like 0.1.0, anything else sorts first
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(a).cmp(&parse(b))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Change {
+    Installed,
+    Updated,
+    Unchanged,
+}
+
+#[derive(Debug, Serialize)]
+struct Installed {
+    path: PathBuf,
+    change: Change,
+}
+
+#[derive(Debug, Serialize)]
+struct Installation {
+    version: String,
+    /// The version that the project had, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<String>,
+    files: Vec<Installed>,
+}
+
+/// Installs the plugin of this uwu into the project, or updates the one it has. Refuses to
+/// replace a newer plugin unless forced
+pub fn install(project: &Path, force: bool) -> anyhow::Result<()> {
+    if discovery::find_project(project).as_deref() != Some(project) {
+        bail!(
+            "{} is not a Unity project, pass one to --project",
+            project.display()
+        );
+    }
+
+    let version = plugin_version(CLIENT).expect("The plugin of uwu has a version");
+    let previous = find_file(project, "UWUClient.cs")
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|source| plugin_version(&source).unwrap_or_else(|| "unknown".to_string()));
+
+    if let Some(previous) = &previous {
+        if compare_versions(previous, &version) == Ordering::Greater && !force {
+            bail!(
+                "The project has plugin {}, which is newer than the {} of this uwu. \
+                Update uwu with `uwu self-update`, or pass --force",
+                previous,
+                version
+            );
+        }
+    }
+
+    let mut files = vec![];
+    for file in FILES {
+        let existing = find_file(project, file.name);
+        let path = existing
+            .clone()
+            .unwrap_or_else(|| project.join(file.default_dir).join(file.name));
+
+        let change = match existing.map(fs::read_to_string).transpose()? {
+            Some(contents) if contents == file.contents => Change::Unchanged,
+            Some(_) => Change::Updated,
+            None => Change::Installed,
+        };
+        if !matches!(change, Change::Unchanged) {
+            let dir = path.parent().expect("Files are in a folder");
+            fs::create_dir_all(dir)
+                .and_then(|()| fs::write(&path, file.contents))
+                .with_context(|| format!("Could not write {}", path.display()))?;
+        }
+
+        let path = path.strip_prefix(project).unwrap_or(&path).to_path_buf();
+        files.push(Installed { path, change });
+    }
+
+    let installation = Installation {
+        version,
+        previous,
+        files,
+    };
+    print_result(&installation, |installation| {
+        for file in &installation.files {
+            let change = match file.change {
+                Change::Installed => "installed",
+                Change::Updated => "updated",
+                Change::Unchanged => "up to date",
+            };
+            println!("{}: {}", file.path.display(), change);
+        }
+
+        match &installation.previous {
+            Some(previous) if *previous != installation.version => println!(
+                "Plugin {}, was {}. Unity compiles it once the editor is focused, or run \
+                `uwu refresh`",
+                installation.version, previous
+            ),
+            Some(_) => println!("Plugin {}, like uwu", installation.version),
+            None => println!(
+                "Plugin {}. Unity compiles it once the editor is focused",
+                installation.version
+            ),
+        }
+    });
+    Ok(())
+}
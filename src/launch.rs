@@ -0,0 +1,244 @@
+use crate::discovery::PortFile;
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use sysinfo::{Pid, PidExt, ProcessExt, System, SystemExt};
+
+/// The editor version that the project was last opened with
+pub fn project_version(project: &Path) -> anyhow::Result<String> {
+    let path = project.join("ProjectSettings").join("ProjectVersion.txt");
+    let contents = std::fs::read_to_string(&path).with_context(|| {
+        format!(
+            "Could not read {}. Is this a Unity project?",
+            path.display()
+        )
+    })?;
+
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("m_EditorVersion:"))
+        .map(|version| version.trim().to_owned())
+        .with_context(|| format!("No m_EditorVersion in {}", path.display()))
+}
+
+// where Unity Hub keeps its settings
+fn hub_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|appdata| PathBuf::from(appdata).join("UnityHub"))
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join("Library")
+                .join("Application Support")
+                .join("UnityHub")
+        })
+    } else {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("UnityHub"))
+    }
+}
+
+// the folders that the Hub installs editors into, one subfolder per version
+fn install_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+
+    // set when the install location was changed in the Hub preferences
+    if let Some(config) = hub_config_dir() {
+        let secondary = std::fs::read(config.join("secondaryInstallPath.json"))
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<String>(&contents).ok())
+            .filter(|path| !path.is_empty());
+        if let Some(path) = secondary {
+            dirs.push(PathBuf::from(path));
+        }
+    }
+
+    if cfg!(windows) {
+        dirs.push(PathBuf::from(r"C:\Program Files\Unity\Hub\Editor"));
+    } else if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/Applications/Unity/Hub/Editor"));
+    } else if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join("Unity").join("Hub").join("Editor"));
+    }
+
+    dirs
+}
+
+// Editors added to the Hub by hand are listed in editors.json, or editors-v2.json for
+// newer Hubs. Both have objects with a version and a location, somewhere
+fn located_editors(value: &serde_json::Value, editors: &mut Vec<(String, PathBuf)>) {
+    match value {
+        serde_json::Value::Object(object) => {
+            let version = object.get("version").and_then(|version| version.as_str());
+            let location = object
+                .get("location")
+                .and_then(|location| location.as_array())
+                .and_then(|location| location.first())
+                .and_then(|location| location.as_str());
+
+            if let (Some(version), Some(location)) = (version, location) {
+                editors.push((version.to_owned(), PathBuf::from(location)));
+            }
+
+            for value in object.values() {
+                located_editors(value, editors);
+            }
+        }
+        serde_json::Value::Array(array) => {
+            for value in array {
+                located_editors(value, editors);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The executable inside an install folder or an app bundle
+fn executable(location: &Path) -> PathBuf {
+    if location.extension().map_or(false, |ext| ext == "app") {
+        location.join("Contents").join("MacOS").join("Unity")
+    } else if location.is_dir() {
+        let editor = location.join("Editor");
+        if cfg!(windows) {
+            editor.join("Unity.exe")
+        } else if cfg!(target_os = "macos") {
+            location
+                .join("Unity.app")
+                .join("Contents")
+                .join("MacOS")
+                .join("Unity")
+        } else {
+            editor.join("Unity")
+        }
+    } else {
+        location.to_owned()
+    }
+}
+
+/// Looks for an editor of this exact version among the ones Unity Hub knows about
+pub fn find_editor(version: &str) -> anyhow::Result<PathBuf> {
+    let mut candidates: Vec<PathBuf> = install_dirs()
+        .into_iter()
+        .map(|dir| executable(&dir.join(version)))
+        .collect();
+
+    if let Some(config) = hub_config_dir() {
+        for name in &["editors-v2.json", "editors.json"] {
+            let editors = std::fs::read(config.join(name))
+                .ok()
+                .and_then(|contents| serde_json::from_slice(&contents).ok());
+
+            if let Some(editors) = editors {
+                let mut located = vec![];
+                located_editors(&editors, &mut located);
+
+                candidates.extend(
+                    located
+                        .into_iter()
+                        .filter(|(located_version, _)| located_version == version)
+                        .map(|(_, location)| executable(&location)),
+                );
+            }
+        }
+    }
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .with_context(|| {
+            format!(
+                "Unity {} is not installed. Install it with Unity Hub, or pass --unity-version",
+                version
+            )
+        })
+}
+
+// the editor itself, not the Hub or the helpers that it starts, like UnityShaderCompiler
+fn is_editor(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name == "unity" || name == "unity.exe"
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) if cfg!(windows) => {
+            a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+        }
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether an editor has the project open. Some(false) only when it's certain that none
+/// has: the editor of the port file is gone, or no editor was started with the project
+pub fn editor_running(project: &Path, port_file: Option<&PortFile>) -> Option<bool> {
+    let mut system = System::new();
+
+    if let Some(pid) = port_file.and_then(|port_file| port_file.pid) {
+        return Some(system.refresh_process(Pid::from_u32(pid)));
+    }
+
+    system.refresh_processes();
+    let mut editors = system
+        .processes()
+        .values()
+        .filter(|process| is_editor(process.name()))
+        .peekable();
+    if editors.peek().is_none() {
+        return Some(false);
+    }
+
+    let mut unknown = false;
+    for editor in editors {
+        let cmd = editor.cmd();
+        match cmd
+            .iter()
+            .position(|arg| arg.eq_ignore_ascii_case("-projectPath"))
+            .and_then(|index| cmd.get(index + 1))
+        {
+            Some(open) if same_path(Path::new(open), project) => return Some(true),
+            Some(_) => {}
+            // opened from the project picker, it could be any project
+            None => unknown = true,
+        }
+    }
+
+    if unknown {
+        None
+    } else {
+        Some(false)
+    }
+}
+
+/// Starts the editor in the background, it keeps running after uwu exits
+pub fn launch(editor: &Path, project: &Path) -> anyhow::Result<()> {
+    std::process::Command::new(editor)
+        .arg("-projectPath")
+        .arg(project)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .with_context(|| format!("Could not start {}", editor.display()))?;
+
+    Ok(())
+}
+
+/// Starts the editor without a window, for CI. Unlike launch it belongs to uwu: it's
+/// killed if uwu exits before quitting it. Its log goes to log_file
+pub fn launch_batch(
+    editor: &Path,
+    project: &Path,
+    log_file: &Path,
+) -> anyhow::Result<tokio::process::Child> {
+    tokio::process::Command::new(editor)
+        .arg("-batchmode")
+        .arg("-projectPath")
+        .arg(project)
+        .arg("-logFile")
+        .arg(log_file)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Could not start {}", editor.display()))
+}
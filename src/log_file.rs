@@ -0,0 +1,127 @@
+use anyhow::Context;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::OnceCell;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+// the file starts over past this size, and the older ones are kept next to it
+const MAX_SIZE: u64 = 10 * 1024 * 1024;
+const KEEP: usize = 3;
+
+static FILE: OnceCell<Mutex<RotatingFile>> = OnceCell::new();
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+// uwu.log.1 is the newest of the old ones
+fn old_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> std::io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for index in (1..KEEP).rev() {
+            let from = old_path(&self.path, index);
+            if from.exists() {
+                std::fs::rename(&from, old_path(&self.path, index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, old_path(&self.path, 1))?;
+
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size + line.len() as u64 > MAX_SIZE {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Logs to stderr like env_logger, and to the --log-file once it's open
+struct Logger {
+    stderr: env_logger::Logger,
+}
+
+// the debug logs of the dependencies are mostly noise
+fn is_ours(metadata: &Metadata<'_>) -> bool {
+    metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.stderr.enabled(metadata) || (FILE.get().is_some() && is_ours(metadata))
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.stderr.matches(record) {
+            self.stderr.log(record);
+        }
+
+        let file = match FILE.get() {
+            Some(file) if is_ours(record.metadata()) && record.level() <= Level::Debug => file,
+            _ => return,
+        };
+        let line = format!(
+            "{} {:<5} {}\n",
+            humantime::format_rfc3339_millis(SystemTime::now()),
+            record.level(),
+            record.args()
+        );
+        // there's nowhere to report that the log can't be written
+        let _ = file.lock().unwrap().write_line(&line);
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+    }
+}
+
+/// Installs the logger, with the level of stderr as set by --verbose, --quiet and RUST_LOG
+pub fn init(stderr: env_logger::Logger) {
+    log::set_max_level(stderr.filter());
+    log::set_boxed_logger(Box::new(Logger { stderr })).expect("The logger is only set once");
+}
+
+/// From here on, everything down to the debug logs also goes to path
+pub fn open(path: &Path) -> anyhow::Result<()> {
+    let file =
+        RotatingFile::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    FILE.set(Mutex::new(file))
+        .map_err(|_| anyhow::format_err!("The log file is only opened once"))?;
+
+    log::set_max_level(LevelFilter::Debug);
+    log::debug!(
+        "uwu {} started with {:?}",
+        env!("CARGO_PKG_VERSION"),
+        std::env::args().collect::<Vec<_>>()
+    );
+    Ok(())
+}
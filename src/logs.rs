@@ -0,0 +1,74 @@
+use crate::{json_output, retry_policy, settings, ID_SOURCE};
+use std::io::Write;
+use uwu_core::transport::Endpoint;
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+// With split, warnings and errors go to stderr like the ones of any other program
+fn print_log(kind: &str, message: &str, stack: Option<&str>, colors: bool, split: bool) {
+    // one object per line, before the JSON output of the command itself
+    if json_output() {
+        let log = serde_json::json!({ "kind": kind, "message": message, "stack": stack });
+        println!("{}", log);
+        return;
+    }
+
+    let color = match kind {
+        "error" => RED,
+        "warn" => YELLOW,
+        _ => "",
+    };
+
+    let mut out: Box<dyn Write> = if split && kind != "info" {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+    if colors && !color.is_empty() {
+        let _ = writeln!(out, "{}{}{}", color, message, RESET);
+    } else {
+        let _ = writeln!(out, "{}", message);
+    }
+
+    // stack traces are only worth the noise for errors
+    if let (Some(stack), "error") = (stack, kind) {
+        for line in stack.lines().filter(|line| !line.is_empty()) {
+            let _ = writeln!(out, "    {}", line);
+        }
+    }
+}
+
+/// Prints the console of the editor, see uwu_core::logs::subscribe
+pub async fn logs(endpoint: &Endpoint, level: &'static str, follow: bool) -> anyhow::Result<()> {
+    let colors = atty::is(atty::Stream::Stdout);
+
+    uwu_core::logs::subscribe(
+        endpoint,
+        &settings(),
+        &ID_SOURCE,
+        retry_policy().max_attempts,
+        level,
+        follow,
+        |log| print_log(&log.kind, &log.message, log.stack.as_deref(), colors, false),
+    )
+    .await
+}
+
+/// Prints the whole console of the editor until dropped, the warnings and the errors on
+/// stderr. For uwu run, where the console is the output of the game
+pub async fn forward(endpoint: &Endpoint) -> anyhow::Result<()> {
+    let colors = atty::is(atty::Stream::Stdout) && atty::is(atty::Stream::Stderr);
+
+    uwu_core::logs::subscribe(
+        endpoint,
+        &settings(),
+        &ID_SOURCE,
+        retry_policy().max_attempts,
+        "info",
+        true,
+        |log| print_log(&log.kind, &log.message, log.stack.as_deref(), colors, true),
+    )
+    .await
+}
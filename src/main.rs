@@ -1,268 +1,4220 @@
-mod id64;
+mod bench;
+mod broadcast;
+mod config;
+mod daemon;
+mod discovery;
+mod doctor;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod hook;
+mod hub;
+mod install;
+mod launch;
+mod log_file;
+mod logs;
+mod mcp;
+mod progress;
+mod repl;
+mod run;
+mod self_update;
+mod serve;
+mod service;
+mod snippet;
+mod stats;
+mod tail_log;
+mod telemetry;
+mod tui;
+mod view;
+mod watch;
+mod webhook;
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use clap::{
-    crate_authors, crate_description, crate_name, crate_version, App, AppSettings, Arg, SubCommand,
+    crate_authors, crate_description, crate_name, crate_version, App, AppSettings, Arg, ArgMatches,
+    SubCommand,
 };
-use id64::Id64;
-use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
-use once_cell::sync::Lazy;
+use discovery::PortFile;
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::{
-    net::{SocketAddr, UdpSocket},
-    path::PathBuf,
-    str::FromStr,
-    time::Duration,
+    ffi::OsString,
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::Instrument;
+use uwu_core::{
+    auth,
+    encoding::Encoding,
+    id64::IdSource,
+    mock::{MockServer, Scenario},
+    protocol::{
+        self, Alive, BuildOutput, BuildScenes, BuildTargetInfo, Bundles, Command, CompilerMessage,
+        CompilerMessages, ContentBuild, Defines, EditorStatus, ExecResult, FoundAssets,
+        GameObjectNode, HelloReply, Hierarchy, MenuItems, OpenedScene, Package, Packages,
+        PauseState, PlayModeResult, PlayModeSettings, PlayerBuild, Pref, Request, SavedScenes,
+        TestReport, TimeScaleState, Toggle, Transfer, PROTOCOL_VERSION,
+    },
+    record,
+    send::{self, decode_payload, Settings},
+    transport::{Endpoint, TransportKind},
+    AsyncClient, UwuError,
 };
 
 const UNITY_ADDR_STR: &str = "127.0.0.1:38910";
-const UNITY_ADDR: Lazy<SocketAddr> =
-    Lazy::new(|| SocketAddr::from_str(UNITY_ADDR_STR).expect("Failed to parse UNITY_ADDR_STR"));
-// very short timeout, this is supposed to be used over localhost
-const TIMEOUT: Duration = Duration::from_secs(5);
+// how long to wait for editors to answer a discovery request
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(1);
+// how often `uwu launch --wait` checks whether the editor is up
+const LAUNCH_POLL_DELAY: Duration = Duration::from_secs(2);
+// how long `uwu ci` gives the editor to quit once the commands are done
+const CI_QUIT_TIMEOUT: Duration = Duration::from_secs(60);
+// the commands that `uwu ci` can't run in the editor it starts
+const CI_UNAVAILABLE: &[&str] = &[
+    "ci",
+    "completions",
+    "daemon",
+    "doctor",
+    "hook",
+    "install",
+    "instances",
+    "launch",
+    "mcp",
+    "mock-server",
+    "quit",
+    "repl",
+    "replay",
+    "schema",
+    "self-update",
+    "serve",
+    "service",
+    "stats",
+    "tail-log",
+    "watch",
+];
+// how often `uwu wait-compile` checks whether the editor is done
+const COMPILE_POLL_DELAY: Duration = Duration::from_millis(500);
+
+// where request ids come from, can be seeded with --seed to make runs reproducible
+static ID_SOURCE: Lazy<Mutex<IdSource>> = Lazy::new(|| Mutex::new(IdSource::Sequential));
+
+// negotiated by the handshake, JSON until then
+static ENCODING: Lazy<Mutex<Encoding>> = Lazy::new(|| Mutex::new(Encoding::Json));
+
+// the token of the project, if the editor asks for signed requests
+static AUTH_TOKEN: OnceCell<Vec<u8>> = OnceCell::new();
+
+// round trip time measured by the handshake
+static RTT: Lazy<Mutex<Option<Duration>>> = Lazy::new(|| Mutex::new(None));
+
+/// Set from the command line with --timeout, --retries and --max-wait
+#[derive(Debug, Default)]
+struct RetryPolicy {
+    // replaces the timeout measured by the handshake
+    timeout: Option<Duration>,
+    // None keeps trying until the editor answers
+    max_attempts: Option<u32>,
+    // how long a command may run after the editor acknowledged it, None waits forever
+    max_wait: Option<Duration>,
+}
+
+static RETRY_POLICY: OnceCell<RetryPolicy> = OnceCell::new();
+
+/// How the commands print their results, set from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputMode {
+    Human,
+    /// --quiet, only errors are printed
+    Quiet,
+    /// --json, see JsonOutput
+    Json,
+}
+
+static OUTPUT_MODE: OnceCell<OutputMode> = OnceCell::new();
+// what the command returned, for --json
+static JSON_RESULT: Lazy<Mutex<serde_json::Value>> =
+    Lazy::new(|| Mutex::new(serde_json::Value::Null));
+// when uwu started, for the duration of --json
+static STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// How compile errors are printed, set from the command line with --format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    /// file:line:column: error: message, like gcc and rustc
+    Gcc,
+    /// file(line,column): error CS0000: message, that the $msCompile problem matcher of
+    /// VS Code and most IDEs understand
+    Msvc,
+    /// Like rustc, with the line of the source and a caret under the column
+    Pretty,
+}
+
+// unset, it's pretty on terminals and gcc for everything else
+static ERROR_FORMAT: OnceCell<ErrorFormat> = OnceCell::new();
+
+fn format_compiler_message(message: &CompilerMessage, stream: atty::Stream) -> String {
+    let format = match ERROR_FORMAT.get() {
+        Some(format) => *format,
+        None if atty::is(stream) => ErrorFormat::Pretty,
+        None => ErrorFormat::Gcc,
+    };
+    match format {
+        ErrorFormat::Gcc => message.to_string(),
+        ErrorFormat::Pretty => snippet::pretty(message, stream),
+        // the message of Unity already starts with the code, like CS0103: The name...
+        ErrorFormat::Msvc => format!(
+            "{}({},{}): {} {}",
+            message.file, message.line, message.column, message.kind, message.message
+        ),
+    }
+}
+
+fn output_mode() -> OutputMode {
+    OUTPUT_MODE.get().copied().unwrap_or(OutputMode::Human)
+}
+
+fn json_output() -> bool {
+    output_mode() == OutputMode::Json
+}
+
+// The result of a command, that print shows to humans. With --json it's kept instead,
+// and printed in the JSON output once the command is done
+fn print_result<T: Serialize>(result: &T, print: impl FnOnce(&T)) {
+    match output_mode() {
+        OutputMode::Human => print(result),
+        OutputMode::Quiet => {}
+        OutputMode::Json => {
+            *JSON_RESULT.lock().unwrap() =
+                serde_json::to_value(result).expect("Results have string keys")
+        }
+    }
+}
 
-static SOCKET: Lazy<UdpSocket> =
-    Lazy::new(|| UdpSocket::bind("127.0.0.1:0").expect("Failed to bind to a random port"));
+fn print_ok() {
+    print_result(&serde_json::Value::Null, |_| {
+        println!("{}", progress::ok_line(STARTED.elapsed()))
+    });
+}
 
+/// What --json prints once the command is done, instead of its usual output
 #[derive(Debug, Serialize)]
-enum Command {
-    Play,
-    CheckAlive,
-    Stop,
-    Refresh,
-    BackgroundRefresh,
-    Build,
+struct JsonOutput {
+    /// ok or error
+    status: &'static str,
+    exit_code: i32,
+    duration_secs: f64,
+    /// What the command returned, usually the payload that the editor sent
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonError>,
 }
 
 #[derive(Debug, Serialize)]
-struct Request {
-    id: Id64,
-    cmd: Command,
+struct JsonError {
+    message: String,
+    /// One of UwuError::kind, or cli for anything else
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stack: Option<String>,
+    /// How many compile errors there are, for compile_failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<usize>,
+}
+
+impl JsonError {
+    fn new(error: &anyhow::Error) -> Self {
+        let typed = UwuError::of(error);
+
+        let (stack, errors) = match typed {
+            Some(UwuError::Editor { stack, .. }) => (stack.clone(), None),
+            Some(UwuError::CompileFailed { errors }) => (None, Some(*errors)),
+            _ => (None, None),
+        };
+
+        JsonError {
+            message: format!("{:#}", error),
+            kind: typed.map_or("cli", UwuError::kind),
+            stack,
+            errors,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-enum Response {
-    Success,
-    Error,
-    Wait,
+fn print_json(exit_code: i32, error: Option<JsonError>) {
+    let output = JsonOutput {
+        status: if error.is_some() { "error" } else { "ok" },
+        exit_code,
+        duration_secs: STARTED.elapsed().as_secs_f64(),
+        result: JSON_RESULT.lock().unwrap().take(),
+        error,
+    };
+    println!(
+        "{}",
+        serde_json::to_string(&output).expect("The output is valid JSON")
+    );
 }
 
-// Send one message over UDP, and retry if it times out until ACK is received
-// This is needed because Unity may be recreating the socket, and the message could get lost
-fn send_reliable_blocking(request: &Request) -> anyhow::Result<()> {
-    SOCKET.set_read_timeout(Some(TIMEOUT))?;
+// The exit codes of every command but status, that scripts rely on. Never change them
+const EXIT_FAILURE: i32 = 1;
+const EXIT_UNREACHABLE: i32 = 2;
+const EXIT_EDITOR_ERROR: i32 = 5;
+const EXIT_COMPILE_FAILED: i32 = 6;
+const EXIT_TIMED_OUT: i32 = 7;
+const EXIT_PROTOCOL_MISMATCH: i32 = 8;
 
-    let msg = serde_json::to_vec(request)?;
+const EXIT_CODES_HELP: &str = "EXIT CODES:
+    0    Success
+    1    Any other failure, like invalid arguments
+    2    No editor answered
+    5    The editor failed to run the command
+    6    The scripts have compile errors
+    7    Timed out waiting for the editor
+    8    The plugin and uwu speak different versions of the protocol
 
-    log::debug!("Sending {}", String::from_utf8_lossy(&msg));
+    uwu status has exit codes of its own, see uwu help status";
 
-    // repeat until acknowledged
-    let mut recv_buf = [0; 1024];
-    loop {
-        // send the message
-        SOCKET.send_to(&msg, &*UNITY_ADDR)?;
-
-        // receive the response
-        match SOCKET.recv_from(&mut recv_buf) {
-            Ok((size, _src)) => {
-                // deserialize the response
-                let response: Response = serde_json::from_slice(&recv_buf[..size])?;
-
-                match response {
-                    // Success means that we're done
-                    Response::Success => {
-                        log::debug!("Response received");
-                        return Ok(());
-                    }
-                    // Wait means that we should receive Success or Error later.
-                    // Break the loop and wait for the next message
-                    Response::Wait => {
-                        break;
-                    }
-                    Response::Error => {
-                        bail!("Unity-side error");
-                    }
-                }
-            }
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::WouldBlock => {
-                    log::debug!("No ACK received within timeout, retrying");
-                }
-                _ => {
-                    return Err(e.into());
-                }
-            },
+fn exit_code(error: &anyhow::Error) -> i32 {
+    match UwuError::of(error) {
+        Some(UwuError::Unreachable(_)) => EXIT_UNREACHABLE,
+        Some(UwuError::Editor { .. }) => EXIT_EDITOR_ERROR,
+        Some(UwuError::CompileFailed { .. }) => EXIT_COMPILE_FAILED,
+        Some(UwuError::Timeout(_)) => EXIT_TIMED_OUT,
+        Some(UwuError::ProtocolMismatch(_)) => EXIT_PROTOCOL_MISMATCH,
+        None => EXIT_FAILURE,
+    }
+}
+
+// std::process::exit never gets to the end of main, where the JSON output is printed
+fn exit(code: i32) -> ! {
+    if json_output() {
+        print_json(code, None);
+    }
+    telemetry::shutdown();
+    std::process::exit(code);
+}
+
+fn retry_policy() -> &'static RetryPolicy {
+    RETRY_POLICY.get_or_init(RetryPolicy::default)
+}
+
+// What the handshake and the command line settled on, for the next request
+fn settings() -> Settings {
+    Settings {
+        encoding: *ENCODING.lock().unwrap(),
+        rtt: *RTT.lock().unwrap(),
+        timeout: retry_policy().timeout,
+        max_wait: retry_policy().max_wait,
+        token: AUTH_TOKEN.get().cloned(),
+    }
+}
+
+async fn fetch_transfer(endpoint: &Endpoint, transfer: &Transfer) -> anyhow::Result<Vec<u8>> {
+    send::fetch_transfer(transfer, |fetch| single_command(endpoint, fetch)).await
+}
+
+fn new_request(command: Command) -> Request {
+    Request {
+        // time ordered, so that the server can tell resends from new requests, or the same
+        // sequence on every run with --seed
+        id: ID_SOURCE.lock().unwrap().next_id(),
+        cmd: command,
+    }
+}
+
+async fn send_command(
+    endpoint: &Endpoint,
+    command: Command,
+    max_attempts: Option<u32>,
+) -> anyhow::Result<serde_json::Value> {
+    let request = new_request(command);
+    let mut observer = progress::Spinners::new(&request);
+    let settings = settings();
+    if let Endpoint::Udp(addr) = endpoint {
+        if let Some(result) =
+            hub::send(*addr, &settings, &request, max_attempts, &mut observer).await
+        {
+            return result;
+        }
+    }
+    send::send(endpoint, &settings, &request, max_attempts, &mut observer).await
+}
+
+// Only until the editor acknowledges the command, for --no-wait. Errors that come later,
+// like compile errors, are only in the console of the editor
+async fn send_no_wait(endpoint: &Endpoint, command: Command) -> anyhow::Result<()> {
+    let request = new_request(command);
+    send::send_ack(endpoint, &settings(), &request, retry_policy().max_attempts).await?;
+    Ok(())
+}
+
+async fn single_command(
+    endpoint: &Endpoint,
+    command: Command,
+) -> anyhow::Result<serde_json::Value> {
+    send_command(endpoint, command, retry_policy().max_attempts).await
+}
+
+// Make sure that the plugin speaks our protocol before sending any real command
+async fn handshake(endpoint: &Endpoint, max_attempts: Option<u32>) -> anyhow::Result<()> {
+    let mut settings = settings();
+    let daemon = match endpoint {
+        Endpoint::Udp(addr) => hub::handshake(*addr, &mut settings, max_attempts).await,
+        _ => None,
+    };
+    match daemon {
+        Some(result) => result?,
+        None => {
+            let id = ID_SOURCE.lock().unwrap().next_id();
+            send::handshake(endpoint, &mut settings, id, max_attempts).await?;
         }
     }
 
-    log::info!("Waiting for Unity...");
+    *ENCODING.lock().unwrap() = settings.encoding;
+    *RTT.lock().unwrap() = settings.rtt;
+    Ok(())
+}
+
+// The port file of --project is required, unless an editor that has it open answers
+// a discovery request instead. Without --project, the fallback project may have one
+async fn find_port_file(
+    matches: &ArgMatches<'_>,
+    fallback_project: &Path,
+) -> anyhow::Result<Option<PortFile>> {
+    let project = match matches.value_of("project").map(Path::new) {
+        Some(project) => project,
+        None => return discovery::read_port_file(fallback_project),
+    };
+
+    if let Some(port_file) = discovery::read_port_file(project)? {
+        return Ok(Some(port_file));
+    }
+
+    log::debug!(
+        "No port file at {}, asking the editors",
+        discovery::port_file_path(project).display()
+    );
 
-    // wait for the final message
-    SOCKET.set_read_timeout(None)?;
-    let (size, _src) = SOCKET.recv_from(&mut recv_buf)?;
+    let project = project
+        .canonicalize()
+        .with_context(|| format!("No Unity project at {}", project.display()))?;
 
-    // deserialize the response
-    let response: Response = serde_json::from_slice(&recv_buf[..size])?;
+    let instance = discovery::discover(DISCOVERY_TIMEOUT)
+        .await?
+        .into_iter()
+        .find(|instance| instance.project.canonicalize().ok().as_ref() == Some(&project))
+        .with_context(|| {
+            format!(
+                "No editor has {} open. Is the editor running with the uwu plugin?",
+                project.display()
+            )
+        })?;
 
-    match response {
-        // Success means that we're done
-        Response::Success => {
-            log::debug!("Final response received");
-            return Ok(());
+    Ok(Some(PortFile {
+        port: instance.port,
+        tcp_port: Some(instance.tcp_port),
+        ipc: instance.ipc,
+        pid: None,
+    }))
+}
+
+fn named_instance(name: &str, project: &Path) -> anyhow::Result<config::NamedInstance> {
+    let named = config::load(project)?
+        .instances
+        .remove(name)
+        .with_context(|| format!("No instance is named {}, see uwu instances name", name))?;
+
+    if let Some(project) = &named.project {
+        if !project.is_dir() {
+            bail!(
+                "{} is the editor of {}, which isn't there anymore",
+                name,
+                project.display()
+            );
+        }
+    }
+    Ok(named)
+}
+
+// The name that the editor got from uwu instances name, if any
+fn name_of<'a>(config: &'a config::Config, instance: &discovery::Instance) -> Option<&'a str> {
+    let project = instance.project.canonicalize().ok();
+    let addr = instance.addr.map(|addr| addr.to_string());
+
+    config
+        .instances
+        .iter()
+        .find(|(_, named)| match (&named.project, &named.addr) {
+            (Some(named), _) => project.is_some() && named.canonicalize().ok() == project,
+            (None, Some(named)) => Some(named) == addr.as_ref(),
+            (None, None) => false,
+        })
+        .map(|(name, _)| name.as_str())
+}
+
+async fn instances(
+    instances_matches: &ArgMatches<'_>,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    if let Some(name_matches) = instances_matches.subcommand_matches("name") {
+        let name = name_matches.value_of("NAME").expect("NAME is required");
+        let instance = name_matches
+            .value_of("INSTANCE")
+            .expect("INSTANCE is required");
+        if name.parse::<u16>().is_ok() {
+            bail!("A name can't be a number, it would look like a port");
         }
-        Response::Error => {
-            bail!("Unity-side error");
+
+        let named = name_target(instance).await?;
+        let path = config::name_instance(name, &named)?;
+        match (&named.project, &named.addr) {
+            (Some(project), _) => log::info!("{} is the editor of {}", name, project.display()),
+            (None, Some(addr)) => log::info!("{} is the editor at {}", name, addr),
+            (None, None) => unreachable!("name_target picks one"),
+        }
+        log::debug!("Saved in {}", path.display());
+        print_ok();
+        return Ok(());
+    }
+
+    if let Some(forget_matches) = instances_matches.subcommand_matches("forget") {
+        let name = forget_matches.value_of("NAME").expect("NAME is required");
+        config::forget_instance(name)?;
+        print_ok();
+        return Ok(());
+    }
+
+    let mut instances = discovery::discover(DISCOVERY_TIMEOUT).await?;
+    for instance in &mut instances {
+        instance.name = name_of(config, instance).map(str::to_string);
+    }
+
+    print_result(&instances, |instances| {
+        if instances.is_empty() {
+            println!("No Unity editors found");
         }
-        // Wait means that we should receive Success or Error later.
-        // Break the loop and wait for the next message
-        Response::Wait => {
-            bail!("Unexpected Wait response");
+
+        for instance in instances {
+            let addr = instance.addr.expect("Filled in by discover");
+            let name = instance
+                .name
+                .as_ref()
+                .map_or(String::new(), |name| format!("\t({})", name));
+            println!(
+                "{}\tUnity {}\t{}\t{}{}",
+                instance.project.display(),
+                instance.unity_version,
+                addr,
+                instance.state,
+                name
+            );
         }
+    });
+
+    Ok(())
+}
+
+// What a name stands for: the project of a local editor, whose port changes between
+// sessions, or the address of the others
+async fn name_target(instance: &str) -> anyhow::Result<config::NamedInstance> {
+    let by_project = |project: PathBuf| config::NamedInstance {
+        project: Some(project),
+        addr: None,
+    };
+    let by_addr = |addr: String| config::NamedInstance {
+        project: None,
+        addr: Some(addr),
+    };
+
+    let path = Path::new(instance);
+    if path.is_dir() {
+        let project = path
+            .canonicalize()
+            .with_context(|| format!("Could not resolve {}", path.display()))?;
+        return Ok(by_project(project));
     }
+
+    let addr: SocketAddr = match instance.parse::<u16>() {
+        Ok(port) => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port),
+        Err(_) => instance
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .with_context(|| {
+                format!(
+                    "'{}' is neither a port, an address nor a project directory",
+                    instance
+                )
+            })?,
+    };
+
+    // a port alone means an editor that uwu instances lists, wherever it is
+    let found = discovery::discover(DISCOVERY_TIMEOUT)
+        .await?
+        .into_iter()
+        .find(|found| {
+            let found_addr = found.addr.expect("Filled in by discover");
+            if instance.parse::<u16>().is_ok() {
+                found.port == addr.port()
+            } else {
+                found_addr == addr
+            }
+        });
+
+    Ok(match found {
+        // the editor answers from any interface, its project tells whether it's this machine
+        Some(found) if found.project.is_dir() => by_project(found.project),
+        Some(found) => by_addr(found.addr.expect("Filled in by discover").to_string()),
+        None => {
+            log::warn!(
+                "No editor answers at {}, naming the address rather than the project",
+                addr
+            );
+            by_addr(addr.to_string())
+        }
+    })
 }
 
-fn single_command(command: Command) -> anyhow::Result<()> {
-    let req = Request {
-        // pick a random ID so that the server can keep track of mistaken resends
-        id: Id64::random(),
-        cmd: command,
+// Find out where the editor listens, in order of priority:
+// --addr or UWU_ADDR (or the address of --instance), the editor of --project, the port file of the fallback project
+// (if any) and finally the default port. --port, or the port of uwu.toml, only replaces
+// the port, so it can be combined with any of these
+async fn resolve_endpoint(
+    matches: &ArgMatches<'_>,
+    fallback_project: &Path,
+    config: &config::Config,
+) -> anyhow::Result<Endpoint> {
+    let kind: TransportKind = matches
+        .value_of("transport")
+        .or(config.transport.as_deref())
+        .unwrap_or("udp")
+        .parse()?;
+
+    // the IPC endpoint is only known through the port file
+    if kind == TransportKind::Ipc {
+        let path = find_port_file(matches, fallback_project)
+            .await?
+            .and_then(|port_file| port_file.ipc)
+            .context(
+            "The editor doesn't offer IPC. Run uwu from the project directory or pass --project, \
+            and note that IPC needs Unity 2021.2+ outside of Windows",
+        )?;
+
+        return Ok(Endpoint::Ipc(path));
+    }
+
+    let mut addr = if let Some(addr_str) = matches.value_of("addr").or(config.addr.as_deref()) {
+        addr_str
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid Unity address '{}'", addr_str))?
+            .next()
+            .with_context(|| format!("'{}' did not resolve to any address", addr_str))?
+    } else {
+        let mut addr: SocketAddr = UNITY_ADDR_STR
+            .parse()
+            .expect("Failed to parse UNITY_ADDR_STR");
+
+        if let Some(port_file) = find_port_file(matches, fallback_project).await? {
+            addr.set_port(port_file.port_for(kind));
+        }
+
+        addr
+    };
+
+    if let Some(port) = matches.value_of("port") {
+        addr.set_port(port.parse().context("--port must be a valid port number")?);
+    } else if let Some(port) = config.port {
+        addr.set_port(port);
+    }
+
+    let endpoint = match kind {
+        TransportKind::Tcp => Endpoint::Tcp(addr),
+        _ => Endpoint::Udp(addr),
+    };
+
+    log::debug!("Talking to Unity at {}", endpoint);
+
+    Ok(endpoint)
+}
+
+// Fail right away, rather than retry forever, when the project of a local editor isn't
+// open in any editor. Remote editors, and projects that uwu can't tell about, get retried
+fn check_running(
+    matches: &ArgMatches<'_>,
+    project: &Path,
+    config: &config::Config,
+    endpoint: &Endpoint,
+) -> anyhow::Result<()> {
+    let remote = matches.value_of("addr").is_some() || config.addr.is_some();
+    let is_project = discovery::find_project(project).as_deref() == Some(project);
+    if remote || !is_project {
+        return Ok(());
+    }
+
+    let port_file = discovery::read_port_file(project).unwrap_or(None);
+    if launch::editor_running(project, port_file.as_ref()) == Some(false) {
+        return Err(
+            anyhow::Error::new(UwuError::Unreachable(endpoint.clone())).context(format!(
+                "Unity is not running for project {}, run `uwu launch`",
+                project.display()
+            )),
+        );
+    }
+    Ok(())
+}
+
+async fn launch_editor(
+    matches: &ArgMatches<'_>,
+    launch_matches: &ArgMatches<'_>,
+    project: &Path,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    let version = match launch_matches.value_of("unity-version") {
+        Some(version) => version.to_owned(),
+        None => launch::project_version(project)?,
     };
+    let editor = launch::find_editor(&version)?;
+
+    if output_mode() == OutputMode::Human {
+        println!("Starting Unity {} for {}", version, project.display());
+    }
+    launch::launch(&editor, project)?;
+
+    let launched = serde_json::json!({ "unity_version": version, "project": project });
+    if !launch_matches.is_present("wait") {
+        print_result(&launched, |_| {});
+        return Ok(());
+    }
+
+    wait_for_editor(matches, project, config).await?;
+    print_result(&launched, |_| println!("ok"));
 
-    send_reliable_blocking(&req)
+    Ok(())
 }
 
-fn watch(mut path: PathBuf, delay: Duration) -> anyhow::Result<()> {
-    println!("Watching project at {}", path.display());
+// The editor takes its time, and only writes its port file once the plugin is loaded
+async fn wait_for_editor(
+    matches: &ArgMatches<'_>,
+    project: &Path,
+    config: &config::Config,
+) -> anyhow::Result<Endpoint> {
+    let endpoint = loop {
+        tokio::time::sleep(LAUNCH_POLL_DELAY).await;
+
+        let endpoint = match resolve_endpoint(matches, project, config).await {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                log::debug!("The editor isn't ready yet: {:#}", e);
+                continue;
+            }
+        };
+
+        // the plugin writes the token of a new project when it starts
+        if AUTH_TOKEN.get().is_none() {
+            if let Some(token) = auth::read_token(project)? {
+                AUTH_TOKEN.set(token).expect("The token is only read once");
+            }
+        }
+
+        match handshake(&endpoint, Some(1)).await {
+            Ok(()) => break endpoint,
+            Err(e) if UwuError::is_unreachable(&e) => {}
+            Err(e) => return Err(e),
+        }
+    };
 
-    path.push("Assets");
+    single_command(&endpoint, Command::CheckAlive).await?;
+    Ok(endpoint)
+}
 
-    if !path.is_dir() {
-        return Err(anyhow::format_err!(
-            "Assets dir not found at {}. Are you sure that this is a valid Unity project?",
-            path.display()
-        ));
+// Starts Unity in batch mode, runs the commands in it one after the other, and quits it.
+// A failing command doesn't stop the others, the first failure is what uwu exits with
+async fn ci(
+    matches: &ArgMatches<'_>,
+    ci_matches: &ArgMatches<'_>,
+    project: &Path,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    let mut args = vec![OsString::from(crate_name!())];
+    args.extend(
+        ci_matches
+            .values_of_os("COMMANDS")
+            .expect("Required")
+            .map(OsString::from),
+    );
+    let chain = parse_chain(&args).map_err(|e| anyhow::format_err!("{}", e.message))?;
+    check_chain(&chain)?;
+    for matches in &chain {
+        let name = matches
+            .subcommand_name()
+            .expect("Clap requires a subcommand");
+        if CI_UNAVAILABLE.contains(&name) {
+            bail!("{} can't run in uwu ci", name);
+        }
     }
 
-    // Create a channel to receive the events.
-    let (tx, rx) = std::sync::mpsc::channel();
+    let startup_timeout = ci_matches
+        .value_of("startup-timeout")
+        .expect("Has a default")
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .context("--startup-timeout must be a positive number of seconds")?;
+
+    let version = match ci_matches.value_of("unity-version") {
+        Some(version) => version.to_owned(),
+        None => launch::project_version(project)?,
+    };
+    let editor = launch::find_editor(&version)?;
+    let editor_log = ci_matches
+        .value_of_os("editor-log")
+        .map_or_else(|| project.join("Library").join("uwu-ci.log"), PathBuf::from);
+
+    log::info!(
+        "Starting Unity {} in batch mode for {}, logging to {}",
+        version,
+        project.display(),
+        editor_log.display()
+    );
+    let mut editor = launch::launch_batch(&editor, project, &editor_log)?;
 
-    let mut watcher = watcher(tx, delay)?;
+    let session = async {
+        let endpoint =
+            tokio::time::timeout(startup_timeout, wait_for_editor(matches, project, config))
+                .await
+                .map_err(|_| {
+                    UwuError::Timeout(format!(
+                        "Unity didn't start in {}s",
+                        startup_timeout.as_secs_f64()
+                    ))
+                })??;
+
+        let mut first_failure = None;
+        for matches in &chain {
+            if let Err(e) = run_command(matches, &endpoint, project, project, config).await {
+                eprintln!("{} {:?}", progress::error_label(), e);
+                first_failure.get_or_insert(e);
+            }
+        }
 
-    watcher.watch(path, RecursiveMode::Recursive)?;
+        // the editor may quit before it answers
+        if let Err(e) = send_no_wait(&endpoint, Command::Quit { force: true }).await {
+            log::debug!("Could not quit the editor: {:#}", e);
+        }
+        first_failure.map_or(Ok(()), Err)
+    };
 
-    let refresh = || {
-        println!("Refreshing");
+    let result = tokio::select! {
+        result = session => result,
+        status = editor.wait() => Err(anyhow::format_err!(
+            "Unity exited with {} before the commands were done, see {}",
+            status?,
+            editor_log.display()
+        )),
+    };
 
-        // handle some errors by breaking and reconnecting, otherwise return the error
-        if let Err(e) = single_command(Command::BackgroundRefresh) {
-            log::error!("An error occurred: {}", e);
+    match tokio::time::timeout(CI_QUIT_TIMEOUT, editor.wait()).await {
+        Ok(status) => log::debug!("Unity exited with {}", status?),
+        Err(_) => {
+            log::warn!("Unity didn't quit, killing it");
+            editor.kill().await?;
         }
+    }
+    result
+}
+
+// The editor wants paths relative to the project root. Paths that aren't in the local
+// project are passed as they are, they might make sense to a remote editor
+fn asset_path(project: &Path, path: &Path) -> String {
+    let relative = path.canonicalize().ok().and_then(|path| {
+        let project = project.canonicalize().ok()?;
+        path.strip_prefix(project).ok().map(Path::to_owned)
+    });
+
+    match relative {
+        // the editor always uses forward slashes
+        Some(relative) => relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
 
-        // NOTE: this may kill the server if scripts are reloaded,
-        // but as it is a background operation, we don't need to wait until it restarts;
-        // we can just queue more refresh requests
+// WIDTHxHEIGHT, as in 1920x1080
+fn parse_size(size: &str) -> anyhow::Result<(u32, u32)> {
+    let parse = || -> Option<(u32, u32)> {
+        let (width, height) = size.split_once('x')?;
+        Some((width.parse().ok()?, height.parse().ok()?))
     };
 
+    match parse() {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => bail!("--size must look like 1920x1080, not '{}'", size),
+    }
+}
+
+// Exit codes of `uwu status`
+const STATUS_IDLE: i32 = 0;
+const STATUS_UNREACHABLE: i32 = 2;
+const STATUS_BUSY: i32 = 3;
+const STATUS_PLAYING: i32 = 4;
+
+// Wait until the editor isn't compiling, importing or reloading scripts anymore, then
+// fail with the compile errors if there are any
+async fn wait_compile(endpoint: &Endpoint) -> anyhow::Result<()> {
     loop {
-        // observe the events that imply that a file is actually changed
-        match rx.recv()? {
-            DebouncedEvent::NoticeWrite(_) => {}
-            DebouncedEvent::NoticeRemove(_) => {}
-            DebouncedEvent::Create(_) => refresh(),
-            DebouncedEvent::Write(_) => refresh(),
-            DebouncedEvent::Chmod(_) => {}
-            DebouncedEvent::Remove(_) => refresh(),
-            DebouncedEvent::Rename(_, _) => refresh(),
-            DebouncedEvent::Rescan => {}
-            DebouncedEvent::Error(e, _) => Err(e)?,
+        match send_command(endpoint, Command::Status, Some(1)).await {
+            Ok(payload) => {
+                let status: EditorStatus = decode_payload(payload)?;
+                if !status.compiling && !status.importing {
+                    break;
+                }
+            }
+            // the domain is reloading
+            Err(e) if UwuError::is_unreachable(&e) => {}
+            Err(e) => return Err(e),
         }
+
+        tokio::time::sleep(COMPILE_POLL_DELAY).await;
+    }
+
+    let compiler: CompilerMessages =
+        decode_payload(single_command(endpoint, Command::CompilerMessages).await?)?;
+
+    let errors = compiler.error_count();
+    if errors > 0 {
+        report_compile_errors(&compiler);
+        return Err(UwuError::CompileFailed { errors }.into());
     }
+
+    Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    let app = App::new(crate_name!())
-        .version(crate_version!())
-        .author(crate_authors!())
-        .about(crate_description!())
-        .arg(
-            Arg::with_name("verbose")
-                .help("Prints more log messages. Same as RUST_LOG=debug")
-                .short("v")
-                .long("verbose")
-                .takes_value(false),
-        )
-        .subcommand(SubCommand::with_name("play").about("Start Play mode"))
-        .subcommand(SubCommand::with_name("stop").about("Stop current Play mode"))
-        .subcommand(SubCommand::with_name("refresh").about("Refresh all assets"))
-        .subcommand(
-            SubCommand::with_name("build")
-                .about("Rebuild all scripts. Only compatible with Unity 2019.3+"),
-        )
-        .subcommand(
-            SubCommand::with_name("watch")
-                .about("Automatically calls refresh if anything under /Assets/ changes")
-                .arg(
-                    Arg::with_name("PROJECT_DIR")
-                        .help("Path to the Unity project to watch")
-                        .required(true)
-                        .index(1),
-                )
-                .arg(
-                    Arg::with_name("delay")
-                        .short("d")
-                        .long("delay")
-                        .value_name("SECONDS")
-                        .help("Only start a refresh after this many seconds")
-                        .default_value("1")
-                        .takes_value(true),
-                ),
-        )
-        .setting(AppSettings::SubcommandRequiredElseHelp);
-    let matches = app.get_matches();
+// Print the compile errors, and keep them for the --notify summary
+fn report_compile_errors(compiler: &CompilerMessages) {
+    let errors: Vec<&CompilerMessage> = compiler
+        .messages
+        .iter()
+        .filter(|m| m.kind == "error")
+        .collect();
+    for message in &errors {
+        eprintln!("{}", format_compiler_message(message, atty::Stream::Stderr));
+    }
+    webhook::compile_errors(errors.iter().map(|m| m.to_string()).collect());
+}
 
-    let log_level = if matches.is_present("verbose") {
-        "debug"
+// A build or a refresh that the editor failed says which scripts don't compile, if that's
+// why. Any other error is returned as is
+async fn explain_failure(endpoint: &Endpoint, error: anyhow::Error) -> anyhow::Error {
+    if !matches!(UwuError::of(&error), Some(UwuError::Editor { .. })) {
+        return error;
+    }
+
+    let compiler: CompilerMessages = match single_command(endpoint, Command::CompilerMessages)
+        .await
+        .and_then(decode_payload)
+    {
+        Ok(compiler) => compiler,
+        Err(e) => {
+            log::debug!("Could not get the compile errors: {:#}", e);
+            return error;
+        }
+    };
+
+    let errors = compiler.error_count();
+    if errors == 0 {
+        return error;
+    }
+    report_compile_errors(&compiler);
+    error.context(UwuError::CompileFailed { errors })
+}
+
+/// What `uwu version` found out
+#[derive(Debug, Serialize)]
+struct Versions {
+    uwu: &'static str,
+    protocol: u32,
+    reachable: bool,
+    /// None if the plugin is too old to say
+    plugin: Option<HelloReply>,
+}
+
+// Print the versions of both sides. Unlike handshake, this never fails on a mismatch,
+// because that's exactly when it's needed
+async fn version(endpoint: &Endpoint) -> anyhow::Result<()> {
+    let hello = Command::Hello {
+        version: PROTOCOL_VERSION,
+        encodings: Encoding::supported(),
+    };
+    let max_attempts = retry_policy().max_attempts.or(Some(1));
+
+    let mut versions = Versions {
+        uwu: crate_version!(),
+        protocol: PROTOCOL_VERSION,
+        reachable: true,
+        plugin: None,
+    };
+    match send_command(endpoint, hello, max_attempts).await {
+        Ok(payload) => versions.plugin = serde_json::from_value(payload).ok(),
+        Err(e) if UwuError::is_unreachable(&e) => versions.reachable = false,
+        Err(e) => return Err(e),
+    }
+
+    print_result(&versions, |versions| {
+        println!("uwu {} (protocol v{})", versions.uwu, versions.protocol);
+
+        match &versions.plugin {
+            _ if !versions.reachable => println!("Unity is not reachable at {}", endpoint),
+            Some(reply) => {
+                let unknown = "unknown";
+                println!(
+                    "Unity {}",
+                    reply.unity_version.as_deref().unwrap_or(unknown)
+                );
+                println!(
+                    "plugin {} (protocol v{}, {})",
+                    reply.plugin_version.as_deref().unwrap_or(unknown),
+                    reply.version,
+                    reply.encoding.as_deref().unwrap_or("json")
+                );
+            }
+            None => println!("plugin: too old to report its version"),
+        }
+    });
+
+    Ok(())
+}
+
+fn watch_options(
+    matches: &ArgMatches<'_>,
+    config: &config::WatchConfig,
+) -> anyhow::Result<watch::Options> {
+    let delay: u64 = match matches.value_of("delay") {
+        Some(delay) => delay
+            .parse()
+            .context("--delay must be a whole number of seconds")?,
+        None => config.delay.unwrap_or(1),
+    };
+
+    let mut ignore = globset::GlobSetBuilder::new();
+    let patterns = matches.values_of("ignore").into_iter().flatten();
+    for pattern in patterns.chain(config.ignore.iter().map(String::as_str)) {
+        let glob = globset::Glob::new(pattern)
+            .with_context(|| format!("Invalid --ignore pattern '{}'", pattern))?;
+        ignore.add(glob);
+    }
+
+    let poll = if matches.is_present("poll") {
+        let interval: f64 = matches
+            .value_of("interval")
+            .expect("Clap provides a default")
+            .parse()
+            .context("--interval must be a number of seconds")?;
+        if interval.is_nan() || interval <= 0.0 {
+            bail!("--interval must be positive");
+        }
+        Some(Duration::from_secs_f64(interval))
     } else {
-        "info"
+        None
     };
 
-    let log_env = env_logger::Env::new().default_filter_or(log_level);
-    env_logger::init_from_env(log_env);
+    let tui = matches.is_present("tui");
+    if tui && (matches.is_present("daemon") || !atty::is(atty::Stream::Stdout)) {
+        bail!("--tui needs a terminal");
+    }
 
-    if let Some(_matches) = matches.subcommand_matches("play") {
-        // Play is complex. First, we need to request to enter play mode, which will succeed
-        // immediately, but it will kill the Unity client, so it cannot return WAIT.
-        single_command(Command::Play)?;
+    Ok(watch::Options {
+        delay: Duration::from_secs(delay),
+        poll,
+        clear: matches.is_present("clear"),
+        ignore: ignore.build()?,
+        extensions: matches
+            .values_of("ext")
+            .into_iter()
+            .flatten()
+            .map(|extension| extension.trim().trim_start_matches('.').to_string())
+            .filter(|extension| !extension.is_empty())
+            .collect(),
+        smart: matches.is_present("smart"),
+        extra_paths: matches
+            .values_of("path")
+            .map_or(vec![], |paths| paths.map(PathBuf::from).collect()),
+        exec: matches
+            .value_of("exec")
+            .map(String::from)
+            .or_else(|| config.exec.clone()),
+        exec_after: matches
+            .value_of("exec-after")
+            .map(String::from)
+            .or_else(|| config.exec_after.clone()),
+        daemon: matches.is_present("daemon"),
+        restart_play: matches.is_present("restart-play"),
+        stop_on_error: matches.is_present("stop-on-error"),
+        test: matches.is_present("test"),
+        test_filter: matches.value_of("test-filter").map(String::from),
+        tui,
+        dry_run: matches.is_present("dry-run"),
+    })
+}
 
-        // Then, we need to wait until the Unity client has restarted and is ready to receive
-        single_command(Command::CheckAlive)?;
+// uwu watch start, stop and status
+async fn editor_daemon(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    if matches.subcommand_matches("stop").is_some() {
+        hub::stop().await?;
+        print_ok();
+    } else if matches.subcommand_matches("status").is_some() {
+        let editors = hub::editors().await?;
 
-        println!("ok");
-    } else if let Some(_matches) = matches.subcommand_matches("stop") {
-        single_command(Command::Stop)?;
+        print_result(&editors, |editors| {
+            if editors.is_empty() {
+                println!("No Unity editors found yet");
+            }
+
+            for editor in editors {
+                let project = editor
+                    .project
+                    .as_ref()
+                    .map_or("?".into(), |project| project.display().to_string());
+                println!(
+                    "{}\tUnity {}\t{}\t{}\t{} queued",
+                    project,
+                    editor.unity_version.as_deref().unwrap_or("?"),
+                    editor.addr,
+                    if editor.online {
+                        editor.state.as_deref().unwrap_or("online")
+                    } else {
+                        "offline"
+                    },
+                    editor.queued
+                );
+            }
+        });
+    } else {
+        hub::serve().await?;
+    }
+    Ok(())
+}
 
-        println!("ok");
-    } else if let Some(_matches) = matches.subcommand_matches("refresh") {
-        single_command(Command::Refresh)?;
+async fn watch_daemon(matches: &ArgMatches<'_>, project: &Path) -> anyhow::Result<()> {
+    // the watcher finds its state from where the project really is
+    let project = project
+        .canonicalize()
+        .with_context(|| format!("No project at {}", project.display()))?;
 
-        // same as Play, wait until our client is ready to receive
-        single_command(Command::CheckAlive)?;
+    if let Some(start) = matches.subcommand_matches("start") {
+        if start.is_present("tui") {
+            bail!("--tui needs a terminal, it can't run in the background");
+        }
+        let pid = daemon::start(&project).await?;
+        let started = serde_json::json!({ "pid": pid, "log": daemon::log_path(&project) });
+        print_result(&started, |_| {
+            println!(
+                "Watching {} in the background (pid {}), logging into {}",
+                project.display(),
+                pid,
+                daemon::log_path(&project).display()
+            )
+        });
+    } else if matches.subcommand_matches("stop").is_some() {
+        if !daemon::stop(&project).await? {
+            bail!("No watcher is running for {}", project.display());
+        }
+        print_ok();
+    } else if matches.subcommand_matches("status").is_some() {
+        let status = daemon::status(&project)
+            .await?
+            .with_context(|| format!("No watcher is running for {}", project.display()))?;
 
-        println!("ok");
-    } else if let Some(_matches) = matches.subcommand_matches("build") {
-        single_command(Command::Build)?;
+        print_result(&status, |status| {
+            println!("pid: {}", status.pid);
+            println!("project: {}", status.project.display());
+            println!(
+                "unity: {}",
+                if status.online { "online" } else { "offline" }
+            );
+            println!("changes: {}", status.events);
+            println!("refreshes: {}", status.refreshes);
+            println!("uptime: {}s", status.uptime_secs);
+        });
+    }
 
-        println!("ok");
-    } else if let Some(matches) = matches.subcommand_matches("watch") {
-        let path = matches
-            .value_of("PROJECT_DIR")
-            .expect("Clap should require this");
+    Ok(())
+}
 
-        let path = PathBuf::from(path);
+async fn status(endpoint: &Endpoint) -> anyhow::Result<i32> {
+    // a single attempt unless --retries asks for more, scripts want an answer quickly
+    let max_attempts = retry_policy().max_attempts.or(Some(1));
 
-        let delay: u64 = matches.value_of("delay").unwrap().parse()?;
+    let result = match handshake(endpoint, max_attempts).await {
+        Ok(()) => send_command(endpoint, Command::Status, max_attempts).await,
+        Err(e) => Err(e),
+    };
+
+    let payload = match result {
+        Ok(payload) => payload,
+        Err(e) if UwuError::is_unreachable(&e) => {
+            print_result(&serde_json::json!({ "reachable": false }), |_| {
+                println!("reachable: no")
+            });
+            return Ok(STATUS_UNREACHABLE);
+        }
+        Err(e) => return Err(e),
+    };
+    let status: EditorStatus = decode_payload(payload)?;
+
+    print_result(&status, |status| {
+        let yes_no = |value| if value { "yes" } else { "no" };
+        println!("reachable: yes");
+        println!("compiling: {}", yes_no(status.compiling));
+        println!("importing: {}", yes_no(status.importing));
+        println!("playing: {}", yes_no(status.playing));
+        println!("paused: {}", yes_no(status.paused));
+    });
+
+    Ok(if status.compiling || status.importing {
+        STATUS_BUSY
+    } else if status.playing {
+        STATUS_PLAYING
+    } else {
+        STATUS_IDLE
+    })
+}
+
+// the arguments of `uwu watch`, that `uwu watch start` takes too
+fn watch_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("PROJECT_DIR")
+            .help(
+                "Path to the Unity project to watch, or several to watch them all at once \
+                [default: the project around the current directory]",
+            )
+            .multiple(true)
+            .index(1),
+        Arg::with_name("delay")
+            .short("d")
+            .long("delay")
+            .value_name("SECONDS")
+            .help("Only start a refresh after this many seconds [default: 1]")
+            .takes_value(true),
+        Arg::with_name("clear")
+            .help("Clear the console before each refresh")
+            .long("clear")
+            .takes_value(false),
+        Arg::with_name("ignore")
+            .help(
+                "Don't refresh for changes to paths matching this glob, like *.orig \
+                or Assets/Generated/**. Can be repeated",
+            )
+            .long("ignore")
+            .value_name("GLOB")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("ext")
+            .help("Only refresh for changes to files with these extensions, like cs,shader,asset")
+            .long("ext")
+            .value_name("EXTENSIONS")
+            .use_delimiter(true)
+            .takes_value(true),
+        Arg::with_name("path")
+            .help("Also watch this folder, can be repeated")
+            .long("path")
+            .value_name("DIR")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("exec")
+            .help(
+                "Run this shell command before each refresh, which only happens \
+                if it succeeds. The changed paths are in UWU_CHANGED_PATHS",
+            )
+            .long("exec")
+            .value_name("COMMAND")
+            .takes_value(true),
+        Arg::with_name("exec-after")
+            .help("Run this shell command after each refresh, like --exec")
+            .long("exec-after")
+            .value_name("COMMAND")
+            .takes_value(true),
+        Arg::with_name("poll")
+            .help("Scan for changes instead, for network drives and containers")
+            .long("poll")
+            .takes_value(false),
+        Arg::with_name("interval")
+            .help("How often --poll scans, in seconds")
+            .long("interval")
+            .value_name("SECS")
+            .default_value("2")
+            .takes_value(true),
+        Arg::with_name("smart")
+            .help("Recompile for .cs and .asmdef changes, and only refresh for the rest")
+            .long("smart")
+            .takes_value(false),
+        Arg::with_name("restart-play")
+            .help("If Unity is in Play mode, stop it for the refresh and start it again after")
+            .long("restart-play")
+            .takes_value(false),
+        Arg::with_name("stop-on-error")
+            .help(
+                "Stop the Play mode of --restart-play as soon as the game logs an error or an \
+                exception, and report it. It starts again on the next refresh",
+            )
+            .long("stop-on-error")
+            .requires("restart-play")
+            .takes_value(false),
+        Arg::with_name("test")
+            .help(
+                "Run the Edit mode tests after each refresh, once the scripts compiled, \
+                and print the failures",
+            )
+            .long("test")
+            .takes_value(false),
+        Arg::with_name("test-filter")
+            .help("With --test, only run the tests whose full name matches this")
+            .long("test-filter")
+            .value_name("FILTER")
+            .requires("test")
+            .takes_value(true),
+        Arg::with_name("tui")
+            .help(
+                "Show a live view of the changes and of the editor, \
+                with keys to play, stop and refresh",
+            )
+            .long("tui")
+            .takes_value(false),
+        Arg::with_name("dry-run")
+            .help(
+                "Print the changes that would trigger a refresh and what would be sent, \
+                without contacting Unity",
+            )
+            .long("dry-run")
+            .takes_value(false),
+        Arg::with_name("daemon")
+            .help("Run as the background watcher of uwu watch start")
+            .long("daemon")
+            .hidden(true)
+            .takes_value(false),
+    ]
+}
+
+// for the commands that can return before Unity is done with them
+fn no_wait_arg() -> Arg<'static, 'static> {
+    Arg::with_name("no-wait")
+        .help("Return as soon as Unity has received the command, without waiting for it")
+        .long("no-wait")
+        .takes_value(false)
+}
+
+// how long uwu input key and click hold it down
+fn hold_arg() -> Arg<'static, 'static> {
+    Arg::with_name("hold")
+        .help("Seconds to hold it down for [default: a frame]")
+        .long("hold")
+        .takes_value(true)
+        .value_name("SECS")
+}
+
+// the arguments shared by the prefs subcommands
+fn pref_key_arg() -> Arg<'static, 'static> {
+    Arg::with_name("KEY")
+        .help("The EditorPrefs key, or the PlayerSettings one with --player")
+        .required(true)
+}
+
+fn player_arg() -> Arg<'static, 'static> {
+    Arg::with_name("player")
+        .help("A PlayerSettings key instead of an EditorPrefs one")
+        .long("player")
+}
+
+// the arguments shared by the defines subcommands
+fn symbol_arg() -> Arg<'static, 'static> {
+    Arg::with_name("SYMBOL")
+        .help("The define symbol, like ENABLE_CHEATS")
+        .required(true)
+        .index(1)
+}
+
+// One line for it and one for each of its children, indented by depth
+fn print_game_object(node: &GameObjectNode, depth: usize, colors: bool) {
+    let indent = "  ".repeat(depth);
+    let mut details = format!("[{}]", node.components.join(", "));
+    if !node.active {
+        details.push_str(" inactive");
+    }
+    if colors {
+        details = progress::paint(&details, progress::DIM);
+    }
+    println!("{}{} {}", indent, node.name, details);
+
+    for child in &node.children {
+        print_game_object(child, depth + 1, colors);
+    }
+    if node.children.is_empty() && node.child_count > 0 {
+        println!("{}  … {} more", indent, node.child_count);
+    }
+}
+
+fn build_scene_arg() -> Arg<'static, 'static> {
+    Arg::with_name("SCENE")
+        .help("The scene, by path or name")
+        .required(true)
+        .index(1)
+}
+
+fn group_arg() -> Arg<'static, 'static> {
+    Arg::with_name("target")
+        .help("A build target group like Standalone or Android, or a build target [default: the active one]")
+        .long("target")
+        .value_name("GROUP")
+        .takes_value(true)
+}
+
+// the whole command line, also used to generate the completion scripts
+fn app() -> App<'static, 'static> {
+    App::new(crate_name!())
+        .version(crate_version!())
+        .author(crate_authors!())
+        .about(crate_description!())
+        .arg(
+            Arg::with_name("verbose")
+                .help("Prints more log messages. Same as RUST_LOG=debug")
+                .short("v")
+                .long("verbose")
+                .takes_value(false),
+        )
+        .after_help(EXIT_CODES_HELP)
+        .arg(
+            Arg::with_name("quiet")
+                .help("Only print errors")
+                .short("q")
+                .long("quiet")
+                .global(true)
+                .conflicts_with("json")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("format")
+                .help(
+                    "How to print compile errors: gcc is file:line:col: error: message, \
+                    msvc and vscode are file(line,col): error CS0000: message, for the \
+                    $msCompile problem matcher of VS Code tasks, and pretty shows the line \
+                    of the source like rustc. Pretty on terminals and gcc otherwise by default",
+                )
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["gcc", "msvc", "vscode", "pretty"])
+                .global(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .help(
+                    "Print a single JSON object with the outcome and the result of the command, \
+                    for scripts and editor integrations",
+                )
+                .long("json")
+                .global(true)
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("addr")
+                .help(
+                    "Address of the Unity editor to talk to, can be on another machine \
+                    [default: 127.0.0.1:38910]",
+                )
+                .long("addr")
+                .value_name("HOST:PORT")
+                .env("UWU_ADDR")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("port")
+                .help("Port of the Unity editor, for when several editors are open")
+                .short("p")
+                .long("port")
+                .value_name("PORT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("transport")
+                .help(
+                    "How to talk to the editor. TCP is more robust for remote editors, \
+                    IPC avoids the network and its firewalls entirely [default: udp]",
+                )
+                .long("transport")
+                .value_name("TRANSPORT")
+                .possible_values(&["udp", "tcp", "ipc"])
+                .env("UWU_TRANSPORT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("project")
+                .help(
+                    "Talk to the editor that has this Unity project open, \
+                    for when several editors are open",
+                )
+                .long("project")
+                .value_name("PROJECT_DIR")
+                .global(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("instance")
+                .help("Talk to the editor that uwu instances name gave this name")
+                .long("instance")
+                .value_name("NAME")
+                .env("UWU_INSTANCE")
+                .global(true)
+                .conflicts_with_all(&["addr", "port", "project"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-daemon")
+                .help(
+                    "Talk to the editor directly, even if uwu daemon is running. Otherwise \
+                    the commands go through the daemon, which queues them for each editor. \
+                    --record and --log-file also talk to it directly",
+                )
+                .long("no-daemon")
+                .env("UWU_NO_DAEMON")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .help(
+                    "Seeds the request ids so that protocol traces can be reproduced. \
+                    For debugging only, seeded ids are predictable",
+                )
+                .long("seed")
+                .value_name("SEED")
+                .env("UWU_SEED")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .help(
+                    "Seconds to wait for Unity to acknowledge a command before trying again. \
+                    By default it's based on how long the editor takes to answer the handshake",
+                )
+                .long("timeout")
+                .value_name("SECONDS")
+                .env("UWU_TIMEOUT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .help(
+                    "Also write the logs to this file, down to every message sent and received. \
+                    Past 10MB it moves to FILE.1 and starts over, and the last 3 are kept",
+                )
+                .long("log-file")
+                .value_name("FILE")
+                .env("UWU_LOG_FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("notify")
+                .help(
+                    "POST a JSON summary of the command to this URL when it fails, or when it \
+                    succeeds after more than 10 seconds. Slack and most chat webhooks show its \
+                    text. watch posts the refreshes that fail",
+                )
+                .long("notify")
+                .value_name("URL")
+                .env("UWU_NOTIFY")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("otel-endpoint")
+                .help(
+                    "Export a span for every command, and for the requests it sends with their \
+                    retries and waits, to this OTLP collector over gRPC. Needs the otel feature",
+                )
+                .long("otel-endpoint")
+                .value_name("URL")
+                .env("UWU_OTEL_ENDPOINT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("record")
+                .help(
+                    "Write every message sent to and received from Unity to this file, with \
+                    the time it went through, to play it back with uwu replay",
+                )
+                .long("record")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-wait")
+                .help(
+                    "Give up on a command that Unity is still running after this many seconds, \
+                    for example when it's stuck importing. Waits forever by default",
+                )
+                .long("max-wait")
+                .value_name("SECONDS")
+                .env("UWU_MAX_WAIT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .help(
+                    "Give up after retrying this many times, for example when Unity isn't \
+                    running. Retries forever by default",
+                )
+                .long("retries")
+                .value_name("COUNT")
+                .env("UWU_RETRIES")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("play")
+                .about("Start Play mode")
+                .arg(
+                    Arg::with_name("scene")
+                        .help("Play this scene, by path or name. The open scenes come back on stop")
+                        .long("scene")
+                        .value_name("SCENE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("wait")
+                        .help(
+                            "Wait until Play mode stops, and exit with the code that the game \
+                            passed to UWU.ExitPlayMode",
+                        )
+                        .long("wait")
+                        .conflicts_with("no-wait")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("stop-on-error")
+                        .help(
+                            "Stop Play mode as soon as the game logs an error or an exception, \
+                            and fail with it. Makes play --wait a smoke test",
+                        )
+                        .long("stop-on-error")
+                        .requires("wait")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("fast")
+                        .help(
+                            "Skip the domain and the scene reload, this time only. Static \
+                            state survives from the last Play mode, see playmode-options",
+                        )
+                        .long("fast")
+                        .takes_value(false),
+                )
+                .arg(no_wait_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about(
+                    "Play until the game stops Play mode, printing its console: warnings and \
+                    errors on stderr. Exits with the code that the game passed to \
+                    UWU.ExitPlayMode",
+                )
+                .arg(
+                    Arg::with_name("scene")
+                        .help("Play this scene, by path or name. The open scenes come back on stop")
+                        .long("scene")
+                        .value_name("SCENE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("deadline")
+                        .help(
+                            "Stop Play mode and fail after this many seconds of play. Unlike the \
+                            global --timeout, how long to wait for Unity to acknowledge each \
+                            request, and --max-wait, this bounds the whole session",
+                        )
+                        .long("deadline")
+                        .value_name("SECS")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("playmode-options")
+                .about(
+                    "Show or change the Enter Play Mode Settings of the project. Without the \
+                    domain reload Play mode starts in a fraction of the time, but static state \
+                    survives from one Play mode to the next",
+                )
+                .arg(
+                    Arg::with_name("domain-reload")
+                        .long("domain-reload")
+                        .takes_value(true)
+                        .possible_values(&["on", "off"])
+                        .help("Reload the scripts when entering Play mode"),
+                )
+                .arg(
+                    Arg::with_name("scene-reload")
+                        .long("scene-reload")
+                        .takes_value(true)
+                        .possible_values(&["on", "off"])
+                        .help("Reload the scenes when entering Play mode"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stop")
+                .about("Stop current Play mode")
+                .arg(all_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("pause").about("Pause Play mode, or resume it if it's paused"),
+        )
+        .subcommand(SubCommand::with_name("step").about("Pause Play mode and advance one frame"))
+        .subcommand(
+            SubCommand::with_name("input")
+                .about(
+                    "Send synthetic input to Play mode. Keys and clicks need the Input System \
+                    package, axes are read by the game through UWU.GetAxis",
+                )
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("key")
+                        .about("Press a key, and release it once held")
+                        .arg(
+                            Arg::with_name("CODE")
+                                .help("A key of the Input System, like Space, A, Digit1 or Enter")
+                                .required(true),
+                        )
+                        .arg(hold_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("click")
+                        .about("Click the left mouse button in the Game view")
+                        .arg(
+                            Arg::with_name("X")
+                                .help("Pixels from the left of the Game view")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("Y")
+                                .help("Pixels from the bottom of the Game view")
+                                .required(true),
+                        )
+                        .arg(hold_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("axis")
+                        .about("Set the value of an axis until Play mode stops")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("The name that the game passes to UWU.GetAxis")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("VALUE")
+                                .help("Usually from -1 to 1")
+                                .allow_hyphen_values(true)
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("timescale")
+                .about(
+                    "Set Time.timeScale in Play mode, like 0.1 for slow motion. Stopping Play \
+                    mode puts back the one of the project",
+                )
+                .arg(
+                    Arg::with_name("VALUE")
+                        .help("From 0, which freezes time, to 100")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("speed")
+                .about("Set Time.timeScale in Play mode: slow is 0.25, normal 1 and fast 2")
+                .arg(
+                    Arg::with_name("SPEED")
+                        .possible_values(&["slow", "normal", "fast"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("refresh")
+                .about("Refresh all assets")
+                .arg(no_wait_arg())
+                .arg(all_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Rebuild all scripts. Only compatible with Unity 2019.3+")
+                .arg(no_wait_arg())
+                .arg(all_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("open-scene")
+                .about("Open a scene in the editor")
+                .arg(
+                    Arg::with_name("SCENE")
+                        .help("Path of the scene, like Assets/Scenes/Main.unity, or just its name")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("additive")
+                        .help("Keep the scenes that are already open")
+                        .long("additive")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("screenshot")
+                .about("Capture the Game or Scene view into a PNG file")
+                .arg(
+                    Arg::with_name("out")
+                        .help("Where to write the PNG")
+                        .long("out")
+                        .value_name("PATH")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("view")
+                        .help("Which view to capture")
+                        .long("view")
+                        .value_name("VIEW")
+                        .possible_values(&["game", "scene"])
+                        .default_value("game")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .help(
+                            "Size of the image [default: 1920x1080, or the size of the Scene view]",
+                        )
+                        .long("size")
+                        .value_name("WxH")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("view")
+                .about(
+                    "Capture the Game view into a folder, once or with --follow several times a \
+                    second until interrupted. uwu serve streams it as MJPEG on GET /view",
+                )
+                .arg(
+                    Arg::with_name("follow")
+                        .help("Keep capturing until Ctrl+C")
+                        .long("follow")
+                        .short("f"),
+                )
+                .arg(
+                    Arg::with_name("fps")
+                        .help("Captures per second, the editor may not keep up")
+                        .long("fps")
+                        .value_name("N")
+                        .default_value("5")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("out-dir")
+                        .help("Where to write the frames, frame-000001.png and so on")
+                        .long("out-dir")
+                        .value_name("DIR")
+                        .default_value("frames")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("view")
+                        .help("Which view to capture")
+                        .long("view")
+                        .value_name("VIEW")
+                        .possible_values(&["game", "scene"])
+                        .default_value("game")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .help("Size of the frames [default: 1920x1080, or the size of the Scene view]")
+                        .long("size")
+                        .value_name("WxH")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("quality")
+                        .help("Write JPEGs of this quality, from 1 to 100, instead of PNGs")
+                        .long("quality")
+                        .value_name("N")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("quit")
+                .about("Close the editor. Fails if there are unsaved changes, unless forced")
+                .arg(
+                    Arg::with_name("force")
+                        .help("Quit without saving, and without asking")
+                        .long("force")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("save")
+                .about("Save the open scenes and the assets. Saves both if neither flag is given")
+                .arg(
+                    Arg::with_name("scenes")
+                        .help("Save the open scenes that have changes")
+                        .long("scenes")
+                        .takes_value(false),
+                )
+                // not --project, which picks the editor
+                .arg(
+                    Arg::with_name("assets")
+                        .help("Save the modified assets and project settings")
+                        .long("assets")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Import some assets, much faster than refreshing everything")
+                .arg(
+                    Arg::with_name("PATHS")
+                        .help("Files or folders to import")
+                        .required(true)
+                        .multiple(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reimport-all")
+                .about("Reimport every asset of the project. For large projects this takes a long time")
+                .arg(
+                    Arg::with_name("confirm")
+                        .help("Confirm that the reimport is intended")
+                        .long("confirm")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("logs")
+                .about("Print the messages of the Unity console")
+                .arg(
+                    Arg::with_name("follow")
+                        .help("Keep printing new messages as they come")
+                        .short("f")
+                        .long("follow")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("level")
+                        .help("Only print messages at least this severe")
+                        .long("level")
+                        .value_name("LEVEL")
+                        .possible_values(&["info", "warn", "error"])
+                        .default_value("info")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tail-log")
+                .about(
+                    "Print the end of Editor.log. Reads the file, so it works even when the \
+                    plugin doesn't answer",
+                )
+                .arg(
+                    Arg::with_name("follow")
+                        .help("Keep printing new lines as the editor writes them")
+                        .short("f")
+                        .long("follow")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("grep")
+                        .help("Only print the lines that match this regex")
+                        .long("grep")
+                        .value_name("PATTERN")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("lines")
+                        .help("How many of the last lines to print before following")
+                        .short("n")
+                        .long("lines")
+                        .value_name("N")
+                        .default_value("10")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .help(
+                            "Read this log instead of the Editor.log of this user, like the \
+                            one of uwu ci",
+                        )
+                        .long("file")
+                        .value_name("FILE")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .about("Record a Profiler capture, that can be opened in the Profiler window")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("start")
+                        .about("Start recording")
+                        .arg(
+                            Arg::with_name("frames")
+                                .help("Stop by itself after this many frames")
+                                .long("frames")
+                                .value_name("N")
+                                .requires("out")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("out")
+                                .help("Where to write the capture, with --frames")
+                                .long("out")
+                                .value_name("PATH")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("stop")
+                        .about("Stop recording, and download the capture")
+                        .arg(
+                            Arg::with_name("out")
+                                .help("Where to write the capture")
+                                .long("out")
+                                .value_name("PATH")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bake")
+                .about("Bake the lighting of the open scenes, and save them")
+                .arg(
+                    Arg::with_name("scene")
+                        .help("Open this scene first, by path or name")
+                        .long("scene")
+                        .value_name("SCENE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("async")
+                        .help("Return as soon as the bake starts, without saving the scenes")
+                        .long("async")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("addressables")
+                .about("Manage the content of the Addressables package")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("build")
+                        .about("Build the Addressables content")
+                        .arg(
+                            Arg::with_name("profile")
+                                .help("Build with this profile instead of the active one")
+                                .long("profile")
+                                .value_name("NAME")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("clean")
+                                .help("Clean the previous content and its cache first")
+                                .long("clean")
+                                .takes_value(false),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("wait-compile")
+                .about("Wait for the scripts to compile and reload, fails if they don't compile")
+                .arg(
+                    Arg::with_name("deadline")
+                        .help(
+                            "Give up after this many seconds. The global --timeout and --max-wait \
+                            only bound each of the status requests that this sends until Unity is \
+                            done, this bounds the whole wait",
+                        )
+                        .long("deadline")
+                        .value_name("SECS")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clear-console").about("Clear the Console window of the editor"),
+        )
+        .subcommand(
+            SubCommand::with_name("errors")
+                .about("Print the errors and warnings of the last script compilation"),
+        )
+        .subcommand(
+            SubCommand::with_name("package")
+                .about("Manage the packages of the project with the Package Manager")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add a package, or change its version")
+                        .arg(
+                            Arg::with_name("PACKAGE")
+                                .help("Name, name@version, git URL or file: path of the package")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Remove a package")
+                        .arg(
+                            Arg::with_name("PACKAGE")
+                                .help("Name of the package")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("list").about("List the packages of the project"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("menu")
+                .about("Run an editor menu item, like \"Assets/Reimport All\"")
+                .arg(
+                    Arg::with_name("PATH")
+                        .help("Path of the menu item")
+                        .required_unless("list")
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("list")
+                        .help("List the menu items instead")
+                        .long("list")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("select")
+                .about("Select an object in the editor, and highlight it in its window")
+                .arg(
+                    Arg::with_name("PATH")
+                        .help("An asset path like Assets/Player.prefab, or a hierarchy path like Level/Player")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("assets")
+                .about("Query the assets of the project")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("find")
+                        .about("Print the path and the GUID of the assets that match a search")
+                        .arg(
+                            Arg::with_name("FILTER")
+                                .help(
+                                    "A search of the Project window, like \"t:Prefab player\" \
+                                    or \"l:Enemy\"",
+                                )
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::with_name("in")
+                                .help("Only search this folder, like Assets/Prefabs")
+                                .long("in")
+                                .value_name("FOLDER")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("hierarchy")
+                .about(
+                    "Print the GameObjects of the loaded scenes as a tree, with their \
+                    components. In Play mode, what exists at runtime",
+                )
+                .arg(
+                    Arg::with_name("scene")
+                        .help("Only this loaded scene, by name or path")
+                        .long("scene")
+                        .value_name("SCENE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .help("How many levels to include, the roots are the first")
+                        .long("depth")
+                        .value_name("N")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("exec")
+                .about("Call a static C# method in the editor, and print what it returns")
+                .arg(
+                    Arg::with_name("METHOD")
+                        .help("Full name of the method, like Namespace.Class.Method")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("arg")
+                        .help("An argument for the method, can be repeated")
+                        .long("arg")
+                        .value_name("VALUE")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("eval")
+                .about(
+                    "Compile C# in the editor and run it, printing what it returns. An \
+                    expression like EditorApplication.isPlaying, or statements that may \
+                    return a value. UnityEngine, UnityEditor, System and System.Linq are in scope",
+                )
+                .arg(
+                    Arg::with_name("CODE")
+                        .required_unless("file")
+                        .conflicts_with("file")
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .help("Run the C# of this file instead")
+                        .long("file")
+                        .value_name("FILE")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("build-player")
+                .about("Build a player with the scenes of the build settings")
+                .arg(
+                    Arg::with_name("target")
+                        .help(
+                            "A BuildTarget, like StandaloneWindows64, Android or WebGL. \
+                            Defaults to the build_target of uwu.toml",
+                        )
+                        .long("target")
+                        .value_name("PLATFORM")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .help("Where to write the player")
+                        .long("out")
+                        .value_name("PATH")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("dev")
+                        .help("Make a development build")
+                        .long("dev")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("report")
+                        .help(
+                            "Write the build report to this JSON file: the total size, what \
+                            each asset adds to it, the scenes that were left out, and the \
+                            steps of the build with how long they took",
+                        )
+                        .long("report")
+                        .value_name("FILE")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("defines")
+                .about("Manage the scripting define symbols, waiting for the scripts to recompile")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add a symbol")
+                        .arg(symbol_arg())
+                        .arg(group_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Remove a symbol")
+                        .arg(symbol_arg())
+                        .arg(group_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the symbols")
+                        .arg(group_arg()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("scenes")
+                .about("Manage the scenes of the Build Settings")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the scenes, with the build index of the enabled ones"),
+                )
+                .subcommand(
+                    SubCommand::with_name("enable")
+                        .about("Include a scene in builds, adding it to the list if needed")
+                        .arg(build_scene_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("disable")
+                        .about("Leave a scene out of builds, it stays in the list")
+                        .arg(build_scene_arg()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("prefs")
+                .about(
+                    "Read and write EditorPrefs, or with --player the PlayerSettings \
+                    product_name, company_name, bundle_version, application_identifier, \
+                    android_version_code and ios_build_number",
+                )
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("get")
+                        .about("Print the value of a key")
+                        .arg(pref_key_arg())
+                        .arg(player_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Change the value of a key, PlayerSettings are saved right away")
+                        .arg(pref_key_arg())
+                        .arg(
+                            Arg::with_name("VALUE")
+                                .allow_hyphen_values(true)
+                                .required(true),
+                        )
+                        .arg(player_arg())
+                        .arg(
+                            Arg::with_name("type")
+                                .help(
+                                    "The type of the EditorPrefs key [default: the one it has, \
+                                    or string for new keys]",
+                                )
+                                .long("type")
+                                .takes_value(true)
+                                .possible_values(&["string", "int", "float"])
+                                .conflicts_with("player"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("switch-target")
+                .about("Change the active build target, and wait for the assets to be reimported")
+                .arg(
+                    Arg::with_name("TARGET")
+                        .help("win64, mac, linux, android, ios, webgl, or any BuildTarget name")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("target")
+                .about("Inspect the build targets of the editor")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("info").about(
+                    "Print the active build target, its scripting backend and API \
+                    compatibility level, and the targets whose module is installed",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("bundles")
+                .about("Manage the AssetBundles of the project")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("build")
+                        .about("Build the AssetBundles, and print their sizes and hashes")
+                        .arg(
+                            Arg::with_name("out")
+                                .help("The folder where to write the bundles")
+                                .long("out")
+                                .value_name("DIR")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("target")
+                                .help("A BuildTarget, like StandaloneWindows64 [default: the active one]")
+                                .long("target")
+                                .value_name("PLATFORM")
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run the tests of the Unity Test Framework, fails if any test fails")
+                .arg(
+                    Arg::with_name("mode")
+                        .help("Which kind of tests to run")
+                        .long("mode")
+                        .value_name("MODE")
+                        .possible_values(&["editmode", "playmode"])
+                        .default_value("editmode")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("filter")
+                        .help("Only run the tests whose full name matches this regex")
+                        .long("filter")
+                        .value_name("PATTERN")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("results")
+                        .help("Write the results in the NUnit XML format to this file")
+                        .long("results")
+                        .value_name("PATH")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("focus")
+                .about("Bring the Unity editor window to the foreground")
+                .arg(
+                    Arg::with_name("game")
+                        .help("Focus the Game view too, for example to play after uwu play")
+                        .long("game")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("version")
+                .about("Print the versions of uwu, and of the editor and plugin it talks to"),
+        )
+        .subcommand(SubCommand::with_name("status").about(
+            "Print what the editor is doing. Exits with 0 if idle, 2 if Unity isn't reachable, \
+            3 while compiling or importing and 4 in Play mode",
+        ))
+        .subcommand(SubCommand::with_name("doctor").about(
+            "Check what it takes to talk to the editor of the project: its port, its process, \
+            the plugin and its protocol, a round trip, and whether Assets can be watched. \
+            Suggests a fix for each check that fails",
+        ))
+        .subcommand(
+            SubCommand::with_name("instances")
+                .about("List the Unity editors running the uwu plugin on this network")
+                .subcommand(
+                    SubCommand::with_name("name")
+                        .about(
+                            "Give an editor a name for --instance, remembered in \
+                            ~/.config/uwu/config.toml. Local editors are remembered by their \
+                            project, so the name still works when their port changes",
+                        )
+                        .arg(
+                            Arg::with_name("INSTANCE")
+                                .help(
+                                    "The port of the editor as uwu instances lists it, its \
+                                    HOST:PORT, or its project directory",
+                                )
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("NAME").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("forget")
+                        .about("Forget the name of an editor")
+                        .arg(Arg::with_name("NAME").required(true)),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("install")
+                .about(
+                    "Install the Unity plugin of this uwu into the project, or update the one \
+                    it has",
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Replace the plugin even if it's newer than this uwu"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("hook")
+                .about("Manage the git hooks that run uwu")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("install")
+                        .about(
+                            "Write git hooks that run uwu commands, so that the editor refreshes \
+                            after a pull or a checkout without being focused. Without options, \
+                            --post-merge refresh and --post-checkout refresh",
+                        )
+                        .args(&["pre-commit", "post-merge", "post-checkout"].map(|hook| {
+                            Arg::with_name(hook)
+                                .long(hook)
+                                .value_name("COMMAND")
+                                .takes_value(true)
+                                .help("The uwu command of this hook, like refresh or save")
+                        }))
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Replace hooks that uwu didn't write"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("service")
+                .about("Manage the services that run uwu")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("install")
+                        .about(
+                            "Install a user service that watches the project in the background \
+                            from login on, and start it. Without options, the service manager \
+                            of this platform",
+                        )
+                        .arg(
+                            Arg::with_name("systemd")
+                                .long("systemd")
+                                .conflicts_with_all(&["launchd", "winservice"])
+                                .help("A systemd user unit"),
+                        )
+                        .arg(
+                            Arg::with_name("launchd")
+                                .long("launchd")
+                                .conflicts_with("winservice")
+                                .help("A launchd agent"),
+                        )
+                        .arg(
+                            Arg::with_name("winservice")
+                                .long("winservice")
+                                .help("A task of the Windows Task Scheduler, that runs at logon"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("self-update")
+                .about(
+                    "Replace uwu with the binary of its latest release on GitHub if it's newer, \
+                    once its checksum matches",
+                )
+                .arg(
+                    Arg::with_name("version")
+                        .long("version")
+                        .takes_value(true)
+                        .value_name("X")
+                        .help("Install this release instead, like 0.2.0, even if it's older"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about(
+                    "Send a command to the editor again and again, and print how long the \
+                    round trips took, and how long the editor took for refresh",
+                )
+                .arg(
+                    Arg::with_name("iterations")
+                        .short("n")
+                        .long("iterations")
+                        .value_name("N")
+                        .takes_value(true)
+                        .default_value("20"),
+                )
+                .arg(
+                    Arg::with_name("command")
+                        .long("command")
+                        .takes_value(true)
+                        .possible_values(&["checkalive", "refresh"])
+                        .default_value("checkalive")
+                        .help("checkalive only measures uwu and the plugin"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about(
+                    "Show how long the last refreshes and builds of the project took, for the \
+                    import, the compilation and the reload of the scripts",
+                )
+                .arg(
+                    Arg::with_name("command")
+                        .long("command")
+                        .takes_value(true)
+                        .possible_values(&["refresh", "build"])
+                        .help("Only show this command"),
+                )
+                .arg(
+                    Arg::with_name("last")
+                        .short("n")
+                        .long("last")
+                        .takes_value(true)
+                        .default_value("20")
+                        .help("How many of the last runs of each command to include"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about(
+                    "Stay connected to every editor on the network, and send the commands of \
+                    the other uwu processes for them, one at a time for each editor. Runs \
+                    until stopped",
+                )
+                .subcommand(SubCommand::with_name("stop").about("Stop the daemon"))
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("List the editors that the daemon knows, and their queues"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("launch")
+                .about("Start Unity for a project, with the editor version it was last opened with")
+                .arg(
+                    Arg::with_name("PROJECT_DIR")
+                        .help(
+                            "Path to the Unity project to open \
+                            [default: the project around the current directory]",
+                        )
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("unity-version")
+                        .help("Use this editor version instead of the one of the project")
+                        .long("unity-version")
+                        .value_name("VERSION")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("wait")
+                        .help("Wait until the editor is ready to receive commands")
+                        .long("wait")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ci")
+                .about(
+                    "Start Unity in batch mode for the --project, run uwu commands in it like \
+                     `uwu ci -- test refresh build-player ...`, then quit it. Fails with the \
+                     first command that failed, after running all of them",
+                )
+                .arg(
+                    Arg::with_name("COMMANDS")
+                        .required(true)
+                        .multiple(true)
+                        .last(true),
+                )
+                .arg(
+                    Arg::with_name("unity-version")
+                        .help("Use this editor version instead of the one of the project")
+                        .long("unity-version")
+                        .value_name("VERSION")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("editor-log")
+                        .help("Where the editor writes its log [default: Library/uwu-ci.log]")
+                        .long("editor-log")
+                        .value_name("FILE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("startup-timeout")
+                        .help("Give up if the plugin isn't up after this many seconds")
+                        .long("startup-timeout")
+                        .value_name("SECONDS")
+                        .default_value("600")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about(
+                    "Automatically calls refresh if anything under /Assets/, /Packages/ or a \
+                    local package changes",
+                )
+                .setting(AppSettings::SubcommandsNegateReqs)
+                .args(&watch_args())
+                .subcommand(
+                    SubCommand::with_name("start")
+                        .about("Watch in the background, logging into Library/uwu-watch.log")
+                        .args(&watch_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("stop").about("Stop the background watcher of the project"),
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Print what the background watcher of the project is doing"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("repl")
+                .about("Read commands from a prompt, and run them over the same connection"),
+        )
+        .subcommand(
+            SubCommand::with_name("mock-server")
+                .about(
+                    "Pretend to be the Unity plugin, to try uwu without Unity. Every command \
+                     succeeds right away unless --script says otherwise",
+                )
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .takes_value(true)
+                        .value_name("PORT")
+                        .default_value("38910")
+                        .help("The UDP port to listen on, on localhost"),
+                )
+                .arg(
+                    Arg::with_name("script")
+                        .long("script")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help(
+                            "A JSON scenario: the encoding and the protocol version of the \
+                             handshake, a drop_rate, and how to answer each command with \
+                             drop, wait, delay, progress, logs, error and payload",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about(
+                    "Answer like Unity did in a session recorded with --record, to run the \
+                     same commands against it without Unity",
+                )
+                .arg(Arg::with_name("FILE").required(true).index(1))
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .takes_value(true)
+                        .value_name("PORT")
+                        .default_value("38910")
+                        .help("The UDP port to listen on, on localhost"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("schema").about(
+                "Print the JSON Schema of the messages between uwu and the plugin, to generate \
+                 the C# types from",
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about(
+                    "Expose the editor over HTTP: POST /play, /stop, /pause, /step, /refresh \
+                     and /build, GET /status, GET /logs as server-sent events, and GET /events \
+                     as a WebSocket of compile, play and console events",
+                )
+                .arg(
+                    Arg::with_name("http")
+                        .long("http")
+                        .takes_value(true)
+                        .value_name("ADDR")
+                        .help(
+                            "Where to listen for HTTP, 127.0.0.1:8080 unless only --grpc is \
+                             given. Anyone who can reach it can drive the editor",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("grpc")
+                        .long("grpc")
+                        .takes_value(true)
+                        .value_name("ADDR")
+                        .help(
+                            "Also serve proto/uwu.proto over gRPC here, if uwu was built with \
+                             the grpc feature",
+                        ),
+                ),
+        )
+        .subcommand(SubCommand::with_name("mcp").about(
+            "Serve the Model Context Protocol over stdin and stdout, so that coding agents can \
+             play, refresh, read the compile errors and run the tests",
+        ))
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print the completion script of a shell, to source from its profile")
+                .arg(
+                    Arg::with_name("SHELL")
+                        .required(true)
+                        .possible_values(&["bash", "zsh", "fish", "powershell", "elvish"])
+                        .index(1),
+                ),
+        )
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+}
+
+/// `uwu refresh play` runs refresh, then play. Anything that clap can parse as a single
+/// command is one, so that values like `exec Foo --arg test` never start another command
+fn parse_chain(args: &[OsString]) -> Result<Vec<ArgMatches<'static>>, clap::Error> {
+    let error = match app().get_matches_from_safe(args) {
+        Ok(matches) => return Ok(vec![matches]),
+        Err(e) => e,
+    };
+    if matches!(
+        error.kind,
+        clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed
+    ) {
+        return Err(error);
+    }
+
+    for split in 2..args.len() {
+        let next = match args[split].to_str() {
+            Some(next) if !next.starts_with('-') => next,
+            _ => continue,
+        };
+        let matches = match app().get_matches_from_safe(&args[..split]) {
+            Ok(matches) => matches,
+            Err(_) => continue,
+        };
+
+        // the options before the first command apply to the next ones too
+        let command = matches
+            .subcommand_name()
+            .expect("Clap requires a subcommand");
+        let globals = args[1..split].iter().take_while(|arg| *arg != command);
+        let rest: Vec<OsString> = args[..1]
+            .iter()
+            .chain(globals)
+            .chain(&args[split..])
+            .cloned()
+            .collect();
+
+        if let Ok(mut chain) = parse_chain(&rest) {
+            if chain[0].subcommand_name() == Some(next) {
+                chain.insert(0, matches);
+                return Ok(chain);
+            }
+        }
+    }
+
+    Err(error)
+}
+
+// --all of refresh, build and stop
+fn all_arg() -> Arg<'static, 'static> {
+    Arg::with_name("all")
+        .help(
+            "Send it to every editor that uwu instances finds, at the same time, instead of \
+            a single one",
+        )
+        .long("all")
+        .conflicts_with("no-wait")
+        .takes_value(false)
+}
+
+// Commands that don't go through the handshake, or never end, can't be followed by others
+fn check_chain(chain: &[ArgMatches<'_>]) -> anyhow::Result<()> {
+    if chain.len() < 2 {
+        return Ok(());
+    }
+
+    for (index, matches) in chain.iter().enumerate() {
+        let name = matches
+            .subcommand_name()
+            .expect("Clap requires a subcommand");
+        let alone = [
+            "instances",
+            "launch",
+            "status",
+            "version",
+            "completions",
+            "schema",
+            "mock-server",
+            "replay",
+            "repl",
+            "serve",
+            "mcp",
+            "ci",
+            "tail-log",
+            "daemon",
+            "stats",
+            "doctor",
+            "self-update",
+            "install",
+            "hook",
+            "service",
+        ];
+        let watch = matches.subcommand_matches("watch");
+        let daemon = watch.map_or(false, |watch| watch.subcommand_name().is_some());
+
+        if alone.contains(&name) || daemon {
+            bail!("{} can't be chained with other commands", name);
+        }
+        if matches
+            .subcommand()
+            .1
+            .map_or(false, |matches| matches.is_present("all"))
+        {
+            bail!("{} --all can't be chained with other commands", name);
+        }
+        if watch.is_some() && index + 1 < chain.len() {
+            bail!("watch never ends, it can only be the last command");
+        }
+    }
+    Ok(())
+}
+
+async fn serve(matches: &ArgMatches<'_>, endpoint: Endpoint) -> anyhow::Result<()> {
+    let parse = |name: &str| -> anyhow::Result<Option<SocketAddr>> {
+        matches
+            .value_of(name)
+            .map(|addr| {
+                addr.parse()
+                    .with_context(|| format!("--{} must be an address like 127.0.0.1:8080", name))
+            })
+            .transpose()
+    };
+    let grpc = parse("grpc")?;
+    let http = match parse("http")? {
+        Some(addr) => Some(addr),
+        None if grpc.is_none() => Some(SocketAddr::from(([127, 0, 0, 1], 8080))),
+        None => None,
+    };
+
+    let client = Arc::new(AsyncClient::with_endpoint(endpoint, settings()).await?);
+    let http = async {
+        match http {
+            Some(addr) => serve::serve(addr, client.clone()).await,
+            None => std::future::pending().await,
+        }
+    };
+    let grpc = async {
+        match grpc {
+            #[cfg(feature = "grpc")]
+            Some(addr) => grpc::serve(addr, client.clone()).await,
+            #[cfg(not(feature = "grpc"))]
+            Some(_) => bail!("This uwu was built without the grpc feature"),
+            None => std::future::pending().await,
+        }
+    };
+
+    // each one only ends if it fails
+    tokio::try_join!(http, grpc)?;
+    Ok(())
+}
+
+// the --port of the commands that stand in for Unity
+fn local_addr(matches: &ArgMatches<'_>) -> anyhow::Result<SocketAddr> {
+    let port: u16 = matches
+        .value_of("port")
+        .expect("Has a default")
+        .parse()
+        .context("--port must be a port number")?;
+    Ok(SocketAddr::from(([127, 0, 0, 1], port)))
+}
+
+async fn mock_server(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let scenario = match matches.value_of_os("script") {
+        Some(path) => {
+            let path = Path::new(path);
+            let json = std::fs::read_to_string(path)
+                .with_context(|| format!("Could not read {}", path.display()))?;
+            serde_json::from_str(&json)
+                .with_context(|| format!("{} is not a valid scenario", path.display()))?
+        }
+        None => Scenario::default(),
+    };
+
+    let addr = local_addr(matches)?;
+    let server = MockServer::bind(addr, scenario)
+        .await
+        .with_context(|| format!("Could not listen on {}", addr))?;
+    log::info!("Pretending to be Unity on {}", server.local_addr()?);
+    server.run().await?;
+    Ok(())
+}
+
+// a single thread is plenty for a handful of sockets
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let chain = parse_chain(&args).unwrap_or_else(|e| e.exit());
+    let matches = &chain[0];
+
+    let log_level = if matches.is_present("verbose") {
+        "debug"
+    } else if matches.is_present("quiet") {
+        "error"
+    } else {
+        "info"
+    };
+
+    let log_env = env_logger::Env::new().default_filter_or(log_level);
+    let logger = env_logger::Builder::from_env(log_env).build();
+    let stderr_level = logger.filter();
+    log_file::init(logger);
+
+    Lazy::force(&STARTED);
+    if let Some(format) = matches.value_of("format") {
+        let error_format = match format {
+            "msvc" | "vscode" => ErrorFormat::Msvc,
+            "pretty" => ErrorFormat::Pretty,
+            _ => ErrorFormat::Gcc,
+        };
+        ERROR_FORMAT
+            .set(error_format)
+            .expect("The error format is only set once");
+    }
+    let output_mode = if matches.is_present("json") {
+        OutputMode::Json
+    } else if matches.is_present("quiet") {
+        OutputMode::Quiet
+    } else {
+        OutputMode::Human
+    };
+    OUTPUT_MODE
+        .set(output_mode)
+        .expect("The output mode is only set once");
+
+    // dropping the command on Ctrl-C cancels it cleanly, and closes its connections.
+    // watch finishes its refresh first, see watch::interrupted, and repl only cancels the
+    // command it's running
+    let watching = chain
+        .last()
+        .and_then(|matches| matches.subcommand_matches("watch"))
+        .map_or(false, |watch| watch.subcommand_name().is_none());
+    // debug logs would scroll the spinners away
+    if output_mode == OutputMode::Human
+        && stderr_level == log::LevelFilter::Info
+        && !watching
+        && atty::is(atty::Stream::Stderr)
+    {
+        progress::enable();
+    }
+    let interruptible = !watching && matches.subcommand_matches("repl").is_none();
+    let result = tokio::select! {
+        result = run(&chain) => result,
+        _ = tokio::signal::ctrl_c(), if interruptible => Err(anyhow::format_err!("Interrupted")),
+    };
+
+    // the servers and watch end when they are told to, there's nothing to report
+    let commands: Vec<&str> = chain
+        .iter()
+        .filter_map(|matches| matches.subcommand_name())
+        .collect();
+    let long_running = [
+        "watch",
+        "repl",
+        "serve",
+        "mcp",
+        "mock-server",
+        "replay",
+        "tail-log",
+        "daemon",
+    ];
+    if !commands.iter().any(|name| long_running.contains(name)) {
+        let command = commands.join(" ");
+        if let Some(sent) = webhook::notify(&command, STARTED.elapsed(), &result) {
+            let _ = sent.await;
+        }
+    }
+    telemetry::shutdown();
+
+    match result {
+        Ok(()) if json_output() => print_json(0, None),
+        Ok(()) => {}
+        Err(e) => {
+            let code = exit_code(&e);
+            if json_output() {
+                print_json(code, Some(JsonError::new(&e)));
+            } else {
+                eprintln!("{} {:?}", progress::error_label(), e);
+            }
+            std::process::exit(code);
+        }
+    }
+    Ok(())
+}
+
+async fn tail_log(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let path = match matches.value_of_os("file") {
+        Some(path) => PathBuf::from(path),
+        None => tail_log::editor_log_path().context("Could not tell where Editor.log is")?,
+    };
+    let lines = matches
+        .value_of("lines")
+        .expect("Has a default")
+        .parse()
+        .context("--lines must be a positive integer")?;
+    let filter = matches
+        .value_of("grep")
+        .map(Regex::new)
+        .transpose()
+        .context("--grep must be a valid regex")?;
+
+    tail_log::tail_log(&path, lines, filter.as_ref(), matches.is_present("follow")).await
+}
+
+async fn run(chain: &[ArgMatches<'_>]) -> anyhow::Result<()> {
+    // the options before the first command are the same for all of them
+    let matches = &chain[0];
+    check_chain(chain)?;
+
+    // nothing to talk to, and nothing to configure
+    if let Some(matches) = matches.subcommand_matches("completions") {
+        let shell: clap::Shell = matches
+            .value_of("SHELL")
+            .expect("Required")
+            .parse()
+            .map_err(anyhow::Error::msg)?;
+        app().gen_completions_to(crate_name!(), shell, &mut std::io::stdout());
+        return Ok(());
+    }
+
+    if matches.subcommand_matches("schema").is_some() {
+        let schema = serde_json::to_string_pretty(&protocol::schema())?;
+        println!("{}", schema);
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("mock-server") {
+        return mock_server(matches).await;
+    }
+    if let Some(matches) = matches.subcommand_matches("tail-log") {
+        return tail_log(matches).await;
+    }
+    if let Some(matches) = matches.subcommand_matches("replay") {
+        let datagrams = record::load(Path::new(matches.value_of_os("FILE").expect("Required")))?;
+        return record::replay(local_addr(matches)?, &datagrams).await;
+    }
+
+    if let Some(seed) = matches.value_of("seed") {
+        let seed: u64 = seed.parse().context("--seed must be a positive integer")?;
+        *ID_SOURCE.lock().unwrap() = IdSource::seeded(seed);
+    }
+
+    // watch and launch can be told which project they are about, otherwise it's the
+    // project that the current directory is in
+    let fallback_project = match matches
+        .subcommand_matches("watch")
+        .map(|watch| watch.subcommand_matches("start").unwrap_or(watch))
+        .or_else(|| matches.subcommand_matches("launch"))
+        .and_then(|matches| matches.value_of("PROJECT_DIR"))
+    {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let current_dir = std::env::current_dir()?;
+            match discovery::find_project(&current_dir) {
+                Some(project) => {
+                    log::debug!("Found the project at {}", project.display());
+                    project
+                }
+                None => current_dir,
+            }
+        }
+    };
+
+    // a named instance stands for its project, or for its address
+    let named = match matches.value_of("instance") {
+        Some(name) => Some(named_instance(name, &fallback_project)?),
+        None => None,
+    };
+    let fallback_project = match named.as_ref().and_then(|named| named.project.clone()) {
+        Some(project) => project,
+        None => fallback_project,
+    };
+
+    // the command line wins over the settings of uwu.toml
+    let config_project = matches
+        .value_of("project")
+        .map_or(fallback_project.as_path(), Path::new);
+    let mut config = config::load(config_project)?;
+    if let Some(addr) = named.and_then(|named| named.addr) {
+        config.addr = Some(addr);
+    }
+    snippet::set_project(config_project);
+
+    // the log file of uwu.toml is relative to the project
+    let log_file = matches
+        .value_of_os("log-file")
+        .map(PathBuf::from)
+        .or_else(|| {
+            config
+                .log_file
+                .as_ref()
+                .map(|path| config_project.join(path))
+        });
+    // what they capture is what this process sends, not what a daemon sends for it
+    let capturing = log_file.is_some() || matches.is_present("record");
+    if let Some(path) = log_file {
+        log_file::open(&path)?;
+    }
+    if let Some(path) = matches.value_of_os("record") {
+        record::start(Path::new(path))?;
+    }
+    if let Some(url) = matches
+        .value_of("notify")
+        .map(String::from)
+        .or_else(|| config.notify.clone())
+    {
+        webhook::set_url(url);
+    }
+    if let Some(endpoint) = matches
+        .value_of("otel-endpoint")
+        .map(String::from)
+        .or_else(|| config.otel_endpoint.clone())
+    {
+        telemetry::init(&endpoint)?;
+    }
+
+    let timeout = matches
+        .value_of("timeout")
+        .map(|timeout| {
+            timeout
+                .parse::<f64>()
+                .context("--timeout must be a number of seconds")
+        })
+        .transpose()?
+        .or(config.timeout)
+        .map(|secs| -> anyhow::Result<Duration> {
+            if !secs.is_finite() || secs <= 0.0 {
+                bail!("--timeout must be a positive number of seconds");
+            }
+            Ok(Duration::from_secs_f64(secs))
+        })
+        .transpose()?;
+    let max_attempts = matches
+        .value_of("retries")
+        .map(|retries| {
+            retries
+                .parse::<u32>()
+                .context("--retries must be a positive integer")
+        })
+        .transpose()?
+        .or(config.retries)
+        .map(|retries| retries.saturating_add(1));
+    let max_wait = matches
+        .value_of("max-wait")
+        .map(|max_wait| {
+            max_wait
+                .parse::<f64>()
+                .context("--max-wait must be a number of seconds")
+        })
+        .transpose()?
+        .or(config.max_wait)
+        .map(|secs| -> anyhow::Result<Duration> {
+            if !secs.is_finite() || secs <= 0.0 {
+                bail!("--max-wait must be a positive number of seconds");
+            }
+            Ok(Duration::from_secs_f64(secs))
+        })
+        .transpose()?;
+    RETRY_POLICY
+        .set(RetryPolicy {
+            timeout,
+            max_attempts,
+            max_wait,
+        })
+        .expect("The retry policy is only set once");
+
+    if let Some(daemon_matches) = matches.subcommand_matches("daemon") {
+        return editor_daemon(daemon_matches).await;
+    }
+    hub::init(!matches.is_present("no-daemon") && !capturing);
+
+    // instances talks to every editor at once, not to a specific one
+    if let Some(instances_matches) = matches.subcommand_matches("instances") {
+        return instances(instances_matches, &config).await;
+    }
+
+    if let Some(install_matches) = matches.subcommand_matches("install") {
+        return install::install(config_project, install_matches.is_present("force"));
+    }
+
+    if let Some(hook_matches) = matches.subcommand_matches("hook") {
+        let install_matches = hook_matches
+            .subcommand_matches("install")
+            .expect("Clap requires a subcommand");
+        let mut hooks: Vec<(&'static str, String)> = ["pre-commit", "post-merge", "post-checkout"]
+            .iter()
+            .filter_map(|hook| Some((*hook, install_matches.value_of(hook)?.to_string())))
+            .collect();
+        if hooks.is_empty() {
+            hooks = vec![
+                ("post-merge", "refresh".to_string()),
+                ("post-checkout", "refresh".to_string()),
+            ];
+        }
+        return hook::install(config_project, &hooks, install_matches.is_present("force"));
+    }
+
+    if let Some(service_matches) = matches.subcommand_matches("service") {
+        let install_matches = service_matches
+            .subcommand_matches("install")
+            .expect("Clap requires a subcommand");
+        let kind = if install_matches.is_present("systemd") {
+            service::Kind::Systemd
+        } else if install_matches.is_present("launchd") {
+            service::Kind::Launchd
+        } else if install_matches.is_present("winservice") {
+            service::Kind::WinService
+        } else {
+            service::Kind::native()
+        };
+        return service::install(config_project, kind).await;
+    }
+
+    if let Some(update_matches) = matches.subcommand_matches("self-update") {
+        return self_update::self_update(update_matches.value_of("version"));
+    }
+
+    // the timings are on disk, the editor isn't needed
+    if let Some(stats_matches) = matches.subcommand_matches("stats") {
+        let last = stats_matches
+            .value_of("last")
+            .expect("Has a default")
+            .parse::<usize>()
+            .context("--last must be a number of runs")?;
+        return stats::stats(config_project, stats_matches.value_of("command"), last);
+    }
+
+    // there's no editor to talk to yet
+    if let Some(launch_matches) = matches.subcommand_matches("launch") {
+        return launch_editor(matches, launch_matches, &fallback_project, &config).await;
+    }
+
+    if let Some(ci_matches) = matches.subcommand_matches("ci") {
+        return ci(matches, ci_matches, config_project, &config).await;
+    }
+
+    // --all talks to every editor at once, like instances
+    if let (name, Some(command_matches)) = matches.subcommand() {
+        if command_matches.is_present("all") {
+            let command = match name {
+                "refresh" => Command::Refresh,
+                "build" => Command::Build,
+                "stop" => Command::Stop,
+                _ => unreachable!("Only refresh, build and stop have --all"),
+            };
+            let kind = matches
+                .value_of("transport")
+                .or(config.transport.as_deref())
+                .unwrap_or("udp")
+                .parse()?;
+            return broadcast::broadcast(command, kind).await;
+        }
+    }
+
+    // the background watcher talks to the editor, these only talk to the watcher
+    if let Some(watch_matches) = matches.subcommand_matches("watch") {
+        let projects: Vec<PathBuf> = watch_matches
+            .subcommand_matches("start")
+            .unwrap_or(watch_matches)
+            .values_of_os("PROJECT_DIR")
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect();
+        if projects.len() > 1 {
+            if watch_matches.subcommand_name().is_some() {
+                bail!("uwu watch start takes a single project, start one watcher for each");
+            }
+            if watch_matches.is_present("tui") {
+                bail!("--tui shows a single project");
+            }
+            if let Some(name) = ["addr", "port", "instance", "project"]
+                .iter()
+                .find(|name| matches.is_present(name))
+            {
+                bail!(
+                    "--{} picks a single editor, the projects are watched in theirs",
+                    name
+                );
+            }
+            if json_output() {
+                bail!("watch has no JSON output");
+            }
+            return watch::watch_projects(projects).await;
+        }
+
+        if watch_matches.subcommand_name().is_some() {
+            let project = matches
+                .value_of("project")
+                .map_or(fallback_project.as_path(), Path::new);
+            return watch_daemon(watch_matches, project).await;
+        }
+    }
+
+    // sign every request if the project has a token, editors that don't need it ignore it
+    let project = matches
+        .value_of("project")
+        .map_or(fallback_project.as_path(), Path::new);
+    if let Some(token) = auth::read_token(project)? {
+        log::debug!(
+            "Signing requests with {}",
+            auth::token_path(project).display()
+        );
+        AUTH_TOKEN.set(token).expect("The token is only read once");
+    }
+
+    // doctor reports the endpoint that can't be resolved, and unreachable editors
+    if matches.subcommand_matches("doctor").is_some() {
+        return doctor::doctor(matches, project, &fallback_project, &config).await;
+    }
+
+    let endpoint = resolve_endpoint(matches, &fallback_project, &config).await?;
+
+    // status reports unreachable editors instead of waiting for them
+    if matches.subcommand_matches("status").is_some() {
+        let code = status(&endpoint).await?;
+        exit(code);
+    }
+
+    // and version reports mismatched plugins instead of failing the handshake
+    if matches.subcommand_matches("version").is_some() {
+        return version(&endpoint).await;
+    }
+
+    // watch can start before the editor, so it does its own handshake
+    if chain.len() > 1 || matches.subcommand_matches("watch").is_none() {
+        check_running(matches, project, &config, &endpoint)?;
+        handshake(&endpoint, retry_policy().max_attempts)
+            .instrument(tracing::info_span!("handshake"))
+            .await?;
+    }
+
+    if matches.subcommand_matches("repl").is_some() {
+        if json_output() {
+            bail!("repl has no JSON output");
+        }
+        return repl::repl(&endpoint, project, &fallback_project, &config).await;
+    }
+
+    // stdout belongs to the protocol
+    if matches.subcommand_matches("mcp").is_some() {
+        if json_output() {
+            bail!("mcp has no JSON output");
+        }
+        let client = AsyncClient::with_endpoint(endpoint, settings()).await?;
+        return mcp::mcp(client).await;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("serve") {
+        return serve(matches, endpoint).await;
+    }
+
+    // one after the other over the same endpoint, stopping at the first failure
+    for matches in chain {
+        // editor_secs is filled in by the commands that get timings back
+        let span = tracing::info_span!(
+            "command",
+            name = matches.subcommand_name().unwrap_or_default(),
+            editor_secs = tracing::field::Empty,
+        );
+        run_command(matches, &endpoint, project, &fallback_project, &config)
+            .instrument(span)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// The commands that can be chained, once the editor answered the handshake
+async fn run_command(
+    matches: &ArgMatches<'_>,
+    endpoint: &Endpoint,
+    project: &Path,
+    fallback_project: &Path,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    if let Some(matches) = matches.subcommand_matches("play") {
+        let play = Command::Play {
+            scene: matches.value_of("scene").map(String::from),
+            fast: matches.is_present("fast"),
+        };
+
+        if matches.is_present("no-wait") {
+            send_no_wait(endpoint, play).await?;
+            print_ok();
+            return Ok(());
+        }
+
+        // Play is complex. First, we need to request to enter play mode, which will succeed
+        // immediately, but it will kill the Unity client, so it cannot return WAIT.
+        single_command(endpoint, play).await?;
+
+        // Then, we need to wait until the Unity client has restarted and is ready to receive
+        single_command(endpoint, Command::CheckAlive).await?;
+
+        if matches.is_present("wait") {
+            let stop_on_error = matches.is_present("stop-on-error");
+            let wait = Command::WaitPlayMode { stop_on_error };
+            let played: PlayModeResult = decode_payload(single_command(endpoint, wait).await?)?;
+
+            if let Some(error) = played.error.filter(|_| stop_on_error) {
+                log::info!("Stopping Play mode");
+                single_command(endpoint, Command::Stop).await?;
+                return Err(send::editor_error(
+                    format!("The game failed in Play mode: {}", error.message),
+                    error.stack,
+                ));
+            }
+
+            print_result(&played, |played| {
+                if let Some(result) = &played.result {
+                    println!("{}", result);
+                }
+            });
+            if played.exit_code != 0 {
+                exit(played.exit_code);
+            }
+        } else {
+            print_ok();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("run") {
+        let timeout = matches
+            .value_of("deadline")
+            .map(|secs| match secs.parse::<f64>() {
+                Ok(secs) if secs.is_finite() && secs > 0.0 => Ok(Duration::from_secs_f64(secs)),
+                _ => Err(anyhow::format_err!(
+                    "--deadline must be a positive number of seconds"
+                )),
+            })
+            .transpose()?;
+
+        let code = run::run(
+            endpoint,
+            matches.value_of("scene").map(String::from),
+            timeout,
+        )
+        .await?;
+        if code != 0 {
+            exit(code);
+        }
+    } else if let Some(_matches) = matches.subcommand_matches("stop") {
+        single_command(endpoint, Command::Stop).await?;
+
+        print_ok();
+    } else if let Some(matches) = matches.subcommand_matches("playmode-options") {
+        let toggle = |name| match matches.value_of(name) {
+            Some("on") => Some(Toggle::On),
+            Some(_) => Some(Toggle::Off),
+            None => None,
+        };
+        let options = Command::PlayModeOptions {
+            domain_reload: toggle("domain-reload"),
+            scene_reload: toggle("scene-reload"),
+        };
+        let settings: PlayModeSettings = decode_payload(single_command(endpoint, options).await?)?;
+
+        print_result(&settings, |settings| {
+            let on_off = |on| if on { "on" } else { "off" };
+            println!("domain reload: {}", on_off(settings.domain_reload));
+            println!("scene reload: {}", on_off(settings.scene_reload));
+        });
+    } else if let Some(_matches) = matches.subcommand_matches("pause") {
+        let state: PauseState = decode_payload(single_command(endpoint, Command::Pause).await?)?;
+
+        print_result(&state, |state| {
+            println!("{}", if state.paused { "paused" } else { "resumed" })
+        });
+    } else if let Some(_matches) = matches.subcommand_matches("step") {
+        single_command(endpoint, Command::Step).await?;
+
+        print_ok();
+    } else if let Some(input_matches) = matches.subcommand_matches("input") {
+        let number = |matches: &ArgMatches<'_>, name: &str| -> anyhow::Result<f32> {
+            matches
+                .value_of(name)
+                .map(str::parse::<f32>)
+                .transpose()
+                .ok()
+                .flatten()
+                .filter(|value| value.is_finite())
+                .with_context(|| format!("{} must be a number", name))
+        };
+        let hold = |matches: &ArgMatches<'_>| -> anyhow::Result<f32> {
+            if !matches.is_present("hold") {
+                return Ok(0.0);
+            }
+            match number(matches, "hold") {
+                Ok(hold) if hold >= 0.0 => Ok(hold),
+                _ => bail!("--hold must be a positive number of seconds"),
+            }
+        };
+
+        let command = if let Some(matches) = input_matches.subcommand_matches("key") {
+            Command::PressKey {
+                key: matches
+                    .value_of("CODE")
+                    .expect("Clap requires a key")
+                    .to_string(),
+                hold: hold(matches)?,
+            }
+        } else if let Some(matches) = input_matches.subcommand_matches("click") {
+            Command::Click {
+                x: number(matches, "X")?,
+                y: number(matches, "Y")?,
+                hold: hold(matches)?,
+            }
+        } else {
+            let matches = input_matches
+                .subcommand_matches("axis")
+                .expect("Clap requires a subcommand");
+            Command::SetAxis {
+                axis: matches
+                    .value_of("NAME")
+                    .expect("Clap requires a name")
+                    .to_string(),
+                value: number(matches, "VALUE")?,
+            }
+        };
+        single_command(endpoint, command).await?;
+
+        print_ok();
+    } else if let Some(speed_matches) = matches
+        .subcommand_matches("timescale")
+        .or_else(|| matches.subcommand_matches("speed"))
+    {
+        let scale = match speed_matches.value_of("SPEED") {
+            Some("slow") => 0.25,
+            Some("fast") => 2.0,
+            Some(_) => 1.0,
+            None => speed_matches
+                .value_of("VALUE")
+                .expect("Clap requires a value")
+                .parse::<f32>()
+                .ok()
+                .filter(|scale| (0.0..=100.0).contains(scale))
+                .context("The time scale must be a number from 0 to 100")?,
+        };
+        let state: TimeScaleState =
+            decode_payload(single_command(endpoint, Command::TimeScale { scale }).await?)?;
+
+        print_result(&state, |state| println!("time scale: {}", state.scale));
+    } else if let Some(matches) = matches.subcommand_matches("refresh") {
+        if matches.is_present("no-wait") {
+            send_no_wait(endpoint, Command::Refresh).await?;
+        } else {
+            if let Err(e) = single_command(endpoint, Command::Refresh).await {
+                return Err(explain_failure(endpoint, e).await);
+            }
+
+            // same as Play, wait until our client is ready to receive
+            let alive: Alive =
+                serde_json::from_value(single_command(endpoint, Command::CheckAlive).await?)
+                    .unwrap_or_default();
+
+            if let Some(timings) = &alive.timings {
+                stats::record(project, "refresh", timings);
+            }
+            print_result(&alive, |alive| {
+                if let Some(timings) = &alive.timings {
+                    println!("{}", stats::describe(timings));
+                }
+                println!("{}", progress::ok_line(STARTED.elapsed()));
+            });
+            return Ok(());
+        }
+
+        print_ok();
+    } else if let Some(matches) = matches.subcommand_matches("build") {
+        // there's no build output yet
+        if matches.is_present("no-wait") {
+            send_no_wait(endpoint, Command::Build).await?;
+            print_ok();
+            return Ok(());
+        }
+
+        let payload = match single_command(endpoint, Command::Build).await {
+            Ok(payload) => payload,
+            Err(e) => return Err(explain_failure(endpoint, e).await),
+        };
+        let mut output: BuildOutput = decode_payload(payload)?;
+
+        // the scripts reload after a build too, the cycle ends once the editor is back
+        let alive: Alive =
+            serde_json::from_value(single_command(endpoint, Command::CheckAlive).await?)
+                .unwrap_or_default();
+        output.timings = alive.timings;
+        if let Some(timings) = &output.timings {
+            stats::record(project, "build", timings);
+        }
+
+        print_result(&output, |output| {
+            // script-only rebuilds don't produce any artifact
+            if let Some(output_path) = &output.output_path {
+                println!("Build output: {}", output_path);
+            }
+            if let Some(timings) = &output.timings {
+                println!("{}", stats::describe(timings));
+            }
+
+            print_ok();
+        });
+    } else if let Some(matches) = matches.subcommand_matches("open-scene") {
+        let open = Command::OpenScene {
+            scene: matches.value_of("SCENE").expect("Required").into(),
+            additive: matches.is_present("additive"),
+        };
+        let scene: OpenedScene = decode_payload(single_command(endpoint, open).await?)?;
+
+        print_result(&scene, |scene| println!("Opened {}", scene.path));
+    } else if let Some(matches) = matches.subcommand_matches("screenshot") {
+        // 0 lets the editor pick
+        let (width, height) = match matches.value_of("size") {
+            Some(size) => parse_size(size)?,
+            None => (0, 0),
+        };
+
+        let screenshot = Command::Screenshot {
+            view: matches
+                .value_of("view")
+                .expect("Clap provides a default")
+                .into(),
+            width,
+            height,
+            quality: 0,
+        };
+        let transfer: Transfer = decode_payload(single_command(endpoint, screenshot).await?)?;
+        let png = fetch_transfer(endpoint, &transfer).await?;
+
+        let out = matches.value_of("out").expect("Required");
+        std::fs::write(out, png).with_context(|| format!("Could not write {}", out))?;
+
+        print_result(&serde_json::json!({ "path": out }), |_| {
+            println!("Saved {}", out)
+        });
+    } else if let Some(matches) = matches.subcommand_matches("view") {
+        let (width, height) = match matches.value_of("size") {
+            Some(size) => parse_size(size)?,
+            None => (0, 0),
+        };
+        let quality = match matches.value_of("quality") {
+            Some(quality) => quality
+                .parse::<u32>()
+                .ok()
+                .filter(|quality| (1..=100).contains(quality))
+                .context("--quality must be a number from 1 to 100")?,
+            None => 0,
+        };
+
+        let options = view::Options {
+            view: matches
+                .value_of("view")
+                .expect("Clap provides a default")
+                .into(),
+            width,
+            height,
+            quality,
+            fps: matches
+                .value_of("fps")
+                .expect("Clap provides a default")
+                .parse()
+                .context("--fps must be a number")?,
+            follow: matches.is_present("follow"),
+            out_dir: matches
+                .value_of("out-dir")
+                .expect("Clap provides a default")
+                .into(),
+        };
+        view::view(endpoint, &options).await?;
+    } else if let Some(matches) = matches.subcommand_matches("quit") {
+        let quit = Command::Quit {
+            force: matches.is_present("force"),
+        };
+        single_command(endpoint, quit).await?;
+
+        print_ok();
+    } else if let Some(matches) = matches.subcommand_matches("save") {
+        let scenes = matches.is_present("scenes");
+        let assets = matches.is_present("assets");
+
+        let save = if scenes || assets {
+            Command::Save { scenes, assets }
+        } else {
+            Command::Save {
+                scenes: true,
+                assets: true,
+            }
+        };
+        let saved: SavedScenes = decode_payload(single_command(endpoint, save).await?)?;
+
+        print_result(&saved, |saved| {
+            for scene in &saved.saved_scenes {
+                println!("Saved {}", scene);
+            }
+
+            print_ok();
+        });
+    } else if let Some(matches) = matches.subcommand_matches("import") {
+        let paths = matches
+            .values_of("PATHS")
+            .expect("Required")
+            .map(|path| asset_path(project, Path::new(path)))
+            .collect();
+
+        single_command(endpoint, Command::Import { paths }).await?;
+
+        // importing scripts reloads them, same as Refresh
+        single_command(endpoint, Command::CheckAlive).await?;
+
+        print_ok();
+    } else if let Some(matches) = matches.subcommand_matches("reimport-all") {
+        if !matches.is_present("confirm") {
+            bail!("Reimporting everything can take a very long time, pass --confirm to go ahead");
+        }
+
+        single_command(endpoint, Command::ReimportAll).await?;
+
+        // scripts are reimported too, same as Refresh
+        single_command(endpoint, Command::CheckAlive).await?;
+
+        print_ok();
+    } else if let Some(matches) = matches.subcommand_matches("logs") {
+        let level = match matches.value_of("level") {
+            Some("warn") => "warn",
+            Some("error") => "error",
+            _ => "info",
+        };
+
+        logs::logs(endpoint, level, matches.is_present("follow")).await?;
+    } else if let Some(matches) = matches.subcommand_matches("profile") {
+        let (profile, out) = if let Some(matches) = matches.subcommand_matches("start") {
+            let frames = match matches.value_of("frames") {
+                Some(frames) => frames
+                    .parse()
+                    .with_context(|| format!("Invalid frame count '{}'", frames))?,
+                None => 0,
+            };
+
+            (Command::ProfileStart { frames }, matches.value_of("out"))
+        } else if let Some(matches) = matches.subcommand_matches("stop") {
+            (Command::ProfileStop, matches.value_of("out"))
+        } else {
+            unreachable!("Clap requires a subcommand")
+        };
+
+        let payload = single_command(endpoint, profile).await?;
+
+        match out {
+            Some(out) => {
+                let transfer: Transfer = decode_payload(payload)?;
+                let capture = fetch_transfer(endpoint, &transfer).await?;
+                std::fs::write(out, capture).with_context(|| format!("Could not write {}", out))?;
+
+                print_result(&serde_json::json!({ "path": out }), |_| {
+                    println!("Saved {}", out)
+                });
+            }
+            None => print_result(&serde_json::json!({ "recording": true }), |_| {
+                println!("Recording, run uwu profile stop --out <PATH> to save the capture")
+            }),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("bake") {
+        let bake = Command::BakeLighting {
+            scene: matches.value_of("scene").map(String::from),
+            wait: !matches.is_present("async"),
+        };
+        single_command(endpoint, bake).await?;
+
+        print_ok();
+    } else if let Some(matches) = matches.subcommand_matches("addressables") {
+        if let Some(matches) = matches.subcommand_matches("build") {
+            let build = Command::BuildAddressables {
+                profile: matches.value_of("profile").map(String::from),
+                clean: matches.is_present("clean"),
+            };
+            let build: ContentBuild = decode_payload(single_command(endpoint, build).await?)?;
+
+            print_result(&build, |build| {
+                println!("Built {} in {:.0}s", build.output_path, build.duration_secs)
+            });
+        }
+    } else if let Some(matches) = matches.subcommand_matches("wait-compile") {
+        match matches.value_of("deadline") {
+            Some(secs) => {
+                let secs: u64 = secs
+                    .parse()
+                    .with_context(|| format!("Invalid deadline '{}'", secs))?;
+
+                tokio::time::timeout(Duration::from_secs(secs), wait_compile(endpoint))
+                    .await
+                    .map_err(|_| {
+                        UwuError::Timeout(format!("Unity was still compiling after {}s", secs))
+                    })??;
+            }
+            None => wait_compile(endpoint).await?,
+        }
+
+        print_ok();
+    } else if let Some(_matches) = matches.subcommand_matches("clear-console") {
+        single_command(endpoint, Command::ClearConsole).await?;
+
+        print_ok();
+    } else if let Some(_matches) = matches.subcommand_matches("errors") {
+        let compiler: CompilerMessages =
+            decode_payload(single_command(endpoint, Command::CompilerMessages).await?)?;
+
+        print_result(&compiler, |compiler| {
+            for message in &compiler.messages {
+                println!("{}", format_compiler_message(message, atty::Stream::Stdout));
+            }
+        });
+
+        let errors = compiler.error_count();
+        if errors > 0 {
+            return Err(UwuError::CompileFailed { errors }.into());
+        }
+    } else if let Some(matches) = matches.subcommand_matches("package") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            let add = Command::AddPackage {
+                package: matches.value_of("PACKAGE").expect("Required").into(),
+            };
+            let package: Package = decode_payload(single_command(endpoint, add).await?)?;
+
+            print_result(&package, |package| {
+                println!("Added {}@{}", package.name, package.version)
+            });
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            let remove = Command::RemovePackage {
+                package: matches.value_of("PACKAGE").expect("Required").into(),
+            };
+            single_command(endpoint, remove).await?;
+
+            print_ok();
+        } else if let Some(_matches) = matches.subcommand_matches("list") {
+            let list: Packages =
+                decode_payload(single_command(endpoint, Command::ListPackages).await?)?;
+
+            print_result(&list, |list| {
+                for package in &list.packages {
+                    println!("{}@{}\t{}", package.name, package.version, package.source);
+                }
+            });
+        }
+    } else if let Some(matches) = matches.subcommand_matches("menu") {
+        if matches.is_present("list") {
+            let menus: MenuItems =
+                decode_payload(single_command(endpoint, Command::ListMenus).await?)?;
+
+            print_result(&menus, |menus| {
+                for item in &menus.items {
+                    println!("{}", item);
+                }
+            });
+        } else {
+            let path = matches.value_of("PATH").expect("Required without --list");
+            single_command(endpoint, Command::Menu { path: path.into() }).await?;
+
+            print_ok();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("select") {
+        let select = Command::Select {
+            path: matches.value_of("PATH").expect("Required").into(),
+        };
+        single_command(endpoint, select).await?;
+
+        print_ok();
+    } else if let Some(matches) = matches.subcommand_matches("assets") {
+        let matches = matches
+            .subcommand_matches("find")
+            .expect("Clap requires a subcommand");
+        let find = Command::FindAssets {
+            filter: matches.value_of("FILTER").expect("Required").into(),
+            folders: matches
+                .values_of("in")
+                .into_iter()
+                .flatten()
+                .map(|folder| folder.trim_end_matches('/').to_string())
+                .collect(),
+        };
+        let found: FoundAssets = decode_payload(single_command(endpoint, find).await?)?;
+
+        print_result(&found, |found| {
+            for asset in &found.assets {
+                println!("{}\t{}", asset.guid, asset.path);
+            }
+        });
+    } else if let Some(matches) = matches.subcommand_matches("bench") {
+        let iterations = match matches
+            .value_of("iterations")
+            .expect("Has a default")
+            .parse()
+        {
+            Ok(0) | Err(_) => bail!("--iterations must be a positive number"),
+            Ok(iterations) => iterations,
+        };
+        let command = match matches.value_of("command") {
+            Some("refresh") => "refresh",
+            _ => "checkalive",
+        };
+        bench::bench(endpoint, command, iterations).await?;
+    } else if let Some(matches) = matches.subcommand_matches("hierarchy") {
+        let depth = match matches.value_of("depth") {
+            Some(depth) => match depth.parse() {
+                Ok(0) | Err(_) => bail!("--depth must be a positive number of levels"),
+                Ok(depth) => depth,
+            },
+            None => 0,
+        };
+        let hierarchy = Command::Hierarchy {
+            scene: matches.value_of("scene").map(String::from),
+            depth,
+        };
+        let hierarchy: Hierarchy = decode_payload(single_command(endpoint, hierarchy).await?)?;
+
+        print_result(&hierarchy, |hierarchy| {
+            let colors = progress::colors(atty::Stream::Stdout);
+            for scene in &hierarchy.scenes {
+                let name = match scene.path.as_str() {
+                    "" => scene.name.clone(),
+                    path => format!("{} ({})", scene.name, path),
+                };
+                println!(
+                    "{}",
+                    if colors {
+                        progress::paint(&name, progress::BOLD)
+                    } else {
+                        name
+                    }
+                );
+                for root in &scene.roots {
+                    print_game_object(root, 1, colors);
+                }
+            }
+        });
+    } else if let Some(matches) = matches.subcommand_matches("exec") {
+        let exec = Command::Exec {
+            method: matches.value_of("METHOD").expect("Required").into(),
+            args: matches
+                .values_of("arg")
+                .map_or(vec![], |args| args.map(String::from).collect()),
+        };
+        let output: ExecResult = decode_payload(single_command(endpoint, exec).await?)?;
+
+        print_result(&output, |output| match &output.result {
+            serde_json::Value::Null => {}
+            serde_json::Value::String(result) => println!("{}", result),
+            result => println!("{}", result),
+        });
+    } else if let Some(matches) = matches.subcommand_matches("eval") {
+        let code = match matches.value_of_os("file") {
+            Some(file) => std::fs::read_to_string(file)
+                .with_context(|| format!("Could not read {}", Path::new(file).display()))?,
+            None => matches.value_of("CODE").expect("Required").to_string(),
+        };
+        let output: ExecResult =
+            decode_payload(single_command(endpoint, Command::Eval { code }).await?)?;
+
+        print_result(&output, |output| match &output.result {
+            serde_json::Value::Null => {}
+            serde_json::Value::String(result) => println!("{}", result),
+            result => println!("{}", result),
+        });
+    } else if let Some(matches) = matches.subcommand_matches("build-player") {
+        // the editor resolves relative paths from the project folder, not from here
+        let output = std::env::current_dir()?.join(matches.value_of("out").expect("Required"));
+        let report = matches.value_of_os("report").map(PathBuf::from);
+
+        let build = Command::BuildPlayer {
+            target: matches
+                .value_of("target")
+                .map(String::from)
+                .or_else(|| config.build_target.clone())
+                .context("No --target given, and no build_target in uwu.toml")?,
+            output,
+            development: matches.is_present("dev"),
+            report: report.is_some(),
+        };
+        let build: PlayerBuild = decode_payload(single_command(endpoint, build).await?)?;
+
+        if let Some(report) = report {
+            let transfer = build
+                .report
+                .as_ref()
+                .context("The Unity plugin didn't send the build report")?;
+            let json: serde_json::Value =
+                serde_json::from_slice(&fetch_transfer(endpoint, transfer).await?)
+                    .context("The build report is not valid JSON")?;
+            std::fs::write(&report, serde_json::to_string_pretty(&json)?)
+                .with_context(|| format!("Could not write {}", report.display()))?;
+        }
+
+        print_result(&build, |build| {
+            println!(
+                "Built {} ({:.1} MB) in {:.0}s",
+                build.output_path,
+                build.total_size as f64 / (1024.0 * 1024.0),
+                build.duration_secs
+            )
+        });
+    } else if let Some(matches) = matches.subcommand_matches("defines") {
+        let defines = if let Some(matches) = matches.subcommand_matches("add") {
+            Command::AddDefine {
+                symbol: matches.value_of("SYMBOL").expect("Required").into(),
+                group: matches.value_of("target").map(String::from),
+            }
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            Command::RemoveDefine {
+                symbol: matches.value_of("SYMBOL").expect("Required").into(),
+                group: matches.value_of("target").map(String::from),
+            }
+        } else if let Some(matches) = matches.subcommand_matches("list") {
+            Command::ListDefines {
+                group: matches.value_of("target").map(String::from),
+            }
+        } else {
+            unreachable!("Clap requires a subcommand")
+        };
+
+        let defines: Defines = decode_payload(single_command(endpoint, defines).await?)?;
+
+        print_result(&defines, |defines| {
+            for symbol in &defines.symbols {
+                println!("{}", symbol);
+            }
+        });
+    } else if let Some(matches) = matches.subcommand_matches("prefs") {
+        let prefs = if let Some(matches) = matches.subcommand_matches("get") {
+            Command::GetPref {
+                key: matches.value_of("KEY").expect("Required").into(),
+                player: matches.is_present("player"),
+            }
+        } else if let Some(matches) = matches.subcommand_matches("set") {
+            Command::SetPref {
+                key: matches.value_of("KEY").expect("Required").into(),
+                new_value: matches.value_of("VALUE").expect("Required").into(),
+                player: matches.is_present("player"),
+                kind: matches.value_of("type").map(String::from),
+            }
+        } else {
+            unreachable!("Clap requires a subcommand")
+        };
+
+        let pref: Pref = decode_payload(single_command(endpoint, prefs).await?)?;
+
+        print_result(&pref, |pref| println!("{}", pref.value));
+    } else if let Some(matches) = matches.subcommand_matches("scenes") {
+        let scenes = if matches.subcommand_matches("list").is_some() {
+            Command::ListBuildScenes
+        } else if let Some(matches) = matches.subcommand_matches("enable") {
+            Command::EnableBuildScene {
+                scene: matches.value_of("SCENE").expect("Required").into(),
+            }
+        } else if let Some(matches) = matches.subcommand_matches("disable") {
+            Command::DisableBuildScene {
+                scene: matches.value_of("SCENE").expect("Required").into(),
+            }
+        } else {
+            unreachable!("Clap requires a subcommand")
+        };
+
+        let scenes: BuildScenes = decode_payload(single_command(endpoint, scenes).await?)?;
+
+        print_result(&scenes, |scenes| {
+            for scene in &scenes.scenes {
+                match scene.index {
+                    Some(index) => println!("{:>3}  {}", index, scene.path),
+                    None => println!("  -  {} (disabled)", scene.path),
+                }
+            }
+        });
+    } else if let Some(matches) = matches.subcommand_matches("switch-target") {
+        let switch = Command::SwitchTarget {
+            target: matches.value_of("TARGET").expect("Required").into(),
+        };
+        single_command(endpoint, switch).await?;
+
+        print_ok();
+    } else if let Some(_matches) = matches.subcommand_matches("target") {
+        let info: BuildTargetInfo =
+            decode_payload(single_command(endpoint, Command::TargetInfo).await?)?;
+
+        print_result(&info, |info| {
+            println!("target: {} ({})", info.target, info.group);
+            println!("scripting backend: {}", info.scripting_backend);
+            println!("api compatibility level: {}", info.api_compatibility_level);
+            println!("installed: {}", info.installed_targets.join(", "));
+        });
+    } else if let Some(matches) = matches.subcommand_matches("bundles") {
+        if let Some(matches) = matches.subcommand_matches("build") {
+            // the editor resolves relative paths from the project folder, not from here
+            let output = std::env::current_dir()?.join(matches.value_of("out").expect("Required"));
+
+            let build = Command::BuildBundles {
+                output,
+                target: matches
+                    .value_of("target")
+                    .map(String::from)
+                    .or_else(|| config.build_target.clone()),
+            };
+            let build: Bundles = decode_payload(single_command(endpoint, build).await?)?;
+
+            print_result(&build, |build| {
+                for bundle in &build.bundles {
+                    println!("{}\t{}\t{}", bundle.name, bundle.size, bundle.hash);
+                }
+            });
+        }
+    } else if let Some(matches) = matches.subcommand_matches("test") {
+        let results = matches.value_of("results").map(PathBuf::from);
+
+        let test = Command::Test {
+            mode: matches
+                .value_of("mode")
+                .expect("Clap provides a default")
+                .into(),
+            filter: matches.value_of("filter").map(String::from),
+            xml: results.is_some(),
+        };
+        let report: TestReport = decode_payload(single_command(endpoint, test).await?)?;
+
+        if let Some(results) = results {
+            let xml = report
+                .xml
+                .as_ref()
+                .context("The Unity plugin didn't send the XML results")?;
+            std::fs::write(&results, xml)
+                .with_context(|| format!("Could not write {}", results.display()))?;
+        }
+
+        print_result(&report, |report| {
+            for test in &report.tests {
+                match test.result.as_str() {
+                    "Passed" => println!("PASS {}", test.name),
+                    "Failed" => println!("FAIL {}", test.name),
+                    _ => println!("SKIP {}", test.name),
+                }
+                if test.result == "Failed" {
+                    if let Some(message) = &test.message {
+                        println!("    {}", message.trim().replace('\n', "\n    "));
+                    }
+                }
+            }
+
+            println!(
+                "{} passed, {} failed, {} skipped",
+                report.passed, report.failed, report.skipped
+            );
+        });
+
+        if report.failed > 0 {
+            bail!("{} tests failed", report.failed);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("focus") {
+        let focus = Command::FocusEditor {
+            game: matches.is_present("game"),
+        };
+        single_command(endpoint, focus)
+            .await
+            .context("Unity could not bring its window to the foreground")?;
+
+        print_ok();
+    } else if let Some(matches) = matches.subcommand_matches("watch") {
+        // it prints as it goes, for as long as it runs
+        if json_output() {
+            bail!("watch has no JSON output");
+        }
 
-        watch(path, Duration::from_secs(delay))?;
+        let options = watch_options(matches, &config.watch)?;
+        watch::watch(endpoint.clone(), fallback_project.to_path_buf(), options).await?;
     }
 
     Ok(())
@@ -0,0 +1,206 @@
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use uwu_core::{
+    protocol::{Command, CompilerMessages, TestReport},
+    send::decode_payload,
+    AsyncClient,
+};
+
+// the revision of the Model Context Protocol that this speaks
+const MCP_VERSION: &str = "2024-11-05";
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Deserialize)]
+struct Message {
+    // notifications have no id, and get no answer
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "unity_play",
+            "description": "Enter Play mode in the Unity editor, optionally in another scene, \
+                and wait for the scripts to reload",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "scene": { "type": "string", "description": "A scene path or name" }
+                }
+            }
+        },
+        {
+            "name": "unity_stop",
+            "description": "Leave Play mode in the Unity editor",
+            "inputSchema": { "type": "object", "properties": {} }
+        },
+        {
+            "name": "unity_refresh",
+            "description": "Import the changed assets and recompile the scripts, like focusing \
+                the Unity editor, then report the compile errors",
+            "inputSchema": { "type": "object", "properties": {} }
+        },
+        {
+            "name": "unity_get_compile_errors",
+            "description": "The errors and warnings of the last compilation of the scripts",
+            "inputSchema": { "type": "object", "properties": {} }
+        },
+        {
+            "name": "unity_run_tests",
+            "description": "Run the tests of the Unity Test Framework and report the results",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "mode": { "type": "string", "enum": ["editmode", "playmode"] },
+                    "filter": {
+                        "type": "string",
+                        "description": "Only run the tests whose full name matches this regex"
+                    }
+                }
+            }
+        }
+    ])
+}
+
+// The text of a tool's result, and whether it's a failure that the agent should fix
+type Outcome = (String, bool);
+
+// One message per line, in the format of compilers, so that the agent can find them
+async fn compile_errors(client: &AsyncClient) -> anyhow::Result<Outcome> {
+    let compiler: CompilerMessages = decode_payload(client.send(Command::CompilerMessages).await?)?;
+
+    if compiler.messages.is_empty() {
+        return Ok(("No compile errors or warnings".to_string(), false));
+    }
+    let lines: Vec<String> = compiler.messages.iter().map(|m| m.to_string()).collect();
+    Ok((lines.join("\n"), compiler.error_count() > 0))
+}
+
+async fn run_tests(client: &AsyncClient, arguments: &Value) -> anyhow::Result<Outcome> {
+    let test = Command::Test {
+        mode: arguments["mode"].as_str().unwrap_or("editmode").to_string(),
+        filter: arguments["filter"].as_str().map(String::from),
+        xml: false,
+    };
+    let report: TestReport = decode_payload(client.send(test).await?)?;
+
+    let mut text = format!(
+        "{} passed, {} failed, {} skipped",
+        report.passed, report.failed, report.skipped
+    );
+    for test in report.tests.iter().filter(|test| test.result == "Failed") {
+        text.push_str(&format!("\n{}: {}", test.name, test.result));
+        if let Some(message) = &test.message {
+            text.push_str(&format!("\n    {}", message.trim().replace('\n', "\n    ")));
+        }
+    }
+    Ok((text, report.failed > 0))
+}
+
+async fn call_tool(client: &AsyncClient, call: &ToolCall) -> anyhow::Result<Outcome> {
+    match call.name.as_str() {
+        "unity_play" => {
+            match call.arguments["scene"].as_str() {
+                Some(scene) => client.play_scene(scene).await?,
+                None => client.play().await?,
+            }
+            Ok(("Playing".to_string(), false))
+        }
+        "unity_stop" => {
+            client.stop().await?;
+            Ok(("Stopped".to_string(), false))
+        }
+        "unity_refresh" => {
+            client.refresh().await?;
+            compile_errors(client).await
+        }
+        "unity_get_compile_errors" => compile_errors(client).await,
+        "unity_run_tests" => run_tests(client, &call.arguments).await,
+        name => anyhow::bail!("Unknown tool {}", name),
+    }
+}
+
+// The result of a request, or a JSON-RPC error
+async fn answer(client: &AsyncClient, message: &Message) -> Result<Value, (i64, String)> {
+    match message.method.as_str() {
+        "initialize" => Ok(json!({
+            "protocolVersion": MCP_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "uwu", "version": env!("CARGO_PKG_VERSION") },
+        })),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools() })),
+        "tools/call" => {
+            let call: ToolCall = serde_json::from_value(message.params.clone())
+                .map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+
+            // failures of the editor are results too, the agent should read them
+            let (text, is_error) = match call_tool(client, &call).await {
+                Ok(result) => result,
+                Err(e) => (format!("{:#}", e), true),
+            };
+            Ok(json!({
+                "content": [{ "type": "text", "text": text }],
+                "isError": is_error,
+            }))
+        }
+        method => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+    }
+}
+
+/// Speaks the Model Context Protocol over stdin and stdout, so that coding agents can
+/// play, refresh, read the compile errors and run the tests. Logs go to stderr
+pub async fn mcp(client: AsyncClient) -> anyhow::Result<()> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(line) = lines.next_line().await.context("Could not read stdin")? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match serde_json::from_str::<Message>(&line) {
+            Ok(message) => {
+                log::debug!("MCP {}", message.method);
+                let id = match &message.id {
+                    Some(id) => id.clone(),
+                    None => continue,
+                };
+                match answer(&client, &message).await {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, error)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": error },
+                    }),
+                }
+            }
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": PARSE_ERROR, "message": e.to_string() },
+            }),
+        };
+
+        stdout.write_all(format!("{}\n", reply).as_bytes()).await?;
+        stdout.flush().await?;
+    }
+
+    Ok(())
+}
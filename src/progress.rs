@@ -0,0 +1,260 @@
+use crate::json_output;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use uwu_core::{
+    protocol::{Command, Request, TestProgress},
+    send::Observer,
+};
+
+pub const GREEN: &str = "\x1b[32m";
+pub const RED: &str = "\x1b[1;31m";
+pub const YELLOW: &str = "\x1b[1;33m";
+pub const BLUE: &str = "\x1b[1;34m";
+pub const BOLD: &str = "\x1b[1m";
+pub const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+// off unless a person is watching, see enable
+static SPINNERS: AtomicBool = AtomicBool::new(false);
+
+/// Spinners draw over stderr, so only turn them on when it's a terminal that nothing else
+/// owns, like the TUI of watch, and that the debug logs don't scroll
+pub fn enable() {
+    SPINNERS.store(true, Ordering::Relaxed);
+}
+
+/// Whether to color what goes to stream, see https://no-color.org
+pub fn colors(stream: atty::Stream) -> bool {
+    std::env::var_os("NO_COLOR").is_none() && atty::is(stream)
+}
+
+pub fn paint(text: &str, color: &str) -> String {
+    format!("{}{}{}", color, text, RESET)
+}
+
+/// The line that commands without a result print when they are done
+pub fn ok_line(elapsed: Duration) -> String {
+    if !colors(atty::Stream::Stdout) {
+        return "ok".to_string();
+    }
+
+    let elapsed = format!("({:.1}s)", elapsed.as_secs_f64());
+    format!("{} {}", paint("ok", GREEN), paint(&elapsed, DIM))
+}
+
+pub fn error_label() -> String {
+    if colors(atty::Stream::Stderr) {
+        paint("Error:", RED)
+    } else {
+        "Error:".to_string()
+    }
+}
+
+/// Shows what the editor is up to while a command runs. Without a terminal the messages
+/// are logged instead, like they always were. Goes away when dropped
+pub struct Spinner(Option<ProgressBar>);
+
+impl Spinner {
+    pub fn start(message: &str) -> Spinner {
+        if !SPINNERS.load(Ordering::Relaxed) {
+            log::info!("{}...", message);
+            return Spinner(None);
+        }
+
+        let bar = ProgressBar::new_spinner();
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} {msg}… {elapsed}")
+                .expect("The template is valid"),
+        );
+        bar.set_message(message.to_string());
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Spinner(Some(bar))
+    }
+
+    /// What the editor says it's doing, replaces the message
+    pub fn progress(&self, message: &str) {
+        match &self.0 {
+            Some(bar) => bar.set_message(message.trim_end_matches(&['.', '…'][..]).to_string()),
+            None => log::info!("{}", message),
+        }
+    }
+
+    /// Once the editor says how far along it is, the spinner becomes a bar
+    pub fn percent(&self, message: &str, percent: f32) {
+        match &self.0 {
+            Some(bar) => {
+                if bar.length() != Some(100) {
+                    bar.set_style(
+                        ProgressStyle::with_template(
+                            "{spinner} {msg} [{bar:30}] {pos:>3}% {elapsed}",
+                        )
+                        .expect("The template is valid")
+                        .progress_chars("=> "),
+                    );
+                    bar.set_length(100);
+                }
+                bar.set_position(percent.clamp(0.0, 100.0) as u64);
+                bar.set_message(message.trim_end_matches(&['.', '…'][..]).to_string());
+            }
+            None => log::info!("{} ({:.0}%)", message, percent),
+        }
+    }
+
+    /// Console messages stay on screen, above the spinner
+    pub fn log(&self, message: &str) {
+        match &self.0 {
+            Some(bar) => bar.println(message),
+            None => log::info!("{}", message),
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.0 {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+// What the spinner says until the editor tells us more
+fn describe(command: &Command) -> &'static str {
+    match command {
+        Command::Play { .. } => "Entering Play mode",
+        Command::WaitPlayMode { .. } => "Playing",
+        Command::CheckAlive => "Waiting for Unity to reload",
+        Command::Refresh | Command::BackgroundRefresh => "Refreshing",
+        Command::Build => "Compiling",
+        Command::Import { .. } | Command::ReimportAll => "Importing",
+        Command::BakeLighting { .. } => "Baking",
+        Command::BuildAddressables { .. } => "Building Addressables",
+        Command::BuildPlayer { .. } => "Building the player",
+        Command::BuildBundles { .. } => "Building the asset bundles",
+        Command::Test { .. } => "Running the tests",
+        _ => "Waiting for Unity",
+    }
+}
+
+// the tests of Test that are done so far
+#[derive(Default)]
+struct TestCounts {
+    passed: u32,
+    failed: u32,
+    skipped: u32,
+}
+
+/// The spinners of a request, while the editor works on it
+pub struct Spinners {
+    spinner: Option<Spinner>,
+    tests: TestCounts,
+    // the last percentage that was logged, without a terminal
+    logged: Option<(String, u32)>,
+}
+
+// With --json, what the editor reports goes to stderr as one JSON object per line, so that
+// stdout only has the result
+fn print_event(message: &str, percent: Option<f32>) {
+    eprintln!(
+        "{}",
+        serde_json::json!({ "event": "progress", "message": message, "percent": percent })
+    );
+}
+
+impl Spinners {
+    pub fn new(request: &Request) -> Spinners {
+        // the editor doesn't answer while it reloads, which is what CheckAlive waits for
+        let spinner = match request.cmd {
+            Command::CheckAlive => Some(Spinner::start(describe(&request.cmd))),
+            _ => None,
+        };
+        Spinners {
+            spinner,
+            tests: TestCounts::default(),
+            logged: None,
+        }
+    }
+}
+
+impl Observer for Spinners {
+    fn waiting(&mut self, request: &Request) {
+        self.spinner.take();
+        self.spinner = Some(Spinner::start(describe(&request.cmd)));
+    }
+
+    fn progress(&mut self, message: &str) {
+        match &self.spinner {
+            _ if json_output() => print_event(message, None),
+            Some(spinner) => spinner.progress(message),
+            None => log::info!("{}", message),
+        }
+    }
+
+    fn percent(&mut self, message: &str, percent: f32) {
+        if json_output() {
+            print_event(message, Some(percent));
+            return;
+        }
+        if SPINNERS.load(Ordering::Relaxed) {
+            if let Some(spinner) = &self.spinner {
+                spinner.percent(message, percent);
+                return;
+            }
+        }
+
+        // a line every 10% is enough for a log
+        let step = (percent / 10.0) as u32;
+        let logged = self.logged.as_ref();
+        if logged.map_or(true, |(logged, at)| {
+            logged.as_str() != message || *at != step
+        }) {
+            log::info!("{} ({:.0}%)", message, percent);
+            self.logged = Some((message.to_string(), step));
+        }
+    }
+
+    fn log(&mut self, message: &str) {
+        match &self.spinner {
+            Some(spinner) => spinner.log(message),
+            None => log::info!("{}", message),
+        }
+    }
+
+    // A counter on the spinner, and the failures above it as soon as they happen
+    fn test_progress(&mut self, message: &str, test: &TestProgress) {
+        let tests = &mut self.tests;
+        match test.result.as_deref() {
+            None => {}
+            Some("Passed") => tests.passed += 1,
+            Some("Failed") => tests.failed += 1,
+            Some(_) => tests.skipped += 1,
+        }
+
+        // without a terminal, one line per test that's done
+        if !SPINNERS.load(Ordering::Relaxed) {
+            if test.result.is_some() {
+                log::info!("{}", message);
+            }
+            return;
+        }
+
+        let failed = tests.failed;
+        let done = tests.passed + tests.failed + tests.skipped;
+        if test.result.as_deref() == Some("Failed") {
+            let mut failure = format!("FAIL {}", test.name);
+            if colors(atty::Stream::Stderr) {
+                failure = paint(&failure, RED);
+            }
+            if let Some(message) = &test.message {
+                failure.push_str(&format!("\n    {}", message.trim().replace('\n', "\n    ")));
+            }
+            self.log(&failure);
+        }
+        self.progress(&format!(
+            "Running the tests, {}/{} done, {} failed: {}",
+            done, test.total, failed, test.name
+        ));
+    }
+}
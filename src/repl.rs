@@ -0,0 +1,225 @@
+use crate::{check_chain, parse_chain, run_command, status, version};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+use uwu_core::transport::Endpoint;
+
+// what the first word of a line completes to, keep it in sync with app()
+const COMMANDS: &[&str] = &[
+    "addressables",
+    "assets",
+    "bake",
+    "bench",
+    "build",
+    "build-player",
+    "bundles",
+    "clear-console",
+    "defines",
+    "errors",
+    "eval",
+    "exec",
+    "exit",
+    "focus",
+    "hierarchy",
+    "import",
+    "input",
+    "logs",
+    "menu",
+    "open-scene",
+    "package",
+    "pause",
+    "play",
+    "playmode-options",
+    "prefs",
+    "profile",
+    "quit",
+    "refresh",
+    "reimport-all",
+    "run",
+    "save",
+    "scenes",
+    "screenshot",
+    "select",
+    "speed",
+    "status",
+    "step",
+    "stop",
+    "switch-target",
+    "target",
+    "test",
+    "timescale",
+    "version",
+    "view",
+    "wait-compile",
+];
+
+// these need a command line of their own
+const UNAVAILABLE: &[&str] = &[
+    "ci",
+    "completions",
+    "daemon",
+    "doctor",
+    "hook",
+    "install",
+    "instances",
+    "launch",
+    "mcp",
+    "mock-server",
+    "repl",
+    "replay",
+    "schema",
+    "self-update",
+    "serve",
+    "service",
+    "stats",
+    "tail-log",
+    "watch",
+];
+
+struct Commands;
+
+impl Completer for Commands {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        // only the command itself, its arguments are up to the command
+        let word = &line[..pos];
+        if word.contains(' ') {
+            return Ok((pos, vec![]));
+        }
+
+        let candidates = COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(word))
+            .map(|command| command.to_string())
+            .collect();
+        Ok((0, candidates))
+    }
+}
+
+impl Hinter for Commands {
+    type Hint = String;
+}
+
+impl Highlighter for Commands {}
+
+impl Validator for Commands {}
+
+impl Helper for Commands {}
+
+fn history_path(project: &Path) -> PathBuf {
+    project.join("Library").join("uwu-history")
+}
+
+/// Reads commands until `exit` or Ctrl-D, and runs them like the command line would.
+/// The endpoint is only resolved once, so each command goes straight to the editor
+pub async fn repl(
+    endpoint: &Endpoint,
+    project: &Path,
+    fallback_project: &Path,
+    config: &crate::config::Config,
+) -> anyhow::Result<()> {
+    let mut editor = Editor::<Commands, DefaultHistory>::new()?;
+    editor.set_helper(Some(Commands));
+
+    // outside of a project there's nowhere to keep the history
+    let history = history_path(project);
+    let keep_history = history.parent().map_or(false, Path::is_dir);
+    if keep_history {
+        let _ = editor.load_history(&history);
+    }
+
+    println!(
+        "Talking to Unity at {}, type exit or press Ctrl-D to quit",
+        endpoint
+    );
+
+    loop {
+        let line = match editor.readline("uwu> ") {
+            Ok(line) => line,
+            // like shells, Ctrl-C only drops the line
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let words = match shell_words::split(&line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
+        if words.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str())?;
+        if words == ["exit"] {
+            break;
+        }
+
+        // Ctrl-C cancels the command in flight, not the whole session
+        let result = tokio::select! {
+            result = run_line(words, endpoint, project, fallback_project, config) => result,
+            _ = tokio::signal::ctrl_c() => Err(anyhow::format_err!("Interrupted")),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {:#}", e);
+        }
+    }
+
+    if keep_history {
+        editor.save_history(&history)?;
+    }
+    Ok(())
+}
+
+async fn run_line(
+    words: Vec<String>,
+    endpoint: &Endpoint,
+    project: &Path,
+    fallback_project: &Path,
+    config: &crate::config::Config,
+) -> anyhow::Result<()> {
+    let args: Vec<OsString> = std::iter::once("uwu".to_string())
+        .chain(words)
+        .map(OsString::from)
+        .collect();
+
+    let chain = match parse_chain(&args) {
+        Ok(chain) => chain,
+        // the usage, or the help that was asked for
+        Err(e) => {
+            println!("{}", e.message);
+            return Ok(());
+        }
+    };
+    check_chain(&chain)?;
+
+    for matches in &chain {
+        match matches
+            .subcommand_name()
+            .expect("Clap requires a subcommand")
+        {
+            name if UNAVAILABLE.contains(&name) => {
+                anyhow::bail!("{} isn't available in the repl", name)
+            }
+            "status" => {
+                status(endpoint).await?;
+            }
+            "version" => version(endpoint).await?,
+            _ => run_command(matches, endpoint, project, fallback_project, config).await?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! uwu run: Play mode as a program. The console of the game is its output, and what the
+//! game passes to UWU.ExitPlayMode is its exit code
+
+use crate::{logs, print_result, single_command};
+use std::time::Duration;
+use uwu_core::{
+    protocol::{Command, PlayModeResult},
+    send::decode_payload,
+    transport::Endpoint,
+    UwuError,
+};
+
+// the last messages of the game can arrive after Play mode stopped
+const CONSOLE_GRACE: Duration = Duration::from_millis(500);
+
+enum Ended {
+    Stopped(PlayModeResult),
+    TimedOut,
+    Interrupted,
+}
+
+/// Plays until the game stops Play mode, printing its console. Stops it after timeout or
+/// Ctrl+C. Returns the exit code of the game
+pub async fn run(
+    endpoint: &Endpoint,
+    scene: Option<String>,
+    timeout: Option<Duration>,
+) -> anyhow::Result<i32> {
+    single_command(endpoint, Command::Play { scene, fast: false }).await?;
+    // the domain reload restarts the plugin, which forgets the console of Edit mode: what
+    // the console has from now on is the game's
+    single_command(endpoint, Command::CheckAlive).await?;
+
+    let played = async {
+        let wait = single_command(
+            endpoint,
+            Command::WaitPlayMode {
+                stop_on_error: false,
+            },
+        );
+        let payload = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, wait).await {
+                Ok(payload) => payload?,
+                Err(_) => return Ok(Ended::TimedOut),
+            },
+            None => wait.await?,
+        };
+        Ok::<_, anyhow::Error>(Ended::Stopped(decode_payload(payload)?))
+    };
+    let console = logs::forward(endpoint);
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(played, console, interrupted);
+
+    let mut console_lost = false;
+    let ended = loop {
+        tokio::select! {
+            ended = &mut played => break ended?,
+            _ = &mut interrupted => break Ended::Interrupted,
+            result = &mut console, if !console_lost => {
+                // the game goes on without an audience
+                console_lost = true;
+                if let Err(e) = result {
+                    log::warn!("Lost the console of the editor: {:#}", e);
+                }
+            }
+        }
+    };
+
+    match ended {
+        Ended::Stopped(played) => {
+            if !console_lost {
+                let _ = tokio::time::timeout(CONSOLE_GRACE, &mut console).await;
+            }
+
+            print_result(&played, |played| {
+                if let Some(result) = &played.result {
+                    println!("{}", result);
+                }
+            });
+            Ok(played.exit_code)
+        }
+        Ended::TimedOut => {
+            log::info!("Stopping Play mode");
+            single_command(endpoint, Command::Stop).await?;
+            Err(UwuError::Timeout(format!(
+                "Play mode was still running after {}s",
+                timeout
+                    .expect("Only times out with a timeout")
+                    .as_secs_f64()
+            ))
+            .into())
+        }
+        Ended::Interrupted => {
+            log::info!("Stopping Play mode");
+            single_command(endpoint, Command::Stop).await?;
+            Err(anyhow::format_err!("Interrupted"))
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! uwu self-update, for the standalone binaries of the GitHub releases. Each release has
+//! an uwu-ARCH-OS binary for each platform, and its SHA-256 next to it in
+//! uwu-ARCH-OS.sha256
+
+use crate::{print_result, progress::Spinner};
+use anyhow::{bail, Context};
+use clap::crate_version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Ordering,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const RELEASES: &str = "https://api.github.com/repos/Tomcc/uwu/releases";
+// GitHub refuses requests without one
+const USER_AGENT: &str = concat!("uwu/", env!("CARGO_PKG_VERSION"));
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> anyhow::Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .with_context(|| format!("uwu {} has no {}", self.tag_name, name))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Updated {
+    from: &'static str,
+    to: String,
+    path: PathBuf,
+    updated: bool,
+}
+
+// Major, minor and patch, then whether it's a release: 1.2.0-beta.1 comes before 1.2.0.
+// Pre-releases of the same version compare by name
+fn parse_version(version: &str) -> Option<(Vec<u64>, Option<&str>)> {
+    let version = version.trim_start_matches('v');
+    let (core, pre) = match version.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (version, None),
+    };
+    let numbers = core
+        .split('.')
+        .map(|number| number.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    Some((numbers, pre))
+}
+
+fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let (a_numbers, a_pre) = parse_version(a)?;
+    let (b_numbers, b_pre) = parse_version(b)?;
+    Some(
+        a_numbers
+            .cmp(&b_numbers)
+            .then_with(|| match (a_pre, b_pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            }),
+    )
+}
+
+// The name of the binary of this platform in the releases
+fn asset_name() -> String {
+    let name = format!("uwu-{}-{}", std::env::consts::ARCH, std::env::consts::OS);
+    match std::env::consts::EXE_SUFFIX {
+        "" => name,
+        suffix => name + suffix,
+    }
+}
+
+fn find_release(version: Option<&str>) -> anyhow::Result<Release> {
+    let url = match version {
+        Some(version) => format!("{}/tags/v{}", RELEASES, version.trim_start_matches('v')),
+        None => format!("{}/latest", RELEASES),
+    };
+
+    match ureq::get(&url)
+        .set("User-Agent", USER_AGENT)
+        .set("Accept", "application/vnd.github+json")
+        .timeout(TIMEOUT)
+        .call()
+    {
+        Ok(response) => response
+            .into_json()
+            .with_context(|| format!("Invalid release from {}", url)),
+        Err(ureq::Error::Status(404, _)) => match version {
+            Some(version) => bail!("There's no uwu {}", version),
+            None => bail!("uwu has no releases yet"),
+        },
+        Err(e) => Err(e).context("Could not check the releases of uwu on GitHub"),
+    }
+}
+
+fn download(asset: &Asset) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    ureq::get(&asset.browser_download_url)
+        .set("User-Agent", USER_AGENT)
+        .timeout(TIMEOUT)
+        .call()
+        .with_context(|| format!("Could not download {}", asset.name))?
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Could not download {}", asset.name))?;
+    Ok(bytes)
+}
+
+fn verify(binary: &[u8], checksum: &[u8], name: &str) -> anyhow::Result<()> {
+    // sha256sum writes the name of the file after the hash
+    let expected = std::str::from_utf8(checksum)
+        .ok()
+        .and_then(|checksum| checksum.split_whitespace().next())
+        .with_context(|| format!("Invalid checksum for {}", name))?;
+
+    let actual: String = Sha256::digest(binary)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!(
+            "The checksum of {} doesn't match, expected {} but it's {}. Not updating",
+            name,
+            expected,
+            actual
+        );
+    }
+    Ok(())
+}
+
+// Next to the executable, so that the rename doesn't cross file systems
+fn replace_executable(exe: &Path, binary: &[u8]) -> anyhow::Result<()> {
+    let new = exe.with_extension("new");
+    fs::write(&new, binary).with_context(|| format!("Could not write {}", new.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new, fs::Permissions::from_mode(0o755))?;
+    }
+
+    // Windows doesn't replace a running executable, but it lets it be renamed
+    #[cfg(windows)]
+    {
+        let old = exe.with_extension("old");
+        if old.exists() {
+            fs::remove_file(&old).with_context(|| format!("Could not remove {}", old.display()))?;
+        }
+        fs::rename(exe, &old).with_context(|| format!("Could not move {}", exe.display()))?;
+
+        if let Err(e) = fs::rename(&new, exe) {
+            let _ = fs::rename(&old, exe);
+            return Err(e).with_context(|| format!("Could not replace {}", exe.display()));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(windows))]
+    fs::rename(&new, exe).with_context(|| format!("Could not replace {}", exe.display()))
+}
+
+/// Replaces this executable with the binary of another release. Without a version, only
+/// with the latest one if it's newer: a build from source may be ahead of the releases
+pub fn self_update(version: Option<&str>) -> anyhow::Result<()> {
+    let exe = std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("Could not find the uwu executable")?;
+
+    let release = {
+        let _spinner = Spinner::start("Checking the releases of uwu");
+        find_release(version)?
+    };
+    let to = release.tag_name.trim_start_matches('v').to_string();
+
+    let mut updated = Updated {
+        from: crate_version!(),
+        to,
+        path: exe,
+        updated: false,
+    };
+    let ordering = compare_versions(&updated.to, updated.from).with_context(|| {
+        format!(
+            "Could not compare the release {} with uwu {}",
+            updated.to, updated.from
+        )
+    })?;
+    let replace = match version {
+        Some(_) => ordering != Ordering::Equal,
+        None => ordering == Ordering::Greater,
+    };
+    if replace {
+        let name = asset_name();
+        let binary_asset = release
+            .asset(&name)
+            .context("There's no binary of this release for this platform")?;
+        let checksum_asset = release.asset(&format!("{}.sha256", name))?;
+
+        let (binary, checksum) = {
+            let _spinner = Spinner::start(&format!("Downloading uwu {}", updated.to));
+            (download(binary_asset)?, download(checksum_asset)?)
+        };
+        verify(&binary, &checksum, &name)?;
+
+        replace_executable(&updated.path, &binary)?;
+        updated.updated = true;
+    }
+
+    print_result(&updated, |updated| {
+        if updated.updated {
+            println!(
+                "{} {} from {} to {}",
+                if ordering == Ordering::Less {
+                    "Downgraded"
+                } else {
+                    "Updated"
+                },
+                updated.path.display(),
+                updated.from,
+                updated.to
+            );
+        } else if ordering == Ordering::Less {
+            println!(
+                "uwu {} is newer than the latest release {}, --version {} installs it anyway",
+                updated.from, updated.to, updated.to
+            );
+        } else {
+            println!("uwu {} is already installed", updated.from);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!(compare_versions("0.10.0", "0.9.1"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("v1.2.0", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(
+            compare_versions("1.2.0-beta.1", "1.2.0"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare_versions("1.2.0-beta.2", "1.2.0-beta.1"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare_versions("1.2.x", "1.2.0"), None);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::JsonError;
+use futures_util::{SinkExt, StreamExt};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
+use uwu_core::{protocol::EditorStatus, AsyncClient};
+
+// /view without ?fps= and ?quality=
+const VIEW_FPS: f64 = 5.0;
+const VIEW_QUALITY: u32 = 75;
+// the editor doesn't tell when it starts compiling or playing, so /events asks it
+const STATUS_POLL_DELAY: Duration = Duration::from_millis(500);
+// how many events a slow WebSocket client can fall behind before it misses some
+const EVENT_BACKLOG: usize = 256;
+
+type Query = HashMap<String, String>;
+
+struct Gateway {
+    client: Arc<AsyncClient>,
+    // JSON events for the WebSocket clients of /events
+    events: broadcast::Sender<String>,
+}
+
+/// Drives the editor over HTTP, for dashboards and webhooks that can't run uwu:
+/// POST /play (?scene=), /stop, /pause, /step, /refresh and /build, GET /status, and
+/// GET /logs (?level=, ?follow=false) as server-sent events, and GET /view (?fps=, ?view=,
+/// ?quality=) as MJPEG. Answers are JSON, with the result of the command or an error like
+/// the one of --json.
+/// GET /events is a WebSocket that pushes what the editor does as JSON events: log,
+/// compile_started, compile_finished, play_started, play_stopped, paused and resumed
+pub async fn serve(addr: SocketAddr, client: Arc<AsyncClient>) -> anyhow::Result<()> {
+    let (events, _) = broadcast::channel(EVENT_BACKLOG);
+    let gateway = Arc::new(Gateway { client, events });
+    tokio::spawn(watch_status(gateway.clone()));
+    tokio::spawn(forward_logs(gateway.clone()));
+
+    let endpoint = gateway.client.endpoint().clone();
+    let make_service = make_service_fn(move |_| {
+        let gateway = gateway.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let gateway = gateway.clone();
+                async move { Ok::<_, Infallible>(handle(&gateway, request).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    log::info!("Serving {} on http://{}", endpoint, addr);
+    server.await?;
+    Ok(())
+}
+
+fn json(status: StatusCode, value: &impl Serialize) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(value).expect("The answer is valid JSON"),
+        ))
+        .expect("The response is valid")
+}
+
+fn error(status: StatusCode, e: &anyhow::Error) -> Response<Body> {
+    json(
+        status,
+        &serde_json::json!({ "status": "error", "error": JsonError::new(e) }),
+    )
+}
+
+// the result of a command, or the error of the editor that it ran into
+fn answer<T: Serialize>(result: anyhow::Result<T>) -> Response<Body> {
+    match result {
+        Ok(result) => json(
+            StatusCode::OK,
+            &serde_json::json!({ "status": "ok", "result": result }),
+        ),
+        Err(e) => error(StatusCode::BAD_GATEWAY, &e),
+    }
+}
+
+async fn handle(gateway: &Gateway, request: Request<Body>) -> Response<Body> {
+    let client = &gateway.client;
+    let query: Query = request
+        .uri()
+        .query()
+        .map(|query| {
+            form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+    log::info!("{} {}", request.method(), request.uri());
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    match (&method, path.as_str()) {
+        (&Method::POST, "/play") => answer(match query.get("scene") {
+            Some(scene) => client.play_scene(scene).await,
+            None => client.play().await,
+        }),
+        (&Method::POST, "/stop") => answer(client.stop().await),
+        (&Method::POST, "/pause") => answer(client.pause().await),
+        (&Method::POST, "/step") => answer(client.step().await),
+        (&Method::POST, "/refresh") => answer(client.refresh().await),
+        (&Method::POST, "/build") => answer(client.build().await),
+        (&Method::GET, "/status") => answer(client.status().await),
+        (&Method::GET, "/logs") => logs(client, &query),
+        (&Method::GET, "/view") => view(client.clone(), &query),
+        (&Method::GET, "/events") => events(gateway, request),
+        (_, path) => error(
+            StatusCode::NOT_FOUND,
+            &anyhow::format_err!("There is no {} {}", method, path),
+        ),
+    }
+}
+
+// every console message is an event, and a failure of the subscription an error event
+fn logs(client: &AsyncClient, query: &Query) -> Response<Body> {
+    let level = match query.get("level").map(String::as_str) {
+        None | Some("info") => "info",
+        Some("warn") => "warn",
+        Some("error") => "error",
+        Some(level) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &anyhow::format_err!("Unknown level '{}', use info, warn or error", level),
+            )
+        }
+    };
+    let follow = query.get("follow").map_or(true, |follow| follow != "false");
+
+    let events = client.logs(level, follow).map(|log| {
+        let event = match log {
+            Ok(log) => format!(
+                "event: log\ndata: {}\n\n",
+                serde_json::to_string(&log).expect("Logs are valid JSON")
+            ),
+            Err(e) => format!(
+                "event: error\ndata: {}\n\n",
+                serde_json::to_string(&JsonError::new(&e)).expect("Errors are valid JSON")
+            ),
+        };
+        Ok::<_, Infallible>(event)
+    });
+
+    Response::builder()
+        .header("content-type", "text/event-stream")
+        .header("cache-control", "no-cache")
+        .body(Body::wrap_stream(events))
+        .expect("The response is valid")
+}
+
+// Captures of the editor at ?fps=, as a multipart stream of JPEGs that browsers play like
+// a video. A capture that fails, like without a camera, is skipped
+fn view(client: Arc<AsyncClient>, query: &Query) -> Response<Body> {
+    let fps = match query.get("fps").map(|fps| fps.parse::<f64>()) {
+        None => VIEW_FPS,
+        Some(Ok(fps)) if fps.is_finite() && fps > 0.0 => fps,
+        Some(_) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &anyhow::format_err!("?fps= must be a positive number"),
+            )
+        }
+    };
+    let quality = match query.get("quality").map(|quality| quality.parse::<u32>()) {
+        None => VIEW_QUALITY,
+        Some(Ok(quality)) if (1..=100).contains(&quality) => quality,
+        Some(_) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &anyhow::format_err!("?quality= must be a number from 1 to 100"),
+            )
+        }
+    };
+    let view = match query.get("view").map(String::as_str) {
+        None | Some("game") => "game",
+        Some("scene") => "scene",
+        Some(view) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &anyhow::format_err!("Unknown view '{}', use game or scene", view),
+            )
+        }
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / fps));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let frames = futures_util::stream::unfold(interval, move |mut interval| {
+        let client = client.clone();
+        async move {
+            loop {
+                interval.tick().await;
+                match client.screenshot(view, 0, 0, quality).await {
+                    Ok(jpeg) => {
+                        let mut part = format!(
+                            "--frame\r\ncontent-type: image/jpeg\r\ncontent-length: {}\r\n\r\n",
+                            jpeg.len()
+                        )
+                        .into_bytes();
+                        part.extend(jpeg);
+                        part.extend(b"\r\n");
+                        return Some((Ok::<_, Infallible>(part), interval));
+                    }
+                    Err(e) => log::debug!("Could not capture the {} view: {:#}", view, e),
+                }
+            }
+        }
+    });
+
+    Response::builder()
+        .header("content-type", "multipart/x-mixed-replace; boundary=frame")
+        .header("cache-control", "no-cache")
+        .body(Body::wrap_stream(frames))
+        .expect("The response is valid")
+}
+
+// Upgrades to a WebSocket that gets every event from now on
+fn events(gateway: &Gateway, request: Request<Body>) -> Response<Body> {
+    let accept = match request.headers().get(header::SEC_WEBSOCKET_KEY) {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &anyhow::format_err!("/events is a WebSocket"),
+            )
+        }
+    };
+
+    let mut events = gateway.events.subscribe();
+    tokio::spawn(async move {
+        let upgraded = match hyper::upgrade::on(request).await {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                log::debug!("The WebSocket upgrade failed: {}", e);
+                return;
+            }
+        };
+        let mut socket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        if socket.send(Message::Text(event)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        log::debug!("A WebSocket client missed {} events", missed);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                // the clients have nothing to say, only whether they are gone
+                message = socket.next() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::UPGRADE, "websocket")
+        .header(header::CONNECTION, "upgrade")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Body::empty())
+        .expect("The response is valid")
+}
+
+// Turns the changes of the status into events, while anyone is listening
+async fn watch_status(gateway: Arc<Gateway>) {
+    let mut last: Option<EditorStatus> = None;
+
+    loop {
+        tokio::time::sleep(STATUS_POLL_DELAY).await;
+        if gateway.events.receiver_count() == 0 {
+            last = None;
+            continue;
+        }
+
+        let status = match gateway.client.status().await {
+            Ok(status) => status,
+            Err(e) => {
+                log::debug!("Could not get the status of the editor: {:#}", e);
+                continue;
+            }
+        };
+
+        if let Some(last) = &last {
+            let changes = [
+                (
+                    last.compiling,
+                    status.compiling,
+                    "compile_started",
+                    "compile_finished",
+                ),
+                (last.playing, status.playing, "play_started", "play_stopped"),
+                (last.paused, status.paused, "paused", "resumed"),
+            ];
+            for &(was, is, started, stopped) in changes.iter() {
+                if was != is {
+                    let event = if is { started } else { stopped };
+                    let _ = gateway.events.send(json!({ "event": event }).to_string());
+                }
+            }
+        }
+        last = Some(status);
+    }
+}
+
+// The console of the editor, as log events, while someone listens. Subscribes again if
+// the stream fails
+async fn forward_logs(gateway: Arc<Gateway>) {
+    loop {
+        tokio::time::sleep(STATUS_POLL_DELAY).await;
+        if gateway.events.receiver_count() == 0 {
+            continue;
+        }
+
+        // dropping the stream ends the follow on the editor, once the last client is gone
+        let mut logs = gateway.client.logs("info", true);
+        while gateway.events.receiver_count() > 0 {
+            let log = match tokio::time::timeout(STATUS_POLL_DELAY, logs.next()).await {
+                Ok(Some(log)) => log,
+                Ok(None) => break,
+                Err(_) => continue,
+            };
+            let event = match log {
+                Ok(log) => json!({
+                    "event": "log",
+                    "kind": log.kind,
+                    "message": log.message,
+                    "stack": log.stack,
+                }),
+                Err(e) => json!({ "event": "error", "error": JsonError::new(&e) }),
+            };
+            // the last client may have just left
+            let _ = gateway.events.send(event.to_string());
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! uwu service install: runs the background watcher of a project at login, as a systemd
+//! user unit, a launchd agent or a Windows logon task. The service runs `uwu watch
+//! --daemon` like `uwu watch start` does, so `uwu watch status` and `uwu watch stop` work
+//! on it
+
+use crate::{daemon, discovery, print_result};
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Systemd,
+    Launchd,
+    WinService,
+}
+
+impl Kind {
+    /// The service manager of this platform
+    pub fn native() -> Kind {
+        if cfg!(windows) {
+            Kind::WinService
+        } else if cfg!(target_os = "macos") {
+            Kind::Launchd
+        } else {
+            Kind::Systemd
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Service {
+    kind: Kind,
+    name: String,
+    /// Where the definition was written, Windows keeps its tasks itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+    /// Whether it was started now, a watcher that already runs isn't replaced
+    started: bool,
+}
+
+fn home() -> anyhow::Result<PathBuf> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .context("HOME is not set")
+}
+
+// One service per project, named after its folder
+fn service_name(project: &Path) -> String {
+    let name: String = project
+        .file_name()
+        .map_or_else(|| "project".into(), |name| name.to_string_lossy())
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("uwu-watch-{}", name.trim_matches('-'))
+}
+
+fn run(program: &str, args: &[&str]) -> anyhow::Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Could not run {}", program))?;
+    if !output.status.success() {
+        bail!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn write(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let dir = path.parent().expect("Files are in a folder");
+    fs::create_dir_all(dir)
+        .and_then(|()| fs::write(path, contents))
+        .with_context(|| format!("Could not write {}", path.display()))
+}
+
+// systemd splits ExecStart on spaces unless quoted, and expands % itself
+fn systemd_quote(arg: &Path) -> String {
+    let arg = arg.display().to_string();
+    format!(
+        "\"{}\"",
+        arg.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn systemd(exe: &Path, project: &Path, name: &str, start: bool) -> anyhow::Result<PathBuf> {
+    let path = home()?
+        .join(".config")
+        .join("systemd")
+        .join("user")
+        .join(format!("{}.service", name));
+    // a watcher stopped by uwu watch stop exits cleanly, and stays stopped
+    let unit = format!(
+        "[Unit]\n\
+        Description=uwu watch {project}\n\
+        \n\
+        [Service]\n\
+        ExecStart={exe} --project {quoted} watch --daemon\n\
+        Restart=on-failure\n\
+        RestartSec=5\n\
+        \n\
+        [Install]\n\
+        WantedBy=default.target\n",
+        project = project.display(),
+        exe = systemd_quote(exe),
+        quoted = systemd_quote(project),
+    );
+    write(&path, &unit)?;
+
+    run("systemctl", &["--user", "daemon-reload"])?;
+    let unit = format!("{}.service", name);
+    if start {
+        run("systemctl", &["--user", "enable", "--now", &unit])?;
+    } else {
+        run("systemctl", &["--user", "enable", &unit])?;
+    }
+    Ok(path)
+}
+
+fn launchd(exe: &Path, project: &Path, name: &str, start: bool) -> anyhow::Result<PathBuf> {
+    let label = format!("com.github.tomcc.{}", name);
+    let path = home()?
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{}.plist", label));
+
+    let arguments: String = [
+        exe.display().to_string(),
+        "--project".into(),
+        project.display().to_string(),
+        "watch".into(),
+        "--daemon".into(),
+    ]
+    .iter()
+    .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+    .collect();
+    let log = xml_escape(&daemon::log_path(project).display().to_string());
+    let plist = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+        \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+        <plist version=\"1.0\">\n\
+        <dict>\n\
+        \x20   <key>Label</key>\n\
+        \x20   <string>{label}</string>\n\
+        \x20   <key>ProgramArguments</key>\n\
+        \x20   <array>\n\
+        {arguments}\
+        \x20   </array>\n\
+        \x20   <key>RunAtLoad</key>\n\
+        \x20   <true/>\n\
+        \x20   <key>KeepAlive</key>\n\
+        \x20   <dict>\n\
+        \x20       <key>SuccessfulExit</key>\n\
+        \x20       <false/>\n\
+        \x20   </dict>\n\
+        \x20   <key>StandardOutPath</key>\n\
+        \x20   <string>{log}</string>\n\
+        \x20   <key>StandardErrorPath</key>\n\
+        \x20   <string>{log}</string>\n\
+        </dict>\n\
+        </plist>\n",
+        label = label,
+        arguments = arguments,
+        log = log,
+    );
+    write(&path, &plist)?;
+
+    // RunAtLoad starts it when loaded, a later login loads it again
+    if start {
+        let path = path.to_string_lossy();
+        // loading it twice fails, so the old definition goes first
+        let _ = run("launchctl", &["unload", &path]);
+        run("launchctl", &["load", "-w", &path])?;
+    }
+    Ok(path)
+}
+
+// Windows services run before login as another user, so the watcher of the user is a
+// task that runs at their logon
+fn win_service(exe: &Path, project: &Path, name: &str, start: bool) -> anyhow::Result<()> {
+    let command = format!(
+        "\"{}\" --project \"{}\" watch --daemon",
+        exe.display(),
+        project.display()
+    );
+    run(
+        "schtasks",
+        &[
+            "/Create", "/F", "/SC", "ONLOGON", "/RL", "LIMITED", "/TN", name, "/TR", &command,
+        ],
+    )?;
+    if start {
+        run("schtasks", &["/Run", "/TN", name])?;
+    }
+    Ok(())
+}
+
+/// Installs a service that watches the project from login on, and starts it unless a
+/// watcher already runs
+pub async fn install(project: &Path, kind: Kind) -> anyhow::Result<()> {
+    if discovery::find_project(project).as_deref() != Some(project) {
+        bail!(
+            "{} is not a Unity project, pass one to --project",
+            project.display()
+        );
+    }
+    // the service doesn't start where uwu did
+    let project = project
+        .canonicalize()
+        .with_context(|| format!("No project at {}", project.display()))?;
+    let exe = std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("Could not find the uwu executable")?;
+
+    let name = service_name(&project);
+    let start = daemon::status(&project).await?.is_none();
+    let path = match kind {
+        Kind::Systemd => Some(systemd(&exe, &project, &name, start)?),
+        Kind::Launchd => Some(launchd(&exe, &project, &name, start)?),
+        Kind::WinService => {
+            win_service(&exe, &project, &name, start)?;
+            None
+        }
+    };
+
+    let service = Service {
+        kind,
+        name,
+        path,
+        started: start,
+    };
+    print_result(&service, |service| {
+        match &service.path {
+            Some(path) => println!("Installed {} into {}", service.name, path.display()),
+            None => println!("Installed the logon task {}", service.name),
+        }
+        if service.started {
+            println!("Started it, see uwu watch status");
+        } else {
+            println!("A watcher already runs, the service takes over at the next login");
+        }
+    });
+    Ok(())
+}
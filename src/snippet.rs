@@ -0,0 +1,98 @@
+use crate::progress::{self, BLUE, BOLD, RED, YELLOW};
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+use uwu_core::protocol::CompilerMessage;
+
+// the paths of the compiler are relative to the project
+static PROJECT: OnceCell<PathBuf> = OnceCell::new();
+
+pub fn set_project(project: &Path) {
+    PROJECT
+        .set(project.to_path_buf())
+        .expect("The project is only set once");
+}
+
+// Unity starts its messages with the code of the compiler, like CS0103: The name...
+fn split_code(message: &str) -> (Option<&str>, &str) {
+    match message.split_once(": ") {
+        Some((code, rest)) if code.len() > 2 && code.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            (Some(code), rest)
+        }
+        _ => (None, message),
+    }
+}
+
+fn source_line(file: &str, line: u32) -> Option<String> {
+    let path = match PROJECT.get() {
+        Some(project) => project.join(file),
+        None => PathBuf::from(file),
+    };
+    let source = std::fs::read_to_string(path).ok()?;
+    source
+        .lines()
+        .nth(line.checked_sub(1)? as usize)
+        .map(|line| line.trim_end().to_string())
+}
+
+/// A compiler message the way rustc shows them: what went wrong, where, and the line of
+/// the source with a caret under the column. Without the source, only the first two lines
+fn render(message: &CompilerMessage, colors: bool) -> String {
+    let paint = |text: &str, color: &str| {
+        if colors {
+            progress::paint(text, color)
+        } else {
+            text.to_string()
+        }
+    };
+    let color = if message.kind == "error" { RED } else { YELLOW };
+
+    let (code, text) = split_code(&message.message);
+    let title = match code {
+        Some(code) => format!("{}[{}]", message.kind, code),
+        None => message.kind.clone(),
+    };
+    let mut out = format!(
+        "{}{}",
+        paint(&title, color),
+        paint(&format!(": {}", text), BOLD)
+    );
+
+    let number = message.line.to_string();
+    let margin = " ".repeat(number.len());
+    out.push_str(&format!(
+        "\n{}{} {}:{}:{}",
+        margin,
+        paint("-->", BLUE),
+        message.file,
+        message.line,
+        message.column
+    ));
+
+    if let Some(source) = source_line(&message.file, message.line) {
+        let gutter = paint("|", BLUE);
+        // tabs stay tabs, so that the caret lines up however wide they are
+        let indent: String = source
+            .chars()
+            .take(message.column.saturating_sub(1) as usize)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        out.push_str(&format!("\n{} {}", margin, gutter));
+        out.push_str(&format!("\n{} {} {}", paint(&number, BLUE), gutter, source));
+        out.push_str(&format!(
+            "\n{} {} {}{}",
+            margin,
+            gutter,
+            indent,
+            paint("^", color)
+        ));
+    }
+
+    // a blank line between messages, like rustc
+    out.push('\n');
+    out
+}
+
+/// How message looks on stream, colored if it's a terminal
+pub fn pretty(message: &CompilerMessage, stream: atty::Stream) -> String {
+    render(message, progress::colors(stream))
+}
@@ -0,0 +1,196 @@
+use crate::print_result;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uwu_core::protocol::Timings;
+
+/// A refresh or a build, one JSON line each in the history of the project
+#[derive(Debug, Deserialize, Serialize)]
+struct Cycle {
+    /// Seconds since the Unix epoch
+    time: u64,
+    /// refresh or build
+    command: String,
+    #[serde(flatten)]
+    timings: Timings,
+}
+
+// in Library, which belongs to this machine like the timings do
+fn history_path(project: &Path) -> PathBuf {
+    project.join("Library").join("uwu-timings.jsonl")
+}
+
+/// Adds a cycle to the history of the project, and its duration to the span of the
+/// command. The history is only nice to have, so failing to write it doesn't fail the command
+pub fn record(project: &Path, command: &str, timings: &Timings) {
+    if let Some(total_secs) = timings.total_secs {
+        tracing::Span::current().record("editor_secs", total_secs);
+    }
+
+    let cycle = Cycle {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+        command: command.to_string(),
+        timings: timings.clone(),
+    };
+
+    let path = history_path(project);
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            let line = serde_json::to_string(&cycle).expect("Cycles are valid JSON");
+            writeln!(file, "{}", line)
+        });
+    if let Err(e) = written {
+        log::debug!("Could not write to {}: {}", path.display(), e);
+    }
+}
+
+fn phases(timings: &Timings) -> [(&'static str, Option<f64>); 4] {
+    [
+        ("import", timings.import_secs),
+        ("compile", timings.compile_secs),
+        ("reload", timings.reload_secs),
+        ("total", timings.total_secs),
+    ]
+}
+
+/// The phases that happened, like "import 1.2s, compile 3.4s, reload 2.1s, total 7.0s"
+pub fn describe(timings: &Timings) -> String {
+    phases(timings)
+        .iter()
+        .filter_map(|(name, secs)| secs.map(|secs| format!("{} {:.1}s", name, secs)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Durations from the shortest to the longest
+pub fn sorted(secs: &[f64]) -> Vec<f64> {
+    let mut sorted = secs.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).expect("Durations are never NaN"));
+    sorted
+}
+
+/// The duration that this share of the runs took at most, the nearest one
+pub fn quantile(sorted: &[f64], quantile: f64) -> f64 {
+    sorted[((sorted.len() - 1) as f64 * quantile).round() as usize]
+}
+
+/// How long a phase takes over the runs
+#[derive(Debug, Serialize)]
+struct Phase {
+    last: f64,
+    median: f64,
+    p90: f64,
+    max: f64,
+}
+
+impl Phase {
+    // secs in the order of the runs, oldest first
+    fn of(secs: &[f64]) -> Option<Phase> {
+        let last = *secs.last()?;
+        let sorted = sorted(secs);
+
+        Some(Phase {
+            last,
+            median: quantile(&sorted, 0.5),
+            p90: quantile(&sorted, 0.9),
+            max: *sorted.last().expect("Not empty"),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+    command: String,
+    runs: usize,
+    phases: BTreeMap<&'static str, Phase>,
+}
+
+fn summarize(command: &str, cycles: &[&Cycle]) -> Summary {
+    let mut phases = BTreeMap::new();
+    for (index, (name, _)) in self::phases(&Timings::default()).iter().enumerate() {
+        let secs: Vec<f64> = cycles
+            .iter()
+            .filter_map(|cycle| self::phases(&cycle.timings)[index].1)
+            .collect();
+        if let Some(phase) = Phase::of(&secs) {
+            phases.insert(*name, phase);
+        }
+    }
+
+    Summary {
+        command: command.to_string(),
+        runs: cycles.len(),
+        phases,
+    }
+}
+
+/// uwu stats: how long the last refreshes and builds of the project took, for each phase
+pub fn stats(project: &Path, command: Option<&str>, last: usize) -> anyhow::Result<()> {
+    let path = history_path(project);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Could not read {}", path.display())),
+    };
+
+    let cycles: Vec<Cycle> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(cycle) => Some(cycle),
+            Err(e) => {
+                log::debug!("Skipping a line of {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+
+    let summaries: Vec<Summary> = ["refresh", "build"]
+        .iter()
+        .filter(|name| command.map_or(true, |command| command == **name))
+        .map(|name| {
+            let of_command: Vec<&Cycle> = cycles
+                .iter()
+                .filter(|cycle| cycle.command == *name)
+                .collect();
+            let start = of_command.len().saturating_sub(last);
+            summarize(name, &of_command[start..])
+        })
+        .filter(|summary| summary.runs > 0)
+        .collect();
+
+    print_result(&summaries, |summaries| {
+        if summaries.is_empty() {
+            println!(
+                "No refresh or build was timed yet, uwu refresh and uwu build keep them in {}",
+                path.display()
+            );
+        }
+
+        for summary in summaries {
+            println!("{} ({} runs)", summary.command, summary.runs);
+            println!(
+                "  {:<8} {:>8} {:>8} {:>8} {:>8}",
+                "", "last", "median", "p90", "max"
+            );
+            for (name, phase) in &summary.phases {
+                println!(
+                    "  {:<8} {:>7.1}s {:>7.1}s {:>7.1}s {:>7.1}s",
+                    name, phase.last, phase.median, phase.p90, phase.max
+                );
+            }
+        }
+    });
+    Ok(())
+}
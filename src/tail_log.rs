@@ -0,0 +1,173 @@
+use crate::{
+    json_output,
+    progress::{self, DIM, RED},
+};
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// how often --follow checks whether the editor wrote more
+const FOLLOW_POLL_DELAY: Duration = Duration::from_millis(250);
+
+// the .cs(12,5): error CS0103 of the compiler, the exceptions, and what Debug.LogError says
+static ERROR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(: error |^Error|^\w*Exception\b|Exception: |^Assertion failed)")
+        .expect("The regex is valid")
+});
+// the frames of Unity, Namespace.Class:Method (args), those of Mono, and where they are
+static STACK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\S+:\S+ ?\(.*\)( \(at .*\))?$|\s+at |\(Filename: )").expect("The regex is valid")
+});
+
+/// Where the editor writes Editor.log, the log of its last start
+pub fn editor_log_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(|local| {
+            PathBuf::from(local)
+                .join("Unity")
+                .join("Editor")
+                .join("Editor.log")
+        })
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join("Library")
+                .join("Logs")
+                .join("Unity")
+                .join("Editor.log")
+        })
+    } else {
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join(".config")
+                .join("unity3d")
+                .join("Editor.log")
+        })
+    }
+}
+
+fn kind(line: &str) -> &'static str {
+    if STACK.is_match(line) {
+        "stack"
+    } else if ERROR.is_match(line) {
+        "error"
+    } else {
+        "info"
+    }
+}
+
+fn print_line(line: &str, colors: bool) {
+    let kind = kind(line);
+    if json_output() {
+        println!("{}", serde_json::json!({ "kind": kind, "line": line }));
+        return;
+    }
+
+    match kind {
+        "error" if colors => println!("{}", progress::paint(line, RED)),
+        "stack" if colors => println!("{}", progress::paint(line, DIM)),
+        _ => println!("{}", line),
+    }
+}
+
+/// Editor.log, line by line. Tracks where it got to, and starts over when the editor
+/// truncates the log on a restart
+struct Tail {
+    path: PathBuf,
+    offset: u64,
+    // the end of a line that isn't written yet
+    partial: String,
+}
+
+impl Tail {
+    fn read_lines(&mut self) -> anyhow::Result<Vec<String>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            // between two starts of the editor
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Could not open {}", self.path.display()))
+            }
+        };
+
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            log::debug!("{} was truncated, starting over", self.path.display());
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        self.offset += bytes.len() as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&bytes));
+
+        let complete = match self.partial.rfind('\n') {
+            Some(end) => end + 1,
+            None => return Ok(vec![]),
+        };
+        let rest = self.partial.split_off(complete);
+        let text = std::mem::replace(&mut self.partial, rest);
+        Ok(text
+            .lines()
+            .map(|line| line.trim_end_matches('\r').to_string())
+            .collect())
+    }
+}
+
+/// Prints the last lines of Editor.log that match filter, then what the editor writes
+/// next with follow. Reads the file rather than asking the plugin, so it works while the
+/// plugin is stuck or not even loaded
+pub async fn tail_log(
+    path: &Path,
+    lines: usize,
+    filter: Option<&Regex>,
+    follow: bool,
+) -> anyhow::Result<()> {
+    let colors = progress::colors(atty::Stream::Stdout);
+    let matches = |line: &String| filter.map_or(true, |filter| filter.is_match(line));
+
+    let mut tail = Tail {
+        path: path.to_path_buf(),
+        offset: 0,
+        partial: String::new(),
+    };
+    if !follow && !path.exists() {
+        anyhow::bail!("There is no {}, the editor never ran", path.display());
+    }
+
+    let mut existing = tail.read_lines()?;
+    // the editor may be done writing without ending the line
+    if !follow && !tail.partial.is_empty() {
+        existing.push(std::mem::take(&mut tail.partial));
+    }
+
+    let mut last = VecDeque::with_capacity(lines);
+    for line in existing.into_iter().filter(matches) {
+        if last.len() == lines {
+            last.pop_front();
+        }
+        if lines > 0 {
+            last.push_back(line);
+        }
+    }
+    for line in &last {
+        print_line(line, colors);
+    }
+
+    while follow {
+        tokio::time::sleep(FOLLOW_POLL_DELAY).await;
+        for line in tail.read_lines()?.into_iter().filter(matches) {
+            print_line(&line, colors);
+        }
+    }
+    Ok(())
+}
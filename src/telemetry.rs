@@ -0,0 +1,47 @@
+//! --otel-endpoint: the spans of the commands, of the requests that they send and of their
+//! retries and waits, exported over OTLP. Without the otel feature nothing records them
+
+/// Exports the spans from now on to the OTLP collector at endpoint, over gRPC
+#[cfg(feature = "otel")]
+pub fn init(endpoint: &str) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use opentelemetry::{
+        sdk::{trace, Resource},
+        KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    // the batches go out from a thread of their own, the commands don't wait for them
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![
+            KeyValue::new("service.name", "uwu"),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ])))
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)
+        .with_context(|| format!("Could not export to {}", endpoint))?;
+
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)
+        .context("The spans already go somewhere else")?;
+    log::debug!("Exporting spans to {}", endpoint);
+    Ok(())
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn init(_endpoint: &str) -> anyhow::Result<()> {
+    anyhow::bail!("This uwu was built without the otel feature")
+}
+
+/// Sends the spans that are still in a batch, before uwu exits
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -0,0 +1,178 @@
+use crate::daemon;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::Print,
+    terminal,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+use tokio::sync::mpsc;
+use uwu_core::protocol::EditorStatus;
+
+// how many of the changed files stay on screen
+const RECENT_CHANGES: usize = 10;
+
+/// What the keys of the TUI ask the watcher to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Play,
+    Stop,
+    Refresh,
+    Quit,
+    /// The terminal was resized
+    Redraw,
+}
+
+fn action(key: KeyEvent) -> Option<Action> {
+    // Windows reports releases too
+    if key.kind != KeyEventKind::Press {
+        return None;
+    }
+
+    // raw mode turns Ctrl-C into a key instead of a signal
+    if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+        return Some(Action::Quit);
+    }
+
+    match key.code {
+        KeyCode::Char('p') => Some(Action::Play),
+        KeyCode::Char('s') => Some(Action::Stop),
+        KeyCode::Char('r') => Some(Action::Refresh),
+        KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+pub fn describe(status: &EditorStatus) -> &'static str {
+    if status.compiling {
+        "compiling"
+    } else if status.importing {
+        "importing"
+    } else if status.paused {
+        "paused"
+    } else if status.playing {
+        "playing"
+    } else {
+        "idle"
+    }
+}
+
+/// The live view of `uwu watch --tui`. Owns the terminal until dropped, so nothing else
+/// should print meanwhile
+pub struct Tui {
+    project: PathBuf,
+    stats: Arc<daemon::Stats>,
+    recent: VecDeque<PathBuf>,
+    editor: String,
+    status: String,
+}
+
+impl Tui {
+    pub fn start(
+        project: &Path,
+        stats: Arc<daemon::Stats>,
+    ) -> anyhow::Result<(Tui, mpsc::UnboundedReceiver<Action>)> {
+        terminal::enable_raw_mode()?;
+        // from here on, dropping it gives the terminal back
+        let tui = Tui {
+            project: project.to_path_buf(),
+            stats,
+            recent: VecDeque::new(),
+            editor: "connecting".to_string(),
+            status: "Watching".to_string(),
+        };
+        crossterm::execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        // like notify, crossterm only reads the keys blocking
+        let (tx, rx) = mpsc::unbounded_channel();
+        std::thread::spawn(move || loop {
+            let action = match event::read() {
+                Ok(Event::Key(key)) => match action(key) {
+                    Some(action) => action,
+                    None => continue,
+                },
+                Ok(Event::Resize(..)) => Action::Redraw,
+                Ok(_) => continue,
+                Err(_) => break,
+            };
+            if tx.send(action).is_err() {
+                break;
+            }
+        });
+
+        tui.draw();
+        Ok((tui, rx))
+    }
+
+    /// Doesn't draw, so that a batch of changes is drawn once
+    pub fn changed(&mut self, path: &Path) {
+        self.recent.retain(|recent| recent != path);
+        self.recent.push_front(path.to_path_buf());
+        self.recent.truncate(RECENT_CHANGES);
+    }
+
+    pub fn set_editor(&mut self, editor: &str) {
+        if self.editor != editor {
+            self.editor = editor.to_string();
+            self.draw();
+        }
+    }
+
+    pub fn report(&mut self, message: &str) {
+        self.status = message.to_string();
+        self.draw();
+    }
+
+    /// There's nowhere to report a failure to draw, the next draw tries again
+    pub fn draw(&self) {
+        let _ = self.try_draw();
+    }
+
+    fn try_draw(&self) -> io::Result<()> {
+        let mut lines = vec![
+            format!("uwu watch {}", self.project.display()),
+            String::new(),
+            format!("Unity:     {}", self.editor),
+            format!("Status:    {}", self.status),
+            format!(
+                "Changes:   {}   Refreshes: {}",
+                self.stats.events.load(Ordering::Relaxed),
+                self.stats.refreshes.load(Ordering::Relaxed)
+            ),
+            String::new(),
+            "Recent changes:".to_string(),
+        ];
+        if self.recent.is_empty() {
+            lines.push("  none yet".to_string());
+        }
+        for path in &self.recent {
+            let relative = path.strip_prefix(&self.project).unwrap_or(path);
+            lines.push(format!("  {}", relative.display()));
+        }
+        lines.push(String::new());
+        lines.push("p play   s stop   r refresh   q quit".to_string());
+
+        let (width, height) = terminal::size()?;
+        let mut stdout = io::stdout();
+        queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+        for (row, line) in lines.iter().take(height as usize).enumerate() {
+            // long lines would wrap and push the rest down
+            let line: String = line.chars().take(width as usize).collect();
+            queue!(stdout, cursor::MoveTo(0, row as u16), Print(line))?;
+        }
+        stdout.flush()
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -0,0 +1,109 @@
+//! uwu view: captures of the Game view, written into a folder one after the other. `uwu
+//! serve` streams the same captures as MJPEG, on GET /view
+
+use crate::{fetch_transfer, print_result, single_command};
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::time::MissedTickBehavior;
+use uwu_core::{
+    protocol::{Command, Transfer},
+    send::decode_payload,
+    transport::Endpoint,
+};
+
+pub struct Options {
+    pub view: String,
+    /// 0 lets the editor pick
+    pub width: u32,
+    pub height: u32,
+    /// JPEG quality, or PNG if 0
+    pub quality: u32,
+    pub fps: f64,
+    /// Until interrupted, otherwise a single frame
+    pub follow: bool,
+    pub out_dir: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct Frames {
+    out_dir: PathBuf,
+    frames: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last: Option<PathBuf>,
+    /// How many frames per second the editor kept up with
+    fps: f64,
+}
+
+async fn capture(endpoint: &Endpoint, options: &Options) -> anyhow::Result<Vec<u8>> {
+    let screenshot = Command::Screenshot {
+        view: options.view.clone(),
+        width: options.width,
+        height: options.height,
+        quality: options.quality,
+    };
+    let transfer: Transfer = decode_payload(single_command(endpoint, screenshot).await?)?;
+    fetch_transfer(endpoint, &transfer).await
+}
+
+/// Captures the view into the folder, at fps until Ctrl+C with follow. Frames that the
+/// editor is too slow for are skipped
+pub async fn view(endpoint: &Endpoint, options: &Options) -> anyhow::Result<()> {
+    if !options.fps.is_finite() || options.fps <= 0.0 {
+        bail!("--fps must be a positive number");
+    }
+    fs::create_dir_all(&options.out_dir)
+        .with_context(|| format!("Could not create {}", options.out_dir.display()))?;
+    let extension = if options.quality > 0 { "jpg" } else { "png" };
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / options.fps));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+
+    let started = Instant::now();
+    let mut frames = Frames {
+        out_dir: options.out_dir.clone(),
+        frames: 0,
+        last: None,
+        fps: 0.0,
+    };
+    loop {
+        let frame = async {
+            interval.tick().await;
+            capture(endpoint, options).await
+        };
+        let image = tokio::select! {
+            image = frame => image?,
+            _ = &mut interrupted => break,
+        };
+
+        frames.frames += 1;
+        let path = options
+            .out_dir
+            .join(format!("frame-{:06}.{}", frames.frames, extension));
+        fs::write(&path, image).with_context(|| format!("Could not write {}", path.display()))?;
+        log::info!("Saved {}", path.display());
+        frames.last = Some(path);
+
+        if !options.follow {
+            break;
+        }
+    }
+    frames.fps = frames.frames as f64 / started.elapsed().as_secs_f64();
+
+    print_result(&frames, |frames| match (&frames.last, options.follow) {
+        (Some(last), false) => println!("Saved {}", last.display()),
+        _ => println!(
+            "Saved {} frames into {}, {:.1} per second",
+            frames.frames,
+            frames.out_dir.display(),
+            frames.fps
+        ),
+    });
+    Ok(())
+}
@@ -0,0 +1,1161 @@
+use crate::{
+    daemon, handshake, new_request, send_command, settings,
+    tui::{self, Action, Tui},
+    wait_compile, webhook,
+};
+use anyhow::{bail, Context};
+use futures_util::future::join_all;
+use globset::GlobSet;
+use ignore::gitignore::Gitignore;
+use notify::{
+    event::{MetadataKind, ModifyKind},
+    Event, EventKind, PollWatcher, RecursiveMode, Watcher,
+};
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, Instant},
+};
+use sysinfo::{Pid, PidExt, ProcessExt, Signal, System, SystemExt};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    sync::{mpsc, oneshot},
+};
+use tracing::Instrument;
+use uwu_core::{
+    protocol::{Command, EditorStatus, PlayModeError, PlayModeResult, TestReport},
+    send::{self, decode_payload},
+    transport::Endpoint,
+    UwuError,
+};
+
+// how long to wait before checking again if Unity came back online
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+// enough to get over a short script reload without giving up on the editor
+const REFRESH_ATTEMPTS: u32 = 3;
+// how often to check that the editor is still running, even if nothing changes
+const ALIVE_CHECK_DELAY: Duration = Duration::from_secs(5);
+// how often the TUI asks the editor what it's doing
+const STATUS_POLL_DELAY: Duration = Duration::from_secs(1);
+
+/// How `uwu watch` reacts to changes
+pub struct Options {
+    /// Only start a refresh once nothing changed for this long
+    pub delay: Duration,
+    /// Scan the folders this often instead of relying on the OS, which misses the changes
+    /// made on network drives and in some containers
+    pub poll: Option<Duration>,
+    /// Clear the console before each refresh, so that only the new errors are left
+    pub clear: bool,
+    /// Changes to these paths, relative to the project, never trigger a refresh
+    pub ignore: GlobSet,
+    /// Only changes to files with these extensions trigger a refresh, if any are given
+    pub extensions: Vec<String>,
+    /// Recompile for script changes instead of refreshing
+    pub smart: bool,
+    /// Folders to watch on top of the project, like shared code outside of it
+    pub extra_paths: Vec<PathBuf>,
+    /// Shell commands to run before and after each refresh, see run_hook
+    pub exec: Option<String>,
+    pub exec_after: Option<String>,
+    /// Started by `uwu watch start`, serve the control socket
+    pub daemon: bool,
+    /// Stop play mode for the refresh, and start it again once the scripts compiled
+    pub restart_play: bool,
+    /// With restart_play, stop Play mode as soon as the game logs an error
+    pub stop_on_error: bool,
+    /// Run the Edit mode tests once the scripts compiled, only those that match the filter
+    pub test: bool,
+    pub test_filter: Option<String>,
+    /// Show a live view of the changes and of the editor instead of printing
+    pub tui: bool,
+    /// Print the changes and what would be sent for them, without contacting the editor
+    pub dry_run: bool,
+}
+
+/// A path that changed, and what notify saw happen to it
+struct Changed {
+    path: PathBuf,
+    kind: EventKind,
+}
+
+/// What needs to be done for a batch of changes
+#[derive(Debug, Default, Clone)]
+struct Changes {
+    assets: bool,
+    scripts: bool,
+    paths: BTreeSet<PathBuf>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        !self.assets && !self.scripts
+    }
+
+    fn merge(&mut self, other: Changes) {
+        self.assets |= other.assets;
+        self.scripts |= other.scripts;
+        self.paths.extend(other.paths);
+    }
+}
+
+// Runs a --exec command with the shell, from the project folder. The changed paths are in
+// UWU_CHANGED_PATHS, one per line
+async fn run_hook(command: &str, project: &Path, changes: &Changes) -> anyhow::Result<()> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = tokio::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = tokio::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    let paths: Vec<_> = changes
+        .paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+
+    log::debug!("Running `{}`", command);
+    let status = shell
+        .arg(command)
+        .current_dir(project)
+        .env("UWU_PROJECT", project)
+        .env("UWU_CHANGED_PATHS", paths.join("\n"))
+        .status()
+        .await
+        .with_context(|| format!("Could not run `{}`", command))?;
+
+    if !status.success() {
+        bail!("`{}` failed with {}", command, status);
+    }
+    Ok(())
+}
+
+fn is_script(path: &Path) -> bool {
+    path.extension().map_or(false, |extension| {
+        extension == "cs" || extension == "asmdef"
+    })
+}
+
+/// Keeps track of whether the editor can be reached, so that an editor restart doesn't
+/// flood the output with errors, and changes made while it was down still get refreshed
+struct Connection {
+    endpoint: Endpoint,
+    project: PathBuf,
+    options: Options,
+    online: bool,
+    stats: Arc<daemon::Stats>,
+    // what changed while the editor was offline
+    pending: Changes,
+    reconnect_delay: Duration,
+    last_error: Option<String>,
+    tui: Option<Tui>,
+    // where the errors of the game go, see watch_play
+    play_errors: Option<mpsc::UnboundedSender<PlayModeError>>,
+}
+
+impl Connection {
+    fn new(endpoint: Endpoint, project: PathBuf, options: Options) -> Self {
+        let stats = Arc::new(daemon::Stats::default());
+        stats.online.store(true, Ordering::Relaxed);
+
+        Connection {
+            endpoint,
+            project,
+            options,
+            online: true,
+            stats,
+            pending: Changes::default(),
+            reconnect_delay: MIN_RECONNECT_DELAY,
+            last_error: None,
+            tui: None,
+            play_errors: None,
+        }
+    }
+
+    // the TUI owns the terminal, what would be printed goes into its status line
+    fn report(&mut self, message: &str) {
+        match &mut self.tui {
+            Some(tui) => tui.report(message),
+            None => println!("{}", message),
+        }
+    }
+
+    async fn send_refresh(&mut self, changes: &Changes) -> anyhow::Result<()> {
+        // a failing hook, like a linter, keeps the editor from refreshing
+        if let Some(exec) = &self.options.exec {
+            run_hook(exec, &self.project, changes).await?;
+        }
+
+        let endpoint = self.endpoint.clone();
+        let endpoint = &endpoint;
+
+        let restart_play = self.options.restart_play && {
+            let status = send_command(endpoint, Command::Status, Some(REFRESH_ATTEMPTS)).await?;
+            decode_payload::<EditorStatus>(status)?.playing
+        };
+        if restart_play {
+            self.report("Stopping play mode");
+            send_command(endpoint, Command::Stop, Some(REFRESH_ATTEMPTS)).await?;
+        }
+
+        if self.options.clear {
+            send_command(endpoint, Command::ClearConsole, Some(REFRESH_ATTEMPTS)).await?;
+        }
+        if changes.assets {
+            self.report("Refreshing");
+            send_command(endpoint, Command::BackgroundRefresh, Some(REFRESH_ATTEMPTS)).await?;
+        }
+        if changes.scripts {
+            self.report("Recompiling");
+            send_command(endpoint, Command::Build, Some(REFRESH_ATTEMPTS)).await?;
+        }
+
+        // with compile errors, play mode stays off and the tests don't run until the next
+        // change fixes them. The editor answers a background refresh before compiling, so
+        // --notify only hears of the errors by waiting for them
+        let notify = webhook::enabled() && (changes.assets || changes.scripts);
+        if restart_play || self.options.test || notify {
+            wait_compile(endpoint).await?;
+        }
+        // before Play mode, which the tests can't run in
+        if self.options.test {
+            self.run_tests(endpoint).await?;
+        }
+
+        if restart_play {
+            self.report("Restarting play mode");
+            let play = Command::Play {
+                scene: None,
+                fast: false,
+            };
+            send_command(endpoint, play, Some(REFRESH_ATTEMPTS)).await?;
+            send_command(endpoint, Command::CheckAlive, None).await?;
+            if self.options.stop_on_error {
+                self.watch_play();
+            }
+        }
+
+        if let Some(exec_after) = &self.options.exec_after {
+            run_hook(exec_after, &self.project, changes).await?;
+        }
+        Ok(())
+    }
+
+    // only the failures, the passing tests would bury them
+    async fn run_tests(&mut self, endpoint: &Endpoint) -> anyhow::Result<()> {
+        self.report("Running the tests");
+        let test = Command::Test {
+            mode: "editmode".to_string(),
+            filter: self.options.test_filter.clone(),
+            xml: false,
+        };
+        let report: TestReport = decode_payload(send_command(endpoint, test, None).await?)?;
+
+        for test in report.tests.iter().filter(|test| test.result == "Failed") {
+            self.report(&format!("FAIL {}", test.name));
+            if let Some(message) = &test.message {
+                self.report(&format!("    {}", message.trim().replace('\n', "\n    ")));
+            }
+        }
+        self.report(&format!(
+            "{} passed, {} failed, {} skipped",
+            report.passed, report.failed, report.skipped
+        ));
+
+        if report.failed > 0 {
+            bail!("{} tests failed", report.failed);
+        }
+        Ok(())
+    }
+
+    // what send_refresh would do, in the same order
+    fn print_dry_run(&mut self, changes: &Changes) {
+        let mut steps = vec![];
+        if let Some(exec) = &self.options.exec {
+            steps.push(format!("Would run `{}`", exec));
+        }
+        if self.options.restart_play {
+            steps.push("Would send Stop, if playing".to_string());
+        }
+        if self.options.clear {
+            steps.push("Would send ClearConsole".to_string());
+        }
+        if changes.assets {
+            steps.push("Would send BackgroundRefresh".to_string());
+        }
+        if changes.scripts {
+            steps.push("Would send Build".to_string());
+        }
+        if self.options.test {
+            steps.push("Would send Test, once the scripts compiled".to_string());
+        }
+        if self.options.restart_play {
+            steps.push("Would send Play, once the scripts compiled".to_string());
+        }
+        if let Some(exec_after) = &self.options.exec_after {
+            steps.push(format!("Would run `{}`", exec_after));
+        }
+
+        for step in steps {
+            self.report(&step);
+        }
+    }
+
+    async fn refresh(&mut self, changes: Changes) {
+        if self.options.dry_run {
+            self.print_dry_run(&changes);
+            return;
+        }
+
+        if !self.online {
+            self.pending.merge(changes);
+            return;
+        }
+
+        let started = Instant::now();
+        let span = tracing::info_span!(
+            "refresh",
+            assets = changes.assets,
+            scripts = changes.scripts
+        );
+        let result = self.send_refresh(&changes).instrument(span).await;
+        match result {
+            Ok(()) => {
+                self.last_error = None;
+                self.stats.refreshes.fetch_add(1, Ordering::Relaxed);
+                if let Some(tui) = &mut self.tui {
+                    tui.report("Up to date");
+                }
+            }
+            Err(e) if UwuError::is_unreachable(&e) => {
+                self.go_offline();
+                self.pending.merge(changes);
+            }
+            Err(e) => {
+                // only report an error once until something changes
+                let message = e.to_string();
+                if self.last_error.as_ref() != Some(&message) {
+                    match &mut self.tui {
+                        Some(tui) => tui.report(&format!("Error: {}", message)),
+                        None => log::error!("An error occurred: {}", message),
+                    }
+                    webhook::notify("watch refresh", started.elapsed(), &Err(e));
+                }
+                self.last_error = Some(message);
+            }
+        }
+
+        // NOTE: this may kill the server if scripts are reloaded,
+        // but as it is a background operation, we don't need to wait until it restarts;
+        // we can just queue more refresh requests
+    }
+
+    // add the paths that aren't ignored to a batch of changes
+    fn collect(
+        &mut self,
+        changes: &mut Changes,
+        changed: Vec<Changed>,
+        ignore_files: &[Gitignore],
+    ) -> usize {
+        let mut collected = 0;
+        for Changed { path, kind } in changed {
+            if is_ignored(&self.project, &self.options, ignore_files, &path) {
+                log::debug!("Ignoring changes to {}", path.display());
+                continue;
+            }
+
+            if self.options.dry_run {
+                let relative = path.strip_prefix(&self.project).unwrap_or(&path);
+                let message = format!("{:?} {}", kind, relative.display());
+                self.report(&message);
+            }
+
+            if self.options.smart && is_script(&path) {
+                changes.scripts = true;
+            } else {
+                changes.assets = true;
+            }
+            if let Some(tui) = &mut self.tui {
+                tui.changed(&path);
+            }
+            changes.paths.insert(path);
+            self.stats.events.fetch_add(1, Ordering::Relaxed);
+            collected += 1;
+        }
+
+        if let Some(tui) = &self.tui {
+            tui.draw();
+        }
+        collected
+    }
+
+    fn go_offline(&mut self) {
+        self.report("Unity is not running, waiting…");
+        if let Some(tui) = &mut self.tui {
+            tui.set_editor("offline");
+        }
+
+        self.online = false;
+        self.stats.online.store(false, Ordering::Relaxed);
+        self.reconnect_delay = MIN_RECONNECT_DELAY;
+    }
+
+    async fn try_reconnect(&mut self) {
+        log::debug!("Checking if Unity is back online");
+
+        // a single attempt, so that the watcher can keep collecting events meanwhile
+        if send_command(&self.endpoint, Command::CheckAlive, Some(1))
+            .await
+            .is_err()
+        {
+            self.reconnect_delay = (self.reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+            return;
+        }
+
+        self.report("Unity is back, resuming");
+        if let Some(tui) = &mut self.tui {
+            tui.set_editor("online");
+        }
+
+        self.online = true;
+        self.stats.online.store(true, Ordering::Relaxed);
+        self.last_error = None;
+
+        // catch up with everything that changed while offline with a single refresh
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.refresh(pending).await;
+        }
+    }
+
+    // Unity quitting doesn't tell anyone, notice it before a refresh fails. Status is answered
+    // even while the editor is busy importing, so a long import doesn't look like a shutdown
+    async fn check_alive(&mut self) {
+        let status = send_command(&self.endpoint, Command::Status, Some(REFRESH_ATTEMPTS)).await;
+        if let Err(e) = status {
+            if UwuError::is_unreachable(&e) {
+                self.go_offline();
+            }
+        }
+    }
+
+    // what the TUI shows about the editor
+    async fn poll_status(&mut self) {
+        let status = send_command(&self.endpoint, Command::Status, Some(1))
+            .await
+            .and_then(decode_payload::<EditorStatus>);
+
+        let editor = match &status {
+            Ok(status) => tui::describe(status),
+            // most likely reloading scripts, try_reconnect is for when a refresh can't get through
+            Err(e) if UwuError::is_unreachable(e) => "not responding",
+            Err(_) => "unknown",
+        };
+        if let Some(tui) = &mut self.tui {
+            tui.set_editor(editor);
+        }
+    }
+
+    // Waits for the first error of the game in the background, the watcher goes on meanwhile.
+    // Ends without one when Play mode stops, like for the next refresh
+    fn watch_play(&self) {
+        let errors = match &self.play_errors {
+            Some(errors) => errors.clone(),
+            None => return,
+        };
+        let endpoint = self.endpoint.clone();
+
+        tokio::spawn(async move {
+            let request = new_request(Command::WaitPlayMode {
+                stop_on_error: true,
+            });
+            match send::send(&endpoint, &settings(), &request, None, &mut ())
+                .await
+                .and_then(decode_payload::<PlayModeResult>)
+            {
+                Ok(played) => {
+                    if let Some(error) = played.error {
+                        let _ = errors.send(error);
+                    }
+                }
+                Err(e) => log::debug!("Stopped waiting for the errors of the game: {:#}", e),
+            }
+        });
+    }
+
+    async fn stop_play(&mut self, error: PlayModeError) {
+        self.report(&format!(
+            "The game failed in Play mode: {}",
+            error.message.trim()
+        ));
+        if let Some(stack) = &error.stack {
+            log::debug!("Stack trace of the game:\n{}", stack);
+        }
+        self.editor_command("Stopped playing", Command::Stop).await;
+    }
+
+    // the keys of the TUI that talk to the editor directly
+    async fn editor_command(&mut self, done: &str, command: Command) {
+        if self.options.dry_run {
+            self.report(&format!("Would send {:?}", command));
+            return;
+        }
+
+        let result = send_command(&self.endpoint, command, Some(REFRESH_ATTEMPTS)).await;
+        match result {
+            Ok(_) => self.report(done),
+            Err(e) if UwuError::is_unreachable(&e) => self.go_offline(),
+            Err(e) => self.report(&format!("Error: {}", e)),
+        }
+    }
+}
+
+// Editors save through temporary files that they rename over the real one, and keep swap files
+// next to it. Only the real file matters
+fn is_temporary(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    name.ends_with('~')
+        || name.ends_with(".swp")
+        || name.ends_with(".swx")
+        || name.ends_with(".tmp")
+        || name.ends_with("___jb_tmp___")
+        || name.ends_with("___jb_old___")
+        || name.starts_with(".#")
+        || (name.starts_with('#') && name.ends_with('#'))
+        // vim checks that it can write to the folder with this one
+        || name == "4913"
+}
+
+// the paths of the events that imply that a file is actually changed
+fn changed_paths(event: notify::Result<Event>) -> anyhow::Result<Vec<Changed>> {
+    let event = event?;
+
+    let changed = match event.kind {
+        EventKind::Create(_) | EventKind::Remove(_) => true,
+        // the poll watcher reports writes as a new modification time
+        EventKind::Modify(ModifyKind::Metadata(kind)) => kind == MetadataKind::WriteTime,
+        EventKind::Modify(_) => true,
+        EventKind::Access(_) => false,
+        // notify doesn't know what happened, better refresh for nothing than miss a change
+        EventKind::Any | EventKind::Other => true,
+    };
+    if !changed {
+        return Ok(vec![]);
+    }
+
+    let kind = event.kind;
+    Ok(event
+        .paths
+        .into_iter()
+        .filter(|path| !is_temporary(path))
+        .map(|path| Changed { path, kind })
+        .collect())
+}
+
+// Local packages live anywhere, manifest.json references them as "file:" paths relative to the
+// Packages folder. Embedded packages are inside Packages already, and tarballs don't change
+fn local_packages(project: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let packages = project.join("Packages");
+    let manifest = packages.join("manifest.json");
+    if !manifest.is_file() {
+        return Ok(vec![]);
+    }
+
+    let manifest: serde_json::Value = serde_json::from_slice(&std::fs::read(&manifest)?)
+        .with_context(|| format!("Invalid {}", manifest.display()))?;
+
+    let dependencies = manifest["dependencies"].as_object().into_iter().flatten();
+    Ok(dependencies
+        .filter_map(|(_, version)| version.as_str()?.strip_prefix("file:"))
+        .filter_map(|path| packages.join(path).canonicalize().ok())
+        .filter(|path| path.is_dir() && !path.starts_with(&packages))
+        .collect())
+}
+
+// The .gitignore files that apply to the project, from the project up to the root of its
+// repository, and the .uwuignore of the project that uses the same syntax. Deepest first
+fn load_ignore_files(project: &Path) -> Vec<Gitignore> {
+    let mut files = vec![project.join(".uwuignore")];
+    for dir in project.ancestors() {
+        files.push(dir.join(".gitignore"));
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+
+    let mut ignore_files = vec![];
+    for file in files.into_iter().filter(|file| file.is_file()) {
+        let (gitignore, error) = Gitignore::new(&file);
+        if let Some(error) = error {
+            log::warn!("Skipping invalid lines of {}: {}", file.display(), error);
+        }
+        log::debug!("Ignoring the paths listed in {}", file.display());
+        ignore_files.push(gitignore);
+    }
+    ignore_files
+}
+
+fn is_ignored(project: &Path, options: &Options, ignore_files: &[Gitignore], path: &Path) -> bool {
+    // patterns like Assets/Generated/** are relative to the project, *.meta matches anywhere
+    let relative = path.strip_prefix(project).unwrap_or(path);
+    if options.ignore.is_match(relative) {
+        return true;
+    }
+
+    let listed = |extension: &std::ffi::OsStr| {
+        let extension = extension.to_string_lossy();
+        options
+            .extensions
+            .iter()
+            .any(|listed| listed.eq_ignore_ascii_case(&extension))
+    };
+    if !options.extensions.is_empty() && !path.extension().map_or(false, listed) {
+        return true;
+    }
+
+    // like git, the deepest file that has an opinion wins, so that it can un-ignore paths
+    for gitignore in ignore_files {
+        if !path.starts_with(gitignore.path()) {
+            continue;
+        }
+        let matched = gitignore.matched_path_or_any_parents(path, path.is_dir());
+        if !matched.is_none() {
+            return matched.is_ignore();
+        }
+    }
+    false
+}
+
+pub async fn watch(endpoint: Endpoint, project: PathBuf, options: Options) -> anyhow::Result<()> {
+    println!("Watching project at {}", project.display());
+
+    // the paths of the events start like the watched path, and the ignore files need the
+    // real location of the project to find the repository
+    let project = project.canonicalize().unwrap_or(project);
+    let ignore_files = load_ignore_files(&project);
+
+    let path = project.join("Assets");
+
+    if !path.is_dir() {
+        return Err(anyhow::format_err!(
+            "Assets dir not found at {}. Are you sure that this is a valid Unity project?",
+            path.display()
+        ));
+    }
+
+    let mut paths = vec![path];
+    if project.join("Packages").is_dir() {
+        paths.push(project.join("Packages"));
+    }
+    paths.extend(local_packages(&project)?);
+    for path in &options.extra_paths {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Can't watch {}", path.display()))?;
+        paths.push(path);
+    }
+
+    // notify calls the handler from a thread of its own
+    let (tx, rx) = mpsc::unbounded_channel();
+    let handler = move |event: notify::Result<Event>| {
+        let _ = tx.send(changed_paths(event));
+    };
+
+    let mut watcher: Box<dyn Watcher> = match options.poll {
+        Some(interval) => {
+            let config = notify::Config::default().with_poll_interval(interval);
+            Box::new(PollWatcher::new(handler, config)?)
+        }
+        None => Box::new(notify::recommended_watcher(handler)?),
+    };
+
+    for path in paths {
+        log::debug!("Watching {}", path.display());
+        watcher
+            .watch(&path, RecursiveMode::Recursive)
+            .with_context(|| format!("Can't watch {}", path.display()))?;
+    }
+
+    let mut connection = Connection::new(endpoint, project, options);
+    let stats = connection.stats.clone();
+
+    let actions = if connection.options.tui {
+        let (tui, actions) = Tui::start(&connection.project, stats.clone())?;
+        connection.tui = Some(tui);
+        Some(actions)
+    } else {
+        None
+    };
+
+    // the TUI goes away with the connection, before the summary is printed
+    if connection.options.daemon {
+        let project = connection.project.clone();
+        tokio::select! {
+            result = watch_loop(connection, rx, actions, ignore_files) => result?,
+            result = daemon::serve(&project, stats.clone()) => result?,
+        }
+    } else {
+        watch_loop(connection, rx, actions, ignore_files).await?;
+    }
+
+    // the file watcher goes away with this function
+    println!(
+        "Stopped watching after {} changes and {} refreshes",
+        stats.events.load(Ordering::Relaxed),
+        stats.refreshes.load(Ordering::Relaxed)
+    );
+    Ok(())
+}
+
+// Ctrl-C stops the watcher once the refresh in flight is done. The signal is caught for the
+// whole run, so that those arriving during a refresh aren't lost. A second one quits anyway
+fn interrupted() -> oneshot::Receiver<()> {
+    let (tx, rx) = oneshot::channel();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            // no Ctrl-C handling on this system, which mustn't look like it was pressed
+            let _tx = tx;
+            return std::future::pending().await;
+        }
+        println!("Stopping, press Ctrl-C again to quit right away");
+        let _ = tx.send(());
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    rx
+}
+
+// the keys of the TUI, if it's on. None when the terminal is gone
+async fn next_action(actions: &mut Option<mpsc::UnboundedReceiver<Action>>) -> Option<Action> {
+    match actions {
+        Some(actions) => actions.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Waits until nothing changed for the delay, so that saving many files or everything that
+// piled up during the last refresh makes a single refresh. collect returns how many of the
+// paths count: ignored paths don't, so that they can't hold it back forever.
+// notify-debouncer-mini would lose the kinds of the events, and can't tell ignored paths
+async fn debounce(
+    rx: &mut mpsc::UnboundedReceiver<anyhow::Result<Vec<Changed>>>,
+    delay: Duration,
+    mut collect: impl FnMut(Vec<Changed>) -> usize,
+) -> anyhow::Result<()> {
+    let mut deadline = tokio::time::Instant::now() + delay;
+    while let Ok(event) = tokio::time::timeout_at(deadline, rx.recv()).await {
+        let changed = event.context("The file watcher stopped unexpectedly")??;
+        if collect(changed) > 0 {
+            deadline = tokio::time::Instant::now() + delay;
+        }
+    }
+    Ok(())
+}
+
+async fn watch_loop(
+    mut connection: Connection,
+    mut rx: mpsc::UnboundedReceiver<anyhow::Result<Vec<Changed>>>,
+    mut actions: Option<mpsc::UnboundedReceiver<Action>>,
+    ignore_files: Vec<Gitignore>,
+) -> anyhow::Result<()> {
+    let mut interrupted = interrupted();
+
+    // a dry run never talks to the editor, it stays "online" to print every refresh
+    let dry_run = connection.options.dry_run;
+
+    // if Unity isn't open yet, start offline and wait for it
+    if !dry_run {
+        match handshake(&connection.endpoint, Some(1)).await {
+            Ok(()) => {}
+            Err(e) if UwuError::is_unreachable(&e) => connection.go_offline(),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let (play_errors_tx, mut play_errors) = mpsc::unbounded_channel();
+    connection.play_errors = Some(play_errors_tx);
+
+    let polling = connection.tui.is_some() && !dry_run;
+    // unlike the other timers it keeps going through the events, as the editor can quit anytime
+    let mut liveness = tokio::time::interval_at(
+        tokio::time::Instant::now() + ALIVE_CHECK_DELAY,
+        ALIVE_CHECK_DELAY,
+    );
+    liveness.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let mut changes = Changes::default();
+                let changed = event.context("The file watcher stopped unexpectedly")??;
+                if connection.collect(&mut changes, changed, &ignore_files) == 0 {
+                    continue;
+                }
+
+                let delay = connection.options.delay;
+                debounce(&mut rx, delay, |changed| {
+                    connection.collect(&mut changes, changed, &ignore_files)
+                })
+                .await?;
+
+                connection.refresh(changes).await;
+            }
+            // while offline, keep collecting events but periodically check for the editor
+            _ = tokio::time::sleep(connection.reconnect_delay), if !connection.online => {
+                connection.try_reconnect().await;
+            }
+            _ = liveness.tick(), if connection.online && !dry_run => connection.check_alive().await,
+            Some(error) = play_errors.recv() => connection.stop_play(error).await,
+            _ = tokio::time::sleep(STATUS_POLL_DELAY), if polling && connection.online => {
+                connection.poll_status().await;
+            }
+            action = next_action(&mut actions) => match action {
+                Some(Action::Play) => {
+                    let play = Command::Play {
+                        scene: None,
+                        fast: false,
+                    };
+                    connection.editor_command("Playing", play).await;
+                }
+                Some(Action::Stop) => connection.editor_command("Stopped", Command::Stop).await,
+                Some(Action::Refresh) => {
+                    let changes = Changes {
+                        assets: true,
+                        ..Changes::default()
+                    };
+                    connection.refresh(changes).await;
+                }
+                Some(Action::Redraw) => {
+                    if let Some(tui) = &connection.tui {
+                        tui.draw();
+                    }
+                }
+                Some(Action::Quit) | None => break,
+            },
+            _ = &mut interrupted => break,
+        }
+    }
+
+    // one last refresh for the changes that came in meanwhile, if the editor can get it
+    let mut changes = Changes::default();
+    while let Ok(changed) = rx.try_recv() {
+        connection.collect(&mut changes, changed?, &ignore_files);
+    }
+    if connection.online && !changes.is_empty() {
+        connection.refresh(changes).await;
+    }
+
+    Ok(())
+}
+
+// Each Ctrl-C goes to every watcher, which stops once its refresh is done and prints its
+// summary, and this process waits for them. A second one makes them quit right away, like
+// for a single watcher. Windows gives the Ctrl-C of the console to every process on it
+async fn forward_interrupts(pids: Arc<Mutex<Vec<u32>>>) {
+    while tokio::signal::ctrl_c().await.is_ok() {
+        let pids = pids.lock().unwrap().clone();
+        let mut system = System::new();
+        for pid in pids {
+            let pid = Pid::from_u32(pid);
+            if system.refresh_process(pid) {
+                if let Some(process) = system.process(pid) {
+                    process.kill_with(Signal::Interrupt);
+                }
+            }
+        }
+    }
+}
+
+// One watcher prints for each project, its lines start with the name of the project
+async fn forward<R: AsyncRead + Unpin>(name: &str, output: R, stderr: bool) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if stderr {
+            eprintln!("[{}] {}", name, line);
+        } else {
+            println!("[{}] {}", name, line);
+        }
+    }
+}
+
+async fn watch_child(
+    args: Vec<OsString>,
+    project: PathBuf,
+    pids: Arc<Mutex<Vec<u32>>>,
+) -> anyhow::Result<()> {
+    let name = project.file_name().map_or_else(
+        || project.display().to_string(),
+        |name| name.to_string_lossy().into(),
+    );
+
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .args(args)
+        .arg(&project)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // out of reach of the Ctrl-C of the terminal, see forward_interrupts
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = tokio::process::Command::from(command)
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Could not start the watcher of {}", project.display()))?;
+    let pid = child.id();
+    pids.lock().unwrap().extend(pid);
+
+    let stdout = child.stdout.take().expect("Piped");
+    let stderr = child.stderr.take().expect("Piped");
+    tokio::join!(forward(&name, stdout, false), forward(&name, stderr, true));
+
+    let status = child.wait().await;
+    pids.lock().unwrap().retain(|&other| Some(other) != pid);
+    let status = status?;
+    if !status.success() {
+        bail!(
+            "The watcher of {} exited with {}",
+            project.display(),
+            status
+        );
+    }
+    Ok(())
+}
+
+/// Watches each project in a watcher of its own, `uwu watch` for one project, so that each
+/// finds its editor from its port file and signs with its token. Runs until they all end
+pub async fn watch_projects(projects: Vec<PathBuf>) -> anyhow::Result<()> {
+    // the same command line, with a single project
+    let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let watch = args
+        .iter()
+        .position(|arg| arg == "watch")
+        .context("Expected a watch subcommand")?;
+    let mut index = watch + 1;
+    while index < args.len() {
+        if projects
+            .iter()
+            .any(|project| args[index] == project.as_os_str())
+        {
+            args.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+
+    let pids = Arc::new(Mutex::new(vec![]));
+    let interrupts = tokio::spawn(forward_interrupts(pids.clone()));
+    let watchers = projects
+        .into_iter()
+        .map(|project| watch_child(args.clone(), project, pids.clone()));
+    let failed: Vec<anyhow::Error> = join_all(watchers)
+        .await
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
+    interrupts.abort();
+
+    for error in &failed {
+        log::error!("{:#}", error);
+    }
+    if !failed.is_empty() {
+        bail!("{} watchers failed", failed.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globset::GlobSetBuilder;
+    use serde_json::{json, Value};
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+    use uwu_core::mock::{MockServer, Reply, Scenario};
+
+    fn options() -> Options {
+        Options {
+            delay: Duration::from_millis(100),
+            poll: None,
+            clear: false,
+            ignore: GlobSetBuilder::new().build().unwrap(),
+            extensions: vec![],
+            smart: false,
+            extra_paths: vec![],
+            exec: None,
+            exec_after: None,
+            daemon: false,
+            restart_play: false,
+            stop_on_error: false,
+            test: false,
+            test_filter: None,
+            tui: false,
+            dry_run: false,
+        }
+    }
+
+    // The JSON body of the first request to the listener, answered with an empty 200
+    fn receive_post(listener: TcpListener) -> Value {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut request = vec![];
+        let mut buf = [0; 4096];
+        loop {
+            let len = stream.read(&mut buf).unwrap();
+            assert!(len > 0, "The request ended early");
+            request.extend_from_slice(&buf[..len]);
+
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length: usize = text[..end]
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse().ok())?
+                    })
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    stream
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                        .unwrap();
+                    return serde_json::from_slice(&request[end + 4..end + 4 + length]).unwrap();
+                }
+            }
+        }
+    }
+
+    // the editor answers the refresh right away, and only the compile tells what went wrong
+    #[tokio::test]
+    async fn notify_reports_the_compile_errors_of_a_refresh() {
+        let webhook = TcpListener::bind("127.0.0.1:0").unwrap();
+        webhook::set_url(format!("http://{}/", webhook.local_addr().unwrap()));
+        let posted = tokio::task::spawn_blocking(move || receive_post(webhook));
+
+        let compiler = Reply {
+            payload: Some(json!({
+                "messages": [{
+                    "file": "Assets/Player.cs",
+                    "line": 12,
+                    "column": 5,
+                    "kind": "error",
+                    "message": "; expected",
+                }],
+            })),
+            ..Default::default()
+        };
+        let scenario = Scenario {
+            commands: vec![("CompilerMessages".to_string(), compiler)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let server = MockServer::bind("127.0.0.1:0".parse().unwrap(), scenario)
+            .await
+            .unwrap();
+        let endpoint = Endpoint::Udp(server.local_addr().unwrap());
+        tokio::spawn(server.run());
+
+        let mut connection = Connection::new(endpoint, PathBuf::from("."), options());
+        let changes = Changes {
+            assets: true,
+            ..Default::default()
+        };
+        connection.refresh(changes).await;
+
+        let summary = posted.await.unwrap();
+        assert_eq!(summary["status"], "error");
+        assert_eq!(
+            summary["compile_errors"],
+            json!(["Assets/Player.cs:12:5: error: ; expected"])
+        );
+    }
+
+    fn changed(path: &str) -> anyhow::Result<Vec<Changed>> {
+        Ok(vec![Changed {
+            path: PathBuf::from(path),
+            kind: EventKind::Any,
+        }])
+    }
+
+    // sends the paths one after the other, each after its delay
+    fn send_later(
+        tx: mpsc::UnboundedSender<anyhow::Result<Vec<Changed>>>,
+        paths: &'static [(u64, &'static str)],
+    ) {
+        tokio::spawn(async move {
+            for (delay, path) in paths {
+                tokio::time::sleep(Duration::from_millis(*delay)).await;
+                let _ = tx.send(changed(path));
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn changes_within_the_delay_make_one_batch() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        send_later(
+            tx,
+            &[(50, "a.cs"), (50, "b.cs"), (50, "c.cs"), (300, "late.cs")],
+        );
+
+        let started = Instant::now();
+        let mut paths = vec![];
+        debounce(&mut rx, Duration::from_millis(200), |changed| {
+            paths.extend(changed.into_iter().map(|changed| changed.path));
+            1
+        })
+        .await
+        .unwrap();
+
+        // the window starts over with each change, and closes 200ms after the last one
+        assert_eq!(paths, ["a.cs", "b.cs", "c.cs"].map(PathBuf::from));
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(450), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn ignored_changes_dont_hold_the_batch_back() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        send_later(
+            tx,
+            &[
+                (50, "a.meta"),
+                (50, "b.meta"),
+                (50, "c.meta"),
+                (50, "d.meta"),
+            ],
+        );
+
+        let started = Instant::now();
+        debounce(&mut rx, Duration::from_millis(120), |_| 0)
+            .await
+            .unwrap();
+
+        let elapsed = started.elapsed();
+        assert!(elapsed < Duration::from_millis(180), "{:?}", elapsed);
+    }
+}
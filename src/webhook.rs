@@ -0,0 +1,85 @@
+use crate::JsonError;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use std::{sync::Mutex, time::Duration};
+use tokio::task::JoinHandle;
+
+// successes that took less than this aren't worth a ping, failures always are
+const MIN_DURATION: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+static URL: OnceCell<String> = OnceCell::new();
+// the compile errors that the command ran into, for the summary
+static COMPILE_ERRORS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// What --notify posts. text is what Slack and most chat webhooks show
+#[derive(Debug, Serialize)]
+struct Summary {
+    text: String,
+    command: String,
+    /// ok or error
+    status: &'static str,
+    duration_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonError>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    compile_errors: Vec<String>,
+}
+
+pub fn set_url(url: String) {
+    URL.set(url).expect("The notification URL is only set once");
+}
+
+pub fn enabled() -> bool {
+    URL.get().is_some()
+}
+
+pub fn compile_errors(errors: Vec<String>) {
+    *COMPILE_ERRORS.lock().unwrap() = errors;
+}
+
+/// Posts the outcome of command to the --notify URL in the background, if there's one.
+/// A notification that can't be sent is only logged, the command itself went fine
+pub fn notify(
+    command: &str,
+    duration: Duration,
+    result: &anyhow::Result<()>,
+) -> Option<JoinHandle<()>> {
+    let url = URL.get()?.clone();
+    if result.is_ok() && duration < MIN_DURATION {
+        return None;
+    }
+
+    let project = std::env::current_dir()
+        .ok()
+        .and_then(|dir| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    let text = match result {
+        Ok(()) => format!(
+            "uwu {} finished in {:.1}s ({})",
+            command,
+            duration.as_secs_f64(),
+            project
+        ),
+        Err(e) => format!("uwu {} failed ({}): {:#}", command, project, e),
+    };
+    let summary = Summary {
+        text,
+        command: command.to_string(),
+        status: if result.is_ok() { "ok" } else { "error" },
+        duration_secs: duration.as_secs_f64(),
+        error: result.as_ref().err().map(JsonError::new),
+        compile_errors: std::mem::take(&mut *COMPILE_ERRORS.lock().unwrap()),
+    };
+    let body = serde_json::to_value(&summary).expect("The summary is valid JSON");
+
+    Some(tokio::task::spawn_blocking(move || {
+        match ureq::post(&url).timeout(TIMEOUT).send_json(body) {
+            Ok(_) => log::debug!("Notified {}", url),
+            Err(e) => log::warn!("Could not send the notification: {}", e),
+        }
+    }))
+}
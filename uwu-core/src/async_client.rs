@@ -0,0 +1,325 @@
+use crate::{
+    id64::IdSource,
+    logs::{self, LogMessage},
+    protocol::{BuildOutput, Command, EditorStatus, PauseState, Request, TimeScaleState, Transfer},
+    send::{self, decode_payload, Observer, Settings},
+    transport::Endpoint,
+};
+use anyhow::Context;
+use futures_core::Stream;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+// a client that can't reach the editor gives up, unlike the CLI that waits by default
+pub(crate) const MAX_ATTEMPTS: u32 = 5;
+
+/// What a command streamed with AsyncClient::send_streaming reports
+#[derive(Debug)]
+pub enum Event {
+    /// What the editor says it's doing
+    Progress(String),
+    /// A console message of the editor
+    Log(String),
+    /// The payload of the command, always the last event
+    Done(anyhow::Result<serde_json::Value>),
+}
+
+// forwards what the command reports to the stream
+struct Forward(mpsc::UnboundedSender<Event>);
+
+impl Observer for Forward {
+    fn progress(&mut self, message: &str) {
+        let _ = self.0.send(Event::Progress(message.to_string()));
+    }
+
+    fn log(&mut self, message: &str) {
+        let _ = self.0.send(Event::Log(message.to_string()));
+    }
+}
+
+/// An async connection to an editor running the plugin, for tokio services. Commands can
+/// be sent concurrently, each one over its own socket
+pub struct AsyncClient {
+    endpoint: Endpoint,
+    settings: Settings,
+    ids: Arc<Mutex<IdSource>>,
+    max_attempts: Option<u32>,
+}
+
+impl AsyncClient {
+    /// Connects over UDP, and checks that the plugin speaks the same protocol
+    pub async fn connect(addr: &str) -> anyhow::Result<AsyncClient> {
+        let addr: SocketAddr = tokio::net::lookup_host(addr)
+            .await?
+            .next()
+            .context("The address did not resolve")?;
+
+        AsyncClient::with_endpoint(Endpoint::Udp(addr), Settings::default()).await
+    }
+
+    /// Over any transport, for example with the token of a project that signs requests
+    pub async fn with_endpoint(
+        endpoint: Endpoint,
+        mut settings: Settings,
+    ) -> anyhow::Result<AsyncClient> {
+        let mut ids = IdSource::Sequential;
+        send::handshake(&endpoint, &mut settings, ids.next_id(), Some(MAX_ATTEMPTS)).await?;
+
+        Ok(AsyncClient {
+            endpoint,
+            settings,
+            ids: Arc::new(Mutex::new(ids)),
+            max_attempts: Some(MAX_ATTEMPTS),
+        })
+    }
+
+    /// How many times to send a command that gets no answer, None tries forever
+    pub fn set_max_attempts(&mut self, max_attempts: Option<u32>) {
+        self.max_attempts = max_attempts;
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    fn request(&self, command: Command) -> Request {
+        Request {
+            id: self.ids.lock().unwrap().next_id(),
+            cmd: command,
+        }
+    }
+
+    /// Sends any command, and returns its payload once the editor is done with it
+    pub async fn send(&self, command: Command) -> anyhow::Result<serde_json::Value> {
+        let request = self.request(command);
+        send::send(
+            &self.endpoint,
+            &self.settings,
+            &request,
+            self.max_attempts,
+            &mut (),
+        )
+        .await
+    }
+
+    /// Like send, but streams what the editor reports while it works on the command,
+    /// ending with its payload. Needs a tokio runtime, the command runs on a task of its own
+    pub fn send_streaming(&self, command: Command) -> impl Stream<Item = Event> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let request = self.request(command);
+        let endpoint = self.endpoint.clone();
+        let settings = self.settings.clone();
+        let max_attempts = self.max_attempts;
+        tokio::spawn(async move {
+            let mut forward = Forward(tx.clone());
+            let result =
+                send::send(&endpoint, &settings, &request, max_attempts, &mut forward).await;
+            let _ = tx.send(Event::Done(result));
+        });
+
+        UnboundedReceiverStream::new(rx)
+    }
+
+    /// The console of the editor, down to level: info, warn or error. With follow, it
+    /// never ends, otherwise it ends after the recent messages. A failure to subscribe is
+    /// the last item
+    pub fn logs(
+        &self,
+        level: &'static str,
+        follow: bool,
+    ) -> impl Stream<Item = anyhow::Result<LogMessage>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let endpoint = self.endpoint.clone();
+        let settings = self.settings.clone();
+        let ids = self.ids.clone();
+        let max_attempts = self.max_attempts;
+        tokio::spawn(async move {
+            let on_log = |log| {
+                let _ = tx.send(Ok(log));
+            };
+            let subscription = logs::subscribe(
+                &endpoint,
+                &settings,
+                &ids,
+                max_attempts,
+                level,
+                follow,
+                on_log,
+            );
+
+            // stop following once nobody reads the stream anymore
+            let result = tokio::select! {
+                result = subscription => result,
+                _ = tx.closed() => Ok(()),
+            };
+            if let Err(e) = result {
+                let _ = tx.send(Err(e));
+            }
+        });
+
+        UnboundedReceiverStream::new(rx)
+    }
+
+    /// Entering and leaving Play mode, and refreshing, reload the scripts. Returns once
+    /// the plugin is back
+    async fn wait_reload(&self) -> anyhow::Result<()> {
+        self.send(Command::CheckAlive).await?;
+        Ok(())
+    }
+
+    pub async fn play(&self) -> anyhow::Result<()> {
+        self.send(Command::Play {
+            scene: None,
+            fast: false,
+        })
+        .await?;
+        self.wait_reload().await
+    }
+
+    /// Plays this scene, by path or name. The open scenes come back on stop
+    pub async fn play_scene(&self, scene: &str) -> anyhow::Result<()> {
+        self.send(Command::Play {
+            scene: Some(scene.to_string()),
+            fast: false,
+        })
+        .await?;
+        self.wait_reload().await
+    }
+
+    pub async fn stop(&self) -> anyhow::Result<()> {
+        self.send(Command::Stop).await?;
+        Ok(())
+    }
+
+    /// Pauses Play mode, or resumes it. Returns whether it's paused now
+    pub async fn pause(&self) -> anyhow::Result<bool> {
+        let state: PauseState = decode_payload(self.send(Command::Pause).await?)?;
+        Ok(state.paused)
+    }
+
+    pub async fn step(&self) -> anyhow::Result<()> {
+        self.send(Command::Step).await?;
+        Ok(())
+    }
+
+    /// Sets Time.timeScale until Play mode stops. Returns the one the editor has now
+    pub async fn time_scale(&self, scale: f32) -> anyhow::Result<f32> {
+        let state: TimeScaleState = decode_payload(self.send(Command::TimeScale { scale }).await?)?;
+        Ok(state.scale)
+    }
+
+    /// Captures the game or the scene view, into a JPEG of this quality or a PNG if 0. Both
+    /// sizes 0 let the editor pick
+    pub async fn screenshot(
+        &self,
+        view: &str,
+        width: u32,
+        height: u32,
+        quality: u32,
+    ) -> anyhow::Result<Vec<u8>> {
+        let transfer: Transfer = decode_payload(
+            self.send(Command::Screenshot {
+                view: view.to_string(),
+                width,
+                height,
+                quality,
+            })
+            .await?,
+        )?;
+        send::fetch_transfer(&transfer, |fetch| self.send(fetch)).await
+    }
+
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        self.send(Command::Refresh).await?;
+        self.wait_reload().await
+    }
+
+    /// Rebuilds all the scripts, only compatible with Unity 2019.3+
+    pub async fn build(&self) -> anyhow::Result<BuildOutput> {
+        decode_payload(self.send(Command::Build).await?)
+    }
+
+    /// Answered right away, even while the editor is busy
+    pub async fn status(&self) -> anyhow::Result<EditorStatus> {
+        decode_payload(self.send(Command::Status).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockServer, Reply, Scenario};
+    use serde_json::json;
+
+    // the slower command goes first, so its answer arrives while the other one waits too
+    #[tokio::test]
+    async fn concurrent_sends_get_their_own_payloads() {
+        let reply = |delay, payload| Reply {
+            wait: true,
+            delay,
+            payload: Some(payload),
+            ..Default::default()
+        };
+        let scenario = Scenario {
+            commands: vec![
+                ("Refresh".to_string(), reply(0.5, json!("refreshed"))),
+                ("ClearConsole".to_string(), reply(0.1, json!("cleared"))),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let server = MockServer::bind("127.0.0.1:0".parse().unwrap(), scenario)
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let client = AsyncClient::connect(&addr.to_string()).await.unwrap();
+        let (refreshed, cleared) = tokio::join!(
+            client.send(Command::Refresh),
+            client.send(Command::ClearConsole)
+        );
+        assert_eq!(refreshed.unwrap(), json!("refreshed"));
+        assert_eq!(cleared.unwrap(), json!("cleared"));
+    }
+
+    // the mock gives the same chunk for every index, so a transfer of two is the chunk twice
+    #[tokio::test]
+    async fn screenshot_fetches_the_chunks() {
+        let reply = |payload| Reply {
+            payload: Some(payload),
+            ..Default::default()
+        };
+        let scenario = Scenario {
+            commands: vec![
+                (
+                    "Screenshot".to_string(),
+                    reply(json!({ "transfer": "t", "size": 10, "chunk_count": 2 })),
+                ),
+                (
+                    "FetchChunk".to_string(),
+                    reply(json!({ "data": base64_url::encode(b"hello") })),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let server = MockServer::bind("127.0.0.1:0".parse().unwrap(), scenario)
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let client = AsyncClient::connect(&addr.to_string()).await.unwrap();
+        let png = client.screenshot("game", 0, 0, 0).await.unwrap();
+        assert_eq!(png, b"hellohello");
+    }
+}
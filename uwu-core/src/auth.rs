@@ -0,0 +1,48 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+
+/// A request wrapped with its signature. The payload is the encoded request, so that the
+/// plugin can check the signature against the exact bytes that were signed
+#[derive(Debug, Serialize)]
+pub struct SignedRequest {
+    payload: String,
+    sig: String,
+}
+
+pub fn token_path(project: &Path) -> PathBuf {
+    project.join("Library").join("uwu-token")
+}
+
+/// The plugin only writes a token when it's started with UWU_AUTH=1. Returns None if
+/// the project has no token, in which case requests go out unsigned
+pub fn read_token(project: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    let path = token_path(project);
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let token = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::format_err!("Could not read the token {}: {}", path.display(), e))?;
+
+    Ok(Some(token.trim().as_bytes().to_vec()))
+}
+
+pub fn sign(key: &[u8], msg: &[u8]) -> SignedRequest {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(msg);
+
+    let sig = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    SignedRequest {
+        payload: base64_url::encode(msg),
+        sig,
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{
+    async_client::AsyncClient,
+    protocol::{BuildOutput, Command, EditorStatus},
+    send::Settings,
+    transport::Endpoint,
+};
+use anyhow::Context;
+use std::net::ToSocketAddrs;
+
+// the default port of the plugin on localhost, like the CLI
+const UNITY_ADDR: &str = "127.0.0.1:38910";
+
+/// A blocking connection to an editor running the plugin, for tools that want to drive
+/// Unity without going through the uwu binary. Runs an AsyncClient on a runtime of its own
+///
+/// ```no_run
+/// let mut client = uwu_core::Client::connect("127.0.0.1:38910")?;
+/// client.refresh()?;
+/// client.play()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Client {
+    runtime: tokio::runtime::Runtime,
+    inner: AsyncClient,
+}
+
+impl Client {
+    /// Connects over UDP, and checks that the plugin speaks the same protocol
+    pub fn connect(addr: impl ToSocketAddrs) -> anyhow::Result<Client> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .context("The address did not resolve")?;
+
+        Client::with_endpoint(Endpoint::Udp(addr), Settings::default())
+    }
+
+    /// The editor on this machine, on the default port
+    pub fn local() -> anyhow::Result<Client> {
+        Client::connect(UNITY_ADDR)
+    }
+
+    /// Over any transport, for example with the token of a project that signs requests
+    pub fn with_endpoint(endpoint: Endpoint, settings: Settings) -> anyhow::Result<Client> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let inner = runtime.block_on(AsyncClient::with_endpoint(endpoint, settings))?;
+        Ok(Client { runtime, inner })
+    }
+
+    /// How many times to send a command that gets no answer, None tries forever
+    pub fn set_max_attempts(&mut self, max_attempts: Option<u32>) {
+        self.inner.set_max_attempts(max_attempts);
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        self.inner.endpoint()
+    }
+
+    /// Sends any command, and returns its payload once the editor is done with it
+    pub fn send(&mut self, command: Command) -> anyhow::Result<serde_json::Value> {
+        self.runtime.block_on(self.inner.send(command))
+    }
+
+    pub fn play(&mut self) -> anyhow::Result<()> {
+        self.runtime.block_on(self.inner.play())
+    }
+
+    /// Plays this scene, by path or name. The open scenes come back on stop
+    pub fn play_scene(&mut self, scene: &str) -> anyhow::Result<()> {
+        self.runtime.block_on(self.inner.play_scene(scene))
+    }
+
+    pub fn stop(&mut self) -> anyhow::Result<()> {
+        self.runtime.block_on(self.inner.stop())
+    }
+
+    /// Pauses Play mode, or resumes it. Returns whether it's paused now
+    pub fn pause(&mut self) -> anyhow::Result<bool> {
+        self.runtime.block_on(self.inner.pause())
+    }
+
+    pub fn step(&mut self) -> anyhow::Result<()> {
+        self.runtime.block_on(self.inner.step())
+    }
+
+    pub fn refresh(&mut self) -> anyhow::Result<()> {
+        self.runtime.block_on(self.inner.refresh())
+    }
+
+    /// Rebuilds all the scripts, only compatible with Unity 2019.3+
+    pub fn build(&mut self) -> anyhow::Result<BuildOutput> {
+        self.runtime.block_on(self.inner.build())
+    }
+
+    /// Answered right away, even while the editor is busy
+    pub fn status(&mut self) -> anyhow::Result<EditorStatus> {
+        self.runtime.block_on(self.inner.status())
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::io;
+
+/// How requests and responses are serialized on the wire. Picked during the handshake,
+/// which itself is always JSON so that any plugin can understand it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    /// The encodings that uwu can speak, most preferred first
+    pub const SUPPORTED: &'static [&'static str] = &["msgpack", "json"];
+
+    /// SUPPORTED, for the Hello of the handshake
+    pub fn supported() -> Vec<String> {
+        Encoding::SUPPORTED
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Encoding::Json),
+            "msgpack" => Some(Encoding::MessagePack),
+            _ => None,
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Json => Ok(serde_json::to_vec(value)?),
+            // structs must be maps: the plugin finds the fields of a request by name
+            Encoding::MessagePack => rmp_serde::to_vec_named(value)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, msg: &[u8]) -> io::Result<T> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(msg)?),
+            Encoding::MessagePack => rmp_serde::from_slice(msg)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        id64::{Id64, IdSource},
+        protocol::{Command, Request, Response, ResponseMessage},
+    };
+    use serde_json::{json, Value};
+
+    fn encodings() -> Vec<Encoding> {
+        Encoding::SUPPORTED
+            .iter()
+            .map(|name| Encoding::from_name(name).unwrap())
+            .collect()
+    }
+
+    fn id() -> Id64 {
+        IdSource::seeded(1).next_id()
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let request = Request {
+            id: id(),
+            cmd: Command::Click {
+                x: 10.5,
+                y: 20.0,
+                hold: 0.0,
+            },
+        };
+        for encoding in encodings() {
+            let decoded: Value = encoding
+                .decode(&encoding.encode(&request).unwrap())
+                .unwrap();
+            assert_eq!(
+                decoded,
+                serde_json::to_value(&request).unwrap(),
+                "{:?}",
+                encoding
+            );
+        }
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        // what the plugin writes, in either encoding
+        let progress = json!({
+            "id": id(),
+            "status": "Progress",
+            "message": "Compiling",
+            "percent": 50.0,
+        });
+        let success = json!({
+            "id": id(),
+            "status": "Success",
+            "payload": { "errors": [{ "file": "Assets/Player.cs", "line": 12 }] },
+        });
+        for encoding in encodings() {
+            let message: ResponseMessage = encoding
+                .decode(&encoding.encode(&progress).unwrap())
+                .unwrap();
+            assert_eq!(message.id, id());
+            match message.response {
+                Response::Progress {
+                    message, percent, ..
+                } => {
+                    assert_eq!(message, "Compiling");
+                    assert_eq!(percent, Some(50.0));
+                }
+                other => panic!("{:?}: {:?}", encoding, other),
+            }
+
+            let message: ResponseMessage = encoding
+                .decode(&encoding.encode(&success).unwrap())
+                .unwrap();
+            match message.response {
+                Response::Success { payload } => assert_eq!(payload, success["payload"]),
+                other => panic!("{:?}: {:?}", encoding, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::transport::Endpoint;
+
+/// The failures that scripts can tell apart, by their exit code or by their kind in the
+/// --json output. Anything else is a plain anyhow error, reported as a cli failure
+#[derive(thiserror::Error, Debug)]
+pub enum UwuError {
+    /// No editor answered at all, as opposed to an editor answering with an error
+    #[error("Unity is not reachable at {0}")]
+    Unreachable(Endpoint),
+    /// The editor is there, but didn't finish in time
+    #[error("{0}")]
+    Timeout(String),
+    /// The plugin and the CLI can't understand each other
+    #[error("{0}")]
+    ProtocolMismatch(String),
+    /// The editor answered a command with an error
+    #[error("Unity-side error: {message}")]
+    Editor {
+        message: String,
+        stack: Option<String>,
+    },
+    #[error("{errors} compile errors")]
+    CompileFailed { errors: usize },
+}
+
+impl UwuError {
+    /// The kind of the error in the --json output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            UwuError::Unreachable(_) => "unreachable",
+            UwuError::Timeout(_) => "timeout",
+            UwuError::ProtocolMismatch(_) => "protocol_mismatch",
+            UwuError::Editor { .. } => "editor",
+            UwuError::CompileFailed { .. } => "compile_failed",
+        }
+    }
+
+    /// Finds the typed error behind any context that was added to it
+    pub fn of(error: &anyhow::Error) -> Option<&UwuError> {
+        error.downcast_ref()
+    }
+
+    pub fn is_unreachable(error: &anyhow::Error) -> bool {
+        matches!(UwuError::of(error), Some(UwuError::Unreachable(_)))
+    }
+}
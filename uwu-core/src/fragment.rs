@@ -0,0 +1,255 @@
+//! Messages that don't fit in a UDP datagram, sent as several. Each fragment starts with
+//! a header: MAGIC, the id of the request it answers, its index, how many fragments there
+//! are and the size of the whole message, all little endian. Messages that fit are sent
+//! as they are, so only big ones pay for the header
+
+use crate::id64::Id64;
+use std::{collections::HashMap, convert::TryInto, time::Instant};
+
+/// Neither JSON nor MessagePack messages start like this, they are objects and maps
+pub const MAGIC: [u8; 4] = *b"UWUf";
+const HEADER_SIZE: usize = 20;
+
+/// The biggest payload a UDP datagram can carry
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+// leaves room for the header, must match the plugin
+const FRAGMENT_SIZE: usize = 60 * 1024;
+// bigger messages are surely garbage, don't try to allocate them
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+// a message that lost a fragment is never completed, don't keep too many of them
+const MAX_PARTIAL: usize = 8;
+
+/// The datagrams that carry msg, which answers the request with this id
+pub fn split(id: Id64, msg: &[u8]) -> Vec<Vec<u8>> {
+    if msg.len() <= MAX_DATAGRAM_SIZE {
+        return vec![msg.to_vec()];
+    }
+
+    let chunks: Vec<&[u8]> = msg.chunks(FRAGMENT_SIZE).collect();
+    let count = chunks.len() as u16;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut datagram = Vec::with_capacity(HEADER_SIZE + chunk.len());
+            datagram.extend_from_slice(&MAGIC);
+            datagram.extend_from_slice(&id.to_bytes());
+            datagram.extend_from_slice(&(index as u16).to_le_bytes());
+            datagram.extend_from_slice(&count.to_le_bytes());
+            datagram.extend_from_slice(&(msg.len() as u32).to_le_bytes());
+            datagram.extend_from_slice(chunk);
+            datagram
+        })
+        .collect()
+}
+
+struct Header {
+    id: Id64,
+    index: usize,
+    count: usize,
+    total: usize,
+}
+
+fn parse_header(datagram: &[u8]) -> Option<Header> {
+    if datagram.len() < HEADER_SIZE || datagram[..4] != MAGIC {
+        return None;
+    }
+
+    let u16_at = |at: usize| u16::from_le_bytes([datagram[at], datagram[at + 1]]) as usize;
+    Some(Header {
+        id: Id64::from_bytes(datagram[4..12].try_into().expect("8 bytes")),
+        index: u16_at(12),
+        count: u16_at(14),
+        total: u32::from_le_bytes(datagram[16..20].try_into().expect("4 bytes")) as usize,
+    })
+}
+
+struct Partial {
+    started: Instant,
+    total: usize,
+    fragments: Vec<Option<Vec<u8>>>,
+}
+
+/// Puts the fragments of each message back together, in whatever order they arrive
+#[derive(Default)]
+pub struct Reassembly {
+    partial: HashMap<Id64, Partial>,
+}
+
+impl Reassembly {
+    /// The whole message, once datagram completes it. Datagrams that aren't fragments are
+    /// whole messages already
+    pub fn push(&mut self, datagram: Vec<u8>) -> Option<Vec<u8>> {
+        let header = match parse_header(&datagram) {
+            Some(header) => header,
+            None => return Some(datagram),
+        };
+
+        if header.index >= header.count || header.total > MAX_MESSAGE_SIZE {
+            log::debug!("Ignoring an invalid fragment of {}", header.id);
+            return None;
+        }
+
+        // a resend of the answer may be split differently, start over
+        if let Some(partial) = self.partial.get(&header.id) {
+            if partial.total != header.total || partial.fragments.len() != header.count {
+                self.partial.remove(&header.id);
+            }
+        }
+        if !self.partial.contains_key(&header.id) && self.partial.len() >= MAX_PARTIAL {
+            self.drop_oldest();
+        }
+
+        let partial = self.partial.entry(header.id).or_insert_with(|| Partial {
+            started: Instant::now(),
+            total: header.total,
+            fragments: vec![None; header.count],
+        });
+        log::debug!(
+            "Received fragment {}/{} of {}",
+            header.index + 1,
+            header.count,
+            header.id
+        );
+        partial.fragments[header.index] = Some(datagram[HEADER_SIZE..].to_vec());
+
+        if partial.fragments.iter().any(Option::is_none) {
+            return None;
+        }
+
+        let partial = self.partial.remove(&header.id).expect("Just completed");
+        let msg: Vec<u8> = partial.fragments.into_iter().flatten().flatten().collect();
+        if msg.len() != partial.total {
+            log::debug!(
+                "Ignoring the message of {}, {} bytes instead of {}",
+                header.id,
+                msg.len(),
+                partial.total
+            );
+            return None;
+        }
+        Some(msg)
+    }
+
+    fn drop_oldest(&mut self) {
+        let oldest = self
+            .partial
+            .iter()
+            .min_by_key(|(_, partial)| partial.started)
+            .map(|(id, _)| *id);
+        if let Some(id) = oldest {
+            log::debug!("Giving up on the fragments of {}", id);
+            self.partial.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    fn id(n: u8) -> Id64 {
+        Id64::from([n; 8])
+    }
+
+    // too big for a datagram, the last fragment shorter, and bytes that show any misplaced chunk
+    fn message(fragments: usize) -> Vec<u8> {
+        (0..FRAGMENT_SIZE * (fragments - 1) + 6000)
+            .map(|i| (i % 251) as u8)
+            .collect()
+    }
+
+    fn reassemble(datagrams: Vec<Vec<u8>>) -> Vec<Option<Vec<u8>>> {
+        let mut reassembly = Reassembly::default();
+        datagrams
+            .into_iter()
+            .map(|datagram| reassembly.push(datagram))
+            .collect()
+    }
+
+    #[test]
+    fn in_order() {
+        let msg = message(4);
+        let datagrams = split(id(1), &msg);
+        assert_eq!(datagrams.len(), 4);
+        assert!(datagrams.iter().all(|datagram| datagram[..4] == MAGIC));
+
+        let results = reassemble(datagrams);
+        assert_eq!(results[..3], [None, None, None]);
+        assert_eq!(results[3].as_ref(), Some(&msg));
+    }
+
+    #[test]
+    fn out_of_order() {
+        let msg = message(4);
+        let mut datagrams = split(id(1), &msg);
+        datagrams.swap(0, 2);
+        datagrams.reverse();
+
+        let results = reassemble(datagrams);
+        assert_eq!(results[..3], [None, None, None]);
+        assert_eq!(results[3].as_ref(), Some(&msg));
+    }
+
+    #[test]
+    fn duplicates() {
+        let msg = message(3);
+        let datagrams = split(id(1), &msg);
+        let mut reassembly = Reassembly::default();
+
+        assert_eq!(reassembly.push(datagrams[0].clone()), None);
+        assert_eq!(reassembly.push(datagrams[0].clone()), None);
+        assert_eq!(reassembly.push(datagrams[1].clone()), None);
+        assert_eq!(reassembly.push(datagrams[1].clone()), None);
+        assert_eq!(reassembly.push(datagrams[2].clone()), Some(msg));
+        // a late copy starts a message that never completes
+        assert_eq!(reassembly.push(datagrams[2].clone()), None);
+    }
+
+    #[test]
+    fn total_mismatch() {
+        let msg = message(3);
+        let datagrams = split(id(1), &msg)
+            .into_iter()
+            .map(|mut datagram| {
+                datagram[16..20].copy_from_slice(&(msg.len() as u32 + 1).to_le_bytes());
+                datagram
+            })
+            .collect();
+
+        assert!(reassemble(datagrams).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn oldest_partial_is_evicted() {
+        let messages: Vec<Vec<Vec<u8>>> = (0..=MAX_PARTIAL as u8)
+            .map(|n| split(id(n), &message(2)))
+            .collect();
+        let mut reassembly = Reassembly::default();
+        for datagrams in &messages {
+            assert_eq!(reassembly.push(datagrams[0].clone()), None);
+            // the oldest is the one that started first
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(reassembly.partial.len(), MAX_PARTIAL);
+
+        // the first message lost its first fragment to make room
+        assert_eq!(reassembly.push(messages[0][1].clone()), None);
+        assert_eq!(
+            reassembly.push(messages[MAX_PARTIAL][1].clone()),
+            Some(message(2))
+        );
+    }
+
+    #[test]
+    fn whole_datagrams_have_no_header() {
+        let msg = vec![b'{'; MAX_DATAGRAM_SIZE];
+        let datagrams = split(id(1), &msg);
+        assert_eq!(datagrams, vec![msg.clone()]);
+        assert_eq!(reassemble(datagrams), vec![Some(msg)]);
+
+        let bigger = vec![b'{'; MAX_DATAGRAM_SIZE + 1];
+        assert_eq!(split(id(1), &bigger).len(), 2);
+    }
+}
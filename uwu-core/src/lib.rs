@@ -0,0 +1,20 @@
+//! The protocol of the uwu Unity plugin, and a client that speaks it. The uwu binary is a
+//! command line over this crate
+
+mod async_client;
+pub mod auth;
+mod client;
+pub mod encoding;
+pub mod error;
+pub mod fragment;
+pub mod id64;
+pub mod logs;
+pub mod mock;
+pub mod protocol;
+pub mod record;
+pub mod send;
+pub mod transport;
+
+pub use async_client::{AsyncClient, Event};
+pub use client::Client;
+pub use error::UwuError;
@@ -0,0 +1,152 @@
+use crate::{
+    id64::IdSource,
+    protocol::{Command, Request, Response, ResponseMessage},
+    send::{editor_error, retry_delay, Settings},
+    transport::{self, Endpoint, Transport},
+    UwuError,
+};
+use serde::Serialize;
+use std::{io, sync::Mutex, time::Duration};
+
+// the editor forgets subscriptions after 30s without a renewal
+const RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A message of the console of the editor
+#[derive(Debug, Clone, Serialize)]
+pub struct LogMessage {
+    /// info, warn or error
+    pub kind: String,
+    pub message: String,
+    pub stack: Option<String>,
+}
+
+/// Streams the console of the editor to on_log, down to level: info, warn or error.
+/// With follow, keeps going until dropped, and subscribes again whenever the editor
+/// reloads and forgets about us. Otherwise returns after the recent messages.
+/// Over UDP messages can get lost or arrive out of order, use another transport if every
+/// single line matters
+pub async fn subscribe(
+    endpoint: &Endpoint,
+    settings: &Settings,
+    ids: &Mutex<IdSource>,
+    max_attempts: Option<u32>,
+    level: &'static str,
+    follow: bool,
+    mut on_log: impl FnMut(LogMessage) + Send,
+) -> anyhow::Result<()> {
+    let encoding = settings.encoding;
+    let timeout = settings.ack_timeout();
+
+    let subscribe = |history| Request {
+        id: ids.lock().unwrap().next_id(),
+        cmd: Command::SubscribeLogs {
+            level: level.to_string(),
+            follow,
+            history,
+        },
+    };
+
+    let mut transport = Transport::open(endpoint, timeout).await?;
+    let mut subscription = subscribe(true);
+    transport
+        .send(&settings.encode_request(&subscription)?)
+        .await?;
+
+    let mut acknowledged = false;
+    let mut attempts = 1;
+    let mut renewal: Option<Request> = None;
+
+    loop {
+        let wait = if acknowledged {
+            RENEW_INTERVAL
+        } else {
+            timeout
+        };
+
+        let msg = match transport.recv(Some(wait)).await {
+            Ok(msg) => msg,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                if !acknowledged {
+                    // the subscription got lost
+                    if max_attempts.map_or(false, |max| attempts >= max) {
+                        return Err(UwuError::Unreachable(endpoint.clone()).into());
+                    }
+                    attempts += 1;
+                    transport
+                        .send(&settings.encode_request(&subscription)?)
+                        .await?;
+                } else if follow {
+                    let renew = Request {
+                        id: ids.lock().unwrap().next_id(),
+                        cmd: Command::RenewLogs {
+                            subscription: subscription.id,
+                        },
+                    };
+                    transport.send(&settings.encode_request(&renew)?).await?;
+                    renewal = Some(renew);
+                }
+                continue;
+            }
+            Err(e) if follow && transport::is_transient(&e) => {
+                // the editor went away, probably to reload scripts. Come back without
+                // printing the history again
+                log::debug!("Lost the log stream ({}), subscribing again", e);
+                tokio::time::sleep(retry_delay(1)).await;
+
+                transport = match Transport::open(endpoint, timeout).await {
+                    Ok(transport) => transport,
+                    Err(e) if transport::is_transient(&e) => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                subscription = subscribe(false);
+                acknowledged = false;
+                transport
+                    .send(&settings.encode_request(&subscription)?)
+                    .await?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let message: ResponseMessage = encoding.decode(&msg)?;
+
+        if renewal.as_ref().map(|renew| renew.id) == Some(message.id) {
+            // an error means that the editor reloaded and lost the subscription
+            if let Response::Error { .. } = message.response {
+                log::debug!("The editor forgot the log subscription, subscribing again");
+                subscription = subscribe(false);
+                acknowledged = false;
+                transport
+                    .send(&settings.encode_request(&subscription)?)
+                    .await?;
+            }
+            renewal = None;
+            continue;
+        }
+
+        if message.id != subscription.id {
+            log::debug!("Ignoring a stale response to request {}", message.id);
+            continue;
+        }
+
+        match message.response {
+            Response::Wait => acknowledged = true,
+            Response::Log {
+                kind,
+                message,
+                stack,
+            } => {
+                acknowledged = true;
+                on_log(LogMessage {
+                    kind,
+                    message,
+                    stack,
+                });
+            }
+            // only without follow, after the history
+            Response::Success { .. } => return Ok(()),
+            Response::Error { message, stack } => return Err(editor_error(message, stack)),
+            Response::Progress { message, .. } => log::info!("{}", message),
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use crate::{
+    encoding::Encoding,
+    fragment::{self, MAX_DATAGRAM_SIZE},
+    id64::Id64,
+    protocol::PROTOCOL_VERSION,
+};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, io, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::net::UdpSocket;
+
+/// How the mock editor behaves, usually read from a JSON file. Commands that the scenario
+/// doesn't mention succeed right away
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    /// The encoding that Hello picks, json unless set
+    pub encoding: Option<String>,
+    /// The protocol version that Hello answers, to test mismatches
+    pub version: Option<u32>,
+    /// The chance of losing each incoming datagram, between 0 and 1
+    pub drop_rate: f64,
+    /// How to answer each command, by its name on the wire, like Build or Play
+    pub commands: HashMap<String, Reply>,
+}
+
+/// How the mock editor answers a command
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Reply {
+    /// Ignore the first requests of this command, like datagrams that got lost
+    pub drop: u32,
+    /// Acknowledge with Wait first, like a long running command
+    pub wait: bool,
+    /// Seconds before the final answer, or before the only one without wait
+    pub delay: f64,
+    /// Progress messages, sent after the Wait
+    pub progress: Vec<String>,
+    /// Console messages, sent after the Wait
+    pub logs: Vec<String>,
+    /// Fail with this message instead of succeeding
+    pub error: Option<String>,
+    /// The payload of the Success, see protocol for what each command returns
+    pub payload: Option<Value>,
+}
+
+// what a real editor answers when the scenario doesn't say, so that the CLI can read it
+fn default_reply(cmd: &str) -> Reply {
+    let payload = match cmd {
+        "Status" => Some(json!({
+            "compiling": false,
+            "importing": false,
+            "playing": false,
+            "paused": false,
+        })),
+        "Build" => Some(json!({})),
+        "Pause" => Some(json!({ "paused": true })),
+        "WaitPlayMode" => Some(json!({ "exit_code": 0, "result": null })),
+        _ => None,
+    };
+
+    Reply {
+        wait: cmd == "SubscribeLogs",
+        payload,
+        ..Default::default()
+    }
+}
+
+/// Emulates the Unity plugin over UDP, for testing the CLI and the client without Unity.
+/// Signatures aren't checked
+pub struct MockServer {
+    socket: Arc<UdpSocket>,
+    scenario: Arc<Scenario>,
+    encoding: Encoding,
+}
+
+impl MockServer {
+    pub async fn bind(addr: SocketAddr, scenario: Scenario) -> anyhow::Result<MockServer> {
+        let encoding = match &scenario.encoding {
+            Some(name) => Encoding::from_name(name)
+                .ok_or_else(|| anyhow::format_err!("Unknown encoding '{}'", name))?,
+            None => Encoding::Json,
+        };
+
+        Ok(MockServer {
+            socket: Arc::new(UdpSocket::bind(addr).await?),
+            scenario: Arc::new(scenario),
+            encoding,
+        })
+    }
+
+    /// Where to send requests, useful when bound to port 0
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Answers requests until dropped. Each request is answered by a task of its own, so
+    /// that delayed answers don't hold back the others
+    pub async fn run(self) -> io::Result<()> {
+        let mut drops = HashMap::<String, u32>::new();
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+
+        loop {
+            let (len, peer) = self.socket.recv_from(&mut buf).await?;
+
+            if rand::thread_rng().gen_bool(self.scenario.drop_rate.max(0.0).min(1.0)) {
+                log::debug!("Dropping a datagram from {}", peer);
+                continue;
+            }
+
+            let (request, encoding) = match decode_message(&buf[..len]) {
+                Some(decoded) => decoded,
+                None => {
+                    log::warn!("Ignoring a datagram that isn't a request from {}", peer);
+                    continue;
+                }
+            };
+            let cmd = request["cmd"].as_str().unwrap_or_default().to_string();
+            log::info!("{} from {}", cmd, peer);
+
+            let reply = match self.scenario.commands.get(&cmd) {
+                Some(reply) => reply.clone(),
+                None => default_reply(&cmd),
+            };
+
+            // the first attempts at a command get lost
+            let dropped = drops.entry(cmd.clone()).or_insert(0);
+            if *dropped < reply.drop {
+                *dropped += 1;
+                log::debug!("Dropping {} ({}/{})", cmd, dropped, reply.drop);
+                continue;
+            }
+
+            let answer = Answer {
+                socket: self.socket.clone(),
+                peer,
+                id: request["id"].clone(),
+                encoding,
+            };
+            let hello = json!({
+                "version": self.scenario.version.unwrap_or(PROTOCOL_VERSION),
+                "encoding": name(self.encoding),
+                "unity_version": "mock",
+                "plugin_version": env!("CARGO_PKG_VERSION"),
+            });
+            tokio::spawn(async move {
+                let result = match cmd.as_str() {
+                    "Hello" => {
+                        answer
+                            .send(json!({ "status": "Success", "payload": hello }))
+                            .await
+                    }
+                    _ => answer.reply(&request, &reply).await,
+                };
+                if let Err(e) = result {
+                    log::warn!("Could not answer {}: {}", cmd, e);
+                }
+            });
+        }
+    }
+}
+
+fn name(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Json => "json",
+        Encoding::MessagePack => "msgpack",
+    }
+}
+
+// Messages come in whatever encoding the client picked, and requests may be signed.
+// Returns the message and its encoding, which is also the one to answer in
+pub(crate) fn decode_message(msg: &[u8]) -> Option<(Value, Encoding)> {
+    let encoding = if msg.first() == Some(&b'{') {
+        Encoding::Json
+    } else {
+        Encoding::MessagePack
+    };
+
+    let value: Value = encoding.decode(msg).ok()?;
+    match value["payload"].as_str() {
+        // see auth::sign, the signed bytes are the request itself
+        Some(payload) if value.get("sig").is_some() => {
+            let inner: Value = encoding.decode(&base64_url::decode(payload).ok()?).ok()?;
+            Some((inner, encoding))
+        }
+        _ => Some((value, encoding)),
+    }
+}
+
+// The way back to the client that sent a request
+struct Answer {
+    socket: Arc<UdpSocket>,
+    peer: SocketAddr,
+    id: Value,
+    encoding: Encoding,
+}
+
+impl Answer {
+    async fn send(&self, mut response: Value) -> io::Result<()> {
+        response["id"] = self.id.clone();
+        let msg = self.encoding.encode(&response)?;
+        // only big answers need the id, as the key of their fragments
+        let id = serde_json::from_value(self.id.clone()).unwrap_or_else(|_| Id64::random());
+        for datagram in fragment::split(id, &msg) {
+            self.socket.send_to(&datagram, self.peer).await?;
+        }
+        Ok(())
+    }
+
+    async fn reply(&self, request: &Value, reply: &Reply) -> io::Result<()> {
+        let delay = Duration::from_secs_f64(reply.delay.max(0.0));
+
+        if reply.wait {
+            self.send(json!({ "status": "Wait" })).await?;
+            for message in &reply.progress {
+                self.send(json!({ "status": "Progress", "message": message }))
+                    .await?;
+            }
+            for message in &reply.logs {
+                self.send(json!({ "status": "Log", "kind": "info", "message": message }))
+                    .await?;
+            }
+        }
+        tokio::time::sleep(delay).await;
+
+        // following logs never ends, the client stops renewing instead
+        if request["cmd"] == "SubscribeLogs" && request["follow"] == true {
+            return Ok(());
+        }
+
+        match &reply.error {
+            Some(message) => {
+                self.send(json!({ "status": "Error", "message": message }))
+                    .await
+            }
+            None => {
+                let payload = reply.payload.clone().unwrap_or(Value::Null);
+                self.send(json!({ "status": "Success", "payload": payload }))
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        async_client::{AsyncClient, Event},
+        error::UwuError,
+        id64::Id64,
+        protocol::Command,
+        send::{self, Settings},
+        transport::Endpoint,
+    };
+    use tokio_stream::StreamExt;
+
+    async fn serve(scenario: Scenario) -> SocketAddr {
+        let server = MockServer::bind("127.0.0.1:0".parse().unwrap(), scenario)
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+        addr
+    }
+
+    fn answering(cmd: &str, reply: Reply) -> Scenario {
+        Scenario {
+            commands: vec![(cmd.to_string(), reply)].into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    // retries come quickly, instead of after the timeout for a real editor
+    fn settings() -> Settings {
+        Settings {
+            timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        }
+    }
+
+    async fn client(addr: SocketAddr) -> AsyncClient {
+        AsyncClient::with_endpoint(Endpoint::Udp(addr), settings())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn wait_progress_success() {
+        let reply = Reply {
+            wait: true,
+            progress: vec!["Compiling".to_string()],
+            payload: Some(json!({ "done": true })),
+            ..Default::default()
+        };
+        let client = client(serve(answering("Build", reply)).await).await;
+
+        let events: Vec<Event> = client.send_streaming(Command::Build).collect().await;
+        match events.as_slice() {
+            [Event::Progress(message), Event::Done(Ok(payload))] => {
+                assert_eq!(message, "Compiling");
+                assert_eq!(payload, &json!({ "done": true }));
+            }
+            events => panic!("Unexpected events: {:?}", events),
+        }
+    }
+
+    #[tokio::test]
+    async fn error_reply() {
+        let reply = Reply {
+            error: Some("No such scene".to_string()),
+            ..Default::default()
+        };
+        let client = client(serve(answering("Stop", reply)).await).await;
+
+        let error = client.send(Command::Stop).await.unwrap_err();
+        match UwuError::of(&error) {
+            Some(UwuError::Editor { message, .. }) => assert_eq!(message, "No such scene"),
+            _ => panic!("Not an editor error: {:?}", error),
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_requests_are_retried() {
+        let reply = Reply {
+            drop: 2,
+            payload: Some(json!("alive")),
+            ..Default::default()
+        };
+        let client = client(serve(answering("CheckAlive", reply)).await).await;
+
+        assert_eq!(
+            client.send(Command::CheckAlive).await.unwrap(),
+            json!("alive")
+        );
+    }
+
+    #[tokio::test]
+    async fn drop_rate() {
+        let addr = serve(Scenario {
+            drop_rate: 1.0,
+            ..Default::default()
+        })
+        .await;
+
+        let result = send::handshake(
+            &Endpoint::Udp(addr),
+            &mut settings(),
+            Id64::random(),
+            Some(2),
+        )
+        .await;
+        assert!(UwuError::is_unreachable(&result.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn version_mismatch() {
+        let addr = serve(Scenario {
+            version: Some(PROTOCOL_VERSION + 1),
+            ..Default::default()
+        })
+        .await;
+
+        let error = send::handshake(
+            &Endpoint::Udp(addr),
+            &mut settings(),
+            Id64::random(),
+            Some(2),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            UwuError::of(&error),
+            Some(UwuError::ProtocolMismatch(_))
+        ));
+    }
+}
@@ -0,0 +1,683 @@
+use crate::id64::Id64;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{RootSchema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Bumped every time the messages change shape, so that a CLI and a plugin that can't
+// understand each other fail with a clear message instead of garbled JSON
+pub const PROTOCOL_VERSION: u32 = 5;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "cmd")]
+pub enum Command {
+    // sent before any other command, to check that both sides speak the same protocol
+    // and to agree on the encoding of every other message
+    Hello {
+        version: u32,
+        encodings: Vec<String>,
+    },
+    // with a scene, the open scenes are saved and swapped for it, until play mode stops.
+    // fast skips the domain and the scene reload, for this time only
+    Play {
+        scene: Option<String>,
+        #[serde(default)]
+        fast: bool,
+    },
+    CheckAlive,
+    // returns once play mode stops, with what the game passed to UWU.ExitPlayMode. With
+    // stop_on_error, as soon as the game logs an error or an exception, still playing
+    WaitPlayMode {
+        #[serde(default)]
+        stop_on_error: bool,
+    },
+    Stop,
+    Pause,
+    Step,
+    // Time.timeScale, only in Play mode. The editor puts back the one of the project when
+    // Play mode stops
+    TimeScale {
+        scale: f32,
+    },
+    // synthetic input through the Input System, in Play mode. Answers once the key or the
+    // button is up again, after hold seconds or the next frame if 0
+    PressKey {
+        key: String,
+        hold: f32,
+    },
+    // in the pixels of the Game view, from its bottom left corner
+    Click {
+        x: f32,
+        y: f32,
+        hold: f32,
+    },
+    // the value that UWU.GetAxis returns, until Play mode stops
+    SetAxis {
+        axis: String,
+        value: f32,
+    },
+    Refresh,
+    BackgroundRefresh,
+    Build,
+    OpenScene {
+        scene: String,
+        additive: bool,
+    },
+    Save {
+        scenes: bool,
+        assets: bool,
+    },
+    // the image is fetched with FetchChunk. A JPEG of this quality, from 1 to 100, or a
+    // PNG if 0
+    Screenshot {
+        view: String,
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        quality: u32,
+    },
+    FetchChunk {
+        transfer: String,
+        index: u32,
+    },
+    // closes the editor, force skips the save dialog
+    Quit {
+        force: bool,
+    },
+    // paths are relative to the project root, like Assets/Models/Character.fbx
+    Import {
+        paths: Vec<String>,
+    },
+    // may take many minutes
+    ReimportAll,
+    // the errors and warnings of the last compilation
+    CompilerMessages,
+    // like the Clear button of the Console window
+    ClearConsole,
+    // with frames, waits for them to be recorded and returns the capture like ProfileStop
+    ProfileStart {
+        frames: u32,
+    },
+    // the capture is fetched with FetchChunk
+    ProfileStop,
+    // bakes the open scenes, or opens scene first. Without wait, returns as soon as the bake
+    // starts, and the scenes must be saved afterwards to keep the result
+    BakeLighting {
+        scene: Option<String>,
+        wait: bool,
+    },
+    // profile only applies to this build, the active one is restored afterwards
+    BuildAddressables {
+        profile: Option<String>,
+        clean: bool,
+    },
+    // streams Log responses, until the editor forgets the subscription. With follow,
+    // the subscription must be renewed with RenewLogs or it expires
+    SubscribeLogs {
+        level: String,
+        follow: bool,
+        // whether to send the recent messages first
+        history: bool,
+    },
+    RenewLogs {
+        subscription: Id64,
+    },
+    // Package Manager operations, packages are names or anything that manifest.json accepts
+    AddPackage {
+        package: String,
+    },
+    RemovePackage {
+        package: String,
+    },
+    ListPackages,
+    // runs a menu item, like clicking on it
+    Menu {
+        path: String,
+    },
+    ListMenus,
+    // an asset path like Assets/Prefabs/Player.prefab, or a hierarchy path like Level/Player
+    Select {
+        path: String,
+    },
+    // the GameObjects of the loaded scenes, or of the one given by name or path. depth 0
+    // is the whole hierarchy
+    Hierarchy {
+        scene: Option<String>,
+        depth: u32,
+    },
+    // AssetDatabase.FindAssets, with its search syntax like "t:Prefab player". Only in
+    // these folders, if any
+    FindAssets {
+        filter: String,
+        folders: Vec<String>,
+    },
+    // calls a static C# method, like -executeMethod
+    Exec {
+        method: String,
+        args: Vec<String>,
+    },
+    // compiles C# in the editor and runs it: an expression, whose value is the result, or
+    // statements that may return one
+    Eval {
+        code: String,
+    },
+    // a full player build, as opposed to Build that only recompiles scripts
+    BuildPlayer {
+        target: String,
+        output: PathBuf,
+        development: bool,
+        // the BuildReport, as a transfer of the payload
+        report: bool,
+    },
+    // the scripting define symbols of a build target group, the one of the active build
+    // target without group. Changing them waits for the scripts to recompile
+    AddDefine {
+        symbol: String,
+        group: Option<String>,
+    },
+    RemoveDefine {
+        symbol: String,
+        group: Option<String>,
+    },
+    ListDefines {
+        group: Option<String>,
+    },
+    // the scenes of the Build Settings. Enabling a scene that isn't there adds it at the end
+    ListBuildScenes,
+    EnableBuildScene {
+        scene: String,
+    },
+    DisableBuildScene {
+        scene: String,
+    },
+    // an EditorPrefs key, or with player one of the PlayerSettings that the plugin knows,
+    // like bundle_version. Setting an EditorPrefs key keeps its type unless kind is given:
+    // string, int or float
+    GetPref {
+        key: String,
+        player: bool,
+    },
+    SetPref {
+        key: String,
+        new_value: String,
+        player: bool,
+        kind: Option<String>,
+    },
+    // changes the active build target, reimporting the assets for it
+    SwitchTarget {
+        target: String,
+    },
+    // the active build target, and the ones that the editor has the modules of
+    TargetInfo,
+    // the asset bundles of the project, for the active build target unless one is given
+    BuildBundles {
+        output: PathBuf,
+        target: Option<String>,
+    },
+    // runs the Unity Test Framework
+    Test {
+        mode: String,
+        filter: Option<String>,
+        // whether to send back the NUnit XML report too
+        xml: bool,
+    },
+    // game also focuses the Game view, so that it gets the keyboard and the mouse
+    FocusEditor {
+        game: bool,
+    },
+    // the Enter Play Mode Settings of the project. Leaves alone the ones that aren't given,
+    // and answers with all of them
+    PlayModeOptions {
+        domain_reload: Option<Toggle>,
+        scene_reload: Option<Toggle>,
+    },
+    // multicast to every editor, see discovery::discover
+    Discover,
+    // answered right away, even while the editor is busy
+    Status,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Request {
+    pub id: Id64,
+    // the arguments of the command are flattened next to "cmd", so that the plugin can
+    // read every request into a single flat object
+    #[serde(flatten)]
+    pub cmd: Command,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "status")]
+pub enum Response {
+    // the payload depends on the command, and is Null for most of them
+    Success {
+        #[serde(default)]
+        payload: serde_json::Value,
+    },
+    Error {
+        message: String,
+        #[serde(default)]
+        stack: Option<String>,
+    },
+    Wait,
+    // what a long running command is up to, between Wait and the final response
+    Progress {
+        message: String,
+        /// From 0 to 100, when the editor knows how far along it is
+        #[serde(default)]
+        percent: Option<f32>,
+        /// Only sent by Test, as each test starts and finishes
+        #[serde(default)]
+        test: Option<TestProgress>,
+    },
+    // a console message, streamed after the Wait of SubscribeLogs
+    Log {
+        // info, warn or error
+        kind: String,
+        message: String,
+        #[serde(default)]
+        stack: Option<String>,
+    },
+}
+
+/// Every response carries the id of the request it answers
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ResponseMessage {
+    pub id: Id64,
+    #[serde(flatten)]
+    pub response: Response,
+}
+
+/// Payload of Hello
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct HelloReply {
+    pub version: u32,
+    /// Plugins that predate encoding negotiation only speak JSON
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Only sent by plugins that know `uwu version`
+    #[serde(default)]
+    pub unity_version: Option<String>,
+    #[serde(default)]
+    pub plugin_version: Option<String>,
+}
+
+/// Payload of WaitPlayMode
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PlayModeResult {
+    pub exit_code: i32,
+    pub result: Option<String>,
+    /// The first error or exception of the game, if any
+    #[serde(default)]
+    pub error: Option<PlayModeError>,
+}
+
+/// An error that the game logged in Play mode
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PlayModeError {
+    pub message: String,
+    #[serde(default)]
+    pub stack: Option<String>,
+}
+
+/// Payload of Build
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct BuildOutput {
+    /// Where the build wrote its artifacts, not set for script-only rebuilds
+    #[serde(default)]
+    pub output_path: Option<String>,
+    /// Filled in by uwu from the CheckAlive that follows, the editor leaves it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+}
+
+/// Payload of CheckAlive. Older plugins answer without one
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct Alive {
+    /// How the last refresh or build went, if one just ended
+    #[serde(default)]
+    pub timings: Option<Timings>,
+}
+
+/// How long the phases of a refresh or a build took, in seconds. None for the phases that
+/// didn't happen, like the compilation of a refresh that only changed assets
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct Timings {
+    #[serde(default)]
+    pub import_secs: Option<f64>,
+    #[serde(default)]
+    pub compile_secs: Option<f64>,
+    #[serde(default)]
+    pub reload_secs: Option<f64>,
+    /// From the command until the editor answered again
+    #[serde(default)]
+    pub total_secs: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Toggle {
+    On,
+    Off,
+}
+
+/// Payload of PlayModeOptions
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PlayModeSettings {
+    pub domain_reload: bool,
+    pub scene_reload: bool,
+}
+
+/// Payload of Pause
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PauseState {
+    pub paused: bool,
+}
+
+/// Payload of TimeScale
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TimeScaleState {
+    pub scale: f32,
+}
+
+/// Payload of Hierarchy
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Hierarchy {
+    pub scenes: Vec<SceneHierarchy>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SceneHierarchy {
+    pub name: String,
+    /// Empty for scenes that were never saved
+    pub path: String,
+    pub roots: Vec<GameObjectNode>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct GameObjectNode {
+    pub name: String,
+    /// activeSelf, a GameObject under an inactive parent can be active itself
+    pub active: bool,
+    /// The type names of its components, Transform first
+    pub components: Vec<String>,
+    /// Left out below the depth of the request, see child_count
+    #[serde(default)]
+    pub children: Vec<GameObjectNode>,
+    pub child_count: u32,
+}
+
+/// Payload of FindAssets
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FoundAssets {
+    pub assets: Vec<FoundAsset>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FoundAsset {
+    pub path: String,
+    pub guid: String,
+}
+
+/// Payload of OpenScene
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct OpenedScene {
+    pub path: String,
+}
+
+/// Payload of Save
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SavedScenes {
+    pub saved_scenes: Vec<String>,
+}
+
+/// Payload of commands that return more data than fits in a message
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Transfer {
+    pub transfer: String,
+    pub size: usize,
+    pub chunk_count: u32,
+}
+
+/// Payload of FetchChunk
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Chunk {
+    /// url-safe base64
+    pub data: String,
+}
+
+/// Payload of AddPackage, and all the items of ListPackages
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// Registry, Embedded, Local, Git...
+    pub source: String,
+}
+
+/// Payload of ListPackages
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Packages {
+    pub packages: Vec<Package>,
+}
+
+/// Payload of CompilerMessages
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct CompilerMessages {
+    pub messages: Vec<CompilerMessage>,
+}
+
+impl CompilerMessages {
+    pub fn error_count(&self) -> usize {
+        self.messages
+            .iter()
+            .filter(|message| message.kind == "error")
+            .count()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct CompilerMessage {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    /// error or warning
+    pub kind: String,
+    pub message: String,
+}
+
+// the same format as compilers, so that editors and terminals can jump to them
+impl std::fmt::Display for CompilerMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.file, self.line, self.column, self.kind, self.message
+        )
+    }
+}
+
+/// Payload of ListMenus
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct MenuItems {
+    pub items: Vec<String>,
+}
+
+/// Payload of Exec and Eval
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ExecResult {
+    /// Null for void methods
+    pub result: serde_json::Value,
+}
+
+/// Payload of BuildPlayer
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PlayerBuild {
+    pub output_path: String,
+    pub total_size: u64,
+    pub duration_secs: f64,
+    /// Only sent if requested, the JSON of the BuildReport, written to a file
+    #[serde(default, skip_serializing)]
+    pub report: Option<Transfer>,
+}
+
+/// Payload of BuildAddressables
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ContentBuild {
+    pub output_path: String,
+    pub duration_secs: f64,
+}
+
+/// Payload of AddDefine, RemoveDefine and ListDefines
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Defines {
+    pub symbols: Vec<String>,
+}
+
+/// Payload of ListBuildScenes, EnableBuildScene and DisableBuildScene
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct BuildScenes {
+    pub scenes: Vec<BuildScene>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct BuildScene {
+    pub path: String,
+    pub enabled: bool,
+    /// What SceneManager.LoadScene takes, only enabled scenes have one
+    #[serde(default)]
+    pub index: Option<u32>,
+}
+
+/// Payload of TargetInfo. The names are the ones of Unity, like StandaloneWindows64 and
+/// IL2CPP
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct BuildTargetInfo {
+    pub target: String,
+    pub group: String,
+    /// The build targets whose module is installed
+    pub installed_targets: Vec<String>,
+    /// Of the group of the active target
+    pub scripting_backend: String,
+    pub api_compatibility_level: String,
+}
+
+/// Payload of GetPref and SetPref
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Pref {
+    pub key: String,
+    pub value: String,
+    /// string, int or float for EditorPrefs, player for PlayerSettings
+    pub kind: String,
+}
+
+/// Payload of BuildBundles
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Bundles {
+    pub bundles: Vec<Bundle>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Bundle {
+    pub name: String,
+    pub size: u64,
+    pub hash: String,
+}
+
+/// Payload of Test
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TestReport {
+    pub passed: u32,
+    pub failed: u32,
+    pub skipped: u32,
+    pub tests: Vec<TestResult>,
+    /// Only sent if requested, and written to a file rather than printed
+    #[serde(default, skip_serializing)]
+    pub xml: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TestResult {
+    pub name: String,
+    /// Passed, Failed, Skipped or Inconclusive
+    pub result: String,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// How a run of Test is going, in its Progress responses
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TestProgress {
+    pub name: String,
+    /// None when the test starts, then like TestResult::result
+    #[serde(default)]
+    pub result: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// How many tests the run has
+    pub total: u32,
+}
+
+/// Payload of Status
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct EditorStatus {
+    pub compiling: bool,
+    pub importing: bool,
+    pub playing: bool,
+    pub paused: bool,
+}
+
+/// A JSON Schema of every message, for the plugin that has to read and write them: a
+/// message is a Request or a ResponseMessage. The payloads are among the definitions, by
+/// the name of their struct
+pub fn schema() -> RootSchema {
+    let mut gen = SchemaGenerator::default();
+    let messages = vec![
+        gen.subschema_for::<Request>(),
+        gen.subschema_for::<ResponseMessage>(),
+    ];
+
+    // Success carries any of these, depending on the command
+    gen.subschema_for::<HelloReply>();
+    gen.subschema_for::<PlayModeResult>();
+    gen.subschema_for::<BuildOutput>();
+    gen.subschema_for::<Alive>();
+    gen.subschema_for::<PlayModeSettings>();
+    gen.subschema_for::<PauseState>();
+    gen.subschema_for::<TimeScaleState>();
+    gen.subschema_for::<Hierarchy>();
+    gen.subschema_for::<FoundAssets>();
+    gen.subschema_for::<OpenedScene>();
+    gen.subschema_for::<SavedScenes>();
+    gen.subschema_for::<Transfer>();
+    gen.subschema_for::<Chunk>();
+    gen.subschema_for::<Package>();
+    gen.subschema_for::<Packages>();
+    gen.subschema_for::<CompilerMessages>();
+    gen.subschema_for::<MenuItems>();
+    gen.subschema_for::<ExecResult>();
+    gen.subschema_for::<PlayerBuild>();
+    gen.subschema_for::<ContentBuild>();
+    gen.subschema_for::<Defines>();
+    gen.subschema_for::<BuildScenes>();
+    gen.subschema_for::<Pref>();
+    gen.subschema_for::<BuildTargetInfo>();
+    gen.subschema_for::<Bundles>();
+    gen.subschema_for::<TestReport>();
+    gen.subschema_for::<EditorStatus>();
+
+    RootSchema {
+        meta_schema: gen.settings().meta_schema.clone(),
+        schema: SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(messages),
+                ..Default::default()
+            })),
+            ..Default::default()
+        },
+        definitions: gen.take_definitions(),
+    }
+}
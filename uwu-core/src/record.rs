@@ -0,0 +1,170 @@
+use crate::{
+    encoding::Encoding,
+    fragment::{self, MAX_DATAGRAM_SIZE},
+    id64::Id64,
+    mock,
+};
+use anyhow::Context;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    net::SocketAddr,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
+
+static RECORDING: OnceCell<Mutex<Recording>> = OnceCell::new();
+
+struct Recording {
+    file: File,
+    start: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// From the client to the editor
+    Sent,
+    Received,
+}
+
+/// One message of a recorded session, a line of the recording
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Datagram {
+    pub direction: Direction,
+    /// Seconds since the recording started
+    pub time: f64,
+    /// The message as it was on the wire, in url-safe base64
+    pub data: String,
+}
+
+/// From here on, every message sent and received by any transport is appended to path,
+/// one JSON Datagram per line
+pub fn start(path: &Path) -> anyhow::Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Could not create {}", path.display()))?;
+    RECORDING
+        .set(Mutex::new(Recording {
+            file,
+            start: Instant::now(),
+        }))
+        .map_err(|_| anyhow::format_err!("The session is only recorded once"))
+}
+
+pub(crate) fn record(direction: Direction, msg: &[u8]) {
+    let mut recording = match RECORDING.get() {
+        Some(recording) => recording.lock().unwrap(),
+        None => return,
+    };
+
+    let datagram = Datagram {
+        direction,
+        time: recording.start.elapsed().as_secs_f64(),
+        data: base64_url::encode(msg),
+    };
+    let line = serde_json::to_string(&datagram).expect("Datagrams are always valid JSON");
+    // a recording with a hole is still worth more than a failed command
+    if let Err(e) = writeln!(recording.file, "{}", line) {
+        log::debug!("Could not record a message: {}", e);
+    }
+}
+
+pub fn load(path: &Path) -> anyhow::Result<Vec<Datagram>> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+
+    let mut datagrams = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let datagram = serde_json::from_str(&line)
+            .with_context(|| format!("{}:{} is not a datagram", path.display(), index + 1))?;
+        datagrams.push(datagram);
+    }
+    Ok(datagrams)
+}
+
+fn id_of(message: &Value) -> String {
+    message["id"].as_str().unwrap_or_default().to_string()
+}
+
+fn decode(datagram: &Datagram) -> anyhow::Result<(Value, Encoding)> {
+    let msg = base64_url::decode(&datagram.data).context("A datagram is not valid base64")?;
+    mock::decode_message(&msg).context("A datagram is not a message")
+}
+
+/// Plays the editor side of a recording against whoever sends the requests, usually uwu
+/// running the same commands. The ids of the recording are swapped for the live ones,
+/// and the answers keep their original pace. Retries on either side are skipped, so the
+/// live session may time out differently from the recorded one
+pub async fn replay(addr: SocketAddr, datagrams: &[Datagram]) -> anyhow::Result<()> {
+    let socket = UdpSocket::bind(addr)
+        .await
+        .with_context(|| format!("Could not listen on {}", addr))?;
+    log::info!("Replaying {} messages on {}", datagrams.len(), addr);
+
+    // recorded id -> live id, and where it came from
+    let mut ids = HashMap::<String, (String, SocketAddr)>::new();
+    let mut last_sent = 0.0;
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+
+    for datagram in datagrams {
+        let (mut message, encoding) = decode(datagram)?;
+        let recorded_id = id_of(&message);
+
+        match datagram.direction {
+            Direction::Sent => {
+                last_sent = datagram.time;
+                if ids.contains_key(&recorded_id) {
+                    // a retry of the recorded client
+                    continue;
+                }
+
+                // the next request that isn't a retry of the live client
+                let live = loop {
+                    let (len, from) = socket.recv_from(&mut buf).await?;
+                    match mock::decode_message(&buf[..len]) {
+                        Some((request, _))
+                            if !ids.values().any(|(id, _)| *id == id_of(&request)) =>
+                        {
+                            log::info!("{} from {}", request["cmd"], from);
+                            break (id_of(&request), from);
+                        }
+                        _ => log::debug!("Skipping a retry from {}", from),
+                    }
+                };
+                ids.insert(recorded_id, live);
+            }
+            Direction::Received => {
+                let (live_id, peer) = match ids.get(&recorded_id) {
+                    Some(live) => live.clone(),
+                    None => {
+                        log::debug!("Skipping an answer to a request that was never sent");
+                        continue;
+                    }
+                };
+
+                let delay = (datagram.time - last_sent).max(0.0);
+                tokio::time::sleep(Duration::from_secs_f64(delay)).await;
+                last_sent = datagram.time;
+
+                let id = Id64::try_from(live_id.clone()).unwrap_or_else(|_| Id64::random());
+                message["id"] = Value::String(live_id);
+                for datagram in fragment::split(id, &encoding.encode(&message)?) {
+                    socket.send_to(&datagram, peer).await?;
+                }
+            }
+        }
+    }
+
+    log::info!("The recording is over");
+    Ok(())
+}
@@ -0,0 +1,440 @@
+use crate::{
+    auth,
+    encoding::Encoding,
+    error::UwuError,
+    id64::Id64,
+    protocol::{
+        Chunk, Command, HelloReply, Request, Response, ResponseMessage, TestProgress, Transfer,
+        PROTOCOL_VERSION,
+    },
+    transport::{self, Endpoint, Transport},
+};
+use anyhow::{bail, Context};
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+// short timeout for localhost, where the latency of the editor itself dominates
+const TIMEOUT: Duration = Duration::from_secs(5);
+// remote editors get a timeout that scales with the round trip time of the handshake
+const RTT_MULTIPLIER: u32 = 20;
+const MAX_TIMEOUT: Duration = Duration::from_secs(30);
+// wait a bit before trying again, and twice as long after every failed attempt, so that
+// an editor that's busy or restarting doesn't get hammered
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// How to talk to an editor. The encoding and the round trip time come from the handshake
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Negotiated by the handshake, JSON until then
+    pub encoding: Encoding,
+    /// Round trip time measured by the handshake
+    pub rtt: Option<Duration>,
+    /// Replaces the timeout measured by the handshake
+    pub timeout: Option<Duration>,
+    /// How long a command may run after the editor acknowledged it, None waits forever
+    pub max_wait: Option<Duration>,
+    /// The token of the project, if the editor asks for signed requests, see auth
+    pub token: Option<Vec<u8>>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            encoding: Encoding::Json,
+            rtt: None,
+            timeout: None,
+            max_wait: None,
+            token: None,
+        }
+    }
+}
+
+impl Settings {
+    /// How long to wait for an ACK before sending again
+    pub fn ack_timeout(&self) -> Duration {
+        if let Some(timeout) = self.timeout {
+            return timeout;
+        }
+
+        match self.rtt {
+            Some(rtt) => (rtt * RTT_MULTIPLIER).max(TIMEOUT).min(MAX_TIMEOUT),
+            None => TIMEOUT,
+        }
+    }
+
+    /// The handshake is always JSON, it's what picks the encoding of everything else
+    pub fn encoding_for(&self, command: &Command) -> Encoding {
+        match command {
+            Command::Hello { .. } => Encoding::Json,
+            _ => self.encoding,
+        }
+    }
+
+    /// Encode a request, and sign it if the editor asks for signed requests
+    pub fn encode_request(&self, request: &Request) -> std::io::Result<Vec<u8>> {
+        let encoding = self.encoding_for(&request.cmd);
+        let msg = encoding.encode(request)?;
+
+        log::debug!("Sending {:?} as {:?}", request, encoding);
+
+        match &self.token {
+            Some(token) => encoding.encode(&auth::sign(token, &msg)),
+            None => Ok(msg),
+        }
+    }
+}
+
+/// What a long running command reports while the editor works on it. By default the
+/// progress and the console messages are logged
+pub trait Observer: Send {
+    /// The editor acknowledged the request, and answers once it's done
+    fn waiting(&mut self, _request: &Request) {}
+
+    /// What the editor says it's doing
+    fn progress(&mut self, message: &str) {
+        log::info!("{}", message);
+    }
+
+    /// A console message of the editor
+    fn log(&mut self, message: &str) {
+        log::info!("{}", message);
+    }
+
+    /// What the editor is doing, and how far along it is, from 0 to 100. By default the
+    /// percentage goes at the end of the message
+    fn percent(&mut self, message: &str, percent: f32) {
+        self.progress(&format!("{} ({:.0}%)", message, percent));
+    }
+
+    /// A test of Test that started or finished. By default it's progress like any other
+    fn test_progress(&mut self, message: &str, _test: &TestProgress) {
+        self.progress(message);
+    }
+}
+
+impl Observer for () {}
+
+/// Read the payload of a Success response as the type that the command is expected to return
+pub fn decode_payload<T: DeserializeOwned>(payload: serde_json::Value) -> anyhow::Result<T> {
+    serde_json::from_value(payload).context("Unexpected payload in the Unity response")
+}
+
+/// Downloads the data of a transfer one chunk at a time, sending each FetchChunk with send
+pub async fn fetch_transfer<F, R>(transfer: &Transfer, mut send: F) -> anyhow::Result<Vec<u8>>
+where
+    F: FnMut(Command) -> R,
+    R: Future<Output = anyhow::Result<serde_json::Value>>,
+{
+    let mut data = Vec::with_capacity(transfer.size);
+
+    for index in 0..transfer.chunk_count {
+        let fetch = Command::FetchChunk {
+            transfer: transfer.transfer.clone(),
+            index,
+        };
+        let chunk: Chunk = decode_payload(send(fetch).await?)?;
+
+        data.extend(base64_url::decode(&chunk.data).context("Invalid chunk data")?);
+    }
+
+    if data.len() != transfer.size {
+        bail!(
+            "Received {} bytes instead of {}, the transfer is corrupted",
+            data.len(),
+            transfer.size
+        );
+    }
+
+    Ok(data)
+}
+
+pub fn editor_error(message: String, stack: Option<String>) -> anyhow::Error {
+    if let Some(stack) = &stack {
+        log::debug!("Unity-side stack trace:\n{}", stack);
+    }
+    UwuError::Editor { message, stack }.into()
+}
+
+/// Receive messages until one answers the request with this id. Anything else is a late
+/// answer to an earlier retry or to a previous invocation, or a stray or corrupt datagram
+/// that doesn't decode, and is dropped
+pub async fn recv_response(
+    transport: &mut Transport,
+    encoding: Encoding,
+    id: Id64,
+    timeout: Option<Duration>,
+) -> std::io::Result<Response> {
+    loop {
+        let msg = transport.recv(timeout).await?;
+
+        let message: ResponseMessage = match encoding.decode(&msg) {
+            Ok(message) => message,
+            Err(e) => {
+                log::debug!("Ignoring a response that doesn't decode: {}", e);
+                continue;
+            }
+        };
+        log::debug!("Received {:?}", message);
+
+        if message.id == id {
+            return Ok(message.response);
+        }
+
+        log::debug!("Ignoring a stale response to request {}", message.id);
+    }
+}
+
+/// Exponential, with jitter so that several clients retrying together spread out
+pub fn retry_delay(attempts: u32) -> Duration {
+    let delay = RETRY_DELAY
+        .checked_mul(1 << attempts.saturating_sub(1).min(16))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY));
+
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+// One attempt at delivering the message. The transport is opened if needed, and kept
+// across attempts so that a late answer to an earlier attempt still counts
+async fn try_send(
+    endpoint: &Endpoint,
+    transport: &mut Option<Transport>,
+    settings: &Settings,
+    msg: &[u8],
+    request: &Request,
+) -> std::io::Result<Response> {
+    let timeout = settings.ack_timeout();
+
+    if transport.is_none() {
+        *transport = Some(Transport::open(endpoint, timeout).await?);
+    }
+    let transport = transport.as_mut().expect("The transport was just opened");
+    transport.send(msg).await?;
+
+    let encoding = settings.encoding_for(&request.cmd);
+    recv_response(transport, encoding, request.id, Some(timeout)).await
+}
+
+/// How far a command got when the editor acknowledged it
+pub enum Ack {
+    Done(serde_json::Value),
+    /// The final response comes later, over this transport
+    Running(Transport),
+}
+
+/// Send one message, and retry if it times out until ACK is received.
+/// This is needed because Unity may be recreating the socket, and the message could get lost.
+/// If max_attempts is set, give up with UwuError::Unreachable after that many failed attempts
+#[tracing::instrument(name = "ack", skip_all, fields(attempts = tracing::field::Empty))]
+pub async fn send_ack(
+    endpoint: &Endpoint,
+    settings: &Settings,
+    request: &Request,
+    max_attempts: Option<u32>,
+) -> anyhow::Result<Ack> {
+    let msg = settings.encode_request(request)?;
+
+    // repeat until acknowledged
+    let mut attempts = 0;
+    let mut transport = None;
+    loop {
+        attempts += 1;
+        tracing::Span::current().record("attempts", attempts);
+
+        match try_send(endpoint, &mut transport, settings, &msg, request).await {
+            // Success means that we're done
+            Ok(Response::Success { payload }) => {
+                log::debug!("Response received");
+                return Ok(Ack::Done(payload));
+            }
+            // Wait means that we should receive Success or Error later.
+            // Break the loop and wait for the next message
+            Ok(Response::Wait) => break,
+            // only comes after Wait, so the Wait got lost and the command is running
+            Ok(Response::Progress { message, .. }) | Ok(Response::Log { message, .. }) => {
+                log::info!("{}", message);
+                break;
+            }
+            Ok(Response::Error { message, stack }) => {
+                return Err(editor_error(message, stack));
+            }
+            Err(e) if transport::is_transient(&e) => {
+                if max_attempts.map_or(false, |max| attempts >= max) {
+                    return Err(UwuError::Unreachable(endpoint.clone()).into());
+                }
+                let delay = retry_delay(attempts);
+                log::debug!("No ACK received ({}), retrying in {:?}", e, delay);
+
+                // start over with a new connection, unless it's still good
+                if !(e.kind() == std::io::ErrorKind::TimedOut
+                    && transport
+                        .as_ref()
+                        .map_or(false, Transport::survives_timeout))
+                {
+                    transport = None;
+                }
+
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let transport = transport.expect("Wait came through a transport");
+    Ok(Ack::Running(transport))
+}
+
+// The tag of the command, like on the wire. Only computed when the span is recorded
+fn command_name(command: &Command) -> String {
+    serde_json::to_value(command)
+        .ok()
+        .and_then(|value| Some(value.get("cmd")?.as_str()?.to_owned()))
+        .unwrap_or_default()
+}
+
+/// Send one message, and wait for its final response
+#[tracing::instrument(
+    name = "send",
+    skip_all,
+    fields(cmd = %command_name(&request.cmd), id = %request.id)
+)]
+pub async fn send(
+    endpoint: &Endpoint,
+    settings: &Settings,
+    request: &Request,
+    max_attempts: Option<u32>,
+    observer: &mut dyn Observer,
+) -> anyhow::Result<serde_json::Value> {
+    let mut transport = match send_ack(endpoint, settings, request, max_attempts).await? {
+        Ack::Done(payload) => return Ok(payload),
+        Ack::Running(transport) => transport,
+    };
+
+    // wait for the final message
+    observer.waiting(request);
+    let encoding = settings.encoding_for(&request.cmd);
+    let transport = &mut transport;
+    match settings.max_wait {
+        Some(max_wait) => tokio::time::timeout(
+            max_wait,
+            recv_final(transport, encoding, request.id, observer),
+        )
+        .await
+        .map_err(|_| {
+            UwuError::Timeout(format!(
+                "Unity was still running the command after {}s",
+                max_wait.as_secs_f64()
+            ))
+        })?,
+        None => recv_final(transport, encoding, request.id, observer).await,
+    }
+}
+
+// Everything that a command sends after its Wait, until it's done
+#[tracing::instrument(name = "wait", skip_all)]
+async fn recv_final(
+    transport: &mut Transport,
+    encoding: Encoding,
+    id: Id64,
+    observer: &mut dyn Observer,
+) -> anyhow::Result<serde_json::Value> {
+    loop {
+        let response = recv_response(transport, encoding, id, None).await?;
+
+        match response {
+            // Success means that we're done
+            Response::Success { payload } => {
+                log::debug!("Final response received");
+                return Ok(payload);
+            }
+            Response::Error { message, stack } => return Err(editor_error(message, stack)),
+            Response::Progress {
+                message,
+                test: Some(test),
+            } => observer.test_progress(&message, &test),
+            Response::Progress {
+                message,
+                percent: Some(percent),
+                ..
+            } => observer.percent(&message, percent),
+            Response::Progress { message, .. } => observer.progress(&message),
+            Response::Log { message, .. } => observer.log(&message),
+            // Wait means that we should receive Success or Error later.
+            // Break the loop and wait for the next message
+            Response::Wait => {
+                bail!("Unexpected Wait response");
+            }
+        }
+    }
+}
+
+/// Make sure that the plugin speaks our protocol before sending any real command, and
+/// agree on the encoding. Updates the encoding and the round trip time of settings
+pub async fn handshake(
+    endpoint: &Endpoint,
+    settings: &mut Settings,
+    id: Id64,
+    max_attempts: Option<u32>,
+) -> anyhow::Result<()> {
+    let hello = Request {
+        id,
+        cmd: Command::Hello {
+            version: PROTOCOL_VERSION,
+            encodings: Encoding::supported(),
+        },
+    };
+
+    // plugins that predate the handshake either reject Hello or reply in the old format
+    const TOO_OLD: &str =
+        "The Unity plugin is too old for this version of uwu, run `uwu install` to update it";
+
+    let start = Instant::now();
+
+    let payload = match send(endpoint, settings, &hello, max_attempts, &mut ()).await {
+        Ok(payload) => payload,
+        Err(e) if UwuError::is_unreachable(&e) => return Err(e),
+        Err(e) => return Err(e.context(UwuError::ProtocolMismatch(TOO_OLD.to_string()))),
+    };
+
+    // v1 plugins sent the version next to the status instead of in a payload
+    let reply = serde_json::from_value::<HelloReply>(payload).ok();
+
+    match reply.as_ref().map(|reply| reply.version) {
+        Some(version) if version == PROTOCOL_VERSION => {}
+        Some(version) if version < PROTOCOL_VERSION => {
+            return Err(UwuError::ProtocolMismatch(format!(
+                "The Unity plugin speaks protocol v{} but uwu needs v{}, run `uwu install` to update it",
+                version, PROTOCOL_VERSION
+            ))
+            .into())
+        }
+        Some(version) => {
+            return Err(UwuError::ProtocolMismatch(format!(
+                "The Unity plugin speaks protocol v{} but uwu only knows v{}, please update uwu",
+                version, PROTOCOL_VERSION
+            ))
+            .into())
+        }
+        None => return Err(UwuError::ProtocolMismatch(TOO_OLD.to_string()).into()),
+    }
+
+    let encoding = match reply.and_then(|reply| reply.encoding) {
+        Some(name) => Encoding::from_name(&name)
+            .with_context(|| format!("The Unity plugin picked an unknown encoding '{}'", name))?,
+        None => Encoding::Json,
+    };
+
+    log::debug!("Using the {:?} encoding", encoding);
+    settings.encoding = encoding;
+
+    // includes the time Unity takes to get to the message, which is what we want to wait for
+    let rtt = start.elapsed();
+    log::debug!("Handshake took {:?}", rtt);
+    settings.rtt = Some(rtt);
+
+    Ok(())
+}
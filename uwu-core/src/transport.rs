@@ -0,0 +1,235 @@
+use crate::{
+    fragment::{Reassembly, MAX_DATAGRAM_SIZE},
+    record::{self, Direction},
+};
+use std::{
+    convert::TryFrom,
+    fmt, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+// frames bigger than this are surely garbage, don't try to allocate them
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    Udp,
+    Tcp,
+    Ipc,
+}
+
+impl FromStr for TransportKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "udp" => Ok(TransportKind::Udp),
+            "tcp" => Ok(TransportKind::Tcp),
+            "ipc" => Ok(TransportKind::Ipc),
+            _ => Err(anyhow::format_err!("Unknown transport '{}'", s)),
+        }
+    }
+}
+
+/// Where to reach the editor, and how
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+    /// A Unix domain socket, or a named pipe on Windows. Doesn't touch the network stack
+    /// at all, so firewalls never get in the way
+    Ipc(PathBuf),
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Udp(addr) => write!(f, "udp://{}", addr),
+            Endpoint::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Endpoint::Ipc(path) => write!(f, "ipc://{}", path.display()),
+        }
+    }
+}
+
+/// An open channel to the editor, that sends and receives whole messages.
+/// UDP messages are single datagrams, or fragments for those that don't fit, see fragment.
+/// The stream transports prefix each message with its length as a little endian u32.
+/// Every transport has a socket of its own, so that concurrent requests never read each
+/// other's responses
+pub enum Transport {
+    Udp(UdpSocket, Reassembly),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    #[cfg(windows)]
+    Pipe(NamedPipeClient),
+}
+
+impl Transport {
+    pub async fn open(endpoint: &Endpoint, timeout: Duration) -> io::Result<Self> {
+        match endpoint {
+            Endpoint::Udp(addr) => {
+                // bound to every interface, so that editors on other machines can be reached too
+                let local: SocketAddr = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                }
+                .parse()
+                .expect("Valid wildcard address");
+
+                let socket = UdpSocket::bind(local).await?;
+                socket.connect(addr).await?;
+                Ok(Transport::Udp(socket, Reassembly::default()))
+            }
+            Endpoint::Tcp(addr) => {
+                let stream = with_timeout(Some(timeout), TcpStream::connect(addr)).await?;
+                stream.set_nodelay(true)?;
+                Ok(Transport::Tcp(stream))
+            }
+            Endpoint::Ipc(path) => open_ipc(path).await,
+        }
+    }
+
+    pub async fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+        record::record(Direction::Sent, msg);
+
+        match self {
+            Transport::Udp(socket, _) => socket.send(msg).await.map(|_| ()),
+            Transport::Tcp(stream) => send_frame(stream, msg).await,
+            #[cfg(unix)]
+            Transport::Unix(stream) => send_frame(stream, msg).await,
+            #[cfg(windows)]
+            Transport::Pipe(pipe) => send_frame(pipe, msg).await,
+        }
+    }
+
+    /// A timeout can cut a stream in the middle of a frame, after which it can't be read
+    /// anymore. Datagrams are always whole
+    pub fn survives_timeout(&self) -> bool {
+        matches!(self, Transport::Udp(..))
+    }
+
+    /// Waits for the next message. A timeout of None waits forever
+    pub async fn recv(&mut self, timeout: Option<Duration>) -> io::Result<Vec<u8>> {
+        let msg = self.recv_message(timeout).await?;
+        record::record(Direction::Received, &msg);
+        Ok(msg)
+    }
+
+    async fn recv_message(&mut self, timeout: Option<Duration>) -> io::Result<Vec<u8>> {
+        match self {
+            Transport::Udp(socket, fragments) => {
+                let recv = async {
+                    // responses with payloads and stack traces easily outgrow a small
+                    // buffer, and the rest of a datagram that doesn't fit is lost
+                    let mut recv_buf = vec![0; MAX_DATAGRAM_SIZE];
+                    loop {
+                        let size = socket.recv(&mut recv_buf).await?;
+                        if let Some(msg) = fragments.push(recv_buf[..size].to_vec()) {
+                            return Ok(msg);
+                        }
+                    }
+                };
+                with_timeout(timeout, recv).await
+            }
+            Transport::Tcp(stream) => with_timeout(timeout, recv_frame(stream)).await,
+            #[cfg(unix)]
+            Transport::Unix(stream) => with_timeout(timeout, recv_frame(stream)).await,
+            #[cfg(windows)]
+            Transport::Pipe(pipe) => with_timeout(timeout, recv_frame(pipe)).await,
+        }
+    }
+}
+
+// Timeouts look like the ones of blocking sockets, so that is_transient treats them the same
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl std::future::Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+        None => future.await,
+    }
+}
+
+#[cfg(unix)]
+async fn open_ipc(path: &Path) -> io::Result<Transport> {
+    Ok(Transport::Unix(UnixStream::connect(path).await?))
+}
+
+#[cfg(windows)]
+async fn open_ipc(path: &Path) -> io::Result<Transport> {
+    Ok(Transport::Pipe(ClientOptions::new().open(path)?))
+}
+
+async fn send_frame<W: AsyncWrite + Unpin>(stream: &mut W, msg: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(msg.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Message too big"))?;
+
+    stream.write_all(&len.to_le_bytes()).await?;
+    stream.write_all(msg).await?;
+    stream.flush().await
+}
+
+async fn recv_frame<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Received a {} bytes frame, the stream is corrupted", len),
+        ));
+    }
+
+    let mut msg = vec![0; len];
+    stream.read_exact(&mut msg).await?;
+    Ok(msg)
+}
+
+// all the instances of a named pipe are taken, the editor is about to open a new one
+#[cfg(windows)]
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// Errors that mean the message didn't get through, but might on a new attempt because the
+/// editor is just busy or restarting
+pub fn is_transient(error: &io::Error) -> bool {
+    #[cfg(windows)]
+    {
+        if error.raw_os_error() == Some(ERROR_PIPE_BUSY) {
+            return true;
+        }
+    }
+
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock
+            // Windows reports timeouts as TimedOut rather than WouldBlock
+            | io::ErrorKind::TimedOut
+            // nothing is listening on the port, for example because the editor reloads scripts
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            // the editor closed the stream mid-message
+            | io::ErrorKind::UnexpectedEof
+            // the socket file or pipe is gone while the editor reloads scripts
+            | io::ErrorKind::NotFound
+    )
+}
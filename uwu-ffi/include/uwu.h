@@ -0,0 +1,27 @@
+/* The C API of uwu, see uwu-ffi/src/lib.rs */
+
+#ifndef UWU_H
+#define UWU_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct uwu_client uwu_client;
+
+/* NULL connects to the editor on this machine. Returns NULL on failure */
+uwu_client *uwu_connect(const char *addr);
+void uwu_disconnect(uwu_client *client);
+
+/* 0 on success, -1 on failure */
+int uwu_play(uwu_client *client);
+int uwu_refresh(uwu_client *client);
+
+/* Why the last call that failed on this thread did, or NULL */
+const char *uwu_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
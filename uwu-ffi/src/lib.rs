@@ -0,0 +1,106 @@
+//! A C API over uwu_core::Client, for tools that can't link Rust, like the C# editor
+//! extensions and the Python build scripts. See include/uwu.h.
+//!
+//! Functions that can fail return 0 on success and -1 on failure, or NULL for pointers.
+//! uwu_last_error then says what went wrong on this thread
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+use uwu_core::Client;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(message: String) {
+    // messages come from Rust strings, which may contain a NUL in theory
+    let message = CString::new(message.replace('\0', " ")).expect("The NULs were replaced");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// Runs f, and turns its errors and its panics into the last error. A panic must not
+// unwind into C
+fn call<T>(fallback: T, f: impl FnOnce() -> anyhow::Result<T>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_last_error(format!("{:#}", e));
+            fallback
+        }
+        Err(_) => {
+            set_last_error("uwu panicked".to_string());
+            fallback
+        }
+    }
+}
+
+unsafe fn client<'a>(client: *mut Client) -> anyhow::Result<&'a mut Client> {
+    client
+        .as_mut()
+        .ok_or_else(|| anyhow::format_err!("The client is NULL"))
+}
+
+/// Connects to the editor at addr, like "127.0.0.1:38910", or on this machine if addr is
+/// NULL. Returns NULL if the editor can't be reached or speaks another protocol.
+/// Free the client with uwu_disconnect
+///
+/// # Safety
+/// addr must be NULL or a NUL terminated string
+#[no_mangle]
+pub unsafe extern "C" fn uwu_connect(addr: *const c_char) -> *mut Client {
+    call(ptr::null_mut(), || {
+        let client = if addr.is_null() {
+            Client::local()?
+        } else {
+            Client::connect(CStr::from_ptr(addr).to_str()?)?
+        };
+        Ok(Box::into_raw(Box::new(client)))
+    })
+}
+
+/// Closes the connection. Does nothing if client is NULL
+///
+/// # Safety
+/// client must be NULL or come from uwu_connect, and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn uwu_disconnect(client: *mut Client) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Enters Play mode, and returns once the scripts are reloaded
+///
+/// # Safety
+/// client must be NULL or come from uwu_connect
+#[no_mangle]
+pub unsafe extern "C" fn uwu_play(client: *mut Client) -> c_int {
+    call(-1, || {
+        self::client(client)?.play()?;
+        Ok(0)
+    })
+}
+
+/// Refreshes the assets, and returns once the scripts are reloaded
+///
+/// # Safety
+/// client must be NULL or come from uwu_connect
+#[no_mangle]
+pub unsafe extern "C" fn uwu_refresh(client: *mut Client) -> c_int {
+    call(-1, || {
+        self::client(client)?.refresh()?;
+        Ok(0)
+    })
+}
+
+/// What the last call that failed on this thread went wrong with, or NULL. The string
+/// belongs to uwu, and stays valid until the next call that fails on this thread
+#[no_mangle]
+pub extern "C" fn uwu_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}